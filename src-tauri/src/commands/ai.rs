@@ -392,7 +392,7 @@ pub async fn ai_chat_stream(
                     "type": "function",
                     "function": {
                         "name": "explain_query",
-                        "description": "Get the execution plan (EXPLAIN ANALYZE) for a SQL query to understand performance characteristics.",
+                        "description": "Get the estimated execution plan (EXPLAIN) for a SQL query to understand performance characteristics. The query is not executed.",
                         "parameters": {
                             "type": "object",
                             "properties": {
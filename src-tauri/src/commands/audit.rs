@@ -0,0 +1,236 @@
+use crate::commands::database::{AppliedStatement, QueryResultData};
+use crate::db::params::QueryParam;
+use crate::db::{ConnectionPoolManager, ScriptResult};
+use crate::error::VelocityError;
+use crate::export::{ExportFormat, ExportResult};
+use crate::store::audit::{AuditCommand, AuditEntry, AuditFilter, AuditLogStore, NewAuditEntry};
+use chrono::Utc;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use tauri::State;
+
+/// Append a statement to the audit log. Failing to record never fails the
+/// statement itself.
+pub(crate) async fn record_audit(
+    audit: &AuditLogStore,
+    connection_id: &str,
+    command: AuditCommand,
+    sql: &str,
+    rows_affected: Option<i64>,
+    error: Option<String>,
+) {
+    let entry = NewAuditEntry {
+        connection_id,
+        command,
+        sql,
+        rows_affected,
+        error,
+        executed_at: Utc::now(),
+    };
+    if let Err(e) = audit.record(entry).await {
+        eprintln!("[VELOCITY] Failed to record audit entry: {}", e);
+    }
+}
+
+/// Whether `sql` changes data, schema or privileges: anything a read-only
+/// connection would refuse
+async fn is_mutation(pool_manager: &ConnectionPoolManager, connection_id: &str, sql: &str) -> bool {
+    pool_manager
+        .classify_sql(connection_id, sql)
+        .await
+        .is_ok_and(|classes| classes.iter().any(|class| !class.allowed_read_only()))
+}
+
+/// The SQL followed by the values bound to it, like the statements of grid
+/// edits: `... WHERE id = :id -- {"id":7}`, or `-- [7]` when a parameter has
+/// no name
+fn with_params(sql: &str, params: &[QueryParam]) -> String {
+    if params.is_empty() {
+        return sql.to_string();
+    }
+    let named: Option<Map<String, Value>> = params
+        .iter()
+        .map(|param| Some((param.name.clone()?, param.value.clone())))
+        .collect();
+    let values = match named {
+        Some(named) => Value::Object(named),
+        None => Value::Array(params.iter().map(|param| param.value.clone()).collect()),
+    };
+    format!("{} -- {}", sql, values)
+}
+
+/// Record an executed query when it is a mutation, with its parameters
+pub(crate) async fn audit_query(
+    audit: &AuditLogStore,
+    pool_manager: &ConnectionPoolManager,
+    connection_id: &str,
+    command: AuditCommand,
+    sql: &str,
+    params: &[QueryParam],
+    result: &Result<QueryResultData, VelocityError>,
+) {
+    if !is_mutation(pool_manager, connection_id, sql).await {
+        return;
+    }
+    let (rows_affected, error) = match result {
+        // With RETURNING, every returned row is an affected row
+        Ok(data) => (
+            Some(
+                data.rows_affected
+                    .map_or(data.row_count, |rows| rows as i64),
+            ),
+            None,
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let sql = with_params(sql, params);
+    record_audit(audit, connection_id, command, &sql, rows_affected, error).await;
+}
+
+/// Record a streamed query when it is a mutation. A stream only counts the
+/// rows it sent, so affected rows are known when RETURNING sent some.
+pub(crate) async fn audit_stream(
+    audit: &AuditLogStore,
+    pool_manager: &ConnectionPoolManager,
+    connection_id: &str,
+    sql: &str,
+    rows_returned: Option<u64>,
+    error: Option<String>,
+) {
+    if !is_mutation(pool_manager, connection_id, sql).await {
+        return;
+    }
+    let rows_affected = rows_returned
+        .filter(|&rows| rows > 0)
+        .map(|rows| rows as i64);
+    record_audit(
+        audit,
+        connection_id,
+        AuditCommand::ExecuteQueryStream,
+        sql,
+        rows_affected,
+        error,
+    )
+    .await;
+}
+
+/// Record the mutations among the statements of a script that were run
+pub(crate) async fn audit_script(
+    audit: &AuditLogStore,
+    pool_manager: &ConnectionPoolManager,
+    connection_id: &str,
+    script: &ScriptResult,
+) {
+    for statement in &script.results {
+        if !is_mutation(pool_manager, connection_id, &statement.sql).await {
+            continue;
+        }
+        let rows_affected = match statement.error {
+            Some(_) => None,
+            None => statement.rows_affected.map(|rows| rows as i64),
+        };
+        record_audit(
+            audit,
+            connection_id,
+            AuditCommand::ExecuteScript,
+            &statement.sql,
+            rows_affected,
+            statement.error.clone(),
+        )
+        .await;
+    }
+}
+
+/// Record the statements generated for grid edits or their undo
+pub(crate) async fn audit_changes(
+    audit: &AuditLogStore,
+    connection_id: &str,
+    command: AuditCommand,
+    statements: &[AppliedStatement],
+) {
+    for statement in statements {
+        record_audit(
+            audit,
+            connection_id,
+            command,
+            &statement.sql,
+            statement.rows_affected,
+            statement.error.clone(),
+        )
+        .await;
+    }
+}
+
+/// Search the audit log, newest first
+#[tauri::command]
+pub async fn search_audit_log(
+    filter: Option<AuditFilter>,
+    audit: State<'_, AuditLogStore>,
+) -> Result<Vec<AuditEntry>, VelocityError> {
+    audit.search(&filter.unwrap_or_default()).await
+}
+
+/// Export the audit entries matching the filter to a CSV or JSON file
+#[tauri::command]
+pub async fn export_audit_log(
+    filter: Option<AuditFilter>,
+    format: ExportFormat,
+    file_path: String,
+    audit: State<'_, AuditLogStore>,
+) -> Result<ExportResult, VelocityError> {
+    let rows_exported = audit
+        .export(
+            &filter.unwrap_or_default(),
+            &format,
+            &PathBuf::from(&file_path),
+        )
+        .await?;
+    Ok(ExportResult {
+        success: true,
+        file_path,
+        rows_exported,
+        message: Some(format!("Exported {} audit entries", rows_exported)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn param(name: Option<&str>, value: Value) -> QueryParam {
+        QueryParam {
+            name: name.map(str::to_string),
+            param_type: None,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_with_params_named_and_positional() {
+        let sql = "UPDATE t SET a = :a WHERE b = :b";
+        assert_eq!(with_params(sql, &[]), sql);
+        assert_eq!(
+            with_params(
+                sql,
+                &[param(Some("a"), json!("x")), param(Some("b"), json!(2))]
+            ),
+            format!("{} -- {{\"a\":\"x\",\"b\":2}}", sql)
+        );
+        assert_eq!(
+            with_params(
+                "DELETE FROM t WHERE a = ? AND b = ?",
+                &[param(None, json!(7)), param(None, Value::Null)]
+            ),
+            "DELETE FROM t WHERE a = ? AND b = ? -- [7,null]"
+        );
+        // One unnamed parameter makes them all positional
+        assert_eq!(
+            with_params(
+                "SELECT $1, $2",
+                &[param(Some("1"), json!(1)), param(None, json!(2))]
+            ),
+            "SELECT $1, $2 -- [1,2]"
+        );
+    }
+}
@@ -1,8 +1,21 @@
+use crate::commands::audit::{
+    audit_changes, audit_query, audit_script, audit_stream, record_audit,
+};
+use crate::commands::history::run_recorded;
+use crate::db::columns::{column_names, ResultColumn};
+use crate::db::params::{QueryParam, StatementParameter};
 use crate::db::table_data::fetch_table_data;
-use crate::db::{ColumnInfo, ConnectionPoolManager, QueryOptions, TableData, TableDataResponse};
+use crate::db::undo::{CommittedChange, UndoResult};
+use crate::db::{
+    ColumnInfo, ConnectionPoolManager, ExecuteOptions, ExplainOptions, PlanNode, QueryOptions,
+    QueryStreamEvent, RowIdentity, ScriptResult, StatementClass, StreamOptions, TableData,
+    TableDataResponse,
+};
 use crate::error::VelocityError;
 use crate::models::connection::Connection;
+use crate::store::audit::{AuditCommand, AuditLogStore};
 use crate::store::connections::ConnectionsStore;
+use crate::store::history::{HistorySource, QueryHistoryStore};
 use std::sync::Arc;
 use tauri::State;
 
@@ -139,54 +152,213 @@ pub async fn get_table_data(
 pub async fn get_table_data_filtered(
     id: String,
     table_name: String,
-    options: QueryOptions,
+    mut options: QueryOptions,
     pool_manager: State<'_, Arc<ConnectionPoolManager>>,
 ) -> Result<TableDataResponse, VelocityError> {
     let pool = pool_manager
         .get_pool(&id)
         .await
         .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+    let settings = pool_manager.connection_settings(&id).await;
+    options.timeout_ms = options.timeout_ms.or(settings.query_timeout_ms);
 
     let columns = pool_manager
         .get_table_schema(&id, &table_name)
         .await?;
 
-    fetch_table_data(pool.as_ref(), &table_name, &columns, &options).await
+    fetch_table_data(
+        pool.as_ref(),
+        &pool_manager.queries,
+        &table_name,
+        &columns,
+        &options,
+    )
+    .await
 }
 
+/// Key columns of a row mapped to their values
+pub type RowKey = serde_json::Map<String, serde_json::Value>;
+
 /// A pending change to be executed
 #[derive(Debug, Clone, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct PendingChange {
-    pub row_id: String,
+    /// Identifies the edited row by all of its key columns; empty for inserts
+    #[serde(default)]
+    pub key: RowKey,
+    #[serde(default)]
     pub column: String,
+    #[serde(default)]
     pub old_value: serde_json::Value,
+    #[serde(default)]
     pub new_value: serde_json::Value,
+    /// Column values of an inserted row; columns left out get their defaults
+    #[serde(default)]
+    pub values: serde_json::Map<String, serde_json::Value>,
+    /// Apply the update even if the row changed since it was loaded
+    #[serde(default)]
+    pub force: bool,
     #[serde(rename = "type")]
     pub change_type: String, // "update", "insert", "delete"
 }
 
+/// How `execute_changes` treats failing changes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ApplyMode {
+    /// Roll back the whole batch when any change fails
+    #[default]
+    AllOrNothing,
+    /// Commit the changes that succeeded
+    ApplySucceeded,
+}
+
+/// Options of an `execute_changes` call
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChangeOptions {
+    pub mode: ApplyMode,
+    /// Run the changes and roll back, reporting what they would have done
+    pub dry_run: bool,
+    /// How the change keys identify rows, as returned with the table data
+    pub row_identity: RowIdentity,
+    /// Only update rows whose edited column still holds `old_value`
+    pub check_conflicts: bool,
+}
+
+/// What happened to a pending change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ChangeStatus {
+    Applied,
+    Failed,
+    /// Succeeded, but another change failed and the batch was rolled back
+    RolledBack,
+    /// Not supported for this database, nothing was run
+    Skipped,
+    /// The row was changed or deleted since it was loaded; nothing was
+    /// changed
+    Conflict,
+}
+
+impl ChangeStatus {
+    /// The change could not be applied
+    pub fn is_failure(self) -> bool {
+        matches!(self, ChangeStatus::Failed | ChangeStatus::Conflict)
+    }
+}
+
+/// Outcome of a single pending change
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChangeResult {
+    /// Position of the change in the submitted list
+    pub index: usize,
+    pub change_type: String,
+    pub status: ChangeStatus,
+    pub rows_affected: Option<i64>,
+    pub error: Option<String>,
+    /// Database error code: SQLSTATE on PostgreSQL and MySQL, extended
+    /// result code on SQLite
+    pub error_code: Option<String>,
+    /// Key of an inserted or updated row, to edit it further
+    pub key: Option<RowKey>,
+    /// An inserted or updated row as stored, with generated keys, defaults
+    /// and changes made by triggers. None for deletes.
+    pub row: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Current values of a row whose update conflicted; None when the row
+    /// is gone
+    pub current: Option<serde_json::Map<String, serde_json::Value>>,
+}
+
 /// Result of executing changes
 #[derive(Debug, Clone, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExecuteResult {
+    /// False when any change failed, even if the others were applied
     pub success: bool,
+    /// Rows changed by the committed changes, or that would have been
+    /// changed on a dry run
     pub rows_affected: i64,
     pub errors: Vec<String>,
-}
-
-/// Execute pending changes (INSERT, UPDATE, DELETE)
+    /// Status of every submitted change, in order
+    pub changes: Vec<ChangeResult>,
+    /// Everything was rolled back; nothing was committed
+    pub dry_run: bool,
+    /// Statements sent to the database, for the audit log
+    #[serde(skip)]
+    pub statements: Vec<AppliedStatement>,
+    /// What the committed changes overwrote, for undo
+    #[serde(skip)]
+    pub committed: Vec<CommittedChange>,
+}
+
+/// A statement run while executing changes
+#[derive(Debug, Clone)]
+pub struct AppliedStatement {
+    pub sql: String,
+    /// None when the statement failed or was rolled back
+    pub rows_affected: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Execute pending changes (INSERT, UPDATE, DELETE).
+/// Every update and delete names its row by all `primary_key_columns`, or
+/// for tables without a primary key by the `row_identity` returned with
+/// the table data. An insert carries the whole new row in `values`.
+/// The result of each insert and update holds the row as stored, with its
+/// key, so the grid can show it without fetching the table again.
+/// By default the whole batch is rolled back when any change fails; with
+/// `mode` set to `applySucceeded` the changes that succeeded are committed.
+/// With `dry_run` every change is run and the transaction is always rolled
+/// back, so the result shows the rows each change would touch and which
+/// would fail. Sequences used by inserts still advance.
+/// With `check_conflicts` an update only applies while the edited column
+/// still holds `old_value`; otherwise it is reported as a conflict with the
+/// row's current values, and can be sent again with `force` set.
+/// On MongoDB the changes edit documents by `_id`, and an update may name
+/// a nested field by its dotted path.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_changes(
     id: String,
     table_name: String,
     changes: Vec<PendingChange>,
-    primary_key_column: String,
+    primary_key_columns: Vec<String>,
+    mode: Option<ApplyMode>,
+    dry_run: Option<bool>,
+    row_identity: Option<RowIdentity>,
+    check_conflicts: Option<bool>,
     pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+    audit: State<'_, AuditLogStore>,
 ) -> Result<ExecuteResult, VelocityError> {
-    pool_manager
-        .execute_changes(&id, &table_name, &primary_key_column, changes)
-        .await
+    let options = ChangeOptions {
+        mode: mode.unwrap_or_default(),
+        dry_run: dry_run.unwrap_or(false),
+        row_identity: row_identity.unwrap_or_default(),
+        check_conflicts: check_conflicts.unwrap_or(false),
+    };
+    let result = pool_manager
+        .execute_changes(&id, &table_name, &primary_key_columns, changes, options)
+        .await?;
+    if !result.dry_run {
+        audit_changes(&audit, &id, AuditCommand::ExecuteChanges, &result.statements).await;
+    }
+    Ok(result)
+}
+
+/// Revert the last batch of changes committed on a table. Rows that no
+/// longer hold the committed values are reported as conflicts and nothing
+/// is reverted then.
+#[tauri::command]
+pub async fn undo_last_changes(
+    id: String,
+    table_name: String,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+    audit: State<'_, AuditLogStore>,
+) -> Result<UndoResult, VelocityError> {
+    let result = pool_manager.undo_last_changes(&id, &table_name).await?;
+    audit_changes(&audit, &id, AuditCommand::UndoLastChanges, &result.statements).await;
+    Ok(result)
 }
 
 /// Query result for SQL editor
@@ -194,34 +366,247 @@ pub async fn execute_changes(
 #[serde(rename_all = "camelCase")]
 pub struct QueryResultData {
     pub columns: Vec<String>,
+    /// Name, type and nullability of each column, also set when no rows match
+    pub column_info: Vec<ResultColumn>,
     pub rows: Vec<Vec<serde_json::Value>>,
     pub row_count: i64,
+    /// Rows inserted, updated or deleted, set for statements without a
+    /// result set
+    pub rows_affected: Option<u64>,
+}
+
+impl QueryResultData {
+    pub fn new(column_info: Vec<ResultColumn>, rows: Vec<Vec<serde_json::Value>>) -> Self {
+        Self {
+            columns: column_names(&column_info),
+            row_count: rows.len() as i64,
+            column_info,
+            rows,
+            rows_affected: None,
+        }
+    }
+
+    /// Set the affected row count unless the statement returned a result set
+    pub fn with_rows_affected(mut self, rows_affected: u64) -> Self {
+        if self.column_info.is_empty() {
+            self.rows_affected = Some(rows_affected);
+        }
+        self
+    }
 }
 
-/// Execute a raw SQL query
+/// Execute a raw SQL query.
+/// When `query_id` is given the query can be stopped with `cancel_query`;
+/// `timeout_ms` overrides the connection's statement timeout (0 = no limit).
+/// `params` are bound to `:name`, `$n` or `?` placeholders in the SQL.
+/// With `session_id` the query runs inside that transaction session.
+/// On protected connections destructive statements need the
+/// `confirmation_token` returned by a first, refused attempt.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_query(
     id: String,
     sql: String,
+    query_id: Option<String>,
+    timeout_ms: Option<u64>,
+    params: Option<Vec<QueryParam>>,
+    session_id: Option<String>,
+    confirmation_token: Option<String>,
     pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+    history: State<'_, QueryHistoryStore>,
+    audit: State<'_, AuditLogStore>,
 ) -> Result<QueryResultData, VelocityError> {
-    pool_manager.execute_query(&id, &sql).await
+    pool_manager
+        .check_protected(&id, &sql, confirmation_token.as_deref())
+        .await?;
+    let options = ExecuteOptions {
+        query_id,
+        timeout_ms,
+        params: params.unwrap_or_default(),
+        session_id,
+    };
+    let result = run_recorded(
+        &history,
+        &pool_manager,
+        &id,
+        &sql,
+        &options,
+        HistorySource::Editor,
+    )
+    .await;
+    audit_query(
+        &audit,
+        &pool_manager,
+        &id,
+        AuditCommand::ExecuteQuery,
+        &sql,
+        &options.params,
+        &result,
+    )
+    .await;
+    result
 }
 
-/// Get query execution plan (EXPLAIN)
-#[derive(Debug, Clone, serde::Serialize)]
+/// List the placeholders of a statement with their expected types
+#[tauri::command]
+pub async fn list_query_parameters(
+    id: String,
+    sql: String,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<Vec<StatementParameter>, VelocityError> {
+    pool_manager.list_query_parameters(&id, &sql).await
+}
+
+/// Classify each statement of `sql`: its kind, the tables it targets and,
+/// for UPDATE and DELETE, whether it has a WHERE clause
+#[tauri::command]
+pub async fn classify_sql(
+    id: String,
+    sql: String,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<Vec<StatementClass>, VelocityError> {
+    pool_manager.classify_sql(&id, &sql).await
+}
+
+/// Cancel a running query by the query ID it was started with.
+/// Returns false when no such query is running.
+#[tauri::command]
+pub async fn cancel_query(
+    query_id: String,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<bool, VelocityError> {
+    pool_manager.cancel_query(&query_id).await
+}
+
+/// Execute a multi-statement SQL script, returning one result per statement
+#[tauri::command]
+pub async fn execute_script(
+    id: String,
+    sql: String,
+    stop_on_error: Option<bool>,
+    query_id: Option<String>,
+    confirmation_token: Option<String>,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+    audit: State<'_, AuditLogStore>,
+) -> Result<ScriptResult, VelocityError> {
+    pool_manager
+        .check_protected(&id, &sql, confirmation_token.as_deref())
+        .await?;
+    let result = pool_manager
+        .execute_script(
+            &id,
+            &sql,
+            stop_on_error.unwrap_or(true),
+            query_id.as_deref(),
+        )
+        .await?;
+    audit_script(&audit, &pool_manager, &id, &result).await;
+    Ok(result)
+}
+
+/// Stream a query's rows to the frontend in batches over a channel.
+/// The frontend must acknowledge batches with `ack_query_stream` to receive more.
+/// On protected connections destructive statements need the
+/// `confirmation_token` returned by a first, refused attempt. Mutations
+/// (`DELETE ... RETURNING`) are recorded in the audit log.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn execute_query_stream(
+    id: String,
+    sql: String,
+    batch_size: Option<usize>,
+    max_in_flight: Option<usize>,
+    confirmation_token: Option<String>,
+    on_event: tauri::ipc::Channel<QueryStreamEvent>,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+    audit: State<'_, AuditLogStore>,
+) -> Result<(), VelocityError> {
+    pool_manager
+        .check_protected(&id, &sql, confirmation_token.as_deref())
+        .await?;
+    let defaults = StreamOptions::default();
+    let options = StreamOptions {
+        batch_size: batch_size.unwrap_or(defaults.batch_size),
+        max_in_flight: max_in_flight.unwrap_or(defaults.max_in_flight),
+    };
+
+    // Rows sent by a completed stream, or why it failed
+    let mut rows_returned = None;
+    let mut error = None;
+    let result = pool_manager
+        .execute_query_stream(&id, &sql, options, |event| {
+            match &event {
+                QueryStreamEvent::Done { row_count, .. } => rows_returned = Some(*row_count),
+                QueryStreamEvent::Error { message } => error = Some(message.clone()),
+                _ => {}
+            }
+            on_event
+                .send(event)
+                .map_err(|e| VelocityError::TauriError(e.to_string()))
+        })
+        .await;
+    if let Err(e) = &result {
+        error.get_or_insert_with(|| e.to_string());
+    }
+    audit_stream(&audit, &pool_manager, &id, &sql, rows_returned, error).await;
+    result
+}
+
+/// Acknowledge consumed batches of a query stream
+#[tauri::command]
+pub async fn ack_query_stream(
+    stream_id: String,
+    batches: Option<usize>,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<(), VelocityError> {
+    pool_manager
+        .ack_query_stream(&stream_id, batches.unwrap_or(1))
+        .await;
+    Ok(())
+}
+
+/// Close a query stream before all rows were sent
+#[tauri::command]
+pub async fn close_query_stream(
+    stream_id: String,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<(), VelocityError> {
+    pool_manager.close_query_stream(&stream_id).await;
+    Ok(())
+}
+
+/// Query execution plan (EXPLAIN)
+#[derive(Debug, Clone, Default, serde::Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExplainResult {
+    /// Text rendering of the plan, one line per node
     pub plan: Vec<String>,
-}
-
+    /// Root operations of the plan
+    pub nodes: Vec<PlanNode>,
+    /// Whether the statement was executed (EXPLAIN ANALYZE)
+    pub analyzed: bool,
+    /// Milliseconds spent planning (PostgreSQL only)
+    pub planning_time: Option<f64>,
+    /// Milliseconds spent executing (PostgreSQL ANALYZE only)
+    pub execution_time: Option<f64>,
+}
+
+/// Get the execution plan of a statement. `analyze` executes it; statements
+/// that modify data are refused unless `rollback` is set, which runs them in
+/// a transaction that is rolled back.
 #[tauri::command]
 pub async fn explain_query(
     id: String,
     sql: String,
+    analyze: Option<bool>,
+    rollback: Option<bool>,
     pool_manager: State<'_, Arc<ConnectionPoolManager>>,
 ) -> Result<ExplainResult, VelocityError> {
-    pool_manager.explain_query(&id, &sql).await
+    let options = ExplainOptions {
+        analyze: analyze.unwrap_or(false),
+        rollback: rollback.unwrap_or(false),
+    };
+    pool_manager.explain_query(&id, &sql, options).await
 }
 
 // ============================================================================
@@ -241,26 +626,56 @@ pub struct SafeQueryResult {
 }
 
 /// Execute SQL safely with LLM-friendly error messages
-/// This command never errors at the Tauri level - it returns structured results including failures
+/// This command only errors at the Tauri level when a protected connection
+/// asks for confirmation - other failures are returned as structured results
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_sql_safe(
     app_handle: tauri::AppHandle,
     id: String,
     sql: String,
+    timeout_ms: Option<u64>,
+    confirmation_token: Option<String>,
     pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+    history: State<'_, QueryHistoryStore>,
+    audit: State<'_, AuditLogStore>,
 ) -> Result<SafeQueryResult, VelocityError> {
     use tauri::Emitter;
 
-    let result = pool_manager.execute_query(&id, &sql).await;
+    pool_manager
+        .check_protected(&id, &sql, confirmation_token.as_deref())
+        .await?;
+    let options = ExecuteOptions {
+        timeout_ms,
+        ..Default::default()
+    };
+    let result = run_recorded(
+        &history,
+        &pool_manager,
+        &id,
+        &sql,
+        &options,
+        HistorySource::Agent,
+    )
+    .await;
+    audit_query(
+        &audit,
+        &pool_manager,
+        &id,
+        AuditCommand::ExecuteSqlSafe,
+        &sql,
+        &options.params,
+        &result,
+    )
+    .await;
     
     // Check if we need to emit a schema change event (DDL / Mutations)
     if result.is_ok() {
-        let sql_upper = sql.trim().to_uppercase();
-        let is_schema_change = sql_upper.starts_with("CREATE") 
-            || sql_upper.starts_with("DROP") 
-            || sql_upper.starts_with("ALTER")
-            || sql_upper.starts_with("TRUNCATE");
-            
+        let is_schema_change = pool_manager
+            .classify_sql(&id, &sql)
+            .await
+            .is_ok_and(|classes| classes.iter().any(StatementClass::changes_schema));
+
         if is_schema_change {
             let _ = app_handle.emit("database:schema-changed", &id);
         }
@@ -382,7 +797,7 @@ pub async fn get_database_schema_full(
 // ============================================================================
 
 use crate::db::schema_ops::{
-    self, ColumnDefinition, CreateTableRequest, ForeignKeyDefinition, IndexInfo,
+    self, ColumnDefinition, CreateTableRequest, DryRunResult, ForeignKeyDefinition, IndexInfo,
 };
 
 /// Preview SQL for creating a table (returns SQL without executing)
@@ -400,28 +815,50 @@ pub async fn preview_create_table(
     schema_ops::generate_create_table_sql(pool.as_ref(), &request)
 }
 
-/// Execute a DDL statement
+/// Execute a DDL statement. With `dry_run` the statements are run in a
+/// transaction that is always rolled back and their outcome is returned;
+/// nothing is recorded in the audit log then.
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn execute_ddl(
     app_handle: tauri::AppHandle,
     id: String,
     sql: String,
+    confirmation_token: Option<String>,
+    dry_run: Option<bool>,
     pool_manager: State<'_, Arc<ConnectionPoolManager>>,
-) -> Result<(), VelocityError> {
+    audit: State<'_, AuditLogStore>,
+) -> Result<Option<DryRunResult>, VelocityError> {
     use tauri::Emitter;
 
     let pool = pool_manager
         .get_pool(&id)
         .await
         .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+    pool_manager.ensure_writable(&id, "Changing the schema").await?;
+    if dry_run.unwrap_or(false) {
+        return schema_ops::dry_run_ddl(pool.as_ref(), &sql).await.map(Some);
+    }
+    pool_manager
+        .check_protected(&id, &sql, confirmation_token.as_deref())
+        .await?;
 
     let result = schema_ops::execute_ddl(pool.as_ref(), &sql).await;
+    record_audit(
+        &audit,
+        &id,
+        AuditCommand::ExecuteDdl,
+        &sql,
+        None,
+        result.as_ref().err().map(|e| e.to_string()),
+    )
+    .await;
     
     if result.is_ok() {
         let _ = app_handle.emit("database:schema-changed", &id);
     }
     
-    result
+    result.map(|_| None)
 }
 
 /// Preview SQL for adding a column
@@ -0,0 +1,111 @@
+use crate::commands::audit::audit_query;
+use crate::commands::database::QueryResultData;
+use crate::db::{ConnectionPoolManager, ExecuteOptions};
+use crate::error::VelocityError;
+use crate::store::audit::{AuditCommand, AuditLogStore};
+use crate::store::history::{
+    HistoryEntry, HistoryFilter, HistorySource, NewHistoryEntry, QueryHistoryStore,
+};
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use std::time::Instant;
+use tauri::State;
+
+/// Run a statement and record it in the query history. Failing to record
+/// never fails the statement itself.
+pub(crate) async fn run_recorded(
+    history: &QueryHistoryStore,
+    pool_manager: &ConnectionPoolManager,
+    connection_id: &str,
+    sql: &str,
+    options: &ExecuteOptions,
+    source: HistorySource,
+) -> Result<QueryResultData, VelocityError> {
+    let started_at = Utc::now();
+    let started = Instant::now();
+    let result = pool_manager
+        .execute_query_with_options(connection_id, sql, options)
+        .await;
+
+    let entry = NewHistoryEntry {
+        connection_id,
+        sql,
+        params: &options.params,
+        source,
+        started_at,
+        duration_ms: started.elapsed().as_millis() as i64,
+        row_count: result.as_ref().ok().map(|data| {
+            data.rows_affected
+                .map_or(data.row_count, |rows| rows as i64)
+        }),
+        error: result.as_ref().err().map(|e| e.to_string()),
+    };
+    if let Err(e) = history.record(entry).await {
+        eprintln!("[VELOCITY] Failed to record query history: {}", e);
+    }
+    result
+}
+
+/// Search the query history, newest first
+#[tauri::command]
+pub async fn search_query_history(
+    filter: Option<HistoryFilter>,
+    history: State<'_, QueryHistoryStore>,
+) -> Result<Vec<HistoryEntry>, VelocityError> {
+    history.search(&filter.unwrap_or_default()).await
+}
+
+/// Run a history entry again, on its original connection unless another one
+/// is given. The new run is recorded as well. On protected connections
+/// destructive statements need the `confirmation_token` returned by a first,
+/// refused attempt.
+#[tauri::command]
+pub async fn rerun_query_history(
+    entry_id: i64,
+    connection_id: Option<String>,
+    confirmation_token: Option<String>,
+    history: State<'_, QueryHistoryStore>,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+    audit: State<'_, AuditLogStore>,
+) -> Result<QueryResultData, VelocityError> {
+    let entry = history.get(entry_id).await?;
+    let connection_id = connection_id.unwrap_or(entry.connection_id);
+    pool_manager
+        .check_protected(&connection_id, &entry.sql, confirmation_token.as_deref())
+        .await?;
+    let options = ExecuteOptions {
+        params: entry.params,
+        ..Default::default()
+    };
+    let result = run_recorded(
+        &history,
+        &pool_manager,
+        &connection_id,
+        &entry.sql,
+        &options,
+        HistorySource::Editor,
+    )
+    .await;
+    audit_query(
+        &audit,
+        &pool_manager,
+        &connection_id,
+        AuditCommand::RerunQueryHistory,
+        &entry.sql,
+        &options.params,
+        &result,
+    )
+    .await;
+    result
+}
+
+/// Delete entries older than `before` and beyond the newest `max_entries`.
+/// Returns the number of deleted entries.
+#[tauri::command]
+pub async fn prune_query_history(
+    before: Option<DateTime<Utc>>,
+    max_entries: Option<i64>,
+    history: State<'_, QueryHistoryStore>,
+) -> Result<u64, VelocityError> {
+    history.prune(before, max_entries).await
+}
@@ -1,8 +1,11 @@
 use tauri::command;
 use std::sync::Arc;
+use crate::commands::audit::{audit_query, record_audit};
 use crate::db::pool::ConnectionPoolManager;
+use crate::db::SqlDialect;
 use crate::error::VelocityError;
 use crate::import::{CsvPreview, ColumnMapping, ImportResult};
+use crate::store::audit::{AuditCommand, AuditLogStore};
 
 #[command]
 pub async fn import_csv_preview(
@@ -21,7 +24,9 @@ pub async fn import_csv(
     mappings: Vec<ColumnMapping>,
     delimiter: Option<char>,
     pool_manager: tauri::State<'_, Arc<ConnectionPoolManager>>,
+    audit: tauri::State<'_, AuditLogStore>,
 ) -> Result<ImportResult, VelocityError> {
+    pool_manager.ensure_writable(&id, "Importing data").await?;
     let delim = delimiter.unwrap_or(',');
     let rows = crate::import::csv::parse_csv_with_mapping(&file_path, &mappings, delim)?;
     
@@ -46,10 +51,17 @@ pub async fn import_csv(
                 values.join(", ")
             );
             
-            match pool_manager.execute_query(&id, &sql).await {
-                Ok(_) => inserted += 1,
-                Err(e) => errors.push(e.to_string()),
-            }
+            let (rows_affected, error) = match pool_manager.execute_query(&id, &sql).await {
+                Ok(result) => {
+                    inserted += 1;
+                    (result.rows_affected.map(|rows| rows as i64), None)
+                }
+                Err(e) => {
+                    errors.push(e.to_string());
+                    (None, Some(e.to_string()))
+                }
+            };
+            record_audit(&audit, &id, AuditCommand::ImportCsv, &sql, rows_affected, error).await;
         }
     }
     
@@ -65,15 +77,26 @@ pub async fn import_sql(
     id: String,
     file_path: String,
     pool_manager: tauri::State<'_, Arc<ConnectionPoolManager>>,
+    audit: tauri::State<'_, AuditLogStore>,
 ) -> Result<ImportResult, VelocityError> {
+    pool_manager.ensure_writable(&id, "Importing SQL").await?;
+    let pool = pool_manager
+        .get_pool(&id)
+        .await
+        .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+    let dialect = SqlDialect::from_pool(&pool).ok_or_else(|| {
+        VelocityError::Import("SQL import not supported for this database type".to_string())
+    })?;
     let sql_content = crate::import::sql::read_sql_file(&file_path)?;
-    let statements = crate::import::sql::split_sql_statements(&sql_content);
+    let statements = crate::import::sql::split_sql_statements(&sql_content, dialect);
     
     let mut executed = 0;
     let mut errors = Vec::new();
     
     for stmt in &statements {
-        match pool_manager.execute_query(&id, stmt).await {
+        let result = pool_manager.execute_query(&id, stmt).await;
+        audit_query(&audit, &pool_manager, &id, AuditCommand::ImportSql, stmt, &[], &result).await;
+        match result {
             Ok(_) => executed += 1,
             Err(e) => errors.push(format!("Statement failed: {}", e)),
         }
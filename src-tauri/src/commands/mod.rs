@@ -1,7 +1,11 @@
 pub mod ai;
+pub mod audit;
 pub mod connections;
 pub mod database;
 pub mod export;
+pub mod history;
 pub mod import;
 pub mod keychain;
+pub mod saved_queries;
 pub mod ssh;
+pub mod transaction;
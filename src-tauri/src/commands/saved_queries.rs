@@ -0,0 +1,76 @@
+use crate::error::VelocityError;
+use crate::models::saved_query::{QueryFolder, SavedQuery, SavedQueryLibrary};
+use crate::store::connections::ConnectionsStore;
+use crate::store::saved_queries::SavedQueriesStore;
+use std::path::PathBuf;
+use tauri::AppHandle;
+
+/// Load the saved query library. With `connection_id`, only queries that
+/// apply to that connection (by ID or database type) are returned.
+#[tauri::command]
+pub async fn list_saved_queries(
+    app: AppHandle,
+    connection_id: Option<String>,
+) -> Result<SavedQueryLibrary, VelocityError> {
+    let mut library = SavedQueriesStore::new(&app)?.load()?;
+    if let Some(connection_id) = connection_id {
+        let connection = ConnectionsStore::new(&app)?
+            .load()?
+            .into_iter()
+            .find(|c| c.id == connection_id)
+            .ok_or_else(|| VelocityError::ConnectionNotFound(connection_id.clone()))?;
+        library
+            .queries
+            .retain(|q| q.applies_to(&connection.id, &connection.db_type));
+    }
+    Ok(library)
+}
+
+/// Create or update a saved query
+#[tauri::command]
+pub async fn save_saved_query(
+    app: AppHandle,
+    query: SavedQuery,
+) -> Result<SavedQuery, VelocityError> {
+    SavedQueriesStore::new(&app)?.save_query(query)
+}
+
+#[tauri::command]
+pub async fn delete_saved_query(app: AppHandle, id: String) -> Result<(), VelocityError> {
+    SavedQueriesStore::new(&app)?.delete_query(&id)
+}
+
+/// Create, rename or move a folder
+#[tauri::command]
+pub async fn save_query_folder(
+    app: AppHandle,
+    folder: QueryFolder,
+) -> Result<QueryFolder, VelocityError> {
+    SavedQueriesStore::new(&app)?.save_folder(folder)
+}
+
+/// Delete a folder, moving its contents to the parent folder
+#[tauri::command]
+pub async fn delete_query_folder(app: AppHandle, id: String) -> Result<(), VelocityError> {
+    SavedQueriesStore::new(&app)?.delete_folder(&id)
+}
+
+/// Export the library as a JSON file, returning the number of queries
+#[tauri::command]
+pub async fn export_saved_queries(
+    app: AppHandle,
+    file_path: String,
+) -> Result<usize, VelocityError> {
+    SavedQueriesStore::new(&app)?.export(&PathBuf::from(file_path))
+}
+
+/// Import a library file, merging it into the current library unless
+/// `replace` is set. Returns the resulting library.
+#[tauri::command]
+pub async fn import_saved_queries(
+    app: AppHandle,
+    file_path: String,
+    replace: Option<bool>,
+) -> Result<SavedQueryLibrary, VelocityError> {
+    SavedQueriesStore::new(&app)?.import(&PathBuf::from(file_path), replace.unwrap_or(false))
+}
@@ -0,0 +1,63 @@
+use crate::db::{ConnectionPoolManager, TransactionInfo};
+use crate::error::VelocityError;
+use std::sync::Arc;
+use std::time::Duration;
+use tauri::State;
+
+/// Open a transaction on a dedicated connection of `id`. Queries passed the
+/// returned session ID run inside it. The session is rolled back after
+/// `idle_timeout_ms` without activity (default 10 minutes).
+#[tauri::command]
+pub async fn begin_transaction(
+    id: String,
+    session_id: Option<String>,
+    idle_timeout_ms: Option<u64>,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<TransactionInfo, VelocityError> {
+    pool_manager
+        .begin_transaction(
+            &id,
+            session_id.as_deref(),
+            idle_timeout_ms.map(Duration::from_millis),
+        )
+        .await
+}
+
+#[tauri::command]
+pub async fn commit_transaction(
+    session_id: String,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<(), VelocityError> {
+    pool_manager.commit_transaction(&session_id).await
+}
+
+/// Roll back to `savepoint`, keeping the session open, or roll back and
+/// close the whole session
+#[tauri::command]
+pub async fn rollback_transaction(
+    session_id: String,
+    savepoint: Option<String>,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<TransactionInfo, VelocityError> {
+    pool_manager
+        .rollback_transaction(&session_id, savepoint.as_deref())
+        .await
+}
+
+#[tauri::command]
+pub async fn savepoint(
+    session_id: String,
+    name: String,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<TransactionInfo, VelocityError> {
+    pool_manager.create_savepoint(&session_id, &name).await
+}
+
+/// State of a transaction session, null when it is not open
+#[tauri::command]
+pub async fn get_transaction(
+    session_id: String,
+    pool_manager: State<'_, Arc<ConnectionPoolManager>>,
+) -> Result<Option<TransactionInfo>, VelocityError> {
+    Ok(pool_manager.transaction_info(&session_id).await)
+}
@@ -0,0 +1,203 @@
+//! Query cancellation
+//!
+//! Executions that carry a query ID are registered here with what is needed
+//! to interrupt them: the backend PID on PostgreSQL, the connection thread ID
+//! on MySQL, and a flag polled by a progress handler on SQLite. A connection
+//! only goes back to the pool once no cancellation is being sent to it, and
+//! none is sent after that, so it never reaches the next query on the backend.
+
+use crate::db::ConnectionPoolManager;
+use crate::error::VelocityError;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// State shared between a running query, `cancel_query` and the SQLite
+/// progress handler
+pub(crate) struct QueryState {
+    cancelled: AtomicBool,
+    finished: AtomicBool,
+    /// Deadline of the current statement in ms after `origin` (u64::MAX = none)
+    deadline_ms: AtomicU64,
+    origin: Instant,
+    /// Held while a cancellation is sent to the server
+    interrupting: tokio::sync::Mutex<()>,
+}
+
+impl QueryState {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            cancelled: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+            deadline_ms: AtomicU64::new(u64::MAX),
+            origin: Instant::now(),
+            interrupting: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Start timing a statement, returning its start offset
+    pub(crate) fn start_statement(&self, timeout: Option<Duration>) -> Duration {
+        let started = self.origin.elapsed();
+        let deadline = timeout.map_or(u64::MAX, |limit| {
+            crate::db::timeout::duration_ms(started + limit)
+        });
+        self.deadline_ms.store(deadline, Ordering::SeqCst);
+        started
+    }
+
+    /// Time spent in the statement started at `started`
+    pub(crate) fn statement_elapsed(&self, started: Duration) -> Duration {
+        self.origin.elapsed().saturating_sub(started)
+    }
+
+    /// Whether the SQLite progress handler should interrupt the statement.
+    /// Never true once the query finished, so a handler left on a pooled
+    /// connection is inert.
+    pub(crate) fn should_interrupt(&self) -> bool {
+        if self.finished.load(Ordering::SeqCst) {
+            return false;
+        }
+        self.is_cancelled()
+            || crate::db::timeout::duration_ms(self.origin.elapsed())
+                >= self.deadline_ms.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn finish(&self) {
+        self.finished.store(true, Ordering::SeqCst);
+    }
+
+    /// Finish once a cancellation being sent went through, before the
+    /// connection is used for anything else
+    pub(crate) async fn finish_interrupts(&self) {
+        let _interrupting = self.interrupting.lock().await;
+        self.finish();
+    }
+}
+
+/// How a running query is interrupted
+#[derive(Clone)]
+pub(crate) enum CancelTarget {
+    Postgres {
+        pool: sqlx::PgPool,
+        pid: i32,
+    },
+    MySql {
+        pool: sqlx::MySqlPool,
+        thread_id: u64,
+    },
+    /// Interrupted by the progress handler once the flag is set
+    Sqlite,
+}
+
+impl CancelTarget {
+    /// Ask the server to stop the statement running on the connection
+    pub(crate) async fn interrupt(&self) -> Result<(), VelocityError> {
+        let result = match self {
+            CancelTarget::Postgres { pool, pid } => sqlx::query("SELECT pg_cancel_backend($1)")
+                .bind(pid)
+                .execute(pool)
+                .await
+                .map(|_| ()),
+            CancelTarget::MySql { pool, thread_id } => {
+                sqlx::query(&format!("KILL QUERY {}", thread_id))
+                    .execute(pool)
+                    .await
+                    .map(|_| ())
+            }
+            CancelTarget::Sqlite => Ok(()),
+        };
+        result.map_err(|e| VelocityError::Query(e.to_string()))
+    }
+}
+
+struct RunningQuery {
+    state: Arc<QueryState>,
+    target: CancelTarget,
+}
+
+/// Queries currently running under a query ID
+#[derive(Default)]
+pub struct QueryRegistry {
+    running: Mutex<HashMap<String, RunningQuery>>,
+}
+
+impl QueryRegistry {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, RunningQuery>> {
+        self.running.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn register(
+        &self,
+        query_id: &str,
+        state: Arc<QueryState>,
+        target: CancelTarget,
+    ) -> Result<(), VelocityError> {
+        let mut running = self.lock();
+        if running.contains_key(query_id) {
+            return Err(VelocityError::Query(format!(
+                "Query ID already in use: {}",
+                query_id
+            )));
+        }
+        running.insert(query_id.to_string(), RunningQuery { state, target });
+        Ok(())
+    }
+
+    pub(crate) fn unregister(&self, query_id: &str) {
+        self.lock().remove(query_id);
+    }
+
+    /// Mark a query as cancelled and return its state and how to interrupt it
+    fn signal(&self, query_id: &str) -> Option<(Arc<QueryState>, CancelTarget)> {
+        self.lock().get(query_id).map(|query| {
+            query.state.cancelled.store(true, Ordering::SeqCst);
+            (query.state.clone(), query.target.clone())
+        })
+    }
+}
+
+impl ConnectionPoolManager {
+    /// Cancel a running query by its query ID.
+    /// Returns false when no query with this ID is running.
+    pub async fn cancel_query(&self, query_id: &str) -> Result<bool, VelocityError> {
+        let Some((state, target)) = self.queries.signal(query_id) else {
+            return Ok(false);
+        };
+
+        // The connection is not released while the cancellation is sent.
+        // Once the query finished, the backend may run someone else's.
+        let _interrupting = state.interrupting.lock().await;
+        if !state.finished.load(Ordering::SeqCst) {
+            target.interrupt().await?;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_release_waits_for_a_cancellation_being_sent() {
+        let state = QueryState::new();
+        let interrupting = state.interrupting.lock().await;
+
+        let finishing = tokio::spawn({
+            let state = state.clone();
+            async move { state.finish_interrupts().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!finishing.is_finished());
+        assert!(!state.finished.load(Ordering::SeqCst));
+
+        drop(interrupting);
+        finishing.await.unwrap();
+        assert!(state.finished.load(Ordering::SeqCst));
+    }
+}
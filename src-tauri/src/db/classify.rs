@@ -0,0 +1,683 @@
+//! SQL statement classification
+//!
+//! Tells what a statement does: its kind (read, write, DDL, transaction
+//! control, admin), the tables it targets and whether an UPDATE or DELETE is
+//! restricted by a WHERE clause. Statements are parsed with `sqlparser`;
+//! syntax the parser does not know (vendor extensions, data-modifying CTEs)
+//! falls back to a keyword scan that skips comments and literals.
+
+use crate::db::dialect::SqlDialect;
+use crate::db::splitter::{is_ident_byte, skip_non_code, split_statements};
+use crate::db::ConnectionPoolManager;
+use crate::error::VelocityError;
+use serde::Serialize;
+use sqlparser::ast::{
+    CopySource, Expr, FromTable, FunctionArg, FunctionArgExpr, FunctionArguments, ObjectName,
+    ObjectType, Query, SetExpr, Statement, TableFactor, TransactionAccessMode, TransactionMode,
+    Value, Visit, Visitor,
+};
+use sqlparser::dialect::{MySqlDialect, PostgreSqlDialect, SQLiteDialect};
+use sqlparser::parser::Parser;
+use std::ops::ControlFlow;
+
+/// What a statement does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StatementKind {
+    /// Queries and statements that only read (SHOW, EXPLAIN, ...)
+    Read,
+    /// INSERT, UPDATE, DELETE, MERGE and other data changes
+    Write,
+    /// CREATE, ALTER, DROP, TRUNCATE and other schema changes
+    Ddl,
+    /// BEGIN, COMMIT, ROLLBACK and savepoints
+    TransactionControl,
+    /// Session settings, privileges and maintenance
+    Admin,
+}
+
+/// Classification of a single statement
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementClass {
+    pub kind: StatementKind,
+    /// Leading keyword, upper-cased (`SELECT`, `DROP`, `ROLLBACK TO`, ...).
+    /// `SET TRANSACTION` also covers variables that toggle read-only mode,
+    /// and `BEGIN READ WRITE` transaction starts that ask for write access.
+    pub command: String,
+    /// Tables the statement changes or, for reads, the tables it reads
+    pub tables: Vec<String>,
+    /// For UPDATE and DELETE, whether a WHERE clause restricts the rows
+    pub has_where: Option<bool>,
+    /// False when the statement could not be parsed and was classified by
+    /// its keywords; `tables` is empty then
+    pub parsed: bool,
+}
+
+impl StatementClass {
+    fn new(kind: StatementKind, command: &str) -> Self {
+        Self {
+            kind,
+            command: command.to_string(),
+            tables: Vec::new(),
+            has_where: None,
+            parsed: true,
+        }
+    }
+
+    fn with_tables(mut self, tables: Vec<String>) -> Self {
+        self.tables = tables;
+        self
+    }
+
+    fn with_where(mut self, has_where: bool) -> Self {
+        self.has_where = Some(has_where);
+        self
+    }
+
+    /// Whether running the statement could change data or schema. Anything
+    /// that is not plainly a read counts.
+    pub fn modifies_data(&self) -> bool {
+        self.kind != StatementKind::Read
+    }
+
+    pub fn changes_schema(&self) -> bool {
+        self.kind == StatementKind::Ddl
+    }
+
+    /// UPDATE or DELETE without a WHERE clause
+    pub fn is_unbounded(&self) -> bool {
+        self.has_where == Some(false)
+    }
+
+    /// Whether a read-only connection may run the statement
+    pub fn allowed_read_only(&self) -> bool {
+        match self.kind {
+            StatementKind::Read => true,
+            // A read-write transaction overrides the session's read-only mode
+            StatementKind::TransactionControl => self.command != "BEGIN READ WRITE",
+            StatementKind::Admin => matches!(self.command.as_str(), "SET" | "USE"),
+            StatementKind::Write | StatementKind::Ddl => false,
+        }
+    }
+}
+
+/// Classify one statement. When `sql` holds several, the first is used.
+pub fn classify(sql: &str, dialect: SqlDialect) -> StatementClass {
+    let parsed = match dialect {
+        SqlDialect::Postgres => Parser::parse_sql(&PostgreSqlDialect {}, sql),
+        SqlDialect::MySql => Parser::parse_sql(&MySqlDialect {}, sql),
+        SqlDialect::Sqlite => Parser::parse_sql(&SQLiteDialect {}, sql),
+    };
+    match parsed.ok().as_deref() {
+        Some([statement, ..]) => classify_statement(statement),
+        _ => classify_keywords(sql, dialect),
+    }
+}
+
+/// Split a script and classify each of its statements
+pub fn classify_script(script: &str, dialect: SqlDialect) -> Vec<StatementClass> {
+    split_statements(script, dialect)
+        .iter()
+        .map(|statement| classify(&statement.sql, dialect))
+        .collect()
+}
+
+impl ConnectionPoolManager {
+    /// Classify each statement of `sql` in the dialect of the connection
+    pub async fn classify_sql(
+        &self,
+        connection_id: &str,
+        sql: &str,
+    ) -> Result<Vec<StatementClass>, VelocityError> {
+        let pool = self
+            .get_pool(connection_id)
+            .await
+            .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+        let dialect = SqlDialect::from_pool(&pool).ok_or_else(|| {
+            VelocityError::Query(
+                "Statement classification not supported for this database type".to_string(),
+            )
+        })?;
+        Ok(classify_script(sql, dialect))
+    }
+}
+
+fn classify_statement(statement: &Statement) -> StatementClass {
+    use StatementKind::*;
+
+    match statement {
+        Statement::Query(query) => classify_query(statement, query),
+        Statement::Insert(insert) => {
+            let command = if insert.replace_into {
+                "REPLACE"
+            } else {
+                "INSERT"
+            };
+            StatementClass::new(Write, command).with_tables(vec![object_name(&insert.table_name)])
+        }
+        Statement::Update {
+            table, selection, ..
+        } => StatementClass::new(Write, "UPDATE")
+            .with_tables(table_factor_name(&table.relation).into_iter().collect())
+            .with_where(selection.is_some()),
+        Statement::Delete(delete) => {
+            let tables = if delete.tables.is_empty() {
+                match &delete.from {
+                    FromTable::WithFromKeyword(from) | FromTable::WithoutKeyword(from) => from
+                        .iter()
+                        .filter_map(|t| table_factor_name(&t.relation))
+                        .collect(),
+                }
+            } else {
+                delete.tables.iter().map(object_name).collect()
+            };
+            StatementClass::new(Write, "DELETE")
+                .with_tables(tables)
+                .with_where(delete.selection.is_some())
+        }
+        Statement::Merge { table, .. } => StatementClass::new(Write, "MERGE")
+            .with_tables(table_factor_name(table).into_iter().collect()),
+        Statement::Copy { source, to, .. } => {
+            let tables = match source {
+                CopySource::Table { table_name, .. } => vec![object_name(table_name)],
+                CopySource::Query(_) => relations(statement),
+            };
+            StatementClass::new(if *to { Read } else { Write }, "COPY").with_tables(tables)
+        }
+        Statement::LoadData { table_name, .. } => {
+            StatementClass::new(Write, "LOAD DATA").with_tables(vec![object_name(table_name)])
+        }
+        // Procedures can change anything
+        Statement::Call(_) => StatementClass::new(Write, "CALL"),
+
+        Statement::CreateTable(create) => {
+            StatementClass::new(Ddl, "CREATE").with_tables(vec![object_name(&create.name)])
+        }
+        Statement::CreateView { name, .. } | Statement::CreateVirtualTable { name, .. } => {
+            StatementClass::new(Ddl, "CREATE").with_tables(vec![object_name(name)])
+        }
+        Statement::CreateIndex(index) => {
+            StatementClass::new(Ddl, "CREATE").with_tables(vec![object_name(&index.table_name)])
+        }
+        Statement::CreateTrigger { table_name, .. } => {
+            StatementClass::new(Ddl, "CREATE").with_tables(vec![object_name(table_name)])
+        }
+        Statement::CreateSchema { .. }
+        | Statement::CreateDatabase { .. }
+        | Statement::CreateFunction(_)
+        | Statement::CreateProcedure { .. }
+        | Statement::CreateSequence { .. }
+        | Statement::CreateType { .. }
+        | Statement::CreateExtension { .. } => StatementClass::new(Ddl, "CREATE"),
+        Statement::AlterTable { name, .. } | Statement::AlterView { name, .. } => {
+            StatementClass::new(Ddl, "ALTER").with_tables(vec![object_name(name)])
+        }
+        Statement::AlterIndex { .. } => StatementClass::new(Ddl, "ALTER"),
+        Statement::Drop {
+            object_type, names, ..
+        } => {
+            let tables = match object_type {
+                ObjectType::Table | ObjectType::View => names.iter().map(object_name).collect(),
+                _ => Vec::new(),
+            };
+            StatementClass::new(Ddl, "DROP").with_tables(tables)
+        }
+        Statement::DropTrigger { table_name, .. } => {
+            StatementClass::new(Ddl, "DROP").with_tables(vec![object_name(table_name)])
+        }
+        Statement::DropFunction { .. } | Statement::DropProcedure { .. } => {
+            StatementClass::new(Ddl, "DROP")
+        }
+        Statement::Truncate { table_names, .. } => StatementClass::new(Ddl, "TRUNCATE")
+            .with_tables(table_names.iter().map(|t| object_name(&t.name)).collect()),
+        Statement::Comment { .. } => StatementClass::new(Ddl, "COMMENT"),
+
+        Statement::StartTransaction { modes, .. } => {
+            let read_write = modes.iter().any(|mode| {
+                matches!(
+                    mode,
+                    TransactionMode::AccessMode(TransactionAccessMode::ReadWrite)
+                )
+            });
+            StatementClass::new(
+                TransactionControl,
+                if read_write {
+                    "BEGIN READ WRITE"
+                } else {
+                    "BEGIN"
+                },
+            )
+        }
+        Statement::Commit { .. } => StatementClass::new(TransactionControl, "COMMIT"),
+        Statement::Rollback { savepoint, .. } => StatementClass::new(
+            TransactionControl,
+            if savepoint.is_some() {
+                "ROLLBACK TO"
+            } else {
+                "ROLLBACK"
+            },
+        ),
+        Statement::Savepoint { .. } => StatementClass::new(TransactionControl, "SAVEPOINT"),
+        Statement::ReleaseSavepoint { .. } => StatementClass::new(TransactionControl, "RELEASE"),
+
+        // ANALYZE executes the statement
+        Statement::Explain {
+            analyze: true,
+            statement,
+            ..
+        } => classify_statement(statement),
+        Statement::Explain { statement, .. } => {
+            StatementClass::new(Read, "EXPLAIN").with_tables(relations(statement))
+        }
+        Statement::ExplainTable { table_name, .. } => {
+            StatementClass::new(Read, "DESCRIBE").with_tables(vec![object_name(table_name)])
+        }
+        Statement::ShowFunctions { .. }
+        | Statement::ShowVariable { .. }
+        | Statement::ShowStatus { .. }
+        | Statement::ShowVariables { .. }
+        | Statement::ShowCreate { .. }
+        | Statement::ShowColumns { .. }
+        | Statement::ShowDatabases { .. }
+        | Statement::ShowSchemas { .. }
+        | Statement::ShowTables { .. }
+        | Statement::ShowViews { .. }
+        | Statement::ShowCollation { .. } => StatementClass::new(Read, "SHOW"),
+        // `PRAGMA name = value` changes a setting, other forms only read
+        Statement::Pragma { is_eq, .. } => {
+            StatementClass::new(if *is_eq { Admin } else { Read }, "PRAGMA")
+        }
+
+        Statement::SetTransaction { .. } => StatementClass::new(Admin, "SET TRANSACTION"),
+        Statement::SetVariable { variables, .. } => {
+            let toggles_read_only = variables
+                .iter()
+                .any(|name| is_read_only_setting(&object_name(name)));
+            StatementClass::new(
+                Admin,
+                if toggles_read_only {
+                    "SET TRANSACTION"
+                } else {
+                    "SET"
+                },
+            )
+        }
+        Statement::SetTimeZone { .. } | Statement::SetNames { .. } => {
+            StatementClass::new(Admin, "SET")
+        }
+        Statement::Use(_) => StatementClass::new(Admin, "USE"),
+        other => StatementClass::new(Admin, &leading_keyword(&other.to_string())),
+    }
+}
+
+/// A query reads, unless it contains a data-changing statement
+/// (`WITH ... INSERT`), is a `SELECT ... INTO` or switches read-only mode
+/// off through `set_config`
+fn classify_query(statement: &Statement, query: &Query) -> StatementClass {
+    let mut collector = Collector::default();
+    let _ = statement.visit(&mut collector);
+    if let Some(write) = &collector.nested_write {
+        return classify_statement(write);
+    }
+    if collector.sets_read_only {
+        return StatementClass::new(StatementKind::Admin, "SET TRANSACTION");
+    }
+    if let SetExpr::Select(select) = query.body.as_ref() {
+        if let Some(into) = &select.into {
+            return StatementClass::new(StatementKind::Write, "SELECT")
+                .with_tables(vec![object_name(&into.name)]);
+        }
+    }
+    StatementClass::new(StatementKind::Read, "SELECT").with_tables(collector.tables())
+}
+
+/// Tables a statement references, without CTE names
+fn relations(statement: &Statement) -> Vec<String> {
+    let mut collector = Collector::default();
+    let _ = statement.visit(&mut collector);
+    collector.tables()
+}
+
+#[derive(Default)]
+struct Collector {
+    relations: Vec<String>,
+    ctes: Vec<String>,
+    nested_write: Option<Statement>,
+    sets_read_only: bool,
+}
+
+impl Collector {
+    fn tables(self) -> Vec<String> {
+        let ctes = self.ctes;
+        self.relations
+            .into_iter()
+            .filter(|name| !ctes.contains(name))
+            .collect()
+    }
+}
+
+impl Visitor for Collector {
+    type Break = ();
+
+    fn pre_visit_query(&mut self, query: &Query) -> ControlFlow<()> {
+        if let Some(with) = &query.with {
+            self.ctes.extend(
+                with.cte_tables
+                    .iter()
+                    .map(|cte| cte.alias.name.value.clone()),
+            );
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_relation(&mut self, relation: &ObjectName) -> ControlFlow<()> {
+        let name = object_name(relation);
+        if !self.relations.contains(&name) {
+            self.relations.push(name);
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_statement(&mut self, statement: &Statement) -> ControlFlow<()> {
+        if self.nested_write.is_none()
+            && matches!(
+                statement,
+                Statement::Insert(_)
+                    | Statement::Update { .. }
+                    | Statement::Delete(_)
+                    | Statement::Merge { .. }
+            )
+        {
+            self.nested_write = Some(statement.clone());
+        }
+        ControlFlow::Continue(())
+    }
+
+    fn pre_visit_expr(&mut self, expr: &Expr) -> ControlFlow<()> {
+        if let Expr::Function(function) = expr {
+            let is_set_config = function
+                .name
+                .0
+                .last()
+                .is_some_and(|ident| ident.value.eq_ignore_ascii_case("set_config"));
+            if is_set_config && sets_read_only_setting(&function.args) {
+                self.sets_read_only = true;
+            }
+        }
+        ControlFlow::Continue(())
+    }
+}
+
+/// Whether `set_config` arguments may name a read-only setting. A name that
+/// is not a literal could be anything, so it counts.
+fn sets_read_only_setting(args: &FunctionArguments) -> bool {
+    let FunctionArguments::List(list) = args else {
+        return true;
+    };
+    match list.args.first() {
+        Some(FunctionArg::Unnamed(FunctionArgExpr::Expr(Expr::Value(
+            Value::SingleQuotedString(name),
+        )))) => is_read_only_setting(name),
+        _ => true,
+    }
+}
+
+/// Dotted name with quotes removed
+fn object_name(name: &ObjectName) -> String {
+    name.0
+        .iter()
+        .map(|ident| ident.value.as_str())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn table_factor_name(factor: &TableFactor) -> Option<String> {
+    match factor {
+        TableFactor::Table { name, .. } => Some(object_name(name)),
+        _ => None,
+    }
+}
+
+/// Session variables that switch read-only mode off again
+fn is_read_only_setting(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    name.contains("read_only") || name.contains("query_only")
+}
+
+fn leading_keyword(sql: &str) -> String {
+    sql.split_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_uppercase()
+}
+
+/// Classify by keywords when the parser does not understand the statement
+fn classify_keywords(sql: &str, dialect: SqlDialect) -> StatementClass {
+    use StatementKind::*;
+
+    let words = keywords(sql, dialect);
+    let Some(first) = words.first() else {
+        return StatementClass {
+            parsed: false,
+            ..StatementClass::new(Read, "")
+        };
+    };
+    let has = |word: &str| words.iter().any(|w| w == word);
+    let mut class = match first.as_str() {
+        "SELECT" | "WITH" | "VALUES" | "TABLE" => match data_changing_verb(&words) {
+            Some(i) => {
+                let class = StatementClass::new(Write, &words[i]);
+                if matches!(words[i].as_str(), "UPDATE" | "DELETE") {
+                    class.with_where(words[i..].iter().any(|w| w == "WHERE"))
+                } else {
+                    class
+                }
+            }
+            None if has("INTO") => StatementClass::new(Write, "SELECT"),
+            // The setting is a literal the scan skips; assume the worst
+            None if has("SET_CONFIG") => StatementClass::new(Admin, "SET TRANSACTION"),
+            None => StatementClass::new(Read, "SELECT"),
+        },
+        "UPDATE" | "DELETE" => StatementClass::new(Write, first).with_where(has("WHERE")),
+        "INSERT" | "REPLACE" | "UPSERT" | "MERGE" | "COPY" | "CALL" | "LOAD" => {
+            StatementClass::new(Write, first)
+        }
+        "CREATE" | "ALTER" | "DROP" | "TRUNCATE" | "COMMENT" | "RENAME" => {
+            StatementClass::new(Ddl, first)
+        }
+        "BEGIN" | "START" if words.windows(2).any(|w| w == ["READ", "WRITE"]) => {
+            StatementClass::new(TransactionControl, "BEGIN READ WRITE")
+        }
+        "BEGIN" | "START" => StatementClass::new(TransactionControl, "BEGIN"),
+        "COMMIT" | "END" => StatementClass::new(TransactionControl, "COMMIT"),
+        "ROLLBACK" if has("TO") => StatementClass::new(TransactionControl, "ROLLBACK TO"),
+        "ROLLBACK" | "SAVEPOINT" | "RELEASE" => StatementClass::new(TransactionControl, first),
+        "SHOW" | "DESCRIBE" | "DESC" | "EXPLAIN" => match data_changing_verb(&words) {
+            Some(i) if has("ANALYZE") => StatementClass::new(Write, &words[i]),
+            _ => StatementClass::new(Read, first),
+        },
+        "PRAGMA" if !sql.contains('=') => StatementClass::new(Read, "PRAGMA"),
+        "SET"
+            if has("TRANSACTION")
+                || has("CHARACTERISTICS")
+                || words.iter().any(|w| is_read_only_setting(w)) =>
+        {
+            StatementClass::new(Admin, "SET TRANSACTION")
+        }
+        _ => StatementClass::new(Admin, first),
+    };
+    class.parsed = false;
+    class
+}
+
+/// Index of the first INSERT, UPDATE, DELETE or MERGE keyword. Row locks
+/// (`FOR UPDATE`, `FOR NO KEY UPDATE`) do not count.
+fn data_changing_verb(words: &[String]) -> Option<usize> {
+    words
+        .iter()
+        .enumerate()
+        .position(|(i, word)| match word.as_str() {
+            "INSERT" | "DELETE" | "MERGE" => true,
+            "UPDATE" => !matches!(
+                i.checked_sub(1).map(|p| words[p].as_str()),
+                Some("FOR" | "KEY")
+            ),
+            _ => false,
+        })
+}
+
+/// Unquoted words of a statement, upper-cased, skipping literals and comments
+fn keywords(sql: &str, dialect: SqlDialect) -> Vec<String> {
+    let bytes = sql.as_bytes();
+    let mut words = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if let Some(end) = skip_non_code(bytes, i, dialect) {
+            i = end;
+        } else if is_ident_byte(bytes[i]) {
+            let end = bytes[i..]
+                .iter()
+                .position(|&b| !is_ident_byte(b))
+                .map_or(bytes.len(), |p| i + p);
+            words.push(sql[i..end].to_ascii_uppercase());
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PG: SqlDialect = SqlDialect::Postgres;
+
+    #[test]
+    fn test_classifies_reads_past_comments_and_case() {
+        let class = classify(
+            "-- report\n/* x */ select * from users u join orders o on true",
+            PG,
+        );
+        assert_eq!(class.kind, StatementKind::Read);
+        assert!(class.parsed);
+        assert_eq!(class.tables, vec!["users", "orders"]);
+
+        assert!(!classify("SELECT * FROM t WHERE a = 'delete'", PG).modifies_data());
+        assert!(!classify("select * from t for update", PG).modifies_data());
+        assert!(!classify("SELECT \"update\" FROM t", PG).modifies_data());
+        let cte = classify("WITH x AS (SELECT 1 FROM src) SELECT * FROM x", PG);
+        assert!(!cte.modifies_data());
+        assert_eq!(cte.tables, vec!["src"]);
+    }
+
+    #[test]
+    fn test_classifies_writes_and_where_clauses() {
+        let class = classify("DELETE FROM public.t", PG);
+        assert_eq!(class.kind, StatementKind::Write);
+        assert_eq!(class.tables, vec!["public.t"]);
+        assert!(class.is_unbounded());
+
+        let class = classify("update \"T\" set a = 1 where id = 2", PG);
+        assert_eq!(class.command, "UPDATE");
+        assert_eq!(class.tables, vec!["T"]);
+        assert_eq!(class.has_where, Some(true));
+
+        let class = classify("WITH d AS (DELETE FROM t RETURNING *) SELECT * FROM d", PG);
+        assert_eq!(class.kind, StatementKind::Write);
+        assert_eq!(class.command, "DELETE");
+        assert!(class.is_unbounded());
+
+        assert_eq!(
+            classify("SELECT * INTO copy FROM t", PG).kind,
+            StatementKind::Write
+        );
+        assert_eq!(
+            classify("INSERT INTO t (a) SELECT a FROM s", SqlDialect::MySql).tables,
+            vec!["t"]
+        );
+    }
+
+    #[test]
+    fn test_classifies_ddl_transaction_control_and_admin() {
+        let class = classify("CREATE TABLE t AS SELECT 1", PG);
+        assert!(class.changes_schema());
+        assert_eq!(classify("drop table a, b", PG).tables, vec!["a", "b"]);
+        assert_eq!(classify("TRUNCATE t", PG).command, "TRUNCATE");
+
+        assert_eq!(
+            classify("start transaction", SqlDialect::MySql).command,
+            "BEGIN"
+        );
+        assert_eq!(classify("commit;", PG).command, "COMMIT");
+        assert_eq!(
+            classify("ROLLBACK TO SAVEPOINT a", PG).command,
+            "ROLLBACK TO"
+        );
+        assert_eq!(
+            classify("BEGIN", SqlDialect::Sqlite).kind,
+            StatementKind::TransactionControl
+        );
+
+        assert!(classify("SET search_path TO app", PG).allowed_read_only());
+        assert!(!classify("SET default_transaction_read_only = off", PG).allowed_read_only());
+        assert!(
+            !classify("SET SESSION TRANSACTION READ WRITE", SqlDialect::MySql).allowed_read_only()
+        );
+        assert!(!classify("PRAGMA query_only = OFF", SqlDialect::Sqlite).allowed_read_only());
+        assert!(classify("PRAGMA table_info(t)", SqlDialect::Sqlite).allowed_read_only());
+        assert!(!classify("GRANT SELECT ON t TO bob", PG).allowed_read_only());
+    }
+
+    #[test]
+    fn test_read_write_transactions_are_refused_read_only() {
+        for (sql, dialect) in [
+            ("BEGIN READ WRITE", PG),
+            ("begin isolation level serializable, read write", PG),
+            ("START TRANSACTION READ WRITE", PG),
+            ("START TRANSACTION READ WRITE", SqlDialect::MySql),
+            (
+                "START TRANSACTION WITH CONSISTENT SNAPSHOT, READ WRITE",
+                SqlDialect::MySql,
+            ),
+            ("SET SESSION CHARACTERISTICS AS TRANSACTION READ WRITE", PG),
+            ("SET TRANSACTION READ WRITE", PG),
+            ("SET SESSION TRANSACTION READ WRITE", SqlDialect::MySql),
+        ] {
+            assert!(!classify(sql, dialect).allowed_read_only(), "{}", sql);
+        }
+        assert!(classify("BEGIN", PG).allowed_read_only());
+        assert!(classify("START TRANSACTION READ ONLY", SqlDialect::MySql).allowed_read_only());
+        assert!(classify("COMMIT", PG).allowed_read_only());
+    }
+
+    #[test]
+    fn test_set_config_of_read_only_settings_is_refused_read_only() {
+        for sql in [
+            "SELECT set_config('default_transaction_read_only', 'off', false)",
+            "select pg_catalog.SET_CONFIG('transaction_read_only', 'off', true)",
+            "SELECT 1 FROM t WHERE set_config('default_transaction_read_only', 'off', false) <> ''",
+            // The setting name is only known at run time
+            "SELECT set_config(name, 'off', false) FROM settings",
+        ] {
+            let class = classify(sql, PG);
+            assert_eq!(class.kind, StatementKind::Admin, "{}", sql);
+            assert!(!class.allowed_read_only(), "{}", sql);
+        }
+        assert!(classify("SELECT set_config('search_path', 'app', false)", PG).allowed_read_only());
+        assert!(
+            classify("SELECT current_setting('transaction_read_only')", PG).allowed_read_only()
+        );
+    }
+
+    #[test]
+    fn test_classifies_each_statement_of_a_script() {
+        let kinds: Vec<StatementKind> = classify_script("SELECT 1; DELETE FROM t; ", PG)
+            .into_iter()
+            .map(|c| c.kind)
+            .collect();
+        assert_eq!(kinds, vec![StatementKind::Read, StatementKind::Write]);
+    }
+}
@@ -0,0 +1,110 @@
+//! Result column metadata
+//!
+//! Describes the columns of a result set from the prepared statement rather
+//! than from the returned rows, so headers and types are known even when a
+//! query returns no rows.
+
+use crate::db::pool::ColumnInfo;
+use sqlx::{Column, Database, Describe, Row, TypeInfo};
+
+/// Description of one column of a result set
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultColumn {
+    pub name: String,
+    /// Database type name, e.g. `INT4`, `VARCHAR`, `DATETIME`
+    pub type_name: String,
+    /// Whether the column may be NULL; None when the database cannot tell
+    /// (e.g. computed expressions)
+    pub nullable: Option<bool>,
+    /// Table the column is read from, when known
+    pub source_table: Option<String>,
+    /// Column name in the source table, when known
+    pub source_column: Option<String>,
+}
+
+impl ResultColumn {
+    /// Column of a table read directly, described by its schema
+    pub fn from_schema(table_name: &str, column: &ColumnInfo) -> Self {
+        Self {
+            name: column.name.clone(),
+            type_name: column.data_type.clone(),
+            nullable: Some(column.nullable),
+            source_table: Some(table_name.to_string()),
+            source_column: Some(column.name.clone()),
+        }
+    }
+}
+
+/// Result columns of a table read, in `names` order, described by the
+/// table schema
+pub fn table_columns(
+    table_name: &str,
+    schema: &[ColumnInfo],
+    names: &[String],
+) -> Vec<ResultColumn> {
+    names
+        .iter()
+        .map(|name| match schema.iter().find(|c| &c.name == name) {
+            Some(column) => ResultColumn::from_schema(table_name, column),
+            None => ResultColumn {
+                name: name.clone(),
+                type_name: String::new(),
+                nullable: None,
+                source_table: Some(table_name.to_string()),
+                source_column: Some(name.clone()),
+            },
+        })
+        .collect()
+}
+
+/// Result columns of a described statement.
+/// sqlx does not expose the originating table of a column, so the source
+/// fields are left empty.
+pub fn described_columns<DB: Database>(describe: &Describe<DB>) -> Vec<ResultColumn> {
+    describe
+        .columns()
+        .iter()
+        .enumerate()
+        .map(|(i, column)| ResultColumn {
+            name: column.name().to_string(),
+            type_name: column.type_info().name().to_string(),
+            nullable: describe.nullable(i),
+            source_table: None,
+            source_column: None,
+        })
+        .collect()
+}
+
+/// Result columns taken from a returned row, for statements that could not
+/// be described up front
+pub fn row_columns<R: Row>(row: &R) -> Vec<ResultColumn> {
+    row.columns()
+        .iter()
+        .map(|column| ResultColumn {
+            name: column.name().to_string(),
+            type_name: column.type_info().name().to_string(),
+            nullable: None,
+            source_table: None,
+            source_column: None,
+        })
+        .collect()
+}
+
+/// Result columns from the statement description, falling back to the
+/// first returned row when the statement could not be described
+pub fn resolve_columns<DB: Database>(
+    described: Option<&Describe<DB>>,
+    first_row: Option<&DB::Row>,
+) -> Vec<ResultColumn> {
+    match (described, first_row) {
+        (Some(describe), _) => described_columns(describe),
+        (None, Some(row)) => row_columns(row),
+        (None, None) => Vec::new(),
+    }
+}
+
+/// Column names of a list of result columns
+pub fn column_names(columns: &[ResultColumn]) -> Vec<String> {
+    columns.iter().map(|c| c.name.clone()).collect()
+}
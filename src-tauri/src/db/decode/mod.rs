@@ -0,0 +1,136 @@
+//! Type-faithful decoding of result values to JSON
+//!
+//! One decoder per dialect inspects the column type of each value and maps it
+//! to a lossless JSON representation:
+//! - integers, floats and booleans stay JSON numbers and booleans
+//! - decimals are strings so no precision is lost
+//! - dates and timestamps are ISO-8601 strings, intervals ISO-8601 durations
+//! - JSON columns are embedded as JSON
+//! - binary data is base64
+//! - arrays are (nested) JSON arrays
+
+mod mysql;
+mod pg_scalar;
+mod pg_temporal;
+mod postgres;
+mod sqlite;
+
+pub use mysql::decode_mysql_value;
+pub use postgres::decode_pg_value;
+pub use sqlite::decode_sqlite_value;
+
+use base64::Engine;
+use serde_json::Value;
+
+/// Binary data as a base64 string
+pub(crate) fn bytes_to_json(bytes: &[u8]) -> Value {
+    Value::String(base64::engine::general_purpose::STANDARD.encode(bytes))
+}
+
+/// Floats as JSON numbers; NaN and infinities, which JSON cannot represent,
+/// as strings
+pub(crate) fn float_to_json(value: f64) -> Value {
+    match serde_json::Number::from_f64(value) {
+        Some(number) => Value::Number(number),
+        None if value.is_nan() => Value::String("NaN".to_string()),
+        None if value > 0.0 => Value::String("Infinity".to_string()),
+        None => Value::String("-Infinity".to_string()),
+    }
+}
+
+/// Text when the bytes are valid UTF-8, base64 otherwise
+pub(crate) fn text_or_bytes(bytes: &[u8]) -> Value {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Value::String(text.to_string()),
+        Err(_) => bytes_to_json(bytes),
+    }
+}
+
+/// Big-endian cursor over a binary wire value
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.buf.len() < len {
+            return None;
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Some(head)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    pub(crate) fn i16(&mut self) -> Option<i16> {
+        self.bytes(2).map(|b| i16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn u16(&mut self) -> Option<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    pub(crate) fn i32(&mut self) -> Option<i32> {
+        self.bytes(4)
+            .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    pub(crate) fn i64(&mut self) -> Option<i64> {
+        self.bytes(8)
+            .and_then(|b| b.try_into().ok())
+            .map(i64::from_be_bytes)
+    }
+
+    pub(crate) fn f64(&mut self) -> Option<f64> {
+        self.bytes(8)
+            .and_then(|b| b.try_into().ok())
+            .map(f64::from_be_bytes)
+    }
+
+    /// Length-prefixed value; None inside means SQL NULL (length -1)
+    pub(crate) fn value(&mut self) -> Option<Option<&'a [u8]>> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Some(None);
+        }
+        self.bytes(len as usize).map(Some)
+    }
+
+    pub(crate) fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_float_to_json_special_values() {
+        assert_eq!(float_to_json(1.5), serde_json::json!(1.5));
+        assert_eq!(float_to_json(f64::NAN), serde_json::json!("NaN"));
+        assert_eq!(float_to_json(f64::INFINITY), serde_json::json!("Infinity"));
+        assert_eq!(
+            float_to_json(f64::NEG_INFINITY),
+            serde_json::json!("-Infinity")
+        );
+    }
+
+    #[test]
+    fn test_bytes_are_base64() {
+        assert_eq!(bytes_to_json(b"\x00\xffhi"), serde_json::json!("AP9oaQ=="));
+        assert_eq!(text_or_bytes(b"hi"), serde_json::json!("hi"));
+    }
+}
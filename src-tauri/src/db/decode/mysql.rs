@@ -0,0 +1,157 @@
+//! MySQL / MariaDB value decoding
+//!
+//! Dispatches on the column type name reported by sqlx. Typed decoders handle
+//! both the binary (prepared) and text (simple query) formats; whatever they
+//! reject falls back to the raw bytes.
+
+use super::{bytes_to_json, float_to_json, text_or_bytes};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, SecondsFormat, Utc};
+use serde_json::Value;
+use sqlx::mysql::MySqlRow;
+use sqlx::{Row, TypeInfo, ValueRef};
+
+/// Decode column `index` of a MySQL row to JSON
+pub fn decode_mysql_value(row: &MySqlRow, index: usize) -> Value {
+    let Ok(raw) = row.try_get_raw(index) else {
+        return Value::Null;
+    };
+    if raw.is_null() {
+        return Value::Null;
+    }
+    let type_name = raw.type_info().name().to_string();
+    decode_typed(row, index, &type_name).unwrap_or_else(|| {
+        let bytes = row.try_get_unchecked::<&[u8], _>(index).unwrap_or_default();
+        decode_fallback(&type_name, bytes)
+    })
+}
+
+fn decode_typed(row: &MySqlRow, index: usize, type_name: &str) -> Option<Value> {
+    Some(match type_name {
+        "NULL" => Value::Null,
+        "BOOLEAN" => Value::Bool(row.try_get_unchecked::<bool, _>(index).ok()?),
+        "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "BIGINT" => {
+            Value::from(row.try_get_unchecked::<i64, _>(index).ok()?)
+        }
+        name if name.ends_with("UNSIGNED") => {
+            Value::from(row.try_get_unchecked::<u64, _>(index).ok()?)
+        }
+        "YEAR" => Value::from(row.try_get_unchecked::<u16, _>(index).ok()?),
+        "FLOAT" => float_to_json(f64::from(row.try_get_unchecked::<f32, _>(index).ok()?)),
+        "DOUBLE" => float_to_json(row.try_get_unchecked::<f64, _>(index).ok()?),
+        "DECIMAL" => Value::String(row.try_get_unchecked::<String, _>(index).ok()?),
+        "DATE" => Value::String(
+            row.try_get_unchecked::<NaiveDate, _>(index)
+                .ok()?
+                .format("%Y-%m-%d")
+                .to_string(),
+        ),
+        "DATETIME" => Value::String(
+            row.try_get_unchecked::<NaiveDateTime, _>(index)
+                .ok()?
+                .format("%Y-%m-%dT%H:%M:%S%.f")
+                .to_string(),
+        ),
+        "TIMESTAMP" => Value::String(
+            row.try_get_unchecked::<DateTime<Utc>, _>(index)
+                .ok()?
+                .to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        ),
+        "TIME" => Value::String(
+            row.try_get_unchecked::<NaiveTime, _>(index)
+                .ok()?
+                .format("%H:%M:%S%.f")
+                .to_string(),
+        ),
+        "JSON" => {
+            row.try_get_unchecked::<sqlx::types::Json<Value>, _>(index)
+                .ok()?
+                .0
+        }
+        "BIT" => {
+            let bytes = row.try_get_unchecked::<&[u8], _>(index).ok()?;
+            if bytes.len() > 8 {
+                return None;
+            }
+            Value::from(bytes.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b)))
+        }
+        "BINARY" | "VARBINARY" | "TINYBLOB" | "BLOB" | "MEDIUMBLOB" | "LONGBLOB" | "GEOMETRY" => {
+            bytes_to_json(row.try_get_unchecked::<&[u8], _>(index).ok()?)
+        }
+        _ => Value::String(row.try_get_unchecked::<String, _>(index).ok()?),
+    })
+}
+
+/// Values the typed decoders reject: zero dates, times outside 00:00-24:00,
+/// and text in charsets sqlx does not map
+fn decode_fallback(type_name: &str, bytes: &[u8]) -> Value {
+    let is_text = bytes
+        .first()
+        .is_some_and(|b| b.is_ascii_digit() || *b == b'-');
+    match type_name {
+        "DATE" | "DATETIME" | "TIMESTAMP" if !is_text => {
+            // Zero dates carry no value in the binary format
+            Value::String(match type_name {
+                "DATE" => "0000-00-00".to_string(),
+                _ => "0000-00-00T00:00:00".to_string(),
+            })
+        }
+        "TIME" if !is_text => binary_time_to_string(bytes)
+            .map(Value::String)
+            .unwrap_or_else(|| bytes_to_json(bytes)),
+        _ => text_or_bytes(bytes),
+    }
+}
+
+/// Decode a binary TIME (length, sign, days, h, m, s, µs) as `[-]HHH:MM:SS[.ffffff]`
+pub(super) fn binary_time_to_string(bytes: &[u8]) -> Option<String> {
+    let (&len, rest) = bytes.split_first()?;
+    if len == 0 {
+        return Some("00:00:00".to_string());
+    }
+    if rest.len() < 8 {
+        return None;
+    }
+    let negative = rest[0] == 1;
+    let days = u32::from_le_bytes([rest[1], rest[2], rest[3], rest[4]]);
+    let hours = days * 24 + u32::from(rest[5]);
+    let mut out = format!(
+        "{}{:02}:{:02}:{:02}",
+        if negative { "-" } else { "" },
+        hours,
+        rest[6],
+        rest[7]
+    );
+    if let Some(micros) = rest.get(8..12) {
+        let micros = u32::from_le_bytes([micros[0], micros[1], micros[2], micros[3]]);
+        if micros != 0 {
+            out.push_str(&format!(".{:06}", micros));
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_binary_time_beyond_one_day() {
+        // -1 day 02:03:04.5
+        let bytes = [12, 1, 1, 0, 0, 0, 2, 3, 4, 0x20, 0xA1, 0x07, 0x00];
+        assert_eq!(
+            binary_time_to_string(&bytes).as_deref(),
+            Some("-26:03:04.500000")
+        );
+        assert_eq!(binary_time_to_string(&[0]).as_deref(), Some("00:00:00"));
+    }
+
+    #[test]
+    fn test_zero_dates() {
+        assert_eq!(decode_fallback("DATE", &[0]), json!("0000-00-00"));
+        assert_eq!(
+            decode_fallback("DATETIME", b"0000-00-00 00:00:00"),
+            json!("0000-00-00 00:00:00")
+        );
+    }
+}
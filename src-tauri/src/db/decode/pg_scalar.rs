@@ -0,0 +1,285 @@
+//! PostgreSQL binary wire format decoders for built-in scalar types
+
+use super::pg_temporal::{
+    date_to_string, interval_to_iso, time_to_string, timestamp_to_string, utc_offset,
+};
+use super::{bytes_to_json, float_to_json, text_or_bytes, Reader};
+use serde_json::{json, Value};
+
+/// Decode a binary value of a built-in type by its OID.
+/// Unknown types become text when valid UTF-8 (e.g. `citext`), base64 otherwise.
+pub(super) fn decode_scalar(oid: u32, bytes: &[u8]) -> Value {
+    decode_known(oid, bytes).unwrap_or_else(|| text_or_bytes(bytes))
+}
+
+fn decode_known(oid: u32, bytes: &[u8]) -> Option<Value> {
+    let mut r = Reader::new(bytes);
+    Some(match oid {
+        // bool
+        16 => Value::Bool(r.u8()? != 0),
+        // bytea
+        17 => bytes_to_json(bytes),
+        // "char"
+        18 => Value::String(char::from(r.u8()?).to_string()),
+        // int8, int2, int4
+        20 => json!(r.i64()?),
+        21 => json!(r.i16()?),
+        23 => json!(r.i32()?),
+        // regproc, oid, xid, cid, regclass, regtype
+        24 | 26 | 28 | 29 | 2205 | 2206 => json!(r.u32()?),
+        // json
+        114 => serde_json::from_slice(bytes).ok()?,
+        // jsonb: version byte followed by JSON text
+        3802 => {
+            r.u8()?;
+            serde_json::from_slice(r.rest()).ok()?
+        }
+        // float4, float8
+        700 => float_to_json(f64::from(f32::from_bits(r.u32()?))),
+        701 => float_to_json(r.f64()?),
+        // money, in cents
+        790 => Value::String(money_to_string(r.i64()?)),
+        // numeric
+        1700 => Value::String(numeric_to_string(bytes)?),
+        // uuid
+        2950 => Value::String(uuid::Uuid::from_slice(bytes).ok()?.to_string()),
+        // date, time, timetz, timestamp, timestamptz, interval
+        1082 => Value::String(date_to_string(r.i32()?)?),
+        1083 => Value::String(time_to_string(r.i64()?)?),
+        1266 => {
+            let time = time_to_string(r.i64()?)?;
+            Value::String(format!("{}{}", time, utc_offset(r.i32()?)))
+        }
+        1114 => Value::String(timestamp_to_string(r.i64()?, false)?),
+        1184 => Value::String(timestamp_to_string(r.i64()?, true)?),
+        1186 => {
+            let micros = r.i64()?;
+            let days = r.i32()?;
+            let months = r.i32()?;
+            Value::String(interval_to_iso(months, days, micros))
+        }
+        // cidr, inet
+        650 | 869 => Value::String(inet_to_string(bytes)?),
+        // macaddr, macaddr8
+        829 | 774 => Value::String(
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .join(":"),
+        ),
+        // bit, varbit
+        1560 | 1562 => Value::String(bits_to_string(bytes)?),
+        // point, lseg, box, circle, path, polygon
+        600 => point(&mut r)?,
+        601 | 603 => json!([point(&mut r)?, point(&mut r)?]),
+        718 => {
+            let center = point(&mut r)?;
+            json!({ "center": center, "radius": float_to_json(r.f64()?) })
+        }
+        602 => {
+            let closed = r.u8()? != 0;
+            json!({ "closed": closed, "points": points(&mut r)? })
+        }
+        604 => points(&mut r)?,
+        // pg_lsn
+        3220 => {
+            let lsn = r.i64()? as u64;
+            Value::String(format!("{:X}/{:X}", lsn >> 32, lsn & 0xFFFF_FFFF))
+        }
+        // jsonpath: version byte followed by text
+        4072 => {
+            r.u8()?;
+            text_or_bytes(r.rest())
+        }
+        _ => return None,
+    })
+}
+
+fn point(r: &mut Reader<'_>) -> Option<Value> {
+    Some(json!({ "x": float_to_json(r.f64()?), "y": float_to_json(r.f64()?) }))
+}
+
+fn points(r: &mut Reader<'_>) -> Option<Value> {
+    let count = r.i32()?;
+    (0..count)
+        .map(|_| point(r))
+        .collect::<Option<Vec<_>>>()
+        .map(Value::Array)
+}
+
+/// Render a binary NUMERIC (base-10000 digits) as an exact decimal string
+pub(super) fn numeric_to_string(bytes: &[u8]) -> Option<String> {
+    let mut r = Reader::new(bytes);
+    let ndigits = r.i16()?;
+    let weight = i32::from(r.i16()?);
+    let sign = r.u16()?;
+    let dscale = usize::from(r.u16()?);
+    let digits = (0..ndigits).map(|_| r.i16()).collect::<Option<Vec<_>>>()?;
+
+    match sign {
+        0xC000 => return Some("NaN".to_string()),
+        0xD000 => return Some("Infinity".to_string()),
+        0xF000 => return Some("-Infinity".to_string()),
+        _ => {}
+    }
+
+    let digit = |i: i32| -> i16 {
+        usize::try_from(i)
+            .ok()
+            .and_then(|i| digits.get(i).copied())
+            .unwrap_or(0)
+    };
+
+    let mut out = String::new();
+    if sign == 0x4000 && !digits.iter().all(|d| *d == 0) {
+        out.push('-');
+    }
+    if weight >= 0 {
+        for i in 0..=weight {
+            if i == 0 {
+                out.push_str(&digit(i).to_string());
+            } else {
+                out.push_str(&format!("{:04}", digit(i)));
+            }
+        }
+    } else {
+        out.push('0');
+    }
+
+    if dscale > 0 {
+        let groups = dscale.div_ceil(4) as i32;
+        let mut fraction: String = (weight + 1..weight + 1 + groups)
+            .map(|i| format!("{:04}", digit(i)))
+            .collect();
+        fraction.truncate(dscale);
+        out.push('.');
+        out.push_str(&fraction);
+    }
+    Some(out)
+}
+
+fn money_to_string(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    let cents = cents.unsigned_abs();
+    format!("{}{}.{:02}", sign, cents / 100, cents % 100)
+}
+
+fn inet_to_string(bytes: &[u8]) -> Option<String> {
+    let mut r = Reader::new(bytes);
+    let family = r.u8()?;
+    let bits = r.u8()?;
+    let is_cidr = r.u8()? != 0;
+    let len = usize::from(r.u8()?);
+    let addr = r.bytes(len)?;
+
+    let (ip, full) = match (family, addr.len()) {
+        (2, 4) => {
+            let octets: [u8; 4] = addr.try_into().ok()?;
+            (std::net::Ipv4Addr::from(octets).to_string(), 32)
+        }
+        (3, 16) => {
+            let octets: [u8; 16] = addr.try_into().ok()?;
+            (std::net::Ipv6Addr::from(octets).to_string(), 128)
+        }
+        _ => return None,
+    };
+    // Like PostgreSQL, inet host addresses are shown without a prefix length
+    Some(if bits == full && !is_cidr {
+        ip
+    } else {
+        format!("{}/{}", ip, bits)
+    })
+}
+
+fn bits_to_string(bytes: &[u8]) -> Option<String> {
+    let mut r = Reader::new(bytes);
+    let len = usize::try_from(r.i32()?).ok()?;
+    let data = r.rest();
+    (0..len)
+        .map(|i| {
+            data.get(i / 8).map(|byte| {
+                if byte & (0x80 >> (i % 8)) != 0 {
+                    '1'
+                } else {
+                    '0'
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numeric(ndigits: i16, weight: i16, sign: u16, dscale: u16, digits: &[i16]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend(ndigits.to_be_bytes());
+        buf.extend(weight.to_be_bytes());
+        buf.extend(sign.to_be_bytes());
+        buf.extend(dscale.to_be_bytes());
+        for d in digits {
+            buf.extend(d.to_be_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn test_numeric_to_string() {
+        assert_eq!(
+            numeric_to_string(&numeric(2, 0, 0, 2, &[123, 4500])).as_deref(),
+            Some("123.45")
+        );
+        assert_eq!(
+            numeric_to_string(&numeric(1, -1, 0x4000, 3, &[10])).as_deref(),
+            Some("-0.001")
+        );
+        assert_eq!(
+            numeric_to_string(&numeric(1, 1, 0, 0, &[1])).as_deref(),
+            Some("10000")
+        );
+        assert_eq!(
+            numeric_to_string(&numeric(0, 0, 0, 2, &[])).as_deref(),
+            Some("0.00")
+        );
+        assert_eq!(
+            numeric_to_string(&numeric(0, 0, 0xC000, 0, &[])).as_deref(),
+            Some("NaN")
+        );
+    }
+
+    #[test]
+    fn test_temporal_values() {
+        assert_eq!(
+            decode_scalar(1082, &(-1i32).to_be_bytes()),
+            json!("1999-12-31")
+        );
+        assert_eq!(
+            decode_scalar(1114, &1_500_000i64.to_be_bytes()),
+            json!("2000-01-01T00:00:01.500")
+        );
+        assert_eq!(
+            decode_scalar(1184, &0i64.to_be_bytes()),
+            json!("2000-01-01T00:00:00Z")
+        );
+        let mut timetz = 3_600_000_000i64.to_be_bytes().to_vec();
+        timetz.extend((-7200i32).to_be_bytes());
+        assert_eq!(decode_scalar(1266, &timetz), json!("01:00:00+02:00"));
+    }
+
+    #[test]
+    fn test_network_and_bit_values() {
+        assert_eq!(
+            decode_scalar(869, &[2, 32, 0, 4, 192, 168, 0, 1]),
+            json!("192.168.0.1")
+        );
+        assert_eq!(
+            decode_scalar(650, &[2, 24, 1, 4, 10, 0, 0, 0]),
+            json!("10.0.0.0/24")
+        );
+        let mut bits = 5i32.to_be_bytes().to_vec();
+        bits.push(0b1011_0000);
+        assert_eq!(decode_scalar(1560, &bits), json!("10110"));
+    }
+}
@@ -0,0 +1,118 @@
+//! PostgreSQL binary wire format decoders for date and time types
+//!
+//! Dates and timestamps count from 2000-01-01; times and timestamps are in
+//! microseconds.
+
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, SecondsFormat, Utc};
+
+fn pg_epoch() -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(2000, 1, 1)
+}
+
+pub(super) fn date_to_string(days: i32) -> Option<String> {
+    match days {
+        i32::MAX => Some("infinity".to_string()),
+        i32::MIN => Some("-infinity".to_string()),
+        _ => pg_epoch()?
+            .checked_add_signed(Duration::days(i64::from(days)))
+            .map(|d| d.format("%Y-%m-%d").to_string()),
+    }
+}
+
+pub(super) fn time_to_string(micros: i64) -> Option<String> {
+    let secs = u32::try_from(micros.div_euclid(1_000_000)).ok()?;
+    let nanos = u32::try_from(micros.rem_euclid(1_000_000) * 1000).ok()?;
+    if secs == 86_400 {
+        // 24:00:00 is a valid PostgreSQL time
+        return Some("24:00:00".to_string());
+    }
+    NaiveTime::from_num_seconds_from_midnight_opt(secs, nanos)
+        .map(|t| t.format("%H:%M:%S%.f").to_string())
+}
+
+/// timetz stores the zone as seconds *west* of UTC
+pub(super) fn utc_offset(seconds_west: i32) -> String {
+    let east = -seconds_west;
+    let sign = if east < 0 { '-' } else { '+' };
+    let east = east.unsigned_abs();
+    format!("{}{:02}:{:02}", sign, east / 3600, (east % 3600) / 60)
+}
+
+pub(super) fn timestamp_to_string(micros: i64, with_zone: bool) -> Option<String> {
+    match micros {
+        i64::MAX => return Some("infinity".to_string()),
+        i64::MIN => return Some("-infinity".to_string()),
+        _ => {}
+    }
+    let naive = pg_epoch()?
+        .and_hms_opt(0, 0, 0)?
+        .checked_add_signed(Duration::microseconds(micros))?;
+    Some(if with_zone {
+        DateTime::<Utc>::from_naive_utc_and_offset(naive, Utc)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    } else {
+        naive.format("%Y-%m-%dT%H:%M:%S%.f").to_string()
+    })
+}
+
+/// Render an interval as an ISO-8601 duration, e.g. `P1Y2M3DT4H5M6.5S`
+pub(super) fn interval_to_iso(months: i32, days: i32, micros: i64) -> String {
+    let mut out = String::from("P");
+    let (years, months) = (months / 12, months % 12);
+    if years != 0 {
+        out.push_str(&format!("{}Y", years));
+    }
+    if months != 0 {
+        out.push_str(&format!("{}M", months));
+    }
+    if days != 0 {
+        out.push_str(&format!("{}D", days));
+    }
+
+    if micros != 0 {
+        out.push('T');
+        let hours = micros / 3_600_000_000;
+        let minutes = (micros % 3_600_000_000) / 60_000_000;
+        let second_micros = micros % 60_000_000;
+        if hours != 0 {
+            out.push_str(&format!("{}H", hours));
+        }
+        if minutes != 0 {
+            out.push_str(&format!("{}M", minutes));
+        }
+        if second_micros != 0 {
+            let sign = if second_micros < 0 { "-" } else { "" };
+            let abs = second_micros.unsigned_abs();
+            let fraction = abs % 1_000_000;
+            if fraction == 0 {
+                out.push_str(&format!("{}{}S", sign, abs / 1_000_000));
+            } else {
+                let fraction = format!("{:06}", fraction);
+                out.push_str(&format!(
+                    "{}{}.{}S",
+                    sign,
+                    abs / 1_000_000,
+                    fraction.trim_end_matches('0')
+                ));
+            }
+        }
+    } else if out.len() == 1 {
+        out.push_str("T0S");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_to_iso() {
+        assert_eq!(
+            interval_to_iso(14, 3, 4 * 3_600_000_000 + 5 * 60_000_000 + 6_500_000),
+            "P1Y2M3DT4H5M6.5S"
+        );
+        assert_eq!(interval_to_iso(0, 0, 0), "PT0S");
+        assert_eq!(interval_to_iso(0, -1, -500_000), "P-1DT-0.5S");
+    }
+}
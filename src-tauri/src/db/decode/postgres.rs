@@ -0,0 +1,221 @@
+//! PostgreSQL value decoding
+//!
+//! Values arrive in the binary format for prepared queries and in the text
+//! format for simple-protocol scripts; both are handled. Arrays, composites,
+//! domains and ranges are walked using the type metadata of the column.
+
+use super::pg_scalar::decode_scalar;
+use super::{float_to_json, text_or_bytes, Reader};
+use serde_json::{Map, Value};
+use sqlx::postgres::{PgRow, PgTypeInfo, PgTypeKind, PgValueFormat};
+use sqlx::{Row, ValueRef};
+
+// Range flags of the binary wire format
+const RANGE_EMPTY: u8 = 0x01;
+const RANGE_LB_INC: u8 = 0x02;
+const RANGE_UB_INC: u8 = 0x04;
+const RANGE_LB_INF: u8 = 0x08;
+const RANGE_UB_INF: u8 = 0x10;
+
+/// Decode column `index` of a PostgreSQL row to JSON
+pub fn decode_pg_value(row: &PgRow, index: usize) -> Value {
+    let Ok(value) = row.try_get_raw(index) else {
+        return Value::Null;
+    };
+    if value.is_null() {
+        return Value::Null;
+    }
+    let type_info = value.type_info().into_owned();
+    let Ok(bytes) = value.as_bytes() else {
+        return Value::Null;
+    };
+    match value.format() {
+        PgValueFormat::Binary => decode_typed(&type_info, bytes),
+        PgValueFormat::Text => decode_text(type_oid(&type_info), bytes),
+    }
+}
+
+fn type_oid(type_info: &PgTypeInfo) -> u32 {
+    type_info.oid().map_or(0, |oid| oid.0)
+}
+
+/// Decode a binary value using the type metadata of its column
+fn decode_typed(type_info: &PgTypeInfo, bytes: &[u8]) -> Value {
+    let decoded = match type_info.kind() {
+        PgTypeKind::Array(element) => decode_array(bytes, &|oid, bytes| {
+            if type_oid(element) == oid {
+                decode_typed(element, bytes)
+            } else {
+                decode_scalar(oid, bytes)
+            }
+        }),
+        PgTypeKind::Composite(fields) => decode_composite(bytes, fields),
+        PgTypeKind::Domain(base) => Some(decode_typed(base, bytes)),
+        PgTypeKind::Range(element) => decode_range(bytes, &|bytes| decode_typed(element, bytes)),
+        PgTypeKind::Enum(_) => Some(text_or_bytes(bytes)),
+        PgTypeKind::Simple | PgTypeKind::Pseudo => Some(decode_scalar(type_oid(type_info), bytes)),
+    };
+    decoded.unwrap_or_else(|| text_or_bytes(bytes))
+}
+
+/// Decode a binary array into nested JSON arrays, one level per dimension
+pub(super) fn decode_array(bytes: &[u8], element: &dyn Fn(u32, &[u8]) -> Value) -> Option<Value> {
+    let mut r = Reader::new(bytes);
+    let ndim = usize::try_from(r.i32()?).ok()?;
+    let _has_nulls = r.i32()?;
+    let element_oid = r.u32()?;
+    let mut dims = Vec::with_capacity(ndim);
+    for _ in 0..ndim {
+        dims.push(usize::try_from(r.i32()?).ok()?);
+        let _lower_bound = r.i32()?;
+    }
+    if dims.is_empty() {
+        return Some(Value::Array(Vec::new()));
+    }
+    read_dimension(&mut r, &dims, element_oid, element)
+}
+
+fn read_dimension(
+    r: &mut Reader<'_>,
+    dims: &[usize],
+    element_oid: u32,
+    element: &dyn Fn(u32, &[u8]) -> Value,
+) -> Option<Value> {
+    let (len, inner) = dims.split_first()?;
+    let mut items = Vec::with_capacity(*len);
+    for _ in 0..*len {
+        items.push(if inner.is_empty() {
+            r.value()?
+                .map_or(Value::Null, |bytes| element(element_oid, bytes))
+        } else {
+            read_dimension(r, inner, element_oid, element)?
+        });
+    }
+    Some(Value::Array(items))
+}
+
+/// Decode a binary record into a JSON object keyed by field name
+fn decode_composite(bytes: &[u8], fields: &[(String, PgTypeInfo)]) -> Option<Value> {
+    let mut r = Reader::new(bytes);
+    let count = usize::try_from(r.i32()?).ok()?;
+    let mut object = Map::with_capacity(count);
+    for i in 0..count {
+        let oid = r.u32()?;
+        let value = r.value()?;
+        let (name, value) = match fields.get(i) {
+            Some((name, type_info)) => (
+                name.clone(),
+                value.map_or(Value::Null, |bytes| decode_typed(type_info, bytes)),
+            ),
+            None => (
+                format!("f{}", i + 1),
+                value.map_or(Value::Null, |bytes| decode_scalar(oid, bytes)),
+            ),
+        };
+        object.insert(name, value);
+    }
+    Some(Value::Object(object))
+}
+
+/// Decode a binary range into its text form, e.g. `[1,10)` or `empty`
+fn decode_range(bytes: &[u8], element: &dyn Fn(&[u8]) -> Value) -> Option<Value> {
+    let mut r = Reader::new(bytes);
+    let flags = r.u8()?;
+    if flags & RANGE_EMPTY != 0 {
+        return Some(Value::String("empty".to_string()));
+    }
+    let mut bound = |infinite: bool| -> Option<String> {
+        if infinite {
+            return Some(String::new());
+        }
+        Some(match element(r.value()??) {
+            Value::String(text) => text,
+            other => other.to_string(),
+        })
+    };
+    let lower = bound(flags & RANGE_LB_INF != 0)?;
+    let upper = bound(flags & RANGE_UB_INF != 0)?;
+    Some(Value::String(format!(
+        "{}{},{}{}",
+        if flags & RANGE_LB_INC != 0 { '[' } else { '(' },
+        lower,
+        upper,
+        if flags & RANGE_UB_INC != 0 { ']' } else { ')' },
+    )))
+}
+
+/// Decode a text-format value; types with a JSON counterpart are parsed,
+/// everything else is already in PostgreSQL's canonical text form
+fn decode_text(oid: u32, bytes: &[u8]) -> Value {
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return text_or_bytes(bytes);
+    };
+    let parsed = match oid {
+        16 => Some(Value::Bool(text == "t")),
+        20 | 21 | 23 | 26 => text.parse::<i64>().ok().map(Value::from),
+        700 | 701 => text.parse::<f64>().ok().map(float_to_json),
+        114 | 3802 => serde_json::from_str(text).ok(),
+        _ => None,
+    };
+    parsed.unwrap_or_else(|| Value::String(text.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn int4_array(dims: &[i32], items: &[Option<i32>]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend((dims.len() as i32).to_be_bytes());
+        buf.extend(1i32.to_be_bytes());
+        buf.extend(23u32.to_be_bytes());
+        for dim in dims {
+            buf.extend(dim.to_be_bytes());
+            buf.extend(1i32.to_be_bytes());
+        }
+        for item in items {
+            match item {
+                Some(value) => {
+                    buf.extend(4i32.to_be_bytes());
+                    buf.extend(value.to_be_bytes());
+                }
+                None => buf.extend((-1i32).to_be_bytes()),
+            }
+        }
+        buf
+    }
+
+    #[test]
+    fn test_decode_nested_array() {
+        let bytes = int4_array(&[2, 2], &[Some(1), Some(2), None, Some(4)]);
+        assert_eq!(
+            decode_array(&bytes, &decode_scalar),
+            Some(json!([[1, 2], [null, 4]]))
+        );
+        assert_eq!(
+            decode_array(&int4_array(&[], &[]), &decode_scalar),
+            Some(json!([]))
+        );
+    }
+
+    #[test]
+    fn test_decode_range() {
+        let mut bytes = vec![RANGE_LB_INC];
+        for bound in [1i32, 10] {
+            bytes.extend(4i32.to_be_bytes());
+            bytes.extend(bound.to_be_bytes());
+        }
+        let int4 = |bytes: &[u8]| decode_scalar(23, bytes);
+        assert_eq!(decode_range(&bytes, &int4), Some(json!("[1,10)")));
+        assert_eq!(decode_range(&[RANGE_EMPTY], &int4), Some(json!("empty")));
+    }
+
+    #[test]
+    fn test_decode_text_format() {
+        assert_eq!(decode_text(16, b"t"), json!(true));
+        assert_eq!(decode_text(20, b"-42"), json!(-42));
+        assert_eq!(decode_text(3802, b"{\"a\": 1}"), json!({ "a": 1 }));
+        assert_eq!(decode_text(1700, b"1.10"), json!("1.10"));
+    }
+}
@@ -0,0 +1,28 @@
+//! SQLite value decoding
+//!
+//! SQLite is dynamically typed, so values are decoded by their storage class
+//! rather than the declared column type.
+
+use super::{bytes_to_json, float_to_json};
+use serde_json::Value;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row, TypeInfo, ValueRef};
+
+/// Decode column `index` of a SQLite row to JSON
+pub fn decode_sqlite_value(row: &SqliteRow, index: usize) -> Value {
+    let Ok(raw) = row.try_get_raw(index) else {
+        return Value::Null;
+    };
+    if raw.is_null() {
+        return Value::Null;
+    }
+    // The storage class of this value, not the declared type of the column
+    let storage_class = raw.type_info().name().to_string();
+    let value = match storage_class.as_str() {
+        "INTEGER" => row.try_get_unchecked::<i64, _>(index).map(Value::from),
+        "REAL" => row.try_get_unchecked::<f64, _>(index).map(float_to_json),
+        "BLOB" => row.try_get_unchecked::<&[u8], _>(index).map(bytes_to_json),
+        _ => row.try_get_unchecked::<String, _>(index).map(Value::String),
+    };
+    value.unwrap_or(Value::Null)
+}
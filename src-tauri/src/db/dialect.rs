@@ -0,0 +1,51 @@
+//! SQL dialect detection
+//!
+//! Maps a connection pool to the SQL flavour it speaks so that parsing helpers
+//! (statement splitting, quoting) can follow the right lexical rules.
+
+use crate::db::pool::DatabasePool;
+use serde::{Deserialize, Serialize};
+
+/// SQL dialects understood by the parsing helpers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SqlDialect {
+    Postgres,
+    MySql,
+    Sqlite,
+}
+
+impl SqlDialect {
+    /// Dialect of a connected pool (None for non-SQL databases)
+    pub fn from_pool(pool: &DatabasePool) -> Option<Self> {
+        match pool {
+            DatabasePool::Postgres(_) => Some(SqlDialect::Postgres),
+            DatabasePool::MySQL(_) => Some(SqlDialect::MySql),
+            DatabasePool::SQLite(_) => Some(SqlDialect::Sqlite),
+            _ => None,
+        }
+    }
+
+    /// Quote a single identifier, doubling embedded quote characters
+    pub fn quote_identifier(self, name: &str) -> String {
+        let quote = match self {
+            SqlDialect::MySql => "`",
+            SqlDialect::Postgres | SqlDialect::Sqlite => "\"",
+        };
+        format!(
+            "{}{}{}",
+            quote,
+            name.replace(quote, &quote.repeat(2)),
+            quote
+        )
+    }
+
+    /// Comparison that also holds when both sides are NULL
+    pub fn null_safe_eq(self, left: &str, right: &str) -> String {
+        match self {
+            SqlDialect::Postgres => format!("{} IS NOT DISTINCT FROM {}", left, right),
+            SqlDialect::MySql => format!("{} <=> {}", left, right),
+            SqlDialect::Sqlite => format!("{} IS {}", left, right),
+        }
+    }
+}
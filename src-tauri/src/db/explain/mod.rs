@@ -0,0 +1,184 @@
+//! Structured EXPLAIN plans
+//!
+//! Plans are requested in a machine-readable form (`FORMAT JSON` on
+//! PostgreSQL and MySQL, `EXPLAIN QUERY PLAN` rows on SQLite) and turned into
+//! one tree of `PlanNode`s per query. ANALYZE executes the statement, so it
+//! is opt-in and mutating statements are only analyzed inside a transaction
+//! that is rolled back afterwards.
+
+mod mysql;
+mod postgres;
+mod sqlite;
+
+use crate::commands::database::ExplainResult;
+use crate::db::classify::classify;
+use crate::db::dialect::SqlDialect;
+use crate::db::{ConnectionPoolManager, DatabasePool};
+use crate::error::VelocityError;
+use serde_json::{Map, Value};
+
+/// How to explain a statement
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ExplainOptions {
+    /// Execute the statement to collect actual timings and row counts
+    pub analyze: bool,
+    /// Run ANALYZE inside a transaction that is rolled back. Required for
+    /// statements that modify data.
+    pub rollback: bool,
+}
+
+/// One operation of a query plan
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanNode {
+    /// Operation, e.g. `Seq Scan`, `Hash Join`, `SEARCH`
+    pub node_type: String,
+    /// Object the operation works on, e.g. `users using users_pkey`
+    pub detail: Option<String>,
+    /// Estimated cost before the first row is returned
+    pub startup_cost: Option<f64>,
+    /// Estimated cost of returning all rows
+    pub total_cost: Option<f64>,
+    /// Estimated number of rows
+    pub estimated_rows: Option<f64>,
+    /// Milliseconds until the first row (ANALYZE only)
+    pub actual_startup_time: Option<f64>,
+    /// Milliseconds until the last row (ANALYZE only)
+    pub actual_total_time: Option<f64>,
+    /// Rows returned per loop (ANALYZE only)
+    pub actual_rows: Option<f64>,
+    /// Number of times the operation ran (ANALYZE only)
+    pub loops: Option<f64>,
+    /// Remaining properties reported by the database
+    pub properties: Map<String, Value>,
+    pub children: Vec<PlanNode>,
+}
+
+impl PlanNode {
+    fn new(node_type: impl Into<String>) -> Self {
+        Self {
+            node_type: node_type.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl ConnectionPoolManager {
+    /// Get the execution plan of a statement as a tree
+    pub async fn explain_query(
+        &self,
+        connection_id: &str,
+        sql: &str,
+        options: ExplainOptions,
+    ) -> Result<ExplainResult, VelocityError> {
+        let pool = self
+            .get_pool(connection_id)
+            .await
+            .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+        let dialect = SqlDialect::from_pool(&pool)
+            .ok_or_else(|| VelocityError::Query("EXPLAIN not supported".to_string()))?;
+        let sql = sql.trim().trim_end_matches(';');
+        if options.analyze {
+            self.connection_settings(connection_id)
+                .await
+                .check_statement(sql, dialect)?;
+        }
+
+        if options.analyze && !options.rollback && classify(sql, dialect).modifies_data() {
+            return Err(VelocityError::Query(
+                "EXPLAIN ANALYZE executes the statement and this statement modifies data. \
+                 Enable rollback to analyze it inside a transaction that is rolled back."
+                    .to_string(),
+            ));
+        }
+
+        let mut result = match pool.as_ref() {
+            DatabasePool::Postgres(pool) => postgres::explain(pool, sql, options).await?,
+            DatabasePool::MySQL(pool) => mysql::explain(pool, sql, options).await?,
+            DatabasePool::SQLite(pool) => sqlite::explain(pool, sql, options).await?,
+            _ => return Err(VelocityError::Query("EXPLAIN not supported".to_string())),
+        };
+        result.analyzed = options.analyze;
+        result.plan = render_plan(&result.nodes);
+        Ok(result)
+    }
+}
+
+/// Text rendering of a plan, one line per node
+pub fn render_plan(nodes: &[PlanNode]) -> Vec<String> {
+    fn walk(node: &PlanNode, depth: usize, lines: &mut Vec<String>) {
+        let mut line = format!("{}-> {}", "  ".repeat(depth), node.node_type);
+        if let Some(detail) = &node.detail {
+            line.push_str(&format!(" {}", detail));
+        }
+        let estimate = [
+            node.total_cost.map(|total| match node.startup_cost {
+                Some(startup) => format!("cost={:.2}..{:.2}", startup, total),
+                None => format!("cost={:.2}", total),
+            }),
+            node.estimated_rows.map(|rows| format!("rows={}", rows)),
+        ];
+        let actual = [
+            node.actual_total_time.map(|total| {
+                format!(
+                    "actual time={:.3}..{:.3}",
+                    node.actual_startup_time.unwrap_or(0.0),
+                    total
+                )
+            }),
+            node.actual_rows.map(|rows| format!("rows={}", rows)),
+            node.loops.map(|loops| format!("loops={}", loops)),
+        ];
+        for group in [&estimate[..], &actual[..]] {
+            let parts: Vec<&str> = group.iter().flatten().map(String::as_str).collect();
+            if !parts.is_empty() {
+                line.push_str(&format!("  ({})", parts.join(" ")));
+            }
+        }
+        lines.push(line);
+        for child in &node.children {
+            walk(child, depth + 1, lines);
+        }
+    }
+
+    let mut lines = Vec::new();
+    for node in nodes {
+        walk(node, 0, &mut lines);
+    }
+    lines
+}
+
+/// Number from a JSON value that may be a number or a numeric string
+fn json_number(value: Option<&Value>) -> Option<f64> {
+    match value? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_nested_plan() {
+        let mut root = PlanNode::new("Hash Join");
+        root.total_cost = Some(10.5);
+        root.startup_cost = Some(1.0);
+        root.estimated_rows = Some(3.0);
+        let mut scan = PlanNode::new("Seq Scan");
+        scan.detail = Some("on users".to_string());
+        scan.actual_total_time = Some(0.25);
+        scan.actual_rows = Some(3.0);
+        scan.loops = Some(1.0);
+        root.children.push(scan);
+        assert_eq!(
+            render_plan(&[root]),
+            vec![
+                "-> Hash Join  (cost=1.00..10.50 rows=3)",
+                "  -> Seq Scan on users  (actual time=0.000..0.250 rows=3 loops=1)",
+            ]
+        );
+    }
+}
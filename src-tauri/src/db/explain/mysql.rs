@@ -0,0 +1,301 @@
+//! MySQL plans from `EXPLAIN FORMAT=JSON` and `EXPLAIN ANALYZE`
+//!
+//! ANALYZE is only available in the indented tree format, which is parsed
+//! line by line.
+
+use super::{json_number, ExplainOptions, PlanNode};
+use crate::commands::database::ExplainResult;
+use crate::error::VelocityError;
+use serde_json::{Map, Value};
+use sqlx::{MySqlPool, Row};
+
+pub(super) async fn explain(
+    pool: &MySqlPool,
+    sql: &str,
+    options: ExplainOptions,
+) -> Result<ExplainResult, VelocityError> {
+    let explain_sql = if options.analyze {
+        format!("EXPLAIN ANALYZE {}", sql)
+    } else {
+        format!("EXPLAIN FORMAT=JSON {}", sql)
+    };
+    let query = sqlx::query(&explain_sql);
+
+    let row = if options.rollback {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+        let row = query.fetch_one(&mut *tx).await;
+        tx.rollback()
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+        row
+    } else {
+        query.fetch_one(pool).await
+    }
+    .map_err(|e| VelocityError::Query(e.to_string()))?;
+
+    let output: String = row
+        .try_get_unchecked(0)
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+    let nodes = if options.analyze {
+        parse_tree(&output)
+    } else {
+        let json: Value =
+            serde_json::from_str(&output).map_err(|e| VelocityError::Query(e.to_string()))?;
+        json.as_object()
+            .map(|root| {
+                root.iter()
+                    .map(|(key, value)| parse_json(key, value))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    Ok(ExplainResult {
+        nodes,
+        ..Default::default()
+    })
+}
+
+/// Convert one member of the JSON plan (`query_block`, `table`,
+/// `nested_loop`, ...) into a node
+fn parse_json(key: &str, value: &Value) -> PlanNode {
+    let Some(fields) = value.as_object() else {
+        let mut node = PlanNode::new(humanize(key));
+        node.properties.insert(key.to_string(), value.clone());
+        return node;
+    };
+
+    let mut node = PlanNode::new(match key {
+        "table" => table_access(fields),
+        _ => humanize(key),
+    });
+    for (name, value) in fields {
+        match (name.as_str(), value) {
+            ("cost_info", Value::Object(costs)) => {
+                node.total_cost =
+                    json_number(costs.get("query_cost").or_else(|| costs.get("prefix_cost")));
+                node.properties.extend(
+                    costs
+                        .iter()
+                        .filter(|(k, _)| !matches!(k.as_str(), "query_cost" | "prefix_cost"))
+                        .map(|(k, v)| (k.clone(), v.clone())),
+                );
+            }
+            ("rows_produced_per_join", _) => node.estimated_rows = json_number(Some(value)),
+            ("table_name", Value::String(table)) => {
+                node.detail = Some(match fields.get("key").and_then(Value::as_str) {
+                    Some(index) => format!("on {} using {}", table, index),
+                    None => format!("on {}", table),
+                });
+            }
+            (_, Value::Object(_)) => node.children.push(parse_json(name, value)),
+            (_, Value::Array(items)) if items.iter().all(Value::is_object) => {
+                let mut group = PlanNode::new(humanize(name));
+                group.children = items
+                    .iter()
+                    .map(|item| match single_member(item) {
+                        // `nested_loop: [{"table": {...}}, ...]`
+                        Some((inner, inner_value)) => parse_json(inner, inner_value),
+                        None => parse_json(name, item),
+                    })
+                    .collect();
+                node.children.push(group);
+            }
+            _ => {
+                node.properties.insert(name.clone(), value.clone());
+            }
+        }
+    }
+    node
+}
+
+fn single_member(value: &Value) -> Option<(&str, &Value)> {
+    let object = value.as_object().filter(|o| o.len() == 1)?;
+    object
+        .iter()
+        .next()
+        .filter(|(_, v)| v.is_object())
+        .map(|(k, v)| (k.as_str(), v))
+}
+
+/// Node type of a table access, named like the tree format does
+fn table_access(fields: &Map<String, Value>) -> String {
+    match fields.get("access_type").and_then(Value::as_str) {
+        Some("ALL") => "Table scan".to_string(),
+        Some("index") => "Index scan".to_string(),
+        Some("range") => "Index range scan".to_string(),
+        Some("ref" | "ref_or_null" | "fulltext") => "Index lookup".to_string(),
+        Some("eq_ref" | "const" | "system") => "Single-row index lookup".to_string(),
+        Some(other) => format!("Table access ({})", other),
+        None => "Table".to_string(),
+    }
+}
+
+/// `ordering_operation` -> `Ordering operation`
+fn humanize(key: &str) -> String {
+    let text = key.replace('_', " ");
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => text,
+    }
+}
+
+/// Parse the indented `EXPLAIN ANALYZE` tree:
+/// `-> Table scan on t  (cost=0.35 rows=1) (actual time=0.01..0.02 rows=1 loops=1)`
+fn parse_tree(output: &str) -> Vec<PlanNode> {
+    let mut stack: Vec<(usize, PlanNode)> = Vec::new();
+    let mut roots = Vec::new();
+
+    for line in output.lines() {
+        let trimmed = line.trim_start();
+        let Some(text) = trimmed.strip_prefix("-> ") else {
+            // Continuation of a multi-line condition
+            if let Some((_, node)) = stack.last_mut() {
+                let detail = node.detail.get_or_insert_with(String::new);
+                detail.push('\n');
+                detail.push_str(line);
+            }
+            continue;
+        };
+        let depth = line.len() - trimmed.len();
+        while stack.last().is_some_and(|(d, _)| *d >= depth) {
+            attach(&mut stack, &mut roots);
+        }
+        stack.push((depth, parse_tree_line(text)));
+    }
+    while !stack.is_empty() {
+        attach(&mut stack, &mut roots);
+    }
+    roots
+}
+
+/// Pop the innermost node and add it to its parent
+fn attach(stack: &mut Vec<(usize, PlanNode)>, roots: &mut Vec<PlanNode>) {
+    if let Some((_, node)) = stack.pop() {
+        match stack.last_mut() {
+            Some((_, parent)) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+}
+
+fn parse_tree_line(text: &str) -> PlanNode {
+    let metrics_start = [
+        "  (cost=",
+        " (cost=",
+        "  (actual",
+        " (actual",
+        " (never executed)",
+    ]
+    .iter()
+    .filter_map(|marker| text.find(marker))
+    .min()
+    .unwrap_or(text.len());
+    let description = text[..metrics_start].trim();
+
+    // "Filter: (a.id > 1)", "Table scan on t"
+    let split = [(": ", 2), (" on ", 1)]
+        .iter()
+        .filter_map(|(separator, skip)| description.find(separator).map(|i| (i, *skip)))
+        .min();
+    let mut node = match split {
+        Some((i, skip)) => {
+            let mut node = PlanNode::new(&description[..i]);
+            node.detail = Some(description[i + skip..].trim().to_string());
+            node
+        }
+        None => PlanNode::new(description),
+    };
+
+    for group in text[metrics_start..].split('(').skip(1) {
+        let group = group.trim_end().trim_end_matches(')');
+        let actual = group.starts_with("actual");
+        for pair in group.trim_start_matches("actual ").split_whitespace() {
+            let Some((key, value)) = pair.split_once('=') else {
+                continue;
+            };
+            let (from, to) = match value.split_once("..") {
+                Some((from, to)) => (from.parse().ok(), to.parse().ok()),
+                None => (None, value.parse().ok()),
+            };
+            match (actual, key) {
+                (false, "cost") => {
+                    node.startup_cost = from;
+                    node.total_cost = to;
+                }
+                (false, "rows") => node.estimated_rows = to,
+                (true, "time") => {
+                    node.actual_startup_time = from;
+                    node.actual_total_time = to;
+                }
+                (true, "rows") => node.actual_rows = to,
+                (true, "loops") => node.loops = to,
+                _ => {}
+            }
+        }
+    }
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parses_json_plan() {
+        let plan = json!({
+            "select_id": 1,
+            "cost_info": {"query_cost": "1.20"},
+            "nested_loop": [
+                {"table": {"table_name": "a", "access_type": "ALL",
+                           "rows_produced_per_join": 2,
+                           "cost_info": {"read_cost": "0.25", "prefix_cost": "0.45"}}},
+                {"table": {"table_name": "b", "access_type": "eq_ref", "key": "PRIMARY"}}
+            ]
+        });
+        let node = parse_json("query_block", &plan);
+        assert_eq!(node.node_type, "Query block");
+        assert_eq!(node.total_cost, Some(1.2));
+        assert_eq!(node.properties.get("select_id"), Some(&json!(1)));
+
+        let join = &node.children[0];
+        assert_eq!(join.node_type, "Nested loop");
+        assert_eq!(join.children[0].node_type, "Table scan");
+        assert_eq!(join.children[0].detail.as_deref(), Some("on a"));
+        assert_eq!(join.children[0].total_cost, Some(0.45));
+        assert_eq!(join.children[0].estimated_rows, Some(2.0));
+        assert_eq!(
+            join.children[1].detail.as_deref(),
+            Some("on b using PRIMARY")
+        );
+    }
+
+    #[test]
+    fn test_parses_analyze_tree() {
+        let output = "-> Nested loop inner join  (cost=0.70 rows=1) (actual time=0.030..0.033 rows=1 loops=1)\n    -> Filter: (a.id > 1)  (cost=0.35 rows=1) (actual time=0.015..0.017 rows=1 loops=1)\n        -> Table scan on a  (cost=0.35 rows=1) (actual time=0.01..0.012 rows=2 loops=1)\n    -> Single-row index lookup on b using PRIMARY (id=a.id)  (cost=0.35 rows=1) (never executed)\n";
+        let roots = parse_tree(output);
+        assert_eq!(roots.len(), 1);
+        let join = &roots[0];
+        assert_eq!(join.node_type, "Nested loop inner join");
+        assert_eq!(join.total_cost, Some(0.7));
+        assert_eq!(join.actual_total_time, Some(0.033));
+        assert_eq!(join.children.len(), 2);
+
+        let filter = &join.children[0];
+        assert_eq!(filter.node_type, "Filter");
+        assert_eq!(filter.detail.as_deref(), Some("(a.id > 1)"));
+        assert_eq!(filter.children[0].actual_rows, Some(2.0));
+
+        let lookup = &join.children[1];
+        assert_eq!(lookup.node_type, "Single-row index lookup");
+        assert_eq!(
+            lookup.detail.as_deref(),
+            Some("on b using PRIMARY (id=a.id)")
+        );
+        assert_eq!(lookup.loops, None);
+    }
+}
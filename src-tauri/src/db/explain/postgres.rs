@@ -0,0 +1,152 @@
+//! PostgreSQL plans from `EXPLAIN (FORMAT JSON)`
+
+use super::{json_number, ExplainOptions, PlanNode};
+use crate::commands::database::ExplainResult;
+use crate::error::VelocityError;
+use serde_json::{Map, Value};
+use sqlx::types::Json;
+use sqlx::{PgPool, Row};
+
+pub(super) async fn explain(
+    pool: &PgPool,
+    sql: &str,
+    options: ExplainOptions,
+) -> Result<ExplainResult, VelocityError> {
+    let explain_sql = if options.analyze {
+        format!("EXPLAIN (ANALYZE, BUFFERS, FORMAT JSON) {}", sql)
+    } else {
+        format!("EXPLAIN (FORMAT JSON) {}", sql)
+    };
+    let query = sqlx::query(&explain_sql);
+
+    let row = if options.rollback {
+        let mut tx = pool
+            .begin()
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+        let row = query.fetch_one(&mut *tx).await;
+        tx.rollback()
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+        row
+    } else {
+        query.fetch_one(pool).await
+    }
+    .map_err(|e| VelocityError::Query(e.to_string()))?;
+
+    let Json(output) = row
+        .try_get::<Json<Value>, _>(0)
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+    Ok(parse_output(&output))
+}
+
+/// Convert the JSON document (one entry per statement) into plan trees
+fn parse_output(output: &Value) -> ExplainResult {
+    let statements = output.as_array().map(Vec::as_slice).unwrap_or_default();
+    let sum = |key: &str| -> Option<f64> {
+        statements
+            .iter()
+            .filter_map(|s| json_number(s.get(key)))
+            .reduce(|a, b| a + b)
+    };
+    ExplainResult {
+        nodes: statements
+            .iter()
+            .filter_map(|s| s.get("Plan").and_then(Value::as_object))
+            .map(parse_node)
+            .collect(),
+        planning_time: sum("Planning Time"),
+        execution_time: sum("Execution Time"),
+        ..Default::default()
+    }
+}
+
+fn parse_node(plan: &Map<String, Value>) -> PlanNode {
+    let mut fields = plan.clone();
+    let mut take = |key: &str| fields.remove(key);
+
+    let mut node = PlanNode::new(
+        take("Node Type")
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_else(|| "Unknown".to_string()),
+    );
+    node.startup_cost = json_number(take("Startup Cost").as_ref());
+    node.total_cost = json_number(take("Total Cost").as_ref());
+    node.estimated_rows = json_number(take("Plan Rows").as_ref());
+    node.actual_startup_time = json_number(take("Actual Startup Time").as_ref());
+    node.actual_total_time = json_number(take("Actual Total Time").as_ref());
+    node.actual_rows = json_number(take("Actual Rows").as_ref());
+    node.loops = json_number(take("Actual Loops").as_ref());
+    node.children = match take("Plans") {
+        Some(Value::Array(children)) => children
+            .iter()
+            .filter_map(Value::as_object)
+            .map(parse_node)
+            .collect(),
+        _ => Vec::new(),
+    };
+
+    // Same wording as the text format: "using idx on users u"
+    let text = |key: &str| fields.get(key).and_then(Value::as_str);
+    let mut detail = Vec::new();
+    if let Some(index) = text("Index Name") {
+        detail.push(format!("using {}", index));
+    }
+    if let Some(target) = text("Relation Name")
+        .or_else(|| text("CTE Name"))
+        .or_else(|| text("Function Name"))
+    {
+        match text("Alias").filter(|alias| *alias != target) {
+            Some(alias) => detail.push(format!("on {} {}", target, alias)),
+            None => detail.push(format!("on {}", target)),
+        }
+    }
+    node.detail = (!detail.is_empty()).then(|| detail.join(" "));
+    node.properties = fields;
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parses_nested_plan() {
+        let output = json!([{
+            "Plan": {
+                "Node Type": "Nested Loop",
+                "Join Type": "Inner",
+                "Startup Cost": 0.15,
+                "Total Cost": 16.4,
+                "Plan Rows": 3,
+                "Actual Startup Time": 0.01,
+                "Actual Total Time": 0.02,
+                "Actual Rows": 2,
+                "Actual Loops": 1,
+                "Plans": [
+                    {"Node Type": "Seq Scan", "Relation Name": "users", "Alias": "u",
+                     "Total Cost": 1.0, "Plan Rows": 1},
+                    {"Node Type": "Index Scan", "Index Name": "orders_pkey",
+                     "Relation Name": "orders", "Alias": "orders", "Total Cost": 8.0}
+                ]
+            },
+            "Planning Time": 0.1,
+            "Execution Time": 0.05
+        }]);
+        let result = parse_output(&output);
+        assert_eq!(result.planning_time, Some(0.1));
+        assert_eq!(result.execution_time, Some(0.05));
+
+        let root = &result.nodes[0];
+        assert_eq!(root.node_type, "Nested Loop");
+        assert_eq!(root.total_cost, Some(16.4));
+        assert_eq!(root.loops, Some(1.0));
+        assert_eq!(root.properties.get("Join Type"), Some(&json!("Inner")));
+        assert_eq!(root.children[0].detail.as_deref(), Some("on users u"));
+        assert_eq!(
+            root.children[1].detail.as_deref(),
+            Some("using orders_pkey on orders")
+        );
+    }
+}
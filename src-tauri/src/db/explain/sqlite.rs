@@ -0,0 +1,112 @@
+//! SQLite plans from `EXPLAIN QUERY PLAN`
+//!
+//! SQLite reports one row per operation with the ID of its parent and no
+//! cost estimates.
+
+use super::{ExplainOptions, PlanNode};
+use crate::commands::database::ExplainResult;
+use crate::error::VelocityError;
+use sqlx::SqlitePool;
+
+/// Multi-word operations; anything else is named by its first word
+const OPERATIONS: &[&str] = &[
+    "CORRELATED SCALAR SUBQUERY",
+    "CORRELATED LIST SUBQUERY",
+    "SCALAR SUBQUERY",
+    "LIST SUBQUERY",
+    "LEFT-MOST SUBQUERY",
+    "USE TEMP B-TREE",
+    "COMPOUND QUERY",
+    "UNION USING TEMP B-TREE",
+    "UNION ALL",
+    "MULTI-INDEX OR",
+    "BLOOM FILTER",
+    "RIGHT-JOIN",
+];
+
+pub(super) async fn explain(
+    pool: &SqlitePool,
+    sql: &str,
+    options: ExplainOptions,
+) -> Result<ExplainResult, VelocityError> {
+    if options.analyze {
+        return Err(VelocityError::Query(
+            "EXPLAIN ANALYZE is not supported by SQLite".to_string(),
+        ));
+    }
+    let rows: Vec<(i64, i64, i64, String)> = sqlx::query_as(&format!("EXPLAIN QUERY PLAN {}", sql))
+        .fetch_all(pool)
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+    let rows: Vec<(i64, i64, String)> = rows
+        .into_iter()
+        .map(|(id, parent, _, detail)| (id, parent, detail))
+        .collect();
+    Ok(ExplainResult {
+        nodes: build_tree(&rows, 0),
+        ..Default::default()
+    })
+}
+
+/// Nodes whose parent is `parent`, in the order SQLite reported them
+fn build_tree(rows: &[(i64, i64, String)], parent: i64) -> Vec<PlanNode> {
+    rows.iter()
+        .filter(|(id, p, _)| *p == parent && *id != parent)
+        .map(|(id, _, detail)| {
+            let mut node = parse_detail(detail);
+            node.children = build_tree(rows, *id);
+            node
+        })
+        .collect()
+}
+
+/// Split `SEARCH users USING INDEX idx (id=?)` into operation and detail
+fn parse_detail(detail: &str) -> PlanNode {
+    let operation = OPERATIONS
+        .iter()
+        .find(|op| {
+            detail.starts_with(*op)
+                && detail[op.len()..]
+                    .chars()
+                    .next()
+                    .is_none_or(|c| c.is_whitespace())
+        })
+        .map(|op| op.to_string())
+        .unwrap_or_else(|| detail.split_whitespace().next().unwrap_or("").to_string());
+
+    let mut node = PlanNode::new(operation.clone());
+    let rest = detail[operation.len()..].trim();
+    node.detail = (!rest.is_empty()).then(|| rest.to_string());
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builds_tree_from_parent_ids() {
+        let rows = vec![
+            (2, 0, "SCAN users".to_string()),
+            (4, 0, "CORRELATED SCALAR SUBQUERY 1".to_string()),
+            (
+                8,
+                4,
+                "SEARCH orders USING INDEX idx_user (user_id=?)".to_string(),
+            ),
+            (20, 0, "USE TEMP B-TREE FOR ORDER BY".to_string()),
+        ];
+        let nodes = build_tree(&rows, 0);
+        assert_eq!(nodes.len(), 3);
+        assert_eq!(nodes[0].node_type, "SCAN");
+        assert_eq!(nodes[0].detail.as_deref(), Some("users"));
+        assert_eq!(nodes[1].node_type, "CORRELATED SCALAR SUBQUERY");
+        assert_eq!(nodes[1].children[0].node_type, "SEARCH");
+        assert_eq!(
+            nodes[1].children[0].detail.as_deref(),
+            Some("orders USING INDEX idx_user (user_id=?)")
+        );
+        assert_eq!(nodes[2].node_type, "USE TEMP B-TREE");
+        assert_eq!(nodes[2].detail.as_deref(), Some("FOR ORDER BY"));
+    }
+}
@@ -44,9 +44,20 @@ impl DatabaseFactory {
                     database
                 );
 
+                let read_only = connection.read_only;
                 let pool = sqlx::postgres::PgPoolOptions::new()
                     .max_connections(5)
                     .acquire_timeout(std::time::Duration::from_secs(10))
+                    .after_connect(move |conn, _meta| {
+                        Box::pin(async move {
+                            if read_only {
+                                sqlx::query("SET default_transaction_read_only = on")
+                                    .execute(conn)
+                                    .await?;
+                            }
+                            Ok(())
+                        })
+                    })
                     .connect(&url)
                     .await
                     .map_err(|e| VelocityError::Connection(e.to_string()))?;
@@ -96,11 +107,22 @@ impl DatabaseFactory {
                     }
                 }
 
+                let read_only = connection.read_only;
                 let pool = sqlx::mysql::MySqlPoolOptions::new()
                     .max_connections(5)
                     .min_connections(1) // Pre-establish at least one connection
                     .acquire_timeout(std::time::Duration::from_secs(120)) // 2 minutes for slow remote DBs
                     .idle_timeout(std::time::Duration::from_secs(600))
+                    .after_connect(move |conn, _meta| {
+                        Box::pin(async move {
+                            if read_only {
+                                sqlx::query("SET SESSION TRANSACTION READ ONLY")
+                                    .execute(conn)
+                                    .await?;
+                            }
+                            Ok(())
+                        })
+                    })
                     .connect_with(opts)
                     .await
                     .map_err(|e| VelocityError::Connection(e.to_string()))?;
@@ -110,9 +132,18 @@ impl DatabaseFactory {
             ConnectionConfig::SQLite { path } => {
                 let url = format!("sqlite:{}", path.display());
 
+                let read_only = connection.read_only;
                 let pool = sqlx::sqlite::SqlitePoolOptions::new()
                     .max_connections(5)
                     .acquire_timeout(std::time::Duration::from_secs(10))
+                    .after_connect(move |conn, _meta| {
+                        Box::pin(async move {
+                            if read_only {
+                                sqlx::query("PRAGMA query_only = ON").execute(conn).await?;
+                            }
+                            Ok(())
+                        })
+                    })
                     .connect(&url)
                     .await
                     .map_err(|e| VelocityError::Connection(e.to_string()))?;
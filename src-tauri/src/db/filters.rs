@@ -113,6 +113,14 @@ pub struct QueryOptions {
     /// Selecting fewer columns improves performance
     #[serde(default)]
     pub selected_columns: Option<Vec<String>>,
+
+    /// ID under which the fetch can be stopped with `cancel_query`
+    #[serde(default)]
+    pub query_id: Option<String>,
+
+    /// Statement timeout overriding the connection default (0 = no limit)
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
 }
 
 fn default_limit() -> i32 {
@@ -0,0 +1,216 @@
+//! Guardrails for protected connections
+//!
+//! On a connection marked as protected, statements that destroy data in bulk
+//! (DROP, TRUNCATE, UPDATE or DELETE without a WHERE clause) are refused with
+//! a description of what they would affect and a one-time confirmation
+//! token. Repeating the call with that token runs the statement.
+
+use crate::db::classify::{classify, StatementClass};
+use crate::db::dialect::SqlDialect;
+use crate::db::splitter::split_statements;
+use crate::db::{ConnectionPoolManager, DatabasePool};
+use crate::error::VelocityError;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// How long a confirmation token stays valid
+const CONFIRMATION_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// Row counts for the description are skipped when they take longer
+const COUNT_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct PendingConfirmation {
+    connection_id: String,
+    sql: String,
+    expires_at: Instant,
+}
+
+/// Confirmation tokens handed out for refused statements
+#[derive(Default)]
+pub struct ConfirmationRegistry {
+    pending: Mutex<HashMap<String, PendingConfirmation>>,
+}
+
+impl ConfirmationRegistry {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, PendingConfirmation>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn issue(&self, connection_id: &str, sql: &str) -> String {
+        let token = uuid::Uuid::new_v4().to_string();
+        let mut pending = self.lock();
+        let now = Instant::now();
+        pending.retain(|_, p| p.expires_at > now);
+        pending.insert(
+            token.clone(),
+            PendingConfirmation {
+                connection_id: connection_id.to_string(),
+                sql: sql.to_string(),
+                expires_at: now + CONFIRMATION_TTL,
+            },
+        );
+        token
+    }
+
+    /// Use up a token. True when it was issued for this statement on this
+    /// connection and has not expired.
+    fn redeem(&self, token: &str, connection_id: &str, sql: &str) -> bool {
+        self.lock().remove(token).is_some_and(|p| {
+            p.connection_id == connection_id && p.sql == sql && p.expires_at > Instant::now()
+        })
+    }
+}
+
+fn is_destructive(class: &StatementClass) -> bool {
+    matches!(class.command.as_str(), "DROP" | "TRUNCATE") || class.is_unbounded()
+}
+
+impl ConnectionPoolManager {
+    /// On protected connections, refuse DROP, TRUNCATE and unbounded
+    /// UPDATE/DELETE unless `confirmation_token` was returned by an earlier
+    /// refusal of the same SQL
+    pub async fn check_protected(
+        &self,
+        connection_id: &str,
+        sql: &str,
+        confirmation_token: Option<&str>,
+    ) -> Result<(), VelocityError> {
+        if !self.connection_settings(connection_id).await.protected {
+            return Ok(());
+        }
+        let Some(pool) = self.get_pool(connection_id).await else {
+            return Ok(());
+        };
+        let Some(dialect) = SqlDialect::from_pool(&pool) else {
+            return Ok(());
+        };
+
+        let destructive: Vec<(String, StatementClass)> = split_statements(sql, dialect)
+            .into_iter()
+            .map(|statement| {
+                let class = classify(&statement.sql, dialect);
+                (statement.sql, class)
+            })
+            .filter(|(_, class)| is_destructive(class))
+            .collect();
+        if destructive.is_empty() {
+            return Ok(());
+        }
+        if confirmation_token
+            .is_some_and(|token| self.confirmations.redeem(token, connection_id, sql))
+        {
+            return Ok(());
+        }
+
+        let mut effects = Vec::with_capacity(destructive.len());
+        for (statement, class) in &destructive {
+            effects.push(describe_effect(&pool, dialect, statement, class).await);
+        }
+        Err(VelocityError::ConfirmationRequired {
+            message: effects.join("; "),
+            token: self.confirmations.issue(connection_id, sql),
+        })
+    }
+}
+
+/// What a destructive statement would do, with current row counts
+async fn describe_effect(
+    pool: &DatabasePool,
+    dialect: SqlDialect,
+    statement: &str,
+    class: &StatementClass,
+) -> String {
+    if class.tables.is_empty() {
+        let action = match class.command.as_str() {
+            "DELETE" => "DELETE without a WHERE clause would delete every row it targets",
+            "UPDATE" => "UPDATE without a WHERE clause would update every row it targets",
+            "TRUNCATE" => "TRUNCATE would remove every row it targets",
+            _ => "DROP would remove the object",
+        };
+        return format!("{}: `{}`", action, statement);
+    }
+
+    let mut targets = Vec::with_capacity(class.tables.len());
+    for table in &class.tables {
+        let rows = count_rows(pool, dialect, table).await;
+        targets.push(match (class.command.as_str(), rows) {
+            ("DROP", Some(rows)) => format!("{} ({} rows)", table, rows),
+            ("DROP", None) => table.clone(),
+            (_, Some(rows)) => format!("all {} rows of {}", rows, table),
+            (_, None) => format!("all rows of {}", table),
+        });
+    }
+    let targets = targets.join(", ");
+    match class.command.as_str() {
+        "DELETE" => format!("DELETE without a WHERE clause would delete {}", targets),
+        "UPDATE" => format!("UPDATE without a WHERE clause would update {}", targets),
+        "TRUNCATE" => format!("TRUNCATE would remove {}", targets),
+        _ => format!("DROP would remove {}", targets),
+    }
+}
+
+/// Current number of rows in a table. None when it cannot be counted quickly.
+async fn count_rows(pool: &DatabasePool, dialect: SqlDialect, table: &str) -> Option<i64> {
+    let sql = format!("SELECT COUNT(*) FROM {}", quote_table(table, dialect));
+    let count = async {
+        match pool {
+            DatabasePool::Postgres(pool) => sqlx::query_scalar(&sql).fetch_one(pool).await.ok(),
+            DatabasePool::MySQL(pool) => sqlx::query_scalar(&sql).fetch_one(pool).await.ok(),
+            DatabasePool::SQLite(pool) => sqlx::query_scalar(&sql).fetch_one(pool).await.ok(),
+            _ => None,
+        }
+    };
+    tokio::time::timeout(COUNT_TIMEOUT, count)
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Quote each part of a dotted table name
+fn quote_table(table: &str, dialect: SqlDialect) -> String {
+    table
+        .split('.')
+        .map(|part| dialect.quote_identifier(part))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_destructive_statements() {
+        let pg = SqlDialect::Postgres;
+        assert!(is_destructive(&classify("delete from orders", pg)));
+        assert!(is_destructive(&classify("UPDATE t SET a = 1", pg)));
+        assert!(is_destructive(&classify("DROP INDEX idx", pg)));
+        assert!(is_destructive(&classify("truncate t", pg)));
+        assert!(!is_destructive(&classify(
+            "DELETE FROM orders WHERE id = 1",
+            pg
+        )));
+        assert!(!is_destructive(&classify("ALTER TABLE t ADD c int", pg)));
+    }
+
+    #[test]
+    fn test_tokens_are_single_use_and_bound_to_the_statement() {
+        let registry = ConfirmationRegistry::default();
+        let token = registry.issue("c1", "DELETE FROM t");
+        assert!(!registry.redeem(&token, "c1", "DELETE FROM t2"));
+
+        let token = registry.issue("c1", "DELETE FROM t");
+        assert!(registry.redeem(&token, "c1", "DELETE FROM t"));
+        assert!(!registry.redeem(&token, "c1", "DELETE FROM t"));
+    }
+
+    #[test]
+    fn test_quotes_table_names() {
+        assert_eq!(
+            quote_table("app.my\"t", SqlDialect::Postgres),
+            "\"app\".\"my\"\"t\""
+        );
+        assert_eq!(quote_table("orders", SqlDialect::MySql), "`orders`");
+    }
+}
@@ -0,0 +1,223 @@
+//! Row identity for editing
+//!
+//! Edited rows are matched by their primary key. Tables without one fall
+//! back to the physical row location, `ctid` on PostgreSQL and `rowid` on
+//! SQLite, and on MySQL or for views to the values of all columns. The
+//! fetch paths return the key of every row so the grid can send it back
+//! with its changes.
+
+use crate::commands::database::RowKey;
+use crate::db::dialect::SqlDialect;
+use crate::db::pool::{ColumnInfo, DatabasePool};
+use crate::error::VelocityError;
+use serde_json::Value;
+
+/// How the rows of a table are identified
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RowIdentity {
+    /// The primary key columns
+    #[default]
+    PrimaryKey,
+    /// PostgreSQL row location; it changes whenever the row is updated
+    Ctid,
+    /// SQLite rowid
+    Rowid,
+    /// The values of all columns. Edits refuse to apply when more than one
+    /// row matches.
+    FullRow,
+}
+
+impl RowIdentity {
+    /// Column the identity is returned as, next to the table's own columns
+    pub(crate) fn select_expr(self) -> Option<&'static str> {
+        match self {
+            RowIdentity::Ctid => Some("ctid::text AS ctid"),
+            RowIdentity::Rowid => Some("rowid AS rowid"),
+            RowIdentity::PrimaryKey | RowIdentity::FullRow => None,
+        }
+    }
+}
+
+/// Identity and key columns of a table
+#[derive(Debug, Clone, Default)]
+pub struct TableKey {
+    pub identity: RowIdentity,
+    pub columns: Vec<String>,
+}
+
+impl TableKey {
+    /// Key of every fetched row. `row_ids` holds the selected `ctid` or
+    /// `rowid` values. None when a key column was not fetched.
+    pub(crate) fn row_keys(
+        &self,
+        column_names: &[String],
+        rows: &[Vec<Value>],
+        row_ids: Option<Vec<Value>>,
+    ) -> Option<Vec<RowKey>> {
+        if let Some(row_ids) = row_ids {
+            let column = self.columns.first()?;
+            return Some(
+                row_ids
+                    .into_iter()
+                    .map(|id| RowKey::from_iter([(column.clone(), id)]))
+                    .collect(),
+            );
+        }
+        let indexes: Vec<usize> = self
+            .columns
+            .iter()
+            .map(|column| column_names.iter().position(|name| name == column))
+            .collect::<Option<_>>()?;
+        if indexes.is_empty() {
+            return None;
+        }
+        Some(
+            rows.iter()
+                .map(|row| {
+                    self.columns
+                        .iter()
+                        .zip(&indexes)
+                        .map(|(column, &i)| {
+                            (column.clone(), row.get(i).cloned().unwrap_or(Value::Null))
+                        })
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Work out how rows of a table are identified from its columns
+pub(crate) async fn resolve_table_key(
+    pool: &DatabasePool,
+    table_name: &str,
+    columns: &[ColumnInfo],
+) -> Result<TableKey, VelocityError> {
+    let primary_key: Vec<String> = columns
+        .iter()
+        .filter(|c| c.is_primary_key)
+        .map(|c| c.name.clone())
+        .collect();
+    if !primary_key.is_empty() {
+        return Ok(TableKey {
+            identity: RowIdentity::PrimaryKey,
+            columns: primary_key,
+        });
+    }
+
+    // Views and partitioned tables have no usable row location
+    let identity = match pool {
+        DatabasePool::Postgres(pool) => {
+            let kind: Option<String> = sqlx::query_scalar(
+                "SELECT relkind::text FROM pg_class WHERE oid = to_regclass($1)",
+            )
+            .bind(SqlDialect::Postgres.quote_identifier(table_name))
+            .fetch_optional(pool)
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+            match kind.as_deref() {
+                Some("r") => RowIdentity::Ctid,
+                _ => RowIdentity::FullRow,
+            }
+        }
+        DatabasePool::SQLite(pool) => {
+            let kind: Option<String> =
+                sqlx::query_scalar("SELECT type FROM sqlite_master WHERE name = ?")
+                    .bind(table_name)
+                    .fetch_optional(pool)
+                    .await
+                    .map_err(|e| VelocityError::Query(e.to_string()))?;
+            let has_rowid_column = columns.iter().any(|c| c.name.eq_ignore_ascii_case("rowid"));
+            match kind.as_deref() {
+                Some("table") if !has_rowid_column => RowIdentity::Rowid,
+                _ => RowIdentity::FullRow,
+            }
+        }
+        _ => RowIdentity::FullRow,
+    };
+    let columns = match identity {
+        RowIdentity::Ctid => vec!["ctid".to_string()],
+        RowIdentity::Rowid => vec!["rowid".to_string()],
+        _ => columns.iter().map(|c| c.name.clone()).collect(),
+    };
+    Ok(TableKey { identity, columns })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::pool::metadata::get_table_schema;
+    use serde_json::json;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn test_row_keys_from_key_columns() {
+        let key = TableKey {
+            identity: RowIdentity::PrimaryKey,
+            columns: names(&["tenant_id", "id"]),
+        };
+        let rows = vec![vec![json!(7), json!("a"), json!(1)]];
+        let keys = key
+            .row_keys(&names(&["id", "name", "tenant_id"]), &rows, None)
+            .unwrap();
+        assert_eq!(
+            Value::Object(keys[0].clone()),
+            json!({"tenant_id": 1, "id": 7})
+        );
+
+        // A key column that was not fetched leaves the rows without keys
+        assert!(key.row_keys(&names(&["id", "name"]), &rows, None).is_none());
+    }
+
+    #[test]
+    fn test_row_keys_from_row_ids() {
+        let key = TableKey {
+            identity: RowIdentity::Ctid,
+            columns: names(&["ctid"]),
+        };
+        let keys = key
+            .row_keys(
+                &names(&["name"]),
+                &[vec![json!("a")]],
+                Some(vec![json!("(0,1)")]),
+            )
+            .unwrap();
+        assert_eq!(Value::Object(keys[0].clone()), json!({"ctid": "(0,1)"}));
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_tables_without_primary_key() {
+        let sqlite = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE plain(name TEXT);
+             CREATE TABLE shadowed(rowid TEXT, name TEXT);
+             CREATE VIEW names AS SELECT name FROM plain",
+        )
+        .execute(&sqlite)
+        .await
+        .unwrap();
+        let pool = DatabasePool::SQLite(sqlite);
+
+        let mut keys = Vec::new();
+        for table in ["plain", "shadowed", "names"] {
+            let columns = get_table_schema(&pool, table).await.unwrap();
+            keys.push(resolve_table_key(&pool, table, &columns).await.unwrap());
+        }
+        assert_eq!(keys[0].identity, RowIdentity::Rowid);
+        assert_eq!(keys[0].columns, names(&["rowid"]));
+        // A column named rowid hides the real one
+        assert_eq!(keys[1].identity, RowIdentity::FullRow);
+        assert_eq!(keys[1].columns, names(&["rowid", "name"]));
+        assert_eq!(keys[2].identity, RowIdentity::FullRow);
+        assert_eq!(keys[2].columns, names(&["name"]));
+    }
+}
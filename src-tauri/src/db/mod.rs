@@ -1,13 +1,38 @@
+pub mod cancel;
+pub mod classify;
+pub mod columns;
+pub mod decode;
+pub mod dialect;
+pub mod explain;
 pub mod factory;
 pub mod filters;
+pub mod guard;
+pub mod identity;
+pub mod params;
 pub mod pool;
 pub mod query;
 pub mod schema_ops;
+pub mod script;
+pub mod session;
+pub mod splitter;
+pub mod stream;
 pub mod table_data;
+pub mod timeout;
+pub mod tracked;
+pub mod undo;
 
+pub use classify::{StatementClass, StatementKind};
+pub use columns::ResultColumn;
+pub use dialect::SqlDialect;
+pub use explain::{ExplainOptions, PlanNode};
 pub use filters::{
     ColumnFilter, FilterLogic, FilterOperator, QueryOptions, SortConfig, SortDirection,
 };
+pub use identity::{RowIdentity, TableKey};
+pub use query::ExecuteOptions;
 pub use pool::{ColumnInfo, ConnectionPoolManager, DatabasePool, TableData};
 pub use schema_ops::{ColumnDefinition, CreateTableRequest, ForeignKeyDefinition, IndexInfo};
+pub use script::{ScriptResult, StatementResult};
+pub use session::TransactionInfo;
+pub use stream::{QueryStreamEvent, StreamOptions};
 pub use table_data::TableDataResponse;
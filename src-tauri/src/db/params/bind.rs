@@ -0,0 +1,322 @@
+//! Binding parameter values through sqlx
+//!
+//! MySQL and SQLite convert loosely typed parameters on the server, so values
+//! are bound as parsed. PostgreSQL requires the binary value to match the
+//! parameter type exactly: the statement is described first and every value
+//! is converted to the type the server inferred. Types sqlx cannot bind
+//! natively (numeric, interval, enums, ...) are sent as text and cast.
+
+use super::placeholders::parse_placeholders;
+use super::value::{
+    display_name, parse_bool, parse_datetime, parse_time, resolve_params, BoundValue,
+};
+use super::QueryParam;
+use crate::db::dialect::SqlDialect;
+use crate::error::VelocityError;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use sqlx::mysql::{MySql, MySqlArguments};
+use sqlx::postgres::{PgArguments, PgTypeInfo, Postgres};
+use sqlx::query::Query;
+use sqlx::sqlite::{Sqlite, SqliteArguments};
+use sqlx::types::Json;
+use sqlx::{Either, Executor, TypeInfo};
+
+/// A statement rewritten to native placeholders with the values to bind, in
+/// placeholder order
+pub struct BoundStatement<V> {
+    pub sql: String,
+    pub values: Vec<V>,
+}
+
+impl<V> BoundStatement<V> {
+    fn raw(sql: &str) -> Self {
+        Self {
+            sql: sql.to_string(),
+            values: Vec::new(),
+        }
+    }
+}
+
+/// Prepare a MySQL or SQLite statement. Without parameters the SQL is
+/// passed through untouched.
+pub fn bind_loose(
+    sql: &str,
+    params: &[QueryParam],
+    dialect: SqlDialect,
+) -> Result<BoundStatement<BoundValue>, VelocityError> {
+    if params.is_empty() {
+        return Ok(BoundStatement::raw(sql));
+    }
+    let parsed = parse_placeholders(sql, dialect)?;
+    let values = resolve_params(&parsed.params, params)?;
+    Ok(BoundStatement {
+        sql: parsed.sql(),
+        values: parsed.binding_order(&values).into_iter().cloned().collect(),
+    })
+}
+
+/// A value converted to the Rust type of a PostgreSQL parameter
+#[derive(Debug, Clone)]
+pub enum PgBind {
+    Bool(Option<bool>),
+    Int2(Option<i16>),
+    Int4(Option<i32>),
+    Int8(Option<i64>),
+    Float4(Option<f32>),
+    Float8(Option<f64>),
+    Text(Option<String>),
+    Uuid(Option<uuid::Uuid>),
+    Json(Option<Json<serde_json::Value>>),
+    Date(Option<NaiveDate>),
+    Time(Option<NaiveTime>),
+    Timestamp(Option<NaiveDateTime>),
+    Timestamptz(Option<DateTime<Utc>>),
+    Bytes(Option<Vec<u8>>),
+}
+
+/// Prepare a PostgreSQL statement on `conn`, converting every value to the
+/// parameter type the server infers
+pub async fn bind_postgres(
+    conn: &mut sqlx::PgConnection,
+    sql: &str,
+    params: &[QueryParam],
+) -> Result<BoundStatement<PgBind>, VelocityError> {
+    if params.is_empty() {
+        return Ok(BoundStatement::raw(sql));
+    }
+    let parsed = parse_placeholders(sql, SqlDialect::Postgres)?;
+    let values = resolve_params(&parsed.params, params)?;
+    let types = postgres_param_types(conn, &parsed.sql()).await?;
+
+    let mut casts = Vec::with_capacity(values.len());
+    let mut bound = Vec::with_capacity(values.len());
+    for ((name, value), type_info) in parsed.params.iter().zip(values).zip(&types) {
+        let (bind, cast) = to_pg(value, type_info).map_err(|e| {
+            VelocityError::Query(format!("Parameter {}: {}", display_name(name), e))
+        })?;
+        casts.push(cast);
+        bound.push(bind);
+    }
+    Ok(BoundStatement {
+        sql: parsed.render(&casts),
+        values: bound,
+    })
+}
+
+/// Parameter types PostgreSQL infers for a statement
+pub(crate) async fn postgres_param_types(
+    conn: &mut sqlx::PgConnection,
+    sql: &str,
+) -> Result<Vec<PgTypeInfo>, VelocityError> {
+    let described = conn
+        .describe(sql)
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+    Ok(match described.parameters() {
+        Some(Either::Left(types)) => types.to_vec(),
+        _ => Vec::new(),
+    })
+}
+
+/// Convert a value to the Rust type of a PostgreSQL parameter. Returns the
+/// cast target when the value has to be sent as text.
+fn to_pg(value: BoundValue, type_info: &PgTypeInfo) -> Result<(PgBind, Option<String>), String> {
+    to_pg_type(
+        value,
+        type_info.oid().map_or(0, |oid| oid.0),
+        type_info.name(),
+    )
+}
+
+/// Convert a value to the PostgreSQL type with `oid`, named `type_name`.
+/// Types without a native binding are sent as text and cast to `type_name`.
+pub(super) fn to_pg_type(
+    value: BoundValue,
+    oid: u32,
+    type_name: &str,
+) -> Result<(PgBind, Option<String>), String> {
+    let null = value == BoundValue::Null;
+    let expected = || format!("cannot convert {:?} to {}", value, type_name);
+    let text = value.to_text();
+
+    let bind = match oid {
+        16 => PgBind::Bool(match &value {
+            BoundValue::Bool(b) => Some(*b),
+            BoundValue::Int(i) => Some(*i != 0),
+            _ => text
+                .as_deref()
+                .map(|t| parse_bool(t).ok_or_else(expected))
+                .transpose()?,
+        }),
+        20 => PgBind::Int8(to_int(&value).map_err(|_| expected())?),
+        21 => PgBind::Int2(
+            to_int(&value)
+                .map_err(|_| expected())?
+                .map(i16::try_from)
+                .transpose()
+                .map_err(|_| expected())?,
+        ),
+        23 => PgBind::Int4(
+            to_int(&value)
+                .map_err(|_| expected())?
+                .map(i32::try_from)
+                .transpose()
+                .map_err(|_| expected())?,
+        ),
+        700 => PgBind::Float4(to_float(&value).map_err(|_| expected())?.map(|f| f as f32)),
+        701 => PgBind::Float8(to_float(&value).map_err(|_| expected())?),
+        // text, varchar, bpchar, name
+        25 | 1043 | 1042 | 19 => PgBind::Text(text),
+        2950 => PgBind::Uuid(match &value {
+            BoundValue::Uuid(u) => Some(*u),
+            _ => text
+                .as_deref()
+                .map(|t| uuid::Uuid::parse_str(t.trim()).map_err(|_| expected()))
+                .transpose()?,
+        }),
+        114 | 3802 => PgBind::Json((!null).then(|| {
+            Json(match &value {
+                BoundValue::Json(v) => v.clone(),
+                BoundValue::Text(s) => {
+                    serde_json::from_str(s).unwrap_or_else(|_| serde_json::Value::String(s.clone()))
+                }
+                BoundValue::Bool(b) => serde_json::Value::Bool(*b),
+                BoundValue::Int(i) => serde_json::Value::from(*i),
+                BoundValue::Float(f) => serde_json::Value::from(*f),
+                other => serde_json::Value::String(other.to_text().unwrap_or_default()),
+            })
+        })),
+        1082 => PgBind::Date(match &value {
+            BoundValue::Date(d) => Some(*d),
+            BoundValue::DateTime(dt) => Some(dt.date()),
+            _ => text
+                .as_deref()
+                .map(|t| NaiveDate::parse_from_str(t.trim(), "%Y-%m-%d").map_err(|_| expected()))
+                .transpose()?,
+        }),
+        1083 => PgBind::Time(match &value {
+            BoundValue::Time(t) => Some(*t),
+            _ => text
+                .as_deref()
+                .map(|t| parse_time(t.trim()).ok_or_else(expected))
+                .transpose()?,
+        }),
+        1114 | 1184 => {
+            let timestamp = match &value {
+                BoundValue::DateTime(dt) => Some(*dt),
+                BoundValue::Date(d) => d.and_hms_opt(0, 0, 0),
+                _ => text
+                    .as_deref()
+                    .map(|t| parse_datetime(t.trim()).ok_or_else(expected))
+                    .transpose()?,
+            };
+            if oid == 1114 {
+                PgBind::Timestamp(timestamp)
+            } else {
+                PgBind::Timestamptz(
+                    timestamp.map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc)),
+                )
+            }
+        }
+        17 => PgBind::Bytes(match value {
+            BoundValue::Bytes(b) => Some(b),
+            _ => text.map(String::into_bytes),
+        }),
+        _ => return Ok((PgBind::Text(text), Some(type_name.to_string()))),
+    };
+    Ok((bind, None))
+}
+
+fn to_int(value: &BoundValue) -> Result<Option<i64>, ()> {
+    match value {
+        BoundValue::Null => Ok(None),
+        BoundValue::Int(i) => Ok(Some(*i)),
+        BoundValue::Bool(b) => Ok(Some(i64::from(*b))),
+        BoundValue::Float(f) if f.fract() == 0.0 => Ok(Some(*f as i64)),
+        BoundValue::Text(s) | BoundValue::Decimal(s) => s.trim().parse().map(Some).map_err(|_| ()),
+        _ => Err(()),
+    }
+}
+
+fn to_float(value: &BoundValue) -> Result<Option<f64>, ()> {
+    match value {
+        BoundValue::Null => Ok(None),
+        BoundValue::Int(i) => Ok(Some(*i as f64)),
+        BoundValue::Float(f) => Ok(Some(*f)),
+        BoundValue::Text(s) | BoundValue::Decimal(s) => s.trim().parse().map(Some).map_err(|_| ()),
+        _ => Err(()),
+    }
+}
+
+/// Bind converted values to a PostgreSQL query
+pub fn apply_postgres<'q>(
+    mut query: Query<'q, Postgres, PgArguments>,
+    values: Vec<PgBind>,
+) -> Query<'q, Postgres, PgArguments> {
+    for value in values {
+        query = match value {
+            PgBind::Bool(v) => query.bind(v),
+            PgBind::Int2(v) => query.bind(v),
+            PgBind::Int4(v) => query.bind(v),
+            PgBind::Int8(v) => query.bind(v),
+            PgBind::Float4(v) => query.bind(v),
+            PgBind::Float8(v) => query.bind(v),
+            PgBind::Text(v) => query.bind(v),
+            PgBind::Uuid(v) => query.bind(v),
+            PgBind::Json(v) => query.bind(v),
+            PgBind::Date(v) => query.bind(v),
+            PgBind::Time(v) => query.bind(v),
+            PgBind::Timestamp(v) => query.bind(v),
+            PgBind::Timestamptz(v) => query.bind(v),
+            PgBind::Bytes(v) => query.bind(v),
+        };
+    }
+    query
+}
+
+/// Bind parsed values to a MySQL query
+pub fn apply_mysql<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    values: Vec<BoundValue>,
+) -> Query<'q, MySql, MySqlArguments> {
+    for value in values {
+        query = match value {
+            BoundValue::Null => query.bind(None::<String>),
+            BoundValue::Bool(b) => query.bind(b),
+            BoundValue::Int(i) => query.bind(i),
+            BoundValue::Float(f) => query.bind(f),
+            BoundValue::Text(s) | BoundValue::Decimal(s) => query.bind(s),
+            BoundValue::Date(d) => query.bind(d),
+            BoundValue::Time(t) => query.bind(t),
+            BoundValue::DateTime(dt) => query.bind(dt),
+            BoundValue::Json(v) => query.bind(Json(v)),
+            // MySQL has no UUID type; UUIDs are usually stored as text
+            BoundValue::Uuid(u) => query.bind(u.to_string()),
+            BoundValue::Bytes(b) => query.bind(b),
+        };
+    }
+    query
+}
+
+/// Bind parsed values to a SQLite query
+pub fn apply_sqlite<'q>(
+    mut query: Query<'q, Sqlite, SqliteArguments<'q>>,
+    values: Vec<BoundValue>,
+) -> Query<'q, Sqlite, SqliteArguments<'q>> {
+    for value in values {
+        query = match value {
+            BoundValue::Null => query.bind(None::<String>),
+            BoundValue::Bool(b) => query.bind(b),
+            BoundValue::Int(i) => query.bind(i),
+            BoundValue::Float(f) => query.bind(f),
+            BoundValue::Text(s) | BoundValue::Decimal(s) => query.bind(s),
+            BoundValue::Date(d) => query.bind(d),
+            BoundValue::Time(t) => query.bind(t),
+            BoundValue::DateTime(dt) => query.bind(dt),
+            BoundValue::Json(v) => query.bind(Json(v)),
+            BoundValue::Uuid(u) => query.bind(u.to_string()),
+            BoundValue::Bytes(b) => query.bind(b),
+        };
+    }
+    query
+}
@@ -0,0 +1,377 @@
+//! Values of table columns
+//!
+//! Grid edits arrive as the JSON the decoders produced. They are converted
+//! by the declared type of their column, as reported by `get_table_schema`,
+//! and bound like statement parameters: a text key `"007"` stays text, base64
+//! is decoded into bytes, and PostgreSQL types without a native binding
+//! (numeric, enums, arrays, ...) are sent as text and cast to the column type.
+//! SQLite is dynamically typed, so values are bound as they come, except in
+//! BLOB columns whose cells the decoder turned into base64.
+
+use super::bind::{to_pg_type, PgBind};
+use super::value::{BoundValue, ParamType};
+use crate::db::dialect::SqlDialect;
+use crate::db::pool::metadata::get_table_schema;
+use crate::db::DatabasePool;
+use crate::error::VelocityError;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Parameter type of a column by its declared type. None for types whose
+/// values are bound as they come: text, enums, arrays, ...
+pub fn column_param_type(data_type: &str) -> Option<ParamType> {
+    let name = data_type.to_ascii_lowercase();
+    // `varchar(20)`, `decimal(10,2)`, `int unsigned`
+    let base = name.split(['(', ' ']).next().unwrap_or_default();
+    Some(match name.as_str() {
+        "double precision" => ParamType::Float,
+        "time without time zone" => ParamType::Time,
+        "timestamp without time zone" | "timestamp with time zone" => ParamType::DateTime,
+        _ => match base {
+            "smallint" | "integer" | "int" | "bigint" | "tinyint" | "mediumint" | "year" => {
+                ParamType::Integer
+            }
+            "real" | "float" | "double" => ParamType::Float,
+            "numeric" | "decimal" => ParamType::Decimal,
+            "boolean" | "bool" => ParamType::Boolean,
+            "date" => ParamType::Date,
+            "time" if name == "time" => ParamType::Time,
+            "timestamp" | "datetime" if !name.contains("time zone") => ParamType::DateTime,
+            "json" | "jsonb" => ParamType::Json,
+            "uuid" => ParamType::Uuid,
+            "bytea" | "binary" | "varbinary" | "tinyblob" | "blob" | "mediumblob" | "longblob" => {
+                ParamType::Binary
+            }
+            _ => return None,
+        },
+    })
+}
+
+/// Parse a value of a column with the declared type `data_type`
+pub fn column_value(
+    dialect: SqlDialect,
+    data_type: &str,
+    value: &Value,
+) -> Result<BoundValue, String> {
+    let param_type = match dialect {
+        SqlDialect::Sqlite if data_type.to_ascii_lowercase().contains("blob") => {
+            Some(ParamType::Binary)
+        }
+        SqlDialect::Sqlite => None,
+        _ => column_param_type(data_type),
+    };
+    match (param_type, value) {
+        // MySQL booleans are TINYINT(1)
+        (Some(ParamType::Integer), Value::Bool(b)) => Ok(BoundValue::Int(i64::from(*b))),
+        _ => BoundValue::parse(value, param_type),
+    }
+}
+
+/// OID of a built-in PostgreSQL type sqlx binds natively, by the name
+/// `information_schema` reports
+fn pg_type_oid(data_type: &str) -> Option<u32> {
+    Some(match data_type {
+        "boolean" => 16,
+        "bytea" => 17,
+        "name" => 19,
+        "bigint" => 20,
+        "smallint" => 21,
+        "integer" => 23,
+        "text" => 25,
+        "json" => 114,
+        "real" => 700,
+        "double precision" => 701,
+        "character" => 1042,
+        "character varying" => 1043,
+        "date" => 1082,
+        "time without time zone" => 1083,
+        "timestamp without time zone" => 1114,
+        "timestamp with time zone" => 1184,
+        "uuid" => 2950,
+        "jsonb" => 3802,
+        _ => return None,
+    })
+}
+
+/// Convert a value of a PostgreSQL column. Returns the cast target when the
+/// value is sent as text.
+pub fn pg_column_value(data_type: &str, value: &Value) -> Result<(PgBind, Option<String>), String> {
+    if let (Value::Array(_), true) = (value, data_type.ends_with("[]")) {
+        return Ok((
+            PgBind::Text(Some(pg_array_literal(value))),
+            Some(data_type.to_string()),
+        ));
+    }
+    let bound = column_value(SqlDialect::Postgres, data_type, value)?;
+    to_pg_type(bound, pg_type_oid(data_type).unwrap_or(0), data_type)
+}
+
+/// PostgreSQL array literal of a JSON array, e.g. `{1,"a b",NULL}`
+fn pg_array_literal(value: &Value) -> String {
+    let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+    match value {
+        Value::Array(items) => {
+            let items: Vec<String> = items.iter().map(pg_array_literal).collect();
+            format!("{{{}}}", items.join(","))
+        }
+        Value::Null => "NULL".to_string(),
+        Value::Bool(_) | Value::Number(_) => value.to_string(),
+        Value::String(s) => quote(s),
+        Value::Object(_) => quote(&value.to_string()),
+    }
+}
+
+/// Declared types of the columns of a table
+pub struct ColumnTypes {
+    dialect: SqlDialect,
+    types: HashMap<String, String>,
+}
+
+impl ColumnTypes {
+    pub async fn load(
+        pool: &DatabasePool,
+        dialect: SqlDialect,
+        table_name: &str,
+    ) -> Result<Self, VelocityError> {
+        let mut types: HashMap<String, String> = get_table_schema(pool, table_name)
+            .await?
+            .into_iter()
+            .map(|column| (column.name, column.data_type))
+            .collect();
+        // Row locations that stand in for a missing primary key
+        let (row_id, row_id_type) = match dialect {
+            SqlDialect::Postgres => ("ctid", "tid"),
+            SqlDialect::Sqlite => ("rowid", "integer"),
+            SqlDialect::MySql => return Ok(Self { dialect, types }),
+        };
+        types
+            .entry(row_id.to_string())
+            .or_insert_with(|| row_id_type.to_string());
+        Ok(Self { dialect, types })
+    }
+
+    /// Build a statement whose SQL binds its values through `params`
+    pub fn statement(&self, build: impl FnOnce(&mut Params<'_>) -> String) -> Statement {
+        let mut params = Params::new(self);
+        let sql = build(&mut params);
+        params.finish(sql)
+    }
+}
+
+/// Values bound to a statement, converted by the type of their column
+pub struct Params<'a> {
+    types: &'a ColumnTypes,
+    args: StatementArgs,
+    given: Vec<Value>,
+    error: Option<String>,
+}
+
+impl<'a> Params<'a> {
+    pub fn new(types: &'a ColumnTypes) -> Self {
+        let args = match types.dialect {
+            SqlDialect::Postgres => StatementArgs::Postgres(Vec::new()),
+            SqlDialect::MySql | SqlDialect::Sqlite => StatementArgs::Loose(Vec::new()),
+        };
+        Self {
+            types,
+            args,
+            given: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn dialect(&self) -> SqlDialect {
+        self.types.dialect
+    }
+
+    /// Bind a value of `column`, returning its placeholder. Placeholders
+    /// must appear in the SQL in the order they were bound.
+    pub fn bind(&mut self, column: &str, value: &Value) -> String {
+        self.given.push(value.clone());
+        let position = self.given.len();
+        let Some(data_type) = self.types.types.get(column) else {
+            self.fail(column, "unknown column".to_string());
+            return "NULL".to_string();
+        };
+        match &mut self.args {
+            StatementArgs::Postgres(values) => match pg_column_value(data_type, value) {
+                Ok((bind, cast)) => {
+                    values.push(bind);
+                    match cast {
+                        Some(cast) => format!("${}::{}", position, cast),
+                        None => format!("${}", position),
+                    }
+                }
+                Err(e) => {
+                    self.fail(column, e);
+                    format!("${}", position)
+                }
+            },
+            StatementArgs::Loose(values) => {
+                match column_value(self.types.dialect, data_type, value) {
+                    Ok(bound) => values.push(bound),
+                    Err(e) => self.fail(column, e),
+                }
+                "?".to_string()
+            }
+        }
+    }
+
+    /// The quoted `column` and the placeholder of `value`, ready to compare.
+    /// PostgreSQL has no equality for `json`, so both sides become `jsonb`.
+    pub fn comparable(&mut self, column: &str, value: &Value) -> (String, String) {
+        let dialect = self.dialect();
+        let quoted = dialect.quote_identifier(column);
+        let placeholder = self.bind(column, value);
+        let is_json = self.types.types.get(column).is_some_and(|t| t == "json");
+        if dialect == SqlDialect::Postgres && is_json {
+            (
+                format!("{}::jsonb", quoted),
+                format!("{}::jsonb", placeholder),
+            )
+        } else {
+            (quoted, placeholder)
+        }
+    }
+
+    /// Condition that `column` holds `value`, NULL included
+    pub fn holds(&mut self, column: &str, value: &Value) -> String {
+        let (left, right) = self.comparable(column, value);
+        self.dialect().null_safe_eq(&left, &right)
+    }
+
+    fn fail(&mut self, column: &str, error: String) {
+        self.error
+            .get_or_insert_with(|| format!("Column {}: {}", column, error));
+    }
+
+    /// The statement with the bound values
+    pub fn finish(self, sql: String) -> Statement {
+        Statement {
+            sql,
+            args: self.args,
+            given: self.given,
+            error: self.error,
+        }
+    }
+}
+
+/// Converted values of a statement
+#[derive(Debug, Clone)]
+pub enum StatementArgs {
+    Postgres(Vec<PgBind>),
+    /// MySQL and SQLite
+    Loose(Vec<BoundValue>),
+}
+
+/// A statement and the values bound to its placeholders
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub sql: String,
+    pub args: StatementArgs,
+    /// The values as given, for display
+    given: Vec<Value>,
+    /// First value that could not be converted
+    error: Option<String>,
+}
+
+impl Statement {
+    /// Fails when a value could not be converted to its column type; the
+    /// statement cannot be run then
+    pub fn check(&self) -> Result<(), String> {
+        match &self.error {
+            Some(error) => Err(error.clone()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The SQL followed by the bound values, e.g. `... WHERE "id" = $1 -- [7]`
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.given.is_empty() {
+            return f.write_str(&self.sql);
+        }
+        write!(f, "{} -- {}", self.sql, Value::Array(self.given.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_column_param_types() {
+        assert_eq!(column_param_type("integer"), Some(ParamType::Integer));
+        assert_eq!(column_param_type("int unsigned"), Some(ParamType::Integer));
+        assert_eq!(column_param_type("decimal(10,2)"), Some(ParamType::Decimal));
+        assert_eq!(
+            column_param_type("timestamp with time zone"),
+            Some(ParamType::DateTime)
+        );
+        assert_eq!(column_param_type("time with time zone"), None);
+        assert_eq!(column_param_type("character varying"), None);
+        assert_eq!(column_param_type("mood"), None);
+    }
+
+    #[test]
+    fn test_text_keys_stay_text() {
+        assert_eq!(
+            column_value(SqlDialect::MySql, "varchar", &json!("007")),
+            Ok(BoundValue::Text("007".to_string()))
+        );
+        assert_eq!(
+            column_value(SqlDialect::MySql, "tinyint", &json!(true)),
+            Ok(BoundValue::Int(1))
+        );
+        assert_eq!(
+            column_value(SqlDialect::MySql, "blob", &json!("AP8=")),
+            Ok(BoundValue::Bytes(vec![0, 255]))
+        );
+        assert_eq!(
+            column_value(SqlDialect::Sqlite, "BLOB", &json!("AP8=")),
+            Ok(BoundValue::Bytes(vec![0, 255]))
+        );
+        assert_eq!(
+            column_value(SqlDialect::Sqlite, "TEXT", &json!("AP8=")),
+            Ok(BoundValue::Text("AP8=".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_pg_casts() {
+        let (bind, cast) = pg_column_value("numeric", &json!("1.50")).unwrap();
+        assert!(matches!(bind, PgBind::Text(Some(ref s)) if s == "1.50"));
+        assert_eq!(cast.as_deref(), Some("numeric"));
+
+        let (bind, cast) = pg_column_value("text[]", &json!(["a \"b\"", null])).unwrap();
+        assert!(matches!(bind, PgBind::Text(Some(ref s)) if s == r#"{"a \"b\"",NULL}"#));
+        assert_eq!(cast.as_deref(), Some("text[]"));
+
+        assert!(pg_column_value("uuid", &json!("not a uuid")).is_err());
+    }
+
+    #[test]
+    fn test_json_columns_compare_as_jsonb() {
+        let types = ColumnTypes {
+            dialect: SqlDialect::Postgres,
+            types: HashMap::from([
+                ("doc".to_string(), "json".to_string()),
+                ("id".to_string(), "integer".to_string()),
+            ]),
+        };
+        let statement = types.statement(|params| {
+            format!(
+                "{} AND {}",
+                params.holds("doc", &json!({"a": 1})),
+                params.holds("id", &json!(7))
+            )
+        });
+        assert_eq!(
+            statement.sql,
+            r#""doc"::jsonb IS NOT DISTINCT FROM $1::jsonb AND "id" IS NOT DISTINCT FROM $2"#
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Parameterized queries
+//!
+//! Statements may use named (`:user_id`) or positional (`$1`, `?`)
+//! placeholders. They are rewritten to the dialect's native style and the
+//! values are bound through sqlx, never interpolated into the SQL. Grid
+//! edits bind their values the same way, typed by their column.
+
+mod bind;
+mod column;
+mod placeholders;
+mod value;
+
+pub use bind::{
+    apply_mysql, apply_postgres, apply_sqlite, bind_loose, bind_postgres, BoundStatement, PgBind,
+};
+pub use column::{column_param_type, ColumnTypes, Params, Statement, StatementArgs};
+pub use placeholders::{parse_placeholders, ParameterizedSql};
+pub use value::{resolve_params, BoundValue, ParamType, QueryParam};
+
+use crate::db::dialect::SqlDialect;
+use crate::db::ConnectionPoolManager;
+use crate::error::VelocityError;
+use sqlx::TypeInfo;
+
+/// A parameter a statement expects
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementParameter {
+    /// `user_id` for `:user_id`, `1` for `$1` or the first `?`
+    pub name: String,
+    /// 1-based position in binding order
+    pub position: usize,
+    /// Type the database expects, where it reports one (PostgreSQL)
+    pub type_name: Option<String>,
+}
+
+impl ConnectionPoolManager {
+    /// List the parameters of a statement so they can be prompted for
+    pub async fn list_query_parameters(
+        &self,
+        connection_id: &str,
+        sql: &str,
+    ) -> Result<Vec<StatementParameter>, VelocityError> {
+        let pool = self
+            .get_pool(connection_id)
+            .await
+            .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+        let dialect = SqlDialect::from_pool(&pool).ok_or_else(|| {
+            VelocityError::Query("Parameters not supported for this database type".to_string())
+        })?;
+        let parsed = parse_placeholders(sql, dialect)?;
+
+        let types = match pool.as_ref() {
+            crate::db::DatabasePool::Postgres(pool) if !parsed.params.is_empty() => {
+                let mut conn = pool
+                    .acquire()
+                    .await
+                    .map_err(|e| VelocityError::Connection(e.to_string()))?;
+                // Unresolvable statements still list their placeholders
+                bind::postgres_param_types(&mut conn, &parsed.sql())
+                    .await
+                    .unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(parsed
+            .params
+            .iter()
+            .enumerate()
+            .map(|(i, name)| StatementParameter {
+                name: name.clone(),
+                position: i + 1,
+                type_name: types.get(i).map(|t| t.name().to_string()),
+            })
+            .collect())
+    }
+}
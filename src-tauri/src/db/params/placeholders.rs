@@ -0,0 +1,249 @@
+//! Placeholder detection and rewriting
+//!
+//! Recognises named (`:user_id`), numbered (`$1`, SQLite `?1`) and anonymous
+//! (`?`) placeholders outside of literals and comments, and rewrites them to
+//! the native style of the dialect: `$n` on PostgreSQL, `?` elsewhere.
+//! `?` is not a placeholder on PostgreSQL, where it is a JSONB operator.
+
+use crate::db::dialect::SqlDialect;
+use crate::db::splitter::{is_ident_byte, skip_non_code};
+use crate::error::VelocityError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlaceholderKind {
+    Named,
+    Numbered,
+    Anonymous,
+}
+
+/// A statement split around its placeholders
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterizedSql {
+    dialect: SqlDialect,
+    /// SQL text between placeholders; one more entry than `bindings`
+    segments: Vec<String>,
+    /// Distinct parameters in binding order: names for `:name`, 1-based
+    /// numbers for positional placeholders
+    pub params: Vec<String>,
+    /// Parameter referenced by each placeholder, as an index into `params`
+    pub bindings: Vec<usize>,
+}
+
+impl ParameterizedSql {
+    /// Statement with native placeholders
+    pub fn sql(&self) -> String {
+        self.render(&[])
+    }
+
+    /// Statement with native placeholders, casting parameters through text
+    /// where `casts[param]` names a target type (PostgreSQL only)
+    pub fn render(&self, casts: &[Option<String>]) -> String {
+        let mut sql = String::new();
+        for (segment, param) in self.segments.iter().zip(&self.bindings) {
+            sql.push_str(segment);
+            match self.dialect {
+                SqlDialect::Postgres => {
+                    sql.push_str(&format!("${}", param + 1));
+                    if let Some(Some(cast)) = casts.get(*param) {
+                        sql.push_str(&format!("::text::{}", cast));
+                    }
+                }
+                SqlDialect::MySql | SqlDialect::Sqlite => sql.push('?'),
+            }
+        }
+        if let Some(last) = self.segments.last() {
+            sql.push_str(last);
+        }
+        sql
+    }
+
+    /// Values to bind, one per native placeholder, given one value per parameter
+    pub fn binding_order<'a, T>(&self, values: &'a [T]) -> Vec<&'a T> {
+        match self.dialect {
+            // `$n` refers to the n-th value, however often it appears
+            SqlDialect::Postgres => values.iter().collect(),
+            SqlDialect::MySql | SqlDialect::Sqlite => self
+                .bindings
+                .iter()
+                .filter_map(|param| values.get(*param))
+                .collect(),
+        }
+    }
+}
+
+/// Find the placeholders of a statement
+pub fn parse_placeholders(
+    sql: &str,
+    dialect: SqlDialect,
+) -> Result<ParameterizedSql, VelocityError> {
+    let bytes = sql.as_bytes();
+    let mut parsed = ParameterizedSql {
+        dialect,
+        segments: Vec::new(),
+        params: Vec::new(),
+        bindings: Vec::new(),
+    };
+    let mut kind: Option<PlaceholderKind> = None;
+    let mut segment_start = 0;
+    let mut max_number = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if let Some(end) = skip_non_code(bytes, i, dialect) {
+            i = end;
+            continue;
+        }
+        let Some((found, end)) = placeholder_at(bytes, i, dialect) else {
+            i += 1;
+            continue;
+        };
+        if kind.is_some_and(|k| k != found) {
+            return Err(VelocityError::Query(
+                "Cannot mix named and positional parameters in one statement".to_string(),
+            ));
+        }
+        kind = Some(found);
+
+        let param = match found {
+            PlaceholderKind::Named => {
+                let name = &sql[i + 1..end];
+                match parsed.params.iter().position(|p| p == name) {
+                    Some(index) => index,
+                    None => {
+                        parsed.params.push(name.to_string());
+                        parsed.params.len() - 1
+                    }
+                }
+            }
+            PlaceholderKind::Numbered => {
+                let number: usize = sql[i + 1..end].parse().unwrap_or(0);
+                if number == 0 {
+                    return Err(VelocityError::Query(format!(
+                        "Invalid parameter number: {}",
+                        &sql[i..end]
+                    )));
+                }
+                max_number = max_number.max(number);
+                number - 1
+            }
+            PlaceholderKind::Anonymous => {
+                parsed.params.push((parsed.params.len() + 1).to_string());
+                parsed.params.len() - 1
+            }
+        };
+
+        parsed.segments.push(sql[segment_start..i].to_string());
+        parsed.bindings.push(param);
+        segment_start = end;
+        i = end;
+    }
+    parsed.segments.push(sql[segment_start..].to_string());
+
+    if kind == Some(PlaceholderKind::Numbered) {
+        // Every number up to the highest one is a parameter, even if unused
+        parsed.params = (1..=max_number).map(|n| n.to_string()).collect();
+    }
+    Ok(parsed)
+}
+
+/// Placeholder starting at `i` and the index right after it
+fn placeholder_at(bytes: &[u8], i: usize, dialect: SqlDialect) -> Option<(PlaceholderKind, usize)> {
+    let prev = i.checked_sub(1).map(|p| bytes[p]);
+    let next = bytes.get(i + 1).copied();
+    let scan = |from: usize, accept: fn(u8) -> bool| {
+        from + bytes[from..]
+            .iter()
+            .position(|&b| !accept(b))
+            .unwrap_or(bytes.len() - from)
+    };
+
+    match bytes[i] {
+        // Not `::` casts, `a:b` slices or `:=` assignments
+        b':' if prev.is_none_or(|p| p != b':' && !is_ident_byte(p))
+            && next.is_some_and(|n| n.is_ascii_alphabetic() || n == b'_') =>
+        {
+            let end = scan(i + 1, |b| b.is_ascii_alphanumeric() || b == b'_');
+            Some((PlaceholderKind::Named, end))
+        }
+        b'$' if prev.is_none_or(|p| !is_ident_byte(p))
+            && next.is_some_and(|n| n.is_ascii_digit()) =>
+        {
+            Some((
+                PlaceholderKind::Numbered,
+                scan(i + 1, |b| b.is_ascii_digit()),
+            ))
+        }
+        b'?' if dialect != SqlDialect::Postgres => {
+            if dialect == SqlDialect::Sqlite && next.is_some_and(|n| n.is_ascii_digit()) {
+                Some((
+                    PlaceholderKind::Numbered,
+                    scan(i + 1, |b| b.is_ascii_digit()),
+                ))
+            } else {
+                Some((PlaceholderKind::Anonymous, i + 1))
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_named_placeholders_reuse_parameter_numbers() {
+        let parsed = parse_placeholders(
+            "SELECT * FROM t WHERE a = :id OR b = :name OR c = :id",
+            SqlDialect::Postgres,
+        )
+        .unwrap();
+        assert_eq!(parsed.params, vec!["id", "name"]);
+        assert_eq!(
+            parsed.sql(),
+            "SELECT * FROM t WHERE a = $1 OR b = $2 OR c = $1"
+        );
+
+        let parsed = parse_placeholders("SELECT :id, :name, :id", SqlDialect::MySql).unwrap();
+        assert_eq!(parsed.sql(), "SELECT ?, ?, ?");
+        assert_eq!(parsed.bindings, vec![0, 1, 0]);
+        assert_eq!(parsed.binding_order(&["a", "b"]), vec![&"a", &"b", &"a"]);
+    }
+
+    #[test]
+    fn test_ignores_casts_literals_and_comments() {
+        let parsed = parse_placeholders(
+            "SELECT now()::date, ':x', arr[1:n] -- :y\nFROM t WHERE a = :a /* :z */",
+            SqlDialect::Postgres,
+        )
+        .unwrap();
+        assert_eq!(parsed.params, vec!["a"]);
+        assert!(parsed.sql().ends_with("WHERE a = $1 /* :z */"));
+
+        let parsed =
+            parse_placeholders("SELECT data ? 'key' FROM t", SqlDialect::Postgres).unwrap();
+        assert!(parsed.params.is_empty());
+    }
+
+    #[test]
+    fn test_positional_placeholders() {
+        let parsed = parse_placeholders("SELECT ? + ?", SqlDialect::Sqlite).unwrap();
+        assert_eq!(parsed.params, vec!["1", "2"]);
+
+        let parsed = parse_placeholders("SELECT $2, $1, $2", SqlDialect::MySql).unwrap();
+        assert_eq!(parsed.params, vec!["1", "2"]);
+        assert_eq!(parsed.sql(), "SELECT ?, ?, ?");
+        assert_eq!(parsed.bindings, vec![1, 0, 1]);
+
+        assert!(parse_placeholders("SELECT :a, ?", SqlDialect::MySql).is_err());
+    }
+
+    #[test]
+    fn test_postgres_text_casts() {
+        let parsed = parse_placeholders("SELECT :amount + 1", SqlDialect::Postgres).unwrap();
+        assert_eq!(
+            parsed.render(&[Some("NUMERIC".to_string())]),
+            "SELECT $1::text::NUMERIC + 1"
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Typed parameter values
+//!
+//! Parameters arrive as JSON with an optional declared type. They are parsed
+//! into a `BoundValue` once and converted to the Rust type each driver binds.
+
+use crate::error::VelocityError;
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime};
+use serde_json::Value;
+
+/// Declared type of a parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ParamType {
+    Text,
+    Integer,
+    Float,
+    Boolean,
+    /// Exact number, passed as a string
+    Decimal,
+    Date,
+    Time,
+    DateTime,
+    Json,
+    Uuid,
+    /// Base64-encoded bytes
+    Binary,
+}
+
+/// Value for one placeholder of a statement
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryParam {
+    /// Parameter name: `user_id` for `:user_id`, `1` for `$1` or the first `?`.
+    /// Parameters without a name are matched by position.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Declared type; inferred from the JSON value when omitted
+    #[serde(default, rename = "type")]
+    pub param_type: Option<ParamType>,
+    pub value: Value,
+}
+
+/// A parsed parameter value
+#[derive(Debug, Clone, PartialEq)]
+pub enum BoundValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Decimal(String),
+    Date(NaiveDate),
+    Time(NaiveTime),
+    /// Timestamps with an offset are converted to UTC
+    DateTime(NaiveDateTime),
+    Json(Value),
+    Uuid(uuid::Uuid),
+    Bytes(Vec<u8>),
+}
+
+impl BoundValue {
+    /// Parse a JSON value as the declared type, or infer the type from the value
+    pub fn parse(value: &Value, param_type: Option<ParamType>) -> Result<Self, String> {
+        if value.is_null() {
+            return Ok(BoundValue::Null);
+        }
+        let Some(param_type) = param_type else {
+            return Ok(match value {
+                Value::Bool(b) => BoundValue::Bool(*b),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => BoundValue::Int(i),
+                    None => BoundValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+                },
+                Value::String(s) => BoundValue::Text(s.clone()),
+                other => BoundValue::Json(other.clone()),
+            });
+        };
+
+        let text = match value {
+            Value::String(s) => s.trim().to_string(),
+            other => other.to_string(),
+        };
+        let expected = |what: &str| format!("expected {}, got {}", what, value);
+        Ok(match param_type {
+            ParamType::Text => match value {
+                Value::String(s) => BoundValue::Text(s.clone()),
+                other => BoundValue::Text(other.to_string()),
+            },
+            ParamType::Integer => {
+                BoundValue::Int(text.parse().map_err(|_| expected("an integer"))?)
+            }
+            ParamType::Float => BoundValue::Float(text.parse().map_err(|_| expected("a number"))?),
+            ParamType::Boolean => {
+                BoundValue::Bool(parse_bool(&text).ok_or_else(|| expected("a boolean"))?)
+            }
+            ParamType::Decimal => {
+                if text.parse::<f64>().is_err() {
+                    return Err(expected("a decimal number"));
+                }
+                BoundValue::Decimal(text)
+            }
+            ParamType::Date => BoundValue::Date(
+                NaiveDate::parse_from_str(&text, "%Y-%m-%d")
+                    .map_err(|_| expected("a date (YYYY-MM-DD)"))?,
+            ),
+            ParamType::Time => {
+                BoundValue::Time(parse_time(&text).ok_or_else(|| expected("a time (HH:MM:SS)"))?)
+            }
+            ParamType::DateTime => BoundValue::DateTime(
+                parse_datetime(&text).ok_or_else(|| expected("an ISO-8601 timestamp"))?,
+            ),
+            ParamType::Json => match value {
+                // JSON typed into a text field arrives as a string
+                Value::String(s) => {
+                    BoundValue::Json(serde_json::from_str(s).unwrap_or_else(|_| value.clone()))
+                }
+                other => BoundValue::Json(other.clone()),
+            },
+            ParamType::Uuid => {
+                BoundValue::Uuid(uuid::Uuid::parse_str(&text).map_err(|_| expected("a UUID"))?)
+            }
+            ParamType::Binary => BoundValue::Bytes(
+                base64::engine::general_purpose::STANDARD
+                    .decode(&text)
+                    .map_err(|_| expected("base64 data"))?,
+            ),
+        })
+    }
+
+    /// Text form of the value, as sent for types bound as text
+    pub fn to_text(&self) -> Option<String> {
+        Some(match self {
+            BoundValue::Null => return None,
+            BoundValue::Bool(b) => b.to_string(),
+            BoundValue::Int(i) => i.to_string(),
+            BoundValue::Float(f) => f.to_string(),
+            BoundValue::Text(s) | BoundValue::Decimal(s) => s.clone(),
+            BoundValue::Date(d) => d.format("%Y-%m-%d").to_string(),
+            BoundValue::Time(t) => t.format("%H:%M:%S%.f").to_string(),
+            BoundValue::DateTime(dt) => dt.format("%Y-%m-%d %H:%M:%S%.f").to_string(),
+            BoundValue::Json(v) => v.to_string(),
+            BoundValue::Uuid(u) => u.to_string(),
+            BoundValue::Bytes(b) => base64::engine::general_purpose::STANDARD.encode(b),
+        })
+    }
+}
+
+pub(crate) fn parse_bool(text: &str) -> Option<bool> {
+    match text.to_ascii_lowercase().as_str() {
+        "true" | "t" | "1" | "yes" => Some(true),
+        "false" | "f" | "0" | "no" => Some(false),
+        _ => None,
+    }
+}
+
+pub(crate) fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text, "%H:%M:%S%.f")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+        .ok()
+}
+
+pub(crate) fn parse_datetime(text: &str) -> Option<NaiveDateTime> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(text) {
+        return Some(dt.naive_utc());
+    }
+    [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M",
+    ]
+    .iter()
+    .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+    })
+}
+
+/// Match the given values to the parameters of a statement: by name when
+/// the value is named, by position otherwise
+pub fn resolve_params(
+    names: &[String],
+    given: &[QueryParam],
+) -> Result<Vec<BoundValue>, VelocityError> {
+    names
+        .iter()
+        .enumerate()
+        .map(|(position, name)| {
+            let param = given
+                .iter()
+                .find(|p| {
+                    p.name
+                        .as_deref()
+                        .is_some_and(|n| n.trim_start_matches([':', '$', '?']) == name)
+                })
+                .or_else(|| given.get(position).filter(|p| p.name.is_none()))
+                .ok_or_else(|| {
+                    VelocityError::Query(format!(
+                        "Missing value for parameter {}",
+                        display_name(name)
+                    ))
+                })?;
+            BoundValue::parse(&param.value, param.param_type).map_err(|e| {
+                VelocityError::Query(format!("Parameter {}: {}", display_name(name), e))
+            })
+        })
+        .collect()
+}
+
+/// `:name` for named parameters, `$n` for positional ones
+pub(crate) fn display_name(name: &str) -> String {
+    if name.chars().all(|c| c.is_ascii_digit()) {
+        format!("${}", name)
+    } else {
+        format!(":{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn param(name: Option<&str>, param_type: Option<ParamType>, value: Value) -> QueryParam {
+        QueryParam {
+            name: name.map(str::to_string),
+            param_type,
+            value,
+        }
+    }
+
+    #[test]
+    fn test_infers_types_from_json() {
+        assert_eq!(BoundValue::parse(&json!(42), None), Ok(BoundValue::Int(42)));
+        assert_eq!(
+            BoundValue::parse(&json!(1.5), None),
+            Ok(BoundValue::Float(1.5))
+        );
+        assert_eq!(
+            BoundValue::parse(&json!("x"), None),
+            Ok(BoundValue::Text("x".to_string()))
+        );
+        assert_eq!(
+            BoundValue::parse(&json!(null), Some(ParamType::Integer)),
+            Ok(BoundValue::Null)
+        );
+    }
+
+    #[test]
+    fn test_parses_declared_types() {
+        assert_eq!(
+            BoundValue::parse(&json!("42"), Some(ParamType::Integer)),
+            Ok(BoundValue::Int(42))
+        );
+        assert_eq!(
+            BoundValue::parse(
+                &json!("2024-01-31T10:00:00+01:00"),
+                Some(ParamType::DateTime)
+            ),
+            Ok(BoundValue::DateTime(
+                NaiveDate::from_ymd_opt(2024, 1, 31)
+                    .unwrap()
+                    .and_hms_opt(9, 0, 0)
+                    .unwrap()
+            ))
+        );
+        assert_eq!(
+            BoundValue::parse(&json!("AP8="), Some(ParamType::Binary)),
+            Ok(BoundValue::Bytes(vec![0, 255]))
+        );
+        assert!(BoundValue::parse(&json!("abc"), Some(ParamType::Decimal)).is_err());
+    }
+
+    #[test]
+    fn test_resolves_by_name_then_position() {
+        let names = vec!["id".to_string(), "name".to_string()];
+        let given = vec![
+            param(Some(":name"), None, json!("a")),
+            param(Some("id"), None, json!(1)),
+        ];
+        assert_eq!(
+            resolve_params(&names, &given).unwrap(),
+            vec![BoundValue::Int(1), BoundValue::Text("a".to_string())]
+        );
+
+        let names = vec!["1".to_string(), "2".to_string()];
+        let given = vec![param(None, None, json!(1))];
+        assert_eq!(
+            resolve_params(&names, &given).unwrap_err().to_string(),
+            "Query error: Missing value for parameter $2"
+        );
+    }
+}
@@ -2,9 +2,12 @@ use crate::error::VelocityError;
 use super::enums::DatabasePool;
 use super::types::TableData;
 use super::metadata::get_table_schema;
-use sqlx::Row;
+use super::documents::document_key;
+use crate::db::identity::resolve_table_key;
+use crate::db::decode::{bytes_to_json, decode_mysql_value, decode_pg_value, decode_sqlite_value};
 use futures::TryStreamExt;
 use mongodb::bson::Document;
+use sqlx::Row;
 
 pub async fn get_table_data(
     pool: &DatabasePool,
@@ -16,9 +19,20 @@ pub async fn get_table_data(
     // Now we need to call the standalone function get_table_schema.
     let columns = get_table_schema(pool, table_name).await?;
     let column_names: Vec<String> = columns.iter().map(|c| c.name.clone()).collect();
+    let table_key = match pool {
+        DatabasePool::Postgres(_) | DatabasePool::MySQL(_) | DatabasePool::SQLite(_) => {
+            resolve_table_key(pool, table_name, &columns).await?
+        }
+        _ => Default::default(),
+    };
+    let row_id_select = table_key
+        .identity
+        .select_expr()
+        .map(|expr| format!(", {}", expr))
+        .unwrap_or_default();
     let query = format!(
-        "SELECT * FROM {} LIMIT {} OFFSET {}",
-        table_name, limit, offset
+        "SELECT *{} FROM {} LIMIT {} OFFSET {}",
+        row_id_select, table_name, limit, offset
     );
 
     match pool {
@@ -27,35 +41,24 @@ pub async fn get_table_data(
                 .fetch_all(pool)
                 .await
                 .map_err(|e| VelocityError::Query(e.to_string()))?;
-            let data = rows
+            let data: Vec<Vec<serde_json::Value>> = rows
                 .iter()
                 .map(|row| {
-                    use sqlx::Row;
                     column_names
                         .iter()
                         .enumerate()
-                        .map(|(i, _)| {
-                            row.try_get::<String, _>(i)
-                                .map(serde_json::Value::String)
-                                .or_else(|_| {
-                                    row.try_get::<i64, _>(i)
-                                        .map(|v| serde_json::Value::Number(v.into()))
-                                })
-                                .or_else(|_| {
-                                    row.try_get::<i32, _>(i)
-                                        .map(|v| serde_json::Value::Number(v.into()))
-                                })
-                                .or_else(|_| {
-                                    row.try_get::<bool, _>(i).map(serde_json::Value::Bool)
-                                })
-                                .unwrap_or(serde_json::Value::Null)
-                        })
+                        .map(|(i, _)| decode_pg_value(row, i))
                         .collect()
                 })
                 .collect();
+            let row_ids = (!row_id_select.is_empty())
+                .then(|| rows.iter().map(|row| decode_pg_value(row, row.len() - 1)).collect());
             Ok(TableData {
+                row_keys: table_key.row_keys(&column_names, &data, row_ids),
                 columns: column_names,
                 rows: data,
+                row_identity: table_key.identity,
+                key_columns: table_key.columns,
             })
         }
         DatabasePool::MySQL(pool) => {
@@ -63,31 +66,24 @@ pub async fn get_table_data(
                 .fetch_all(pool)
                 .await
                 .map_err(|e| VelocityError::Query(e.to_string()))?;
-            let data = rows
+            let data: Vec<Vec<serde_json::Value>> = rows
                 .iter()
                 .map(|row| {
-                    use sqlx::Row;
                     column_names
                         .iter()
                         .enumerate()
-                        .map(|(i, _)| {
-                            row.try_get::<String, _>(i)
-                                .map(serde_json::Value::String)
-                                .or_else(|_| {
-                                    row.try_get::<i64, _>(i)
-                                        .map(|v| serde_json::Value::Number(v.into()))
-                                })
-                                .or_else(|_| {
-                                    row.try_get::<bool, _>(i).map(serde_json::Value::Bool)
-                                })
-                                .unwrap_or(serde_json::Value::Null)
-                        })
+                        .map(|(i, _)| decode_mysql_value(row, i))
                         .collect()
                 })
                 .collect();
+            let row_ids = (!row_id_select.is_empty())
+                .then(|| rows.iter().map(|row| decode_mysql_value(row, row.len() - 1)).collect());
             Ok(TableData {
+                row_keys: table_key.row_keys(&column_names, &data, row_ids),
                 columns: column_names,
                 rows: data,
+                row_identity: table_key.identity,
+                key_columns: table_key.columns,
             })
         }
         DatabasePool::SQLite(pool) => {
@@ -95,37 +91,27 @@ pub async fn get_table_data(
                 .fetch_all(pool)
                 .await
                 .map_err(|e| VelocityError::Query(e.to_string()))?;
-            let data = rows
+            let data: Vec<Vec<serde_json::Value>> = rows
                 .iter()
                 .map(|row| {
-                    use sqlx::Row;
                     column_names
                         .iter()
                         .enumerate()
-                        .map(|(i, _)| {
-                            row.try_get::<String, _>(i)
-                                .map(serde_json::Value::String)
-                                .or_else(|_| {
-                                    row.try_get::<i64, _>(i)
-                                        .map(|v| serde_json::Value::Number(v.into()))
-                                })
-                                .or_else(|_| {
-                                    row.try_get::<bool, _>(i).map(serde_json::Value::Bool)
-                                })
-                                .unwrap_or(serde_json::Value::Null)
-                        })
+                        .map(|(i, _)| decode_sqlite_value(row, i))
                         .collect()
                 })
                 .collect();
+            let row_ids = (!row_id_select.is_empty())
+                .then(|| rows.iter().map(|row| decode_sqlite_value(row, row.len() - 1)).collect());
             Ok(TableData {
+                row_keys: table_key.row_keys(&column_names, &data, row_ids),
                 columns: column_names,
                 rows: data,
+                row_identity: table_key.identity,
+                key_columns: table_key.columns,
             })
         }
-        DatabasePool::SQLServer(_) => Ok(TableData {
-            columns: vec![],
-            rows: vec![],
-        }),
+        DatabasePool::SQLServer(_) => Ok(TableData::default()),
         DatabasePool::Redis(redis_pool) => {
             let mut conn = redis_pool
                 .client
@@ -143,6 +129,7 @@ pub async fn get_table_data(
             Ok(TableData {
                 columns: vec!["value".into()],
                 rows,
+                ..Default::default()
             })
         }
         DatabasePool::MongoDB(mongo_pool) => {
@@ -165,7 +152,7 @@ pub async fn get_table_data(
             if docs.is_empty() {
                 return Ok(TableData {
                     columns: vec!["_id".into()],
-                    rows: vec![],
+                    ..Default::default()
                 });
             }
             
@@ -184,6 +171,8 @@ pub async fn get_table_data(
                 columns.insert(0, "_id".to_string());
             }
             
+            let row_keys = docs.iter().map(document_key).collect();
+
             // Convert documents to rows
             let rows: Vec<Vec<serde_json::Value>> = docs
                 .into_iter()
@@ -199,13 +188,19 @@ pub async fn get_table_data(
                 })
                 .collect();
             
-            Ok(TableData { columns, rows })
+            Ok(TableData {
+                columns,
+                rows,
+                row_keys: Some(row_keys),
+                key_columns: vec!["_id".into()],
+                ..Default::default()
+            })
         }
     }
 }
 
 /// Convert BSON value to JSON value
-fn bson_to_json(bson: &mongodb::bson::Bson) -> serde_json::Value {
+pub(super) fn bson_to_json(bson: &mongodb::bson::Bson) -> serde_json::Value {
     use mongodb::bson::Bson;
     match bson {
         Bson::Null => serde_json::Value::Null,
@@ -217,7 +212,9 @@ fn bson_to_json(bson: &mongodb::bson::Bson) -> serde_json::Value {
             .unwrap_or(serde_json::Value::Null),
         Bson::String(s) => serde_json::Value::String(s.clone()),
         Bson::ObjectId(oid) => serde_json::Value::String(oid.to_hex()),
-        Bson::DateTime(dt) => serde_json::Value::String(dt.to_string()),
+        Bson::DateTime(dt) => serde_json::Value::String(
+            dt.try_to_rfc3339_string().unwrap_or_else(|_| dt.to_string()),
+        ),
         Bson::Array(arr) => serde_json::Value::Array(
             arr.iter().map(bson_to_json).collect()
         ),
@@ -228,7 +225,7 @@ fn bson_to_json(bson: &mongodb::bson::Bson) -> serde_json::Value {
                 .collect();
             serde_json::Value::Object(map)
         }
-        Bson::Binary(bin) => serde_json::Value::String(format!("<binary {} bytes>", bin.bytes.len())),
+        Bson::Binary(bin) => bytes_to_json(&bin.bytes),
         _ => serde_json::Value::String(bson.to_string()),
     }
 }
@@ -254,14 +251,7 @@ pub async fn get_column_values(
             
             let values: Vec<String> = rows
                 .iter()
-                .map(|row| {
-                    use sqlx::Row;
-                    row.try_get::<String, _>(0)
-                        .or_else(|_| row.try_get::<i64, _>(0).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<i32, _>(0).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<bool, _>(0).map(|v| v.to_string()))
-                        .unwrap_or_else(|_| "".to_string())
-                })
+                .map(|row| distinct_value_to_string(decode_pg_value(row, 0)))
                 .filter(|s| !s.is_empty())
                 .collect();
             Ok(values)
@@ -274,14 +264,7 @@ pub async fn get_column_values(
             
             let values: Vec<String> = rows
                 .iter()
-                .map(|row| {
-                    use sqlx::Row;
-                    row.try_get::<String, _>(0)
-                        .or_else(|_| row.try_get::<i64, _>(0).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<i32, _>(0).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<bool, _>(0).map(|v| v.to_string()))
-                        .unwrap_or_else(|_| "".to_string())
-                })
+                .map(|row| distinct_value_to_string(decode_mysql_value(row, 0)))
                 .filter(|s| !s.is_empty())
                 .collect();
             Ok(values)
@@ -294,14 +277,7 @@ pub async fn get_column_values(
             
              let values: Vec<String> = rows
                 .iter()
-                .map(|row| {
-                    use sqlx::Row;
-                    row.try_get::<String, _>(0)
-                        .or_else(|_| row.try_get::<i64, _>(0).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<i32, _>(0).map(|v| v.to_string()))
-                        .or_else(|_| row.try_get::<bool, _>(0).map(|v| v.to_string()))
-                        .unwrap_or_else(|_| "".to_string())
-                })
+                .map(|row| distinct_value_to_string(decode_sqlite_value(row, 0)))
                 .filter(|s| !s.is_empty())
                 .collect();
             Ok(values)
@@ -347,3 +323,12 @@ pub async fn get_column_values(
         }
     }
 }
+
+/// Render a decoded value for the autocompletion list; NULL becomes empty
+fn distinct_value_to_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
@@ -0,0 +1,664 @@
+//! Applying grid edits to MongoDB documents
+//!
+//! Pending changes become `updateOne`, `insertOne` and `deleteOne` calls on
+//! documents matched by `_id`. Row keys carry the `_id` as relaxed Extended
+//! JSON, so ObjectIds and ids of other types are matched as they are stored.
+//! An update may name a nested field by its dotted path, e.g.
+//! `address.city`. Edited values are converted back to the BSON type of the
+//! value they replace.
+//!
+//! On replica sets and sharded clusters the batch runs in one transaction.
+//! MongoDB aborts a transaction when one of its writes fails, so the batch
+//! is run again without the failed changes until the rest succeed, and only
+//! then committed or rolled back depending on the apply mode. A standalone
+//! server has no transactions: changes are applied one by one, and in
+//! all-or-nothing mode the changes after a failure are not run.
+
+use super::data::bson_to_json;
+use super::mutation::{describe_key, mark_rolled_back};
+use super::types::MongoPool;
+use crate::commands::database::{
+    AppliedStatement, ApplyMode, ChangeOptions, ChangeResult, ChangeStatus, ExecuteResult,
+    PendingChange, RowKey,
+};
+use crate::error::VelocityError;
+use base64::Engine;
+use mongodb::bson::{doc, oid::ObjectId, Binary, Bson, DateTime, Decimal128, Document};
+use mongodb::error::{Error as MongoError, ErrorKind, WriteFailure};
+use mongodb::options::ReturnDocument;
+use mongodb::{ClientSession, Collection};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+/// Why a change failed
+struct DocumentError {
+    message: String,
+    code: Option<String>,
+    /// The document was changed since it was loaded
+    conflict: bool,
+    /// Current fields of a conflicting document; None when it is gone
+    current: Option<Map<String, Value>>,
+}
+
+impl DocumentError {
+    fn conflict(key: &RowKey, current: Option<&Document>) -> Self {
+        let message = match current {
+            Some(_) => format!(
+                "Document {} was changed since it was loaded",
+                describe_key(key)
+            ),
+            None => format!("Document {} no longer exists", describe_key(key)),
+        };
+        Self {
+            message,
+            code: None,
+            conflict: true,
+            current: current.map(document_to_json),
+        }
+    }
+}
+
+impl From<String> for DocumentError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            code: None,
+            conflict: false,
+            current: None,
+        }
+    }
+}
+
+impl From<MongoError> for DocumentError {
+    fn from(error: MongoError) -> Self {
+        let code = match &*error.kind {
+            ErrorKind::Command(e) => Some(e.code),
+            ErrorKind::Write(WriteFailure::WriteError(e)) => Some(e.code),
+            ErrorKind::Write(WriteFailure::WriteConcernError(e)) => Some(e.code),
+            _ => None,
+        };
+        Self {
+            message: error.to_string(),
+            code: code.map(|code| code.to_string()),
+            conflict: false,
+            current: None,
+        }
+    }
+}
+
+/// What a successful change did
+struct Applied {
+    documents: u64,
+    /// Key and fields of the document as stored after an insert or update
+    persisted: Option<(RowKey, Map<String, Value>)>,
+}
+
+pub async fn execute_document_changes(
+    pool: &MongoPool,
+    collection_name: &str,
+    changes: Vec<PendingChange>,
+    options: ChangeOptions,
+) -> Result<ExecuteResult, VelocityError> {
+    let collection = pool
+        .client
+        .database(&pool.database)
+        .collection::<Document>(collection_name);
+    let transactions = supports_transactions(pool).await?;
+    if options.dry_run && !transactions {
+        return Err(VelocityError::Query(
+            "Dry runs on MongoDB need a replica set or sharded cluster".to_string(),
+        ));
+    }
+    let mut session = pool
+        .client
+        .start_session()
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+
+    let (mut results, mut statements, rolled_back) = if transactions {
+        run_in_transaction(&collection, &mut session, &changes, options).await?
+    } else {
+        let (results, statements) =
+            run_one_by_one(&collection, &mut session, &changes, options).await;
+        (results, statements, false)
+    };
+
+    let failed = results.iter().any(|r| r.status.is_failure());
+    if rolled_back && !options.dry_run {
+        for result in results
+            .iter_mut()
+            .filter(|r| r.status == ChangeStatus::Applied)
+        {
+            result.status = ChangeStatus::RolledBack;
+            result.key = None;
+            result.row = None;
+        }
+        mark_rolled_back(&mut statements);
+    }
+    let rows_affected = results
+        .iter()
+        .filter(|r| r.status == ChangeStatus::Applied)
+        .filter_map(|r| r.rows_affected)
+        .sum();
+    let errors = results
+        .iter()
+        .filter(|r| r.status.is_failure())
+        .filter_map(|r| {
+            r.error
+                .as_ref()
+                .map(|error| format!("{}: {}", r.change_type, error))
+        })
+        .collect();
+
+    Ok(ExecuteResult {
+        success: !failed,
+        rows_affected,
+        errors,
+        changes: results,
+        dry_run: options.dry_run,
+        statements,
+        committed: Vec::new(),
+    })
+}
+
+/// Replica sets and sharded clusters support multi-document transactions
+async fn supports_transactions(pool: &MongoPool) -> Result<bool, VelocityError> {
+    let hello = pool
+        .client
+        .database("admin")
+        .run_command(doc! { "hello": 1 })
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+    Ok(hello.contains_key("setName") || hello.get_str("msg") == Ok("isdbgrid"))
+}
+
+type BatchOutcome = (Vec<ChangeResult>, Vec<AppliedStatement>);
+
+/// Run the batch in a transaction, leaving out failed changes until the
+/// rest succeed. Returns whether the transaction was rolled back.
+async fn run_in_transaction(
+    collection: &Collection<Document>,
+    session: &mut ClientSession,
+    changes: &[PendingChange],
+    options: ChangeOptions,
+) -> Result<(Vec<ChangeResult>, Vec<AppliedStatement>, bool), VelocityError> {
+    let query_error = |e: MongoError| VelocityError::Query(e.to_string());
+    // Changes that failed in an earlier run of the batch
+    let mut failed: BTreeMap<usize, (ChangeResult, Option<AppliedStatement>)> = BTreeMap::new();
+    loop {
+        session.start_transaction().await.map_err(query_error)?;
+        let (mut results, mut statements): BatchOutcome = (Vec::new(), Vec::new());
+        let mut new_failure = false;
+        for (index, change) in changes.iter().enumerate() {
+            if let Some((result, statement)) = failed.get(&index) {
+                results.push(result.clone());
+                statements.extend(statement.clone());
+                continue;
+            }
+            let (result, statement) =
+                run_change(collection, session, index, change, options.check_conflicts).await;
+            if result.status.is_failure() {
+                failed.insert(index, (result, statement));
+                new_failure = true;
+                break;
+            }
+            results.push(result);
+            statements.extend(statement);
+        }
+        if new_failure {
+            session.abort_transaction().await.map_err(query_error)?;
+            continue;
+        }
+        let rolled_back =
+            options.dry_run || (!failed.is_empty() && options.mode == ApplyMode::AllOrNothing);
+        if rolled_back {
+            session.abort_transaction().await.map_err(query_error)?;
+        } else {
+            session.commit_transaction().await.map_err(query_error)?;
+        }
+        return Ok((results, statements, rolled_back));
+    }
+}
+
+/// Apply the changes one at a time, on a deployment without transactions
+async fn run_one_by_one(
+    collection: &Collection<Document>,
+    session: &mut ClientSession,
+    changes: &[PendingChange],
+    options: ChangeOptions,
+) -> BatchOutcome {
+    let (mut results, mut statements): BatchOutcome = (Vec::new(), Vec::new());
+    let mut stopped = false;
+    for (index, change) in changes.iter().enumerate() {
+        if stopped {
+            let mut result = change_result(index, change, ChangeStatus::Skipped);
+            result.error = Some(
+                "Not run after an earlier change failed; this MongoDB deployment has no transactions"
+                    .to_string(),
+            );
+            results.push(result);
+            continue;
+        }
+        let (result, statement) =
+            run_change(collection, session, index, change, options.check_conflicts).await;
+        stopped = result.status.is_failure() && options.mode == ApplyMode::AllOrNothing;
+        results.push(result);
+        statements.extend(statement);
+    }
+    (results, statements)
+}
+
+fn change_result(index: usize, change: &PendingChange, status: ChangeStatus) -> ChangeResult {
+    ChangeResult {
+        index,
+        change_type: change.change_type.clone(),
+        status,
+        rows_affected: None,
+        error: None,
+        error_code: None,
+        key: None,
+        row: None,
+        current: None,
+    }
+}
+
+/// Run one change, returning its result and the operation sent for the
+/// audit log. Change types other than update, insert and delete are skipped.
+async fn run_change(
+    collection: &Collection<Document>,
+    session: &mut ClientSession,
+    index: usize,
+    change: &PendingChange,
+    check_conflicts: bool,
+) -> (ChangeResult, Option<AppliedStatement>) {
+    let name = collection.name();
+    let filter = Value::Object(change.key.clone());
+    let (operation, outcome) = match change.change_type.as_str() {
+        "update" => (
+            format!(
+                "db.{}.updateOne({}, {})",
+                name,
+                filter,
+                json!({ "$set": { change.column.clone(): change.new_value } })
+            ),
+            update(
+                collection,
+                session,
+                change,
+                check_conflicts && !change.force,
+            )
+            .await,
+        ),
+        "insert" => (
+            format!(
+                "db.{}.insertOne({})",
+                name,
+                Value::Object(change.values.clone())
+            ),
+            insert(collection, session, change).await,
+        ),
+        "delete" => (
+            format!("db.{}.deleteOne({})", name, filter),
+            delete(collection, session, change).await,
+        ),
+        other => {
+            let mut result = change_result(index, change, ChangeStatus::Skipped);
+            result.error = Some(format!(
+                "Change type '{}' is not supported for this database",
+                other
+            ));
+            return (result, None);
+        }
+    };
+    match outcome {
+        Ok(applied) => {
+            let documents = applied.documents as i64;
+            let (key, row) = applied.persisted.unzip();
+            let mut result = change_result(index, change, ChangeStatus::Applied);
+            result.rows_affected = Some(documents);
+            result.key = key;
+            result.row = row;
+            let statement = AppliedStatement {
+                sql: operation,
+                rows_affected: Some(documents),
+                error: None,
+            };
+            (result, Some(statement))
+        }
+        Err(e) => {
+            let status = if e.conflict {
+                ChangeStatus::Conflict
+            } else {
+                ChangeStatus::Failed
+            };
+            let mut result = change_result(index, change, status);
+            result.error = Some(e.message.clone());
+            result.error_code = e.code;
+            result.current = e.current;
+            let statement = AppliedStatement {
+                sql: operation,
+                rows_affected: None,
+                error: Some(e.message),
+            };
+            (result, Some(statement))
+        }
+    }
+}
+
+async fn update(
+    collection: &Collection<Document>,
+    session: &mut ClientSession,
+    change: &PendingChange,
+    check_conflict: bool,
+) -> Result<Applied, DocumentError> {
+    let id = document_id(&change.key)?;
+    if change.column.is_empty() {
+        return Err("Update names no field".to_string().into());
+    }
+    // The stored value decides the type of the new one
+    let Some(current) = collection
+        .find_one(doc! { "_id": id.clone() })
+        .session(&mut *session)
+        .await?
+    else {
+        if check_conflict {
+            return Err(DocumentError::conflict(&change.key, None));
+        }
+        return Ok(Applied {
+            documents: 0,
+            persisted: None,
+        });
+    };
+    let existing = field_at(&current, &change.column);
+    let mut filter = doc! { "_id": id.clone() };
+    if check_conflict {
+        let stored = existing.map_or(Value::Null, bson_to_json);
+        if !same_value(&stored, &change.old_value) {
+            return Err(DocumentError::conflict(&change.key, Some(&current)));
+        }
+        // Only write while the field still holds what was compared
+        filter.insert(
+            change.column.clone(),
+            existing.cloned().unwrap_or(Bson::Null),
+        );
+    }
+    let value = json_to_bson(&change.new_value, existing)
+        .map_err(|e| format!("Field {}: {}", change.column, e))?;
+    let mut set = Document::new();
+    set.insert(change.column.clone(), value);
+
+    let updated = collection
+        .find_one_and_update(filter, doc! { "$set": set })
+        .return_document(ReturnDocument::After)
+        .session(&mut *session)
+        .await?;
+    match updated {
+        Some(document) => Ok(Applied {
+            documents: 1,
+            persisted: Some((document_key(&document), document_to_json(&document))),
+        }),
+        // Changed between reading and writing it
+        None if check_conflict => {
+            let current = collection
+                .find_one(doc! { "_id": id })
+                .session(&mut *session)
+                .await?;
+            Err(DocumentError::conflict(&change.key, current.as_ref()))
+        }
+        None => Ok(Applied {
+            documents: 0,
+            persisted: None,
+        }),
+    }
+}
+
+async fn insert(
+    collection: &Collection<Document>,
+    session: &mut ClientSession,
+    change: &PendingChange,
+) -> Result<Applied, DocumentError> {
+    let mut document = Document::new();
+    for (field, value) in &change.values {
+        let value = json_to_bson(value, None).map_err(|e| format!("Field {}: {}", field, e))?;
+        document.insert(field.clone(), value);
+    }
+    let inserted = collection
+        .insert_one(&document)
+        .session(&mut *session)
+        .await?;
+    if !document.contains_key("_id") {
+        let mut stored = doc! { "_id": inserted.inserted_id };
+        stored.extend(document);
+        document = stored;
+    }
+    Ok(Applied {
+        documents: 1,
+        persisted: Some((document_key(&document), document_to_json(&document))),
+    })
+}
+
+async fn delete(
+    collection: &Collection<Document>,
+    session: &mut ClientSession,
+    change: &PendingChange,
+) -> Result<Applied, DocumentError> {
+    let id = document_id(&change.key)?;
+    let deleted = collection
+        .delete_one(doc! { "_id": id })
+        .session(&mut *session)
+        .await?;
+    Ok(Applied {
+        documents: deleted.deleted_count,
+        persisted: None,
+    })
+}
+
+/// The `_id` a row key names
+fn document_id(key: &RowKey) -> Result<Bson, String> {
+    let id = key
+        .get("_id")
+        .ok_or_else(|| "Documents are identified by _id".to_string())?;
+    Bson::try_from(id.clone()).map_err(|e| format!("Invalid _id {}: {}", id, e))
+}
+
+/// Row key of a document: its `_id` as relaxed Extended JSON
+pub(crate) fn document_key(document: &Document) -> RowKey {
+    let id = document.get("_id").cloned().unwrap_or(Bson::Null);
+    RowKey::from_iter([("_id".to_string(), id.into_relaxed_extjson())])
+}
+
+/// Top-level fields of a document as the grid shows them
+fn document_to_json(document: &Document) -> Map<String, Value> {
+    document
+        .iter()
+        .map(|(field, value)| (field.clone(), bson_to_json(value)))
+        .collect()
+}
+
+/// Value at a dotted path, stepping into arrays by index
+fn field_at<'a>(document: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut parts = path.split('.');
+    let mut value = document.get(parts.next()?)?;
+    for part in parts {
+        value = match value {
+            Bson::Document(document) => document.get(part)?,
+            Bson::Array(items) => items.get(part.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Whether the grid's value of a field is the stored one. Numbers compare
+/// by value: a whole double such as 7.0 comes back from the grid as 7.
+fn same_value(stored: &Value, sent: &Value) -> bool {
+    match (stored, sent) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => a.as_f64() == b.as_f64(),
+        },
+        (Value::Array(a), Value::Array(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_value(a, b))
+        }
+        (Value::Object(a), Value::Object(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .all(|(field, a)| b.get(field).is_some_and(|b| same_value(a, b)))
+        }
+        _ => stored == sent,
+    }
+}
+
+/// Convert an edited value to BSON. The grid shows ObjectIds, dates and
+/// decimals as strings, binary data as base64 and all numbers alike, so a
+/// value replacing one of those takes its type back. Anything else is read
+/// as Extended JSON, which also lets new values be typed as
+/// `{"$oid": ...}`, `{"$date": ...}` and so on.
+fn json_to_bson(value: &Value, like: Option<&Bson>) -> Result<Bson, String> {
+    let expected = |what: &str| format!("expected {}, got {}", what, value);
+    match (like, value) {
+        (_, Value::Null) => Ok(Bson::Null),
+        (Some(Bson::ObjectId(_)), Value::String(s)) => ObjectId::parse_str(s)
+            .map(Bson::ObjectId)
+            .map_err(|_| expected("an ObjectId")),
+        (Some(Bson::DateTime(_)), Value::String(s)) => DateTime::parse_rfc3339_str(s)
+            .map(Bson::DateTime)
+            .map_err(|_| expected("an RFC 3339 date")),
+        (Some(Bson::Decimal128(_)), Value::String(_) | Value::Number(_)) => {
+            let text = value
+                .as_str()
+                .map_or_else(|| value.to_string(), str::to_string);
+            text.parse::<Decimal128>()
+                .map(Bson::Decimal128)
+                .map_err(|_| expected("a decimal"))
+        }
+        (Some(Bson::Binary(binary)), Value::String(s)) => base64::engine::general_purpose::STANDARD
+            .decode(s)
+            .map(|bytes| {
+                Bson::Binary(Binary {
+                    subtype: binary.subtype,
+                    bytes,
+                })
+            })
+            .map_err(|_| expected("base64 data")),
+        (Some(Bson::Int32(_)), Value::Number(n)) => n
+            .as_i64()
+            .and_then(|i| i32::try_from(i).ok())
+            .map(Bson::Int32)
+            .ok_or_else(|| expected("a 32-bit integer")),
+        (Some(Bson::Int64(_)), Value::Number(n)) => n
+            .as_i64()
+            .map(Bson::Int64)
+            .ok_or_else(|| expected("an integer")),
+        (Some(Bson::Double(_)), Value::Number(n)) => n
+            .as_f64()
+            .map(Bson::Double)
+            .ok_or_else(|| expected("a number")),
+        (Some(Bson::Array(items)), Value::Array(values)) => values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| json_to_bson(value, items.get(i)))
+            .collect::<Result<_, _>>()
+            .map(Bson::Array),
+        (Some(Bson::Document(document)), Value::Object(fields)) if !is_extjson(fields) => fields
+            .iter()
+            .map(|(field, value)| Ok((field.clone(), json_to_bson(value, document.get(field))?)))
+            .collect::<Result<_, String>>()
+            .map(Bson::Document),
+        _ => Bson::try_from(value.clone()).map_err(|e| e.to_string()),
+    }
+}
+
+/// Extended JSON wrappers such as `{"$oid": ...}`
+fn is_extjson(fields: &Map<String, Value>) -> bool {
+    fields.keys().any(|field| field.starts_with('$'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_keep_their_type() {
+        let oid = ObjectId::new();
+        assert_eq!(
+            json_to_bson(&json!(oid.to_hex()), Some(&Bson::ObjectId(ObjectId::new()))),
+            Ok(Bson::ObjectId(oid))
+        );
+        assert_eq!(
+            json_to_bson(&json!(7), Some(&Bson::Int64(1))),
+            Ok(Bson::Int64(7))
+        );
+        assert_eq!(
+            json_to_bson(&json!(7), Some(&Bson::Double(1.5))),
+            Ok(Bson::Double(7.0))
+        );
+        assert!(json_to_bson(&json!(1_i64 << 40), Some(&Bson::Int32(1))).is_err());
+        assert_eq!(
+            json_to_bson(
+                &json!("2024-05-01T10:00:00Z"),
+                Some(&Bson::DateTime(DateTime::now()))
+            ),
+            Ok(Bson::DateTime(
+                DateTime::parse_rfc3339_str("2024-05-01T10:00:00Z").unwrap()
+            ))
+        );
+        // Nested fields follow the stored document
+        let stored = Bson::Document(doc! { "n": Bson::Int64(1), "tags": ["a"] });
+        assert_eq!(
+            json_to_bson(&json!({ "n": 2, "tags": ["b", 3] }), Some(&stored)),
+            Ok(Bson::Document(
+                doc! { "n": Bson::Int64(2), "tags": ["b", 3] }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_new_values_as_extended_json() {
+        let oid = ObjectId::new();
+        assert_eq!(
+            json_to_bson(&json!({ "$oid": oid.to_hex() }), None),
+            Ok(Bson::ObjectId(oid))
+        );
+        assert_eq!(json_to_bson(&json!("text"), None), Ok(Bson::from("text")));
+        assert_eq!(json_to_bson(&json!(3), None), Ok(Bson::Int32(3)));
+    }
+
+    #[test]
+    fn test_whole_doubles_match_integers() {
+        let stored = bson_to_json(&Bson::Double(7.0));
+        assert!(same_value(&stored, &json!(7)));
+        assert!(same_value(&stored, &json!(7.0)));
+        assert!(!same_value(&stored, &json!(8)));
+        assert!(!same_value(&bson_to_json(&Bson::Double(7.5)), &json!(7)));
+        assert!(!same_value(&stored, &json!("7")));
+        assert!(same_value(&bson_to_json(&Bson::Int64(7)), &json!(7)));
+
+        let stored = bson_to_json(&Bson::Document(doc! { "price": 2.0, "sizes": [1.0, 2.5] }));
+        assert!(same_value(
+            &stored,
+            &json!({ "price": 2, "sizes": [1, 2.5] })
+        ));
+        assert!(!same_value(&stored, &json!({ "price": 2 })));
+    }
+
+    #[test]
+    fn test_document_keys_round_trip() {
+        let oid = ObjectId::new();
+        for id in [Bson::ObjectId(oid), Bson::from("007"), Bson::Int32(7)] {
+            let key = document_key(&doc! { "_id": id.clone(), "name": "a" });
+            assert_eq!(document_id(&key), Ok(id));
+        }
+    }
+
+    #[test]
+    fn test_field_at_dotted_paths() {
+        let document = doc! { "address": { "city": "Oslo" }, "tags": ["a", "b"] };
+        assert_eq!(
+            field_at(&document, "address.city"),
+            Some(&Bson::from("Oslo"))
+        );
+        assert_eq!(field_at(&document, "tags.1"), Some(&Bson::from("b")));
+        assert_eq!(field_at(&document, "address.zip"), None);
+    }
+}
@@ -3,9 +3,14 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use crate::error::VelocityError;
 use crate::models::connection::Connection;
-use crate::commands::database::{ExecuteResult, PendingChange, ForeignKeyInfo};
+use crate::commands::database::{ChangeOptions, ExecuteResult, PendingChange, ForeignKeyInfo};
 use super::enums::DatabasePool;
-use super::types::{ColumnInfo, TableData};
+use super::types::{ColumnInfo, ConnectionSettings, TableData};
+use crate::db::cancel::QueryRegistry;
+use crate::db::guard::ConfirmationRegistry;
+use crate::db::session::SessionRegistry;
+use crate::db::stream::StreamRegistry;
+use crate::db::undo::UndoRegistry;
 
 // Delegate functions
 use super::metadata::{list_databases, list_tables, list_views, list_functions, get_table_schema, get_table_foreign_keys};
@@ -15,6 +20,17 @@ use super::mutation::execute_changes;
 /// Global connection pool manager
 pub struct ConnectionPoolManager {
     pools: RwLock<HashMap<String, Arc<DatabasePool>>>,
+    settings: RwLock<HashMap<String, ConnectionSettings>>,
+    /// Flow-control credits of running result streams
+    pub(crate) streams: StreamRegistry,
+    /// Cancellable queries currently running, keyed by query ID
+    pub(crate) queries: QueryRegistry,
+    /// Transactions pinned to a connection, keyed by session ID
+    pub(crate) sessions: SessionRegistry,
+    /// Confirmation tokens for destructive statements on protected connections
+    pub(crate) confirmations: ConfirmationRegistry,
+    /// Committed grid edits that can be undone, per table
+    pub(crate) undo: UndoRegistry,
 }
 
 impl Default for ConnectionPoolManager {
@@ -27,6 +43,12 @@ impl ConnectionPoolManager {
     pub fn new() -> Self {
         Self {
             pools: RwLock::new(HashMap::new()),
+            settings: RwLock::new(HashMap::new()),
+            streams: StreamRegistry::default(),
+            queries: QueryRegistry::default(),
+            sessions: SessionRegistry::default(),
+            confirmations: ConfirmationRegistry::default(),
+            undo: UndoRegistry::default(),
         }
     }
 
@@ -46,12 +68,18 @@ impl ConnectionPoolManager {
 
         let mut pools = self.pools.write().await;
         pools.insert(connection.id.clone(), Arc::new(pool));
+        self.settings
+            .write()
+            .await
+            .insert(connection.id.clone(), ConnectionSettings::from(connection));
         println!("[VELOCITY] Connection stored: {}", connection.id);
         Ok(())
     }
 
     /// Disconnect and remove the pool
     pub async fn disconnect(&self, connection_id: &str) -> Result<(), VelocityError> {
+        self.rollback_sessions(connection_id).await;
+        self.settings.write().await.remove(connection_id);
         let mut pools = self.pools.write().await;
         if let Some(pool) = pools.remove(connection_id) {
             if let Ok(p) = Arc::try_unwrap(pool) {
@@ -76,6 +104,28 @@ impl ConnectionPoolManager {
         self.pools.read().await.get(connection_id).cloned()
     }
 
+    /// Execution settings of a connected pool (defaults when not connected)
+    pub async fn connection_settings(&self, connection_id: &str) -> ConnectionSettings {
+        self.settings
+            .read()
+            .await
+            .get(connection_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Reject `operation` when the connection was opened read-only
+    pub async fn ensure_writable(
+        &self,
+        connection_id: &str,
+        operation: &str,
+    ) -> Result<(), VelocityError> {
+        if self.connection_settings(connection_id).await.read_only {
+            return Err(VelocityError::ReadOnly(operation.to_string()));
+        }
+        Ok(())
+    }
+
     // --- Delegation methods ---
 
     pub async fn list_databases(&self, connection_id: &str) -> Result<Vec<String>, VelocityError> {
@@ -158,14 +208,19 @@ impl ConnectionPoolManager {
         &self,
         connection_id: &str,
         table_name: &str,
-        primary_key_column: &str,
+        primary_key_columns: &[String],
         changes: Vec<PendingChange>,
+        options: ChangeOptions,
     ) -> Result<ExecuteResult, VelocityError> {
         let pool = self
             .get_pool(connection_id)
             .await
             .ok_or_else(|| VelocityError::NotFound(format!("Connection {} not found", connection_id)))?;
-        execute_changes(&pool, table_name, primary_key_column, changes).await
+        self.ensure_writable(connection_id, "Editing data").await?;
+        let mut result = execute_changes(&pool, table_name, primary_key_columns, changes, options).await?;
+        let committed = std::mem::take(&mut result.committed);
+        self.push_undo(connection_id, table_name, options.row_identity, committed);
+        Ok(result)
     }
 
     pub async fn get_column_values(
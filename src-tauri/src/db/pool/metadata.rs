@@ -1,7 +1,7 @@
 use crate::error::VelocityError;
 use crate::commands::database::ForeignKeyInfo;
 use super::enums::DatabasePool;
-use super::types::ColumnInfo;
+use super::types::{ColumnInfo, GeneratedColumn};
 use sqlx::Row;
 
 pub async fn list_databases(pool: &DatabasePool) -> Result<Vec<String>, VelocityError> {
@@ -342,37 +342,50 @@ pub async fn get_table_schema(
 ) -> Result<Vec<ColumnInfo>, VelocityError> {
     match pool {
         DatabasePool::Postgres(pool) => {
-            let rows: Vec<(String, String, String, Option<i32>)> = sqlx::query_as(
-                r#"SELECT column_name, data_type, CASE WHEN is_nullable = 'YES' THEN 'YES' ELSE 'NO' END, character_maximum_length
-                FROM information_schema.columns WHERE table_name = $1 AND table_schema = 'public' ORDER BY ordinal_position"#
+            let rows: Vec<(String, String, String, Option<i32>, bool)> = sqlx::query_as(
+                r#"SELECT c.column_name,
+                    -- The real name of enum and array types, to cast values to
+                    CASE WHEN c.data_type IN ('USER-DEFINED', 'ARRAY')
+                        THEN format('%I.%I', c.udt_schema, c.udt_name)::regtype::text
+                        ELSE c.data_type END,
+                    CASE WHEN c.is_nullable = 'YES' THEN 'YES' ELSE 'NO' END, c.character_maximum_length,
+                    EXISTS (
+                        SELECT 1 FROM information_schema.table_constraints tc
+                        JOIN information_schema.key_column_usage k
+                            ON k.constraint_schema = tc.constraint_schema AND k.constraint_name = tc.constraint_name
+                        WHERE tc.constraint_type = 'PRIMARY KEY' AND tc.table_schema = c.table_schema
+                            AND tc.table_name = c.table_name AND k.column_name = c.column_name
+                    )
+                FROM information_schema.columns c WHERE c.table_name = $1 AND c.table_schema = 'public' ORDER BY c.ordinal_position"#
             ).bind(table_name).fetch_all(pool).await.map_err(|e| VelocityError::Query(e.to_string()))?;
 
             Ok(rows
                 .into_iter()
-                .map(|(name, data_type, nullable, max_length)| ColumnInfo {
+                .map(|(name, data_type, nullable, max_length, is_primary_key)| ColumnInfo {
                     name,
                     data_type,
                     nullable: nullable == "YES",
                     max_length,
-                    is_primary_key: false,
+                    is_primary_key,
                 })
                 .collect())
         }
         DatabasePool::MySQL(pool) => {
-            let rows: Vec<(String, String, String, Option<i64>)> = sqlx::query_as(
-                r#"SELECT COLUMN_NAME, DATA_TYPE, IS_NULLABLE, CHARACTER_MAXIMUM_LENGTH
-                FROM INFORMATION_SCHEMA.COLUMNS WHERE TABLE_NAME = ? ORDER BY ORDINAL_POSITION"#
+            let rows: Vec<(String, String, String, Option<i64>, String)> = sqlx::query_as(
+                r#"SELECT COLUMN_NAME, DATA_TYPE, IS_NULLABLE, CHARACTER_MAXIMUM_LENGTH, COLUMN_KEY
+                FROM INFORMATION_SCHEMA.COLUMNS WHERE TABLE_NAME = ? AND TABLE_SCHEMA = DATABASE()
+                ORDER BY ORDINAL_POSITION"#
             ).bind(table_name).fetch_all(pool).await.map_err(|e| VelocityError::Query(e.to_string()))?;
 
             Ok(rows
                 .into_iter()
-                .map(|(name, data_type, nullable, max_length)| ColumnInfo {
+                .map(|(name, data_type, nullable, max_length, column_key)| ColumnInfo {
                     name,
                     data_type,
                     nullable: nullable == "YES",
                     max_length: max_length.map(|l| l as i32),
-                    is_primary_key: false,
-                    })
+                    is_primary_key: column_key == "PRI",
+                })
                 .collect())
         }
         DatabasePool::SQLite(pool) => {
@@ -389,7 +402,8 @@ pub async fn get_table_schema(
                     data_type,
                     nullable: notnull == 0,
                     max_length: None,
-                    is_primary_key: pk == 1,
+                    // Position within the primary key, 0 for other columns
+                    is_primary_key: pk > 0,
                 })
                 .collect())
         }
@@ -414,3 +428,57 @@ pub async fn get_table_schema(
         }
     }
 }
+
+/// Columns of a table that inserts may not simply set
+pub async fn get_generated_columns(
+    pool: &DatabasePool,
+    table_name: &str,
+) -> Result<Vec<GeneratedColumn>, VelocityError> {
+    match pool {
+        DatabasePool::Postgres(pool) => {
+            let rows: Vec<(String, bool)> = sqlx::query_as(
+                r#"SELECT column_name, is_identity = 'YES'
+                FROM information_schema.columns
+                WHERE table_name = $1 AND table_schema = 'public'
+                    AND (is_generated = 'ALWAYS' OR identity_generation = 'ALWAYS')"#,
+            )
+            .bind(table_name)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+            Ok(rows
+                .into_iter()
+                .map(|(name, identity)| GeneratedColumn { name, identity })
+                .collect())
+        }
+        DatabasePool::MySQL(pool) => {
+            let rows: Vec<(String,)> = sqlx::query_as(
+                r#"SELECT COLUMN_NAME FROM INFORMATION_SCHEMA.COLUMNS
+                WHERE TABLE_NAME = ? AND TABLE_SCHEMA = DATABASE() AND EXTRA LIKE '%GENERATED%'"#,
+            )
+            .bind(table_name)
+            .fetch_all(pool)
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+            Ok(rows
+                .into_iter()
+                .map(|(name,)| GeneratedColumn { name, identity: false })
+                .collect())
+        }
+        DatabasePool::SQLite(pool) => {
+            // Unlike table_info, table_xinfo lists generated columns, marked
+            // hidden 2 (virtual) or 3 (stored)
+            let rows: Vec<(String,)> =
+                sqlx::query_as("SELECT name FROM pragma_table_xinfo(?) WHERE hidden IN (2, 3)")
+                    .bind(table_name)
+                    .fetch_all(pool)
+                    .await
+                    .map_err(|e| VelocityError::Query(e.to_string()))?;
+            Ok(rows
+                .into_iter()
+                .map(|(name,)| GeneratedColumn { name, identity: false })
+                .collect())
+        }
+        _ => Ok(vec![]),
+    }
+}
@@ -1,12 +1,12 @@
 pub mod types;
 pub mod enums;
-pub mod utils;
 pub mod metadata;
 pub mod data;
 pub mod mutation;
+pub mod documents;
 pub mod manager;
 
 // Re-export key items to maintain API compatibility
 pub use manager::ConnectionPoolManager;
 pub use enums::DatabasePool;
-pub use types::{SqlServerPool, RedisPool, MongoPool, ColumnInfo, TableData};
+pub use types::{SqlServerPool, RedisPool, MongoPool, ColumnInfo, ConnectionSettings, TableData};
@@ -1,183 +1,1211 @@
-use crate::error::VelocityError;
-use crate::commands::database::{ExecuteResult, PendingChange};
+//! Applying grid edits
+//!
+//! Pending changes are turned into UPDATE, DELETE and INSERT statements and
+//! run in one transaction, each behind a savepoint so that a failing change
+//! neither aborts the transaction nor undoes the others. Depending on the
+//! apply mode, any failure rolls back the whole batch or only that change.
+//! What each committed change overwrote is returned so it can be undone,
+//! and every inserted or updated row as stored: through RETURNING on
+//! PostgreSQL and SQLite, and by selecting it again on MySQL.
+//! A dry run goes through the same steps and always rolls back. Values are
+//! bound as parameters, converted by the type of their column (see
+//! `db::params`).
+//!
+//! Rows are matched by the key the grid fetched with them: the primary key,
+//! or for tables without one `ctid`, `rowid` or all column values (see
+//! `db::identity`). A change whose full-row match hits more than one row is
+//! refused.
+//!
+//! With conflict checks on, an update also requires the edited column to
+//! still hold the value the grid loaded. If it does not, the change is
+//! reported as a conflict together with the row's current values.
+//!
+//! Changes on MongoDB collections are applied to documents instead (see
+//! `documents`).
+
+use super::documents::execute_document_changes;
 use super::enums::DatabasePool;
-use super::utils::{format_value_for_sql, format_pk_for_sql};
-use sqlx::{Connection, ConnectOptions};
+use crate::commands::database::{
+    AppliedStatement, ApplyMode, ChangeOptions, ChangeResult, ChangeStatus, ExecuteResult,
+    PendingChange, RowKey,
+};
+use crate::db::decode::{decode_mysql_value, decode_pg_value, decode_sqlite_value};
+use crate::db::dialect::SqlDialect;
+use crate::db::identity::RowIdentity;
+use crate::db::params::{
+    apply_mysql, apply_postgres, apply_sqlite, ColumnTypes, Params, Statement, StatementArgs,
+};
+use crate::db::undo::CommittedChange;
+use crate::error::VelocityError;
+use serde_json::{Map, Value};
+use sqlx::{Column, MySql, Postgres, Row, Sqlite, Transaction};
+use std::collections::HashMap;
+
+/// A transaction on one of the SQL pools
+pub(crate) enum MutationTx {
+    Postgres(Transaction<'static, Postgres>),
+    MySql(Transaction<'static, MySql>),
+    Sqlite(Transaction<'static, Sqlite>),
+}
+
+impl MutationTx {
+    /// Begin a transaction; `operation` names what is unsupported on other
+    /// database types
+    pub(crate) async fn begin(pool: &DatabasePool, operation: &str) -> Result<Self, VelocityError> {
+        let tx = match pool {
+            DatabasePool::Postgres(pool) => pool.begin().await.map(MutationTx::Postgres),
+            DatabasePool::MySQL(pool) => pool.begin().await.map(MutationTx::MySql),
+            DatabasePool::SQLite(pool) => pool.begin().await.map(MutationTx::Sqlite),
+            _ => {
+                return Err(VelocityError::Query(format!(
+                    "{} not supported for this database type",
+                    operation
+                )))
+            }
+        };
+        tx.map_err(|e| VelocityError::Query(e.to_string()))
+    }
+
+    /// Run a statement without parameters, returning the number of affected
+    /// rows
+    pub(crate) async fn execute(&mut self, sql: &str) -> Result<u64, sqlx::Error> {
+        let result = match self {
+            MutationTx::Postgres(tx) => sqlx::query(sql).execute(&mut **tx).await?.rows_affected(),
+            MutationTx::MySql(tx) => sqlx::query(sql).execute(&mut **tx).await?.rows_affected(),
+            MutationTx::Sqlite(tx) => sqlx::query(sql).execute(&mut **tx).await?.rows_affected(),
+        };
+        Ok(result)
+    }
+
+    /// Run a statement with bound values, returning the number of affected
+    /// rows and the id an INSERT generated: the AUTO_INCREMENT value on
+    /// MySQL, the rowid on SQLite
+    pub(crate) async fn execute_statement(
+        &mut self,
+        statement: &Statement,
+    ) -> Result<(u64, Option<i64>), sqlx::Error> {
+        let result = match (self, &statement.args) {
+            (MutationTx::Postgres(tx), StatementArgs::Postgres(values)) => {
+                let query = apply_postgres(sqlx::query(&statement.sql), values.clone());
+                (query.execute(&mut **tx).await?.rows_affected(), None)
+            }
+            (MutationTx::MySql(tx), StatementArgs::Loose(values)) => {
+                let query = apply_mysql(sqlx::query(&statement.sql), values.clone());
+                let result = query.execute(&mut **tx).await?;
+                let id = result.last_insert_id();
+                (result.rows_affected(), (id > 0).then_some(id as i64))
+            }
+            (MutationTx::Sqlite(tx), StatementArgs::Loose(values)) => {
+                let query = apply_sqlite(sqlx::query(&statement.sql), values.clone());
+                let result = query.execute(&mut **tx).await?;
+                (result.rows_affected(), Some(result.last_insert_rowid()))
+            }
+            _ => return Err(foreign_statement()),
+        };
+        Ok(result)
+    }
+
+    /// Run a transaction control statement such as SAVEPOINT
+    pub(crate) async fn control(&mut self, sql: &str) -> Result<(), VelocityError> {
+        self.execute(sql)
+            .await
+            .map(|_| ())
+            .map_err(|e| VelocityError::Query(e.to_string()))
+    }
+
+    /// First row returned by a statement, keyed by column name
+    pub(crate) async fn fetch_row(
+        &mut self,
+        statement: &Statement,
+    ) -> Result<Option<Map<String, Value>>, sqlx::Error> {
+        let row = match (self, &statement.args) {
+            (MutationTx::Postgres(tx), StatementArgs::Postgres(values)) => {
+                apply_postgres(sqlx::query(&statement.sql), values.clone())
+                    .fetch_optional(&mut **tx)
+                    .await?
+                    .map(|row| row_object(&row, decode_pg_value))
+            }
+            (MutationTx::MySql(tx), StatementArgs::Loose(values)) => {
+                apply_mysql(sqlx::query(&statement.sql), values.clone())
+                    .fetch_optional(&mut **tx)
+                    .await?
+                    .map(|row| row_object(&row, decode_mysql_value))
+            }
+            (MutationTx::Sqlite(tx), StatementArgs::Loose(values)) => {
+                apply_sqlite(sqlx::query(&statement.sql), values.clone())
+                    .fetch_optional(&mut **tx)
+                    .await?
+                    .map(|row| row_object(&row, decode_sqlite_value))
+            }
+            _ => return Err(foreign_statement()),
+        };
+        Ok(row)
+    }
+
+    pub(crate) async fn commit(self) -> Result<(), VelocityError> {
+        let result = match self {
+            MutationTx::Postgres(tx) => tx.commit().await,
+            MutationTx::MySql(tx) => tx.commit().await,
+            MutationTx::Sqlite(tx) => tx.commit().await,
+        };
+        result.map_err(|e| VelocityError::Query(e.to_string()))
+    }
+
+    pub(crate) async fn rollback(self) -> Result<(), VelocityError> {
+        let result = match self {
+            MutationTx::Postgres(tx) => tx.rollback().await,
+            MutationTx::MySql(tx) => tx.rollback().await,
+            MutationTx::Sqlite(tx) => tx.rollback().await,
+        };
+        result.map_err(|e| VelocityError::Query(e.to_string()))
+    }
+}
+
+/// A statement built for another database than the transaction's
+fn foreign_statement() -> sqlx::Error {
+    sqlx::Error::Protocol("statement was built for another database".to_string())
+}
+
+fn row_object<R: Row>(row: &R, decode: fn(&R, usize) -> Value) -> Map<String, Value> {
+    row.columns()
+        .iter()
+        .map(|column| (column.name().to_string(), decode(row, column.ordinal())))
+        .collect()
+}
+
+/// WHERE condition matching a row by all of its key columns
+pub(crate) fn key_filter(params: &mut Params<'_>, key: &RowKey) -> String {
+    let dialect = params.dialect();
+    key.iter()
+        .map(|(column, value)| match value {
+            Value::Null => format!("{} IS NULL", dialect.quote_identifier(column)),
+            value => {
+                let (column, value) = params.comparable(column, value);
+                format!("{} = {}", column, value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" AND ")
+}
+
+/// Stable text of a key, for looking it up
+fn key_id(key: &RowKey) -> String {
+    serde_json::to_string(key).unwrap_or_default()
+}
+
+/// Key of a row for messages, e.g. `(tenant_id = 1, id = 7)`
+pub(crate) fn describe_key(key: &RowKey) -> String {
+    let columns: Vec<String> = key
+        .iter()
+        .map(|(column, value)| format!("{} = {}", column, value))
+        .collect();
+    format!("({})", columns.join(", "))
+}
+
+/// Table and key columns of the edited rows
+struct Target<'a> {
+    dialect: SqlDialect,
+    table: String,
+    types: ColumnTypes,
+    identity: RowIdentity,
+    key_columns: &'a [String],
+    /// Updates require the edited column to still hold its old value
+    check_conflicts: bool,
+}
+
+impl Target<'_> {
+    fn row_filter(&self, params: &mut Params<'_>, key: &RowKey) -> String {
+        key_filter(params, key)
+    }
+
+    /// Keeps a full-row match on MySQL to a single row
+    fn limit(&self) -> &'static str {
+        match (self.identity, self.dialect) {
+            (RowIdentity::FullRow, SqlDialect::MySql) => " LIMIT 1",
+            _ => "",
+        }
+    }
+
+    /// What an INSERT or UPDATE returns: the whole row, plus ctid or rowid
+    /// when those identify it
+    fn returning(&self) -> String {
+        match self.identity.select_expr() {
+            Some(expr) => format!("*, {}", expr),
+            None => "*".to_string(),
+        }
+    }
+
+    /// Split a returned row into its key and the table's own columns
+    fn persisted(&self, mut row: Map<String, Value>) -> (RowKey, Map<String, Value>) {
+        let key = self.key_of(&row);
+        if self.identity.select_expr().is_some() {
+            for column in self.key_columns {
+                row.remove(column);
+            }
+        }
+        (key, row)
+    }
+
+    /// Current values of a row; None when it is gone
+    async fn fetch(
+        &self,
+        tx: &mut MutationTx,
+        key: &RowKey,
+    ) -> Result<Option<Map<String, Value>>, ChangeError> {
+        let select = self.types.statement(|params| {
+            format!(
+                "SELECT * FROM {} WHERE {} LIMIT 1",
+                self.table,
+                self.row_filter(params, key)
+            )
+        });
+        select.check()?;
+        Ok(tx.fetch_row(&select).await?)
+    }
+
+    /// Refuse a full-row match that hits more than one row
+    async fn check_unique(&self, tx: &mut MutationTx, key: &RowKey) -> Result<(), ChangeError> {
+        if self.identity != RowIdentity::FullRow {
+            return Ok(());
+        }
+        let count = self.types.statement(|params| {
+            format!(
+                "SELECT COUNT(*) AS matches FROM {} WHERE {}",
+                self.table,
+                self.row_filter(params, key)
+            )
+        });
+        count.check()?;
+        let matches = tx
+            .fetch_row(&count)
+            .await?
+            .and_then(|row| row.get("matches").and_then(Value::as_u64))
+            .unwrap_or(0);
+        if matches > 1 {
+            return Err(ChangeError::from(format!(
+                "{} rows hold the same values; refusing to change more than one",
+                matches
+            )));
+        }
+        Ok(())
+    }
+
+    /// The key columns of a returned row
+    fn key_of(&self, row: &Map<String, Value>) -> RowKey {
+        self.key_columns
+            .iter()
+            .map(|column| {
+                let value = row.get(column).cloned().unwrap_or(Value::Null);
+                (column.clone(), value)
+            })
+            .collect()
+    }
+}
+
+/// Reject a change whose key does not name exactly the key columns; a
+/// partial key could match other rows
+fn check_key(key_columns: &[String], change: &PendingChange) -> Result<(), VelocityError> {
+    let matches = change.key.len() == key_columns.len()
+        && key_columns.iter().all(|c| change.key.contains_key(c));
+    if matches || change.change_type == "insert" {
+        return Ok(());
+    }
+    Err(VelocityError::Query(format!(
+        "Row key {} does not match the key columns ({})",
+        describe_key(&change.key),
+        key_columns.join(", ")
+    )))
+}
+
+/// Savepoint set before each change so a failure only undoes that change
+const CHANGE_SAVEPOINT: &str = "velocity_change";
+
+/// Why a change failed
+struct ChangeError {
+    message: String,
+    code: Option<String>,
+    /// The row was changed since it was loaded
+    conflict: bool,
+    /// Current values of a conflicting row; None when it is gone
+    current: Option<Map<String, Value>>,
+}
+
+impl ChangeError {
+    fn conflict(key: &RowKey, current: Option<Map<String, Value>>) -> Self {
+        let message = match current {
+            Some(_) => format!("Row {} was changed since it was loaded", describe_key(key)),
+            None => format!("Row {} no longer exists", describe_key(key)),
+        };
+        Self {
+            message,
+            code: None,
+            conflict: true,
+            current,
+        }
+    }
+}
+
+/// A plain error message, such as a value that could not be converted to its
+/// column type or a refused change
+impl From<String> for ChangeError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            code: None,
+            conflict: false,
+            current: None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for ChangeError {
+    fn from(error: sqlx::Error) -> Self {
+        Self {
+            code: error_code(&error),
+            message: error.to_string(),
+            conflict: false,
+            current: None,
+        }
+    }
+}
+
+/// What a change did
+struct Applied {
+    rows_affected: u64,
+    /// What to record for undo
+    undo: Option<CommittedChange>,
+    /// Key and stored values of an inserted row
+    persisted: Option<(RowKey, Map<String, Value>)>,
+}
+
+/// Result of a single change
+type ChangeOutcome = Result<Applied, ChangeError>;
 
 pub async fn execute_changes(
     pool: &DatabasePool,
     table_name: &str,
-    primary_key_column: &str,
+    primary_key_columns: &[String],
     changes: Vec<PendingChange>,
+    options: ChangeOptions,
 ) -> Result<ExecuteResult, VelocityError> {
-    let mut rows_affected: i64 = 0;
-    let mut errors: Vec<String> = Vec::new();
-
-    match pool {
-        DatabasePool::Postgres(pool) => {
-            // Start transaction
-            let mut tx = pool
-                .begin()
-                .await
-                .map_err(|e| VelocityError::Query(e.to_string()))?;
-
-            for change in changes {
-                let result = match change.change_type.as_str() {
-                    "update" => {
-                        // Use raw SQL with properly formatted value to preserve types
-                        let formatted_value = format_value_for_sql(&change.new_value);
-                        let sql = format!(
-                            "UPDATE \"{}\" SET \"{}\" = {} WHERE \"{}\" = {}",
-                            table_name,
-                            change.column,
-                            formatted_value,
-                            primary_key_column,
-                            format_pk_for_sql(&change.row_id)
-                        );
-                        sqlx::query(&sql).execute(&mut *tx).await
-                    }
-                    "delete" => {
-                        let sql = format!(
-                            "DELETE FROM \"{}\" WHERE \"{}\" = {}",
-                            table_name,
-                            primary_key_column,
-                            format_pk_for_sql(&change.row_id)
-                        );
-                        sqlx::query(&sql).execute(&mut *tx).await
-                    }
-                    "insert" => {
-                        let formatted_value = format_value_for_sql(&change.new_value);
-                        let sql = format!(
-                            "INSERT INTO \"{}\" (\"{}\") VALUES ({})",
-                            table_name, change.column, formatted_value
-                        );
-                        sqlx::query(&sql).execute(&mut *tx).await
-                    }
-                    _ => continue,
-                };
+    if primary_key_columns.is_empty() {
+        return Err(VelocityError::Query(
+            "Editing rows requires at least one key column".to_string(),
+        ));
+    }
+    for change in &changes {
+        check_key(primary_key_columns, change)?;
+    }
+    if let DatabasePool::MongoDB(pool) = pool {
+        return execute_document_changes(pool, table_name, changes, options).await;
+    }
+    let mut results: Vec<ChangeResult> = Vec::with_capacity(changes.len());
+    let mut statements: Vec<AppliedStatement> = Vec::new();
+    let mut committed: Vec<CommittedChange> = Vec::new();
+
+    let Some(dialect) = SqlDialect::from_pool(pool) else {
+        return Err(VelocityError::Query(
+            "Execute changes not supported for this database type".to_string(),
+        ));
+    };
+    // Read before the transaction holds a connection of the pool
+    let types = ColumnTypes::load(pool, dialect, table_name).await?;
+
+    let mut tx = MutationTx::begin(pool, "Execute changes").await?;
+    let target = Target {
+        dialect,
+        table: dialect.quote_identifier(table_name),
+        types,
+        identity: options.row_identity,
+        key_columns: primary_key_columns,
+        check_conflicts: options.check_conflicts,
+    };
+    // Keys of rows updated earlier in the batch, by the key the grid sent
+    let mut moved: HashMap<String, RowKey> = HashMap::new();
+
+    for (index, mut change) in changes.into_iter().enumerate() {
+        let change_type = change.change_type.clone();
+        let sent_key = key_id(&change.key);
+        if let Some(key) = moved.get(&sent_key) {
+            change.key = key.clone();
+        }
+        tx.control(&format!("SAVEPOINT {}", CHANGE_SAVEPOINT))
+            .await?;
 
-                match result {
-                    Ok(r) => rows_affected += r.rows_affected() as i64,
-                    Err(e) => errors.push(format!("{}: {}", change.change_type, e)),
+        let result = match apply_change(&mut tx, &target, change).await {
+            None => ChangeResult {
+                index,
+                status: ChangeStatus::Skipped,
+                rows_affected: None,
+                error: Some(format!(
+                    "Change type '{}' is not supported for this database",
+                    change_type
+                )),
+                error_code: None,
+                key: None,
+                row: None,
+                current: None,
+                change_type,
+            },
+            Some((statement, Ok(applied))) => {
+                if let Some(CommittedChange::Update { key, .. }) = &applied.undo {
+                    moved.insert(sent_key, key.clone());
+                }
+                committed.extend(applied.undo);
+                let rows = applied.rows_affected as i64;
+                statements.push(AppliedStatement {
+                    sql: statement.to_string(),
+                    rows_affected: Some(rows),
+                    error: None,
+                });
+                let (key, row) = applied.persisted.unzip();
+                ChangeResult {
+                    index,
+                    change_type,
+                    status: ChangeStatus::Applied,
+                    rows_affected: Some(rows),
+                    error: None,
+                    error_code: None,
+                    key,
+                    row,
+                    current: None,
                 }
             }
-
-            if errors.is_empty() {
-                tx.commit()
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
-            } else {
-                tx.rollback()
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
+            Some((statement, Err(e))) => {
+                // Undo just this change; the transaction stays usable
+                tx.control(&format!("ROLLBACK TO SAVEPOINT {}", CHANGE_SAVEPOINT))
+                    .await?;
+                statements.push(AppliedStatement {
+                    sql: statement.to_string(),
+                    rows_affected: None,
+                    error: Some(e.message.clone()),
+                });
+                ChangeResult {
+                    index,
+                    change_type,
+                    status: if e.conflict {
+                        ChangeStatus::Conflict
+                    } else {
+                        ChangeStatus::Failed
+                    },
+                    rows_affected: None,
+                    error: Some(e.message),
+                    error_code: e.code,
+                    key: None,
+                    row: None,
+                    current: e.current,
+                }
             }
+        };
+        tx.control(&format!("RELEASE SAVEPOINT {}", CHANGE_SAVEPOINT))
+            .await?;
+        results.push(result);
+    }
+
+    let failed = results.iter().any(|r| r.status.is_failure());
+    let rolled_back = failed && options.mode == ApplyMode::AllOrNothing;
+    if rolled_back {
+        for result in results
+            .iter_mut()
+            .filter(|r| r.status == ChangeStatus::Applied)
+        {
+            result.status = ChangeStatus::RolledBack;
+            result.key = None;
+            result.row = None;
         }
-        DatabasePool::MySQL(pool) => {
-            let mut tx = pool
-                .begin()
-                .await
-                .map_err(|e| VelocityError::Query(e.to_string()))?;
-
-            for change in changes {
-                let result = match change.change_type.as_str() {
-                    "update" => {
-                        let formatted_value = format_value_for_sql(&change.new_value);
-                        let sql = format!(
-                            "UPDATE `{}` SET `{}` = {} WHERE `{}` = {}",
-                            table_name,
-                            change.column,
-                            formatted_value,
-                            primary_key_column,
-                            format_pk_for_sql(&change.row_id)
-                        );
-                        sqlx::query(&sql).execute(&mut *tx).await
-                    }
-                    "delete" => {
-                        let sql = format!(
-                            "DELETE FROM `{}` WHERE `{}` = {}",
-                            table_name,
-                            primary_key_column,
-                            format_pk_for_sql(&change.row_id)
-                        );
-                        sqlx::query(&sql).execute(&mut *tx).await
+    }
+    if rolled_back || options.dry_run {
+        // Counts of a dry run stay as they were inside the transaction
+        tx.rollback().await?;
+        if !options.dry_run {
+            mark_rolled_back(&mut statements);
+        }
+        committed.clear();
+    } else {
+        tx.commit().await?;
+    }
+
+    let rows_affected = results
+        .iter()
+        .filter(|r| r.status == ChangeStatus::Applied)
+        .filter_map(|r| r.rows_affected)
+        .sum();
+    let errors = results
+        .iter()
+        .filter(|r| r.status.is_failure())
+        .filter_map(|r| {
+            r.error
+                .as_ref()
+                .map(|error| format!("{}: {}", r.change_type, error))
+        })
+        .collect();
+
+    Ok(ExecuteResult {
+        success: !failed,
+        rows_affected,
+        errors,
+        changes: results,
+        dry_run: options.dry_run,
+        statements,
+        committed,
+    })
+}
+
+/// SQLSTATE on PostgreSQL and MySQL, extended result code on SQLite
+pub(crate) fn error_code(error: &sqlx::Error) -> Option<String> {
+    error
+        .as_database_error()
+        .and_then(|e| e.code())
+        .map(|code| code.into_owned())
+}
+
+/// Run one change, returning the statement sent and its outcome. None for
+/// change types the database does not support.
+async fn apply_change(
+    tx: &mut MutationTx,
+    target: &Target<'_>,
+    change: PendingChange,
+) -> Option<(Statement, ChangeOutcome)> {
+    let dialect = target.dialect;
+    match change.change_type.as_str() {
+        "update" => {
+            // Return the row as stored, with its new ctid; MySQL reads it back
+            let returning = match dialect {
+                SqlDialect::MySql => String::new(),
+                _ => format!(" RETURNING {}", target.returning()),
+            };
+            let check_conflict = target.check_conflicts && !change.force;
+            let statement = target.types.statement(|params| {
+                let set = params.bind(&change.column, &change.new_value);
+                let mut filter = target.row_filter(params, &change.key);
+                if check_conflict {
+                    filter.push_str(" AND ");
+                    filter.push_str(&params.holds(&change.column, &change.old_value));
+                }
+                format!(
+                    "UPDATE {} SET {} = {} WHERE {}{}{}",
+                    target.table,
+                    dialect.quote_identifier(&change.column),
+                    set,
+                    filter,
+                    target.limit(),
+                    returning
+                )
+            });
+            let outcome = async {
+                statement.check()?;
+                target.check_unique(tx, &change.key).await?;
+                let (rows, key, row) = if returning.is_empty() {
+                    let (rows, _) = tx.execute_statement(&statement).await?;
+                    let mut key = change.key.clone();
+                    // The key moves along when one of its columns is edited
+                    if let Some(value) = key.get_mut(&change.column) {
+                        *value = change.new_value.clone();
                     }
-                    _ => continue,
+                    let row = match rows {
+                        0 => None,
+                        _ => target.fetch(tx, &key).await?,
+                    };
+                    (rows, key, row)
+                } else {
+                    let row = tx.fetch_row(&statement).await?;
+                    let key = row
+                        .as_ref()
+                        .map_or_else(|| change.key.clone(), |row| target.key_of(row));
+                    (u64::from(row.is_some()), key, row)
                 };
-
-                match result {
-                    Ok(r) => rows_affected += r.rows_affected() as i64,
-                    Err(e) => errors.push(format!("{}: {}", change.change_type, e)),
+                if rows == 0 && check_conflict {
+                    let current = target.fetch(tx, &change.key).await?;
+                    return Err(ChangeError::conflict(&change.key, current));
                 }
+                // Undo checks the row still holds the value as stored
+                let stored = row.as_ref().and_then(|row| row.get(&change.column));
+                let undo = (rows > 0).then(|| CommittedChange::Update {
+                    key,
+                    new_value: stored.cloned().unwrap_or(change.new_value),
+                    column: change.column,
+                    old_value: change.old_value,
+                });
+                Ok(Applied {
+                    rows_affected: rows,
+                    undo,
+                    persisted: row.map(|row| target.persisted(row)),
+                })
             }
-
-            if errors.is_empty() {
-                tx.commit()
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
-            } else {
-                tx.rollback()
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
+            .await;
+            Some((statement, outcome))
+        }
+        "delete" => {
+            let statement = target.types.statement(|params| {
+                format!(
+                    "DELETE FROM {} WHERE {}{}",
+                    target.table,
+                    target.row_filter(params, &change.key),
+                    target.limit()
+                )
+            });
+            let outcome = async {
+                statement.check()?;
+                target.check_unique(tx, &change.key).await?;
+                // Keep the whole row so undo can insert it again
+                let row = target.fetch(tx, &change.key).await?;
+                let (rows, _) = tx.execute_statement(&statement).await?;
+                let undo = row.filter(|_| rows > 0).map(|row| CommittedChange::Delete {
+                    key: change.key,
+                    row,
+                });
+                Ok(Applied {
+                    rows_affected: rows,
+                    undo,
+                    persisted: None,
+                })
             }
+            .await;
+            Some((statement, outcome))
         }
-        DatabasePool::SQLite(pool) => {
-            let mut tx = pool
-                .begin()
-                .await
-                .map_err(|e| VelocityError::Query(e.to_string()))?;
-
-            for change in changes {
-                let result = match change.change_type.as_str() {
-                    "update" => {
-                        let formatted_value = format_value_for_sql(&change.new_value);
-                        let sql = format!(
-                            "UPDATE \"{}\" SET \"{}\" = {} WHERE \"{}\" = {}",
-                            table_name,
-                            change.column,
-                            formatted_value,
-                            primary_key_column,
-                            format_pk_for_sql(&change.row_id)
-                        );
-                        sqlx::query(&sql).execute(&mut *tx).await
+        "insert" => {
+            // Return the stored row, with its generated key and defaults
+            let statement = target.types.statement(|params| {
+                let mut sql = insert_sql(target, params, &change.values);
+                if dialect != SqlDialect::MySql {
+                    sql.push_str(&format!(" RETURNING {}", target.returning()));
+                }
+                sql
+            });
+            let outcome = async {
+                statement.check()?;
+                let (rows, persisted) = match dialect {
+                    SqlDialect::MySql => {
+                        let (rows, generated_id) = tx.execute_statement(&statement).await?;
+                        let persisted =
+                            mysql_inserted_row(tx, target, &change.values, generated_id).await?;
+                        (rows, persisted)
                     }
-                    "delete" => {
-                        let sql = format!(
-                            "DELETE FROM \"{}\" WHERE \"{}\" = {}",
-                            table_name,
-                            primary_key_column,
-                            format_pk_for_sql(&change.row_id)
-                        );
-                        sqlx::query(&sql).execute(&mut *tx).await
+                    _ => {
+                        let row = tx.fetch_row(&statement).await?;
+                        (
+                            u64::from(row.is_some()),
+                            row.map(|row| target.persisted(row)),
+                        )
                     }
-                    _ => continue,
                 };
-
-                match result {
-                    Ok(r) => rows_affected += r.rows_affected() as i64,
-                    Err(e) => errors.push(format!("{}: {}", change.change_type, e)),
-                }
+                // Without a known key undo deletes the row by its values
+                let undo = persisted
+                    .as_ref()
+                    .map_or_else(|| change.values.clone(), |(key, _)| key.clone());
+                let undo = (rows > 0 && !undo.is_empty()).then(|| CommittedChange::Insert {
+                    key: undo,
+                    values: as_stored(change.values, persisted.as_ref().map(|(_, row)| row)),
+                });
+                Ok(Applied {
+                    rows_affected: rows,
+                    undo,
+                    persisted,
+                })
             }
+            .await;
+            Some((statement, outcome))
+        }
+        _ => None,
+    }
+}
+
+/// Sent values replaced by what the row stores, which may differ: `1.50`
+/// kept as `1.5`, a `char(n)` padded with spaces
+fn as_stored(sent: Map<String, Value>, row: Option<&Map<String, Value>>) -> Map<String, Value> {
+    sent.into_iter()
+        .map(|(column, value)| {
+            let stored = row.and_then(|row| row.get(&column)).cloned();
+            (column, stored.unwrap_or(value))
+        })
+        .collect()
+}
+
+/// INSERT of one row; a row without values gets the column defaults
+fn insert_sql(target: &Target<'_>, params: &mut Params<'_>, values: &Map<String, Value>) -> String {
+    let dialect = target.dialect;
+    if values.is_empty() {
+        return match dialect {
+            SqlDialect::MySql => format!("INSERT INTO {} () VALUES ()", target.table),
+            _ => format!("INSERT INTO {} DEFAULT VALUES", target.table),
+        };
+    }
+    let columns: Vec<String> = values
+        .keys()
+        .map(|column| dialect.quote_identifier(column))
+        .collect();
+    let row: Vec<String> = values
+        .iter()
+        .map(|(column, value)| params.bind(column, value))
+        .collect();
+    format!(
+        "INSERT INTO {} ({}) VALUES ({})",
+        target.table,
+        columns.join(", "),
+        row.join(", ")
+    )
+}
+
+/// Key and stored values of a row just inserted on MySQL, which has no
+/// RETURNING. A key column left out of the values takes the AUTO_INCREMENT
+/// id; None when the key is still incomplete.
+async fn mysql_inserted_row(
+    tx: &mut MutationTx,
+    target: &Target<'_>,
+    values: &Map<String, Value>,
+    mut generated_id: Option<i64>,
+) -> Result<Option<(RowKey, Map<String, Value>)>, ChangeError> {
+    let mut key = RowKey::new();
+    for column in target.key_columns {
+        let value = match values.get(column) {
+            Some(value) => value.clone(),
+            None => match generated_id.take() {
+                Some(id) => Value::from(id),
+                None => return Ok(None),
+            },
+        };
+        key.insert(column.clone(), value);
+    }
+    let row = target.fetch(tx, &key).await?;
+    Ok(row.map(|row| (key, row)))
+}
+
+/// Statements that succeeded before the transaction was rolled back did not
+/// change anything after all
+pub(crate) fn mark_rolled_back(statements: &mut [AppliedStatement]) {
+    for statement in statements.iter_mut().filter(|s| s.error.is_none()) {
+        statement.rows_affected = None;
+        statement.error = Some("Rolled back because another change failed".to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
 
-            if errors.is_empty() {
-                tx.commit()
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
-            } else {
-                tx.rollback()
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
+    /// An in-memory SQLite database holding the table `t` under test
+    struct Fixture {
+        sqlite: SqlitePool,
+        pool: DatabasePool,
+    }
+
+    impl Fixture {
+        async fn new(schema: &str) -> Self {
+            let sqlite = SqlitePoolOptions::new()
+                .max_connections(1)
+                .connect("sqlite::memory:")
+                .await
+                .unwrap();
+            sqlx::query(schema).execute(&sqlite).await.unwrap();
+            Self {
+                pool: DatabasePool::SQLite(sqlite.clone()),
+                sqlite,
             }
         }
-        _ => {
-            return Err(VelocityError::Query(
-                "Execute changes not supported for this database type".to_string(),
-            ));
+
+        /// `t(id, name NOT NULL)` holding the rows 1 'a', 2 'b' and 3 'c'
+        async fn named_rows() -> Self {
+            Self::new(
+                "CREATE TABLE t(id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+                 INSERT INTO t VALUES (1, 'a'), (2, 'b'), (3, 'c')",
+            )
+            .await
+        }
+
+        /// Applies the changes to `t`, telling rows apart by `key_columns`
+        async fn apply(
+            &self,
+            key_columns: &[&str],
+            changes: Vec<PendingChange>,
+            options: ChangeOptions,
+        ) -> ExecuteResult {
+            let key_columns: Vec<String> = key_columns.iter().map(|c| c.to_string()).collect();
+            execute_changes(&self.pool, "t", &key_columns, changes, options)
+                .await
+                .unwrap()
+        }
+
+        async fn execute(&self, sql: &str) {
+            sqlx::query(sql).execute(&self.sqlite).await.unwrap();
+        }
+
+        async fn rows<T>(&self, sql: &str) -> Vec<T>
+        where
+            T: for<'r> sqlx::FromRow<'r, sqlx::sqlite::SqliteRow> + Send + Unpin,
+        {
+            sqlx::query_as(sql).fetch_all(&self.sqlite).await.unwrap()
+        }
+
+        async fn names(&self) -> Vec<(i64, String)> {
+            self.rows("SELECT id, name FROM t ORDER BY id").await
         }
     }
 
-    Ok(ExecuteResult {
-        success: errors.is_empty(),
-        rows_affected,
-        errors,
-    })
+    fn key(value: Value) -> RowKey {
+        match value {
+            Value::Object(key) => key,
+            _ => panic!("not a key: {}", value),
+        }
+    }
+
+    fn change(change_type: &str, key: RowKey) -> PendingChange {
+        PendingChange {
+            key,
+            column: String::new(),
+            old_value: Value::Null,
+            new_value: Value::Null,
+            values: Map::new(),
+            force: false,
+            change_type: change_type.to_string(),
+        }
+    }
+
+    fn update(row: Value, column: &str, old_value: Value, new_value: Value) -> PendingChange {
+        PendingChange {
+            column: column.to_string(),
+            old_value,
+            new_value,
+            ..change("update", key(row))
+        }
+    }
+
+    fn statuses(result: &ExecuteResult) -> Vec<ChangeStatus> {
+        result.changes.iter().map(|c| c.status).collect()
+    }
+
+    /// Renames row 1, clears the NOT NULL name of row 2 and deletes row 3
+    fn batch_failing_in_the_middle() -> Vec<PendingChange> {
+        vec![
+            update(json!({"id": 1}), "name", json!("a"), json!("x")),
+            update(json!({"id": 2}), "name", json!("b"), Value::Null),
+            change("delete", key(json!({"id": 3}))),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_failed_change_rolls_back_the_batch() {
+        let db = Fixture::named_rows().await;
+        let result = db
+            .apply(
+                &["id"],
+                batch_failing_in_the_middle(),
+                ChangeOptions::default(),
+            )
+            .await;
+
+        assert!(!result.success);
+        assert_eq!(
+            statuses(&result),
+            vec![
+                ChangeStatus::RolledBack,
+                ChangeStatus::Failed,
+                ChangeStatus::RolledBack
+            ]
+        );
+        let failed = &result.changes[1];
+        assert_eq!(failed.index, 1);
+        // SQLITE_CONSTRAINT_NOTNULL
+        assert_eq!(failed.error_code.as_deref(), Some("1299"));
+        assert!(result.changes[0].key.is_none());
+        assert_eq!(result.rows_affected, 0);
+        assert!(result.committed.is_empty());
+        assert!(result.statements.iter().all(|s| s.rows_affected.is_none()));
+        assert_eq!(
+            db.names().await,
+            vec![(1, "a".into()), (2, "b".into()), (3, "c".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_change_leaves_the_others_applied() {
+        let db = Fixture::named_rows().await;
+        let options = ChangeOptions {
+            mode: ApplyMode::ApplySucceeded,
+            ..Default::default()
+        };
+        let result = db
+            .apply(&["id"], batch_failing_in_the_middle(), options)
+            .await;
+
+        assert!(!result.success);
+        assert_eq!(
+            statuses(&result),
+            vec![
+                ChangeStatus::Applied,
+                ChangeStatus::Failed,
+                ChangeStatus::Applied
+            ]
+        );
+        let indexes: Vec<usize> = result.changes.iter().map(|c| c.index).collect();
+        assert_eq!(indexes, vec![0, 1, 2]);
+        assert_eq!(result.changes[1].error_code.as_deref(), Some("1299"));
+        assert_eq!(
+            result.changes[0].row,
+            Some(key(json!({"id": 1, "name": "x"})))
+        );
+        assert_eq!(result.rows_affected, 2);
+        assert_eq!(result.errors.len(), 1);
+        assert_eq!(result.committed.len(), 2);
+        assert_eq!(db.names().await, vec![(1, "x".into()), (2, "b".into())]);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_rolls_back() {
+        let db = Fixture::named_rows().await;
+        let changes = vec![
+            update(json!({"id": 1}), "name", json!("a"), json!("x")),
+            change("delete", key(json!({"id": 3}))),
+        ];
+        let options = ChangeOptions {
+            mode: ApplyMode::ApplySucceeded,
+            dry_run: true,
+            ..Default::default()
+        };
+        let result = db.apply(&["id"], changes, options).await;
+
+        assert!(result.success && result.dry_run);
+        assert_eq!(
+            statuses(&result),
+            vec![ChangeStatus::Applied, ChangeStatus::Applied]
+        );
+        // What the changes would have done, though none of it was kept
+        assert_eq!(result.rows_affected, 2);
+        assert!(result.committed.is_empty());
+        assert_eq!(
+            db.names().await,
+            vec![(1, "a".into()), (2, "b".into()), (3, "c".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_full_row_match_refuses_duplicate_rows() {
+        let db = Fixture::new(
+            "CREATE TABLE t(name TEXT, qty INTEGER);
+             INSERT INTO t VALUES ('a', 1), ('a', 1), ('b', 1)",
+        )
+        .await;
+        let duplicate = json!({"name": "a", "qty": 1});
+        let changes = vec![
+            update(duplicate.clone(), "qty", json!(1), json!(2)),
+            change("delete", key(duplicate)),
+            update(json!({"name": "b", "qty": 1}), "qty", json!(1), json!(5)),
+        ];
+        let options = ChangeOptions {
+            mode: ApplyMode::ApplySucceeded,
+            row_identity: RowIdentity::FullRow,
+            ..Default::default()
+        };
+        let result = db.apply(&["name", "qty"], changes, options).await;
+
+        assert_eq!(
+            statuses(&result),
+            vec![
+                ChangeStatus::Failed,
+                ChangeStatus::Failed,
+                ChangeStatus::Applied
+            ]
+        );
+        for refused in &result.changes[..2] {
+            let error = refused.error.as_deref().unwrap();
+            assert!(
+                error.starts_with("2 rows hold the same values"),
+                "{}",
+                error
+            );
+        }
+        assert_eq!(
+            result.changes[2].key,
+            Some(key(json!({"name": "b", "qty": 5})))
+        );
+        let rows: Vec<(String, i64)> = db.rows("SELECT name, qty FROM t ORDER BY rowid").await;
+        assert_eq!(
+            rows,
+            vec![("a".into(), 1), ("a".into(), 1), ("b".into(), 5)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rowid_tells_identical_rows_apart() {
+        let db = Fixture::new(
+            "CREATE TABLE t(name TEXT);
+             INSERT INTO t VALUES ('a'), ('a')",
+        )
+        .await;
+        let changes = vec![
+            update(json!({"rowid": 2}), "name", json!("a"), json!("b")),
+            change("delete", key(json!({"rowid": 1}))),
+        ];
+        let options = ChangeOptions {
+            row_identity: RowIdentity::Rowid,
+            ..Default::default()
+        };
+        let result = db.apply(&["rowid"], changes, options).await;
+
+        assert!(result.success, "{:?}", result.errors);
+        let updated = &result.changes[0];
+        assert_eq!(updated.key, Some(key(json!({"rowid": 2}))));
+        // The rowid is returned as the key, not as a column of the row
+        assert_eq!(updated.row, Some(key(json!({"name": "b"}))));
+        let rows: Vec<(i64, String)> = db.rows("SELECT rowid, name FROM t").await;
+        assert_eq!(rows, vec![(2, "b".into())]);
+    }
+
+    #[tokio::test]
+    async fn test_insert_returns_generated_key_and_defaults() {
+        let db = Fixture::new(
+            "CREATE TABLE t(
+                id INTEGER PRIMARY KEY,
+                name TEXT,
+                qty INTEGER,
+                note TEXT,
+                status TEXT NOT NULL DEFAULT 'new'
+            )",
+        )
+        .await;
+        let changes = vec![
+            PendingChange {
+                values: key(json!({"name": "a", "qty": 3, "note": "first"})),
+                ..change("insert", RowKey::new())
+            },
+            // Every column left out
+            change("insert", RowKey::new()),
+        ];
+        let result = db.apply(&["id"], changes, ChangeOptions::default()).await;
+
+        assert!(result.success, "{:?}", result.errors);
+        // One INSERT per row, not one per column
+        assert_eq!(result.statements.len(), 2);
+        assert!(result.statements[1]
+            .sql
+            .ends_with("DEFAULT VALUES RETURNING *"));
+        let inserted = &result.changes[0];
+        assert_eq!(inserted.rows_affected, Some(1));
+        assert_eq!(inserted.key, Some(key(json!({"id": 1}))));
+        assert_eq!(
+            inserted.row,
+            Some(key(json!({
+                "id": 1, "name": "a", "qty": 3, "note": "first", "status": "new"
+            })))
+        );
+        let defaults = &result.changes[1];
+        assert_eq!(defaults.key, Some(key(json!({"id": 2}))));
+        assert_eq!(
+            defaults.row,
+            Some(key(json!({
+                "id": 2, "name": null, "qty": null, "note": null, "status": "new"
+            })))
+        );
+
+        let rows: Vec<(i64, Option<String>, String)> =
+            db.rows("SELECT id, name, status FROM t ORDER BY id").await;
+        assert_eq!(
+            rows,
+            vec![(1, Some("a".into()), "new".into()), (2, None, "new".into())]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stale_old_value_conflicts_unless_forced() {
+        let db = Fixture::named_rows().await;
+        // The grid loaded 'a' and 'c', then both rows changed behind its back
+        db.execute("UPDATE t SET name = 'z' WHERE id = 1; DELETE FROM t WHERE id = 3")
+            .await;
+        let stale = || {
+            vec![
+                update(json!({"id": 1}), "name", json!("a"), json!("x")),
+                update(json!({"id": 3}), "name", json!("c"), json!("y")),
+            ]
+        };
+        let options = ChangeOptions {
+            mode: ApplyMode::ApplySucceeded,
+            check_conflicts: true,
+            ..Default::default()
+        };
+        let result = db.apply(&["id"], stale(), options).await;
+
+        assert!(!result.success);
+        assert_eq!(
+            statuses(&result),
+            vec![ChangeStatus::Conflict, ChangeStatus::Conflict]
+        );
+        assert_eq!(
+            result.changes[0].current,
+            Some(key(json!({"id": 1, "name": "z"})))
+        );
+        // A deleted row has no current values
+        assert_eq!(result.changes[1].current, None);
+        assert!(result.committed.is_empty());
+        assert_eq!(db.names().await, vec![(1, "z".into()), (2, "b".into())]);
+
+        let forced = stale()
+            .into_iter()
+            .map(|change| PendingChange {
+                force: true,
+                ..change
+            })
+            .collect();
+        let result = db.apply(&["id"], forced, options).await;
+        assert_eq!(
+            statuses(&result),
+            vec![ChangeStatus::Applied, ChangeStatus::Applied]
+        );
+        // Forcing overwrites the row, but cannot bring back a deleted one
+        assert_eq!(result.changes[0].rows_affected, Some(1));
+        assert_eq!(result.changes[1].rows_affected, Some(0));
+        assert_eq!(db.names().await, vec![(1, "x".into()), (2, "b".into())]);
+    }
+
+    #[tokio::test]
+    async fn test_blob_cells_round_trip() {
+        let db = Fixture::new(
+            "CREATE TABLE t(id INTEGER PRIMARY KEY, data BLOB);
+             INSERT INTO t VALUES (1, x'00ff')",
+        )
+        .await;
+        let row = sqlx::query("SELECT data FROM t")
+            .fetch_one(&db.sqlite)
+            .await
+            .unwrap();
+        // As the grid shows the cell
+        let data = decode_sqlite_value(&row, 0);
+        let changes = vec![
+            update(json!({"id": 1}), "data", data.clone(), data.clone()),
+            PendingChange {
+                values: key(json!({"data": data})),
+                ..change("insert", RowKey::new())
+            },
+        ];
+        let result = db.apply(&["id"], changes, ChangeOptions::default()).await;
+
+        assert!(result.success, "{:?}", result.errors);
+        let rows: Vec<(String, Vec<u8>)> = db
+            .rows("SELECT typeof(data), data FROM t ORDER BY id")
+            .await;
+        let blob = ("blob".to_string(), vec![0, 255]);
+        assert_eq!(rows, vec![blob.clone(), blob]);
+    }
+
+    #[tokio::test]
+    async fn test_undo_keeps_the_values_as_stored() {
+        let db = Fixture::new("CREATE TABLE t(id INTEGER PRIMARY KEY, price REAL)").await;
+        let changes = vec![
+            PendingChange {
+                values: key(json!({"id": 1, "price": "1.50"})),
+                ..change("insert", RowKey::new())
+            },
+            update(json!({"id": 1}), "price", json!(1.5), json!("2.50")),
+        ];
+        let result = db.apply(&["id"], changes, ChangeOptions::default()).await;
+
+        assert!(result.success, "{:?}", result.errors);
+        match &result.committed[..] {
+            [CommittedChange::Insert { values, .. }, CommittedChange::Update { new_value, .. }] => {
+                assert_eq!(values, &key(json!({"id": 1, "price": 1.5})));
+                assert_eq!(new_value, &json!(2.5));
+            }
+            committed => panic!("unexpected undo: {:?}", committed),
+        }
+    }
 }
@@ -1,6 +1,11 @@
-
-use tiberius;
+use crate::commands::database::RowKey;
+use crate::db::classify::classify_script;
+use crate::db::dialect::SqlDialect;
+use crate::db::identity::RowIdentity;
+use crate::error::VelocityError;
+use crate::models::connection::Connection;
 use redis;
+use tiberius;
 
 /// SQL Server connection wrapper
 pub struct SqlServerPool {
@@ -28,8 +33,59 @@ pub struct ColumnInfo {
     pub is_primary_key: bool,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+/// A column the database fills in itself
+#[derive(Debug, Clone)]
+pub struct GeneratedColumn {
+    pub name: String,
+    /// A PostgreSQL `GENERATED ALWAYS AS IDENTITY` column, which takes
+    /// explicit values with `OVERRIDING SYSTEM VALUE`. Other generated
+    /// columns are computed and cannot be written at all.
+    pub identity: bool,
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct TableData {
     pub columns: Vec<String>,
     pub rows: Vec<Vec<serde_json::Value>>,
+    /// How rows are identified when editing
+    pub row_identity: RowIdentity,
+    /// Columns of the row keys
+    pub key_columns: Vec<String>,
+    /// Key of each row; None when the rows cannot be edited
+    pub row_keys: Option<Vec<RowKey>>,
+}
+
+/// Per-connection execution settings captured when the pool is created
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionSettings {
+    pub query_timeout_ms: Option<u64>,
+    pub read_only: bool,
+    pub protected: bool,
+}
+
+impl From<&Connection> for ConnectionSettings {
+    fn from(connection: &Connection) -> Self {
+        Self {
+            query_timeout_ms: connection.query_timeout_ms,
+            read_only: connection.read_only,
+            protected: connection.protected,
+        }
+    }
+}
+
+impl ConnectionSettings {
+    /// Reject statements a read-only connection must not run
+    pub fn check_statement(&self, sql: &str, dialect: SqlDialect) -> Result<(), VelocityError> {
+        if !self.read_only {
+            return Ok(());
+        }
+        match classify_script(sql, dialect)
+            .into_iter()
+            .find(|class| !class.allowed_read_only())
+        {
+            Some(class) => Err(VelocityError::ReadOnly(class.command)),
+            None => Ok(()),
+        }
+    }
 }
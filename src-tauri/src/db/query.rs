@@ -1,11 +1,33 @@
 //! Query execution module for SQL Editor
 //!
-//! Handles raw SQL query execution and EXPLAIN plans for all database types.
-
-use crate::commands::database::{ExplainResult, QueryResultData};
+//! Handles raw SQL query execution for all database types.
+
+use crate::commands::database::QueryResultData;
+use crate::db::columns::resolve_columns;
+use crate::db::decode::{decode_mysql_value, decode_pg_value, decode_sqlite_value};
+use crate::db::dialect::SqlDialect;
+use crate::db::params::{
+    apply_mysql, apply_postgres, apply_sqlite, bind_loose, bind_postgres, QueryParam,
+};
+use crate::db::script::StatementStream;
+use crate::db::timeout::resolve_timeout;
 use crate::db::{ConnectionPoolManager, DatabasePool};
 use crate::error::VelocityError;
-use sqlx::{Column, Row};
+use futures::TryStreamExt;
+use sqlx::{Database, Either, Executor, Row};
+
+/// Per-call execution settings
+#[derive(Debug, Clone, Default)]
+pub struct ExecuteOptions {
+    /// ID under which the execution can be stopped with `cancel_query`
+    pub query_id: Option<String>,
+    /// Statement timeout overriding the connection default (0 = no limit)
+    pub timeout_ms: Option<u64>,
+    /// Values for the placeholders of the statement
+    pub params: Vec<QueryParam>,
+    /// Run inside this transaction session instead of on a pooled connection
+    pub session_id: Option<String>,
+}
 
 impl ConnectionPoolManager {
     /// Execute raw SQL query and return results
@@ -14,15 +36,81 @@ impl ConnectionPoolManager {
         connection_id: &str,
         sql: &str,
     ) -> Result<QueryResultData, VelocityError> {
+        self.execute_query_with_options(connection_id, sql, &ExecuteOptions::default())
+            .await
+    }
+
+    /// Execute raw SQL query on a single connection, honouring per-call options
+    pub async fn execute_query_with_options(
+        &self,
+        connection_id: &str,
+        sql: &str,
+        options: &ExecuteOptions,
+    ) -> Result<QueryResultData, VelocityError> {
+        if let Some(session_id) = &options.session_id {
+            return self
+                .execute_in_session(session_id, connection_id, sql, options)
+                .await;
+        }
         let pool = self
             .get_pool(connection_id)
             .await
             .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+        let query_id = options.query_id.as_deref();
+        let settings = self.connection_settings(connection_id).await;
+        if let Some(dialect) = SqlDialect::from_pool(&pool) {
+            settings.check_statement(sql, dialect)?;
+        }
+        let timeout = resolve_timeout(options.timeout_ms, settings.query_timeout_ms);
 
         match pool.as_ref() {
-            DatabasePool::Postgres(pool) => Self::execute_postgres_query(pool, sql).await,
-            DatabasePool::MySQL(pool) => Self::execute_mysql_query(pool, sql).await,
-            DatabasePool::SQLite(pool) => Self::execute_sqlite_query(pool, sql).await,
+            DatabasePool::Postgres(pool) => {
+                let mut conn = pool
+                    .acquire()
+                    .await
+                    .map_err(|e| VelocityError::Connection(e.to_string()))?;
+                let mut tracked = self
+                    .queries
+                    .track_postgres(query_id, timeout, pool, &mut conn)
+                    .await?;
+                let result = tracked
+                    .run(Self::execute_postgres_query(
+                        &mut conn,
+                        sql,
+                        &options.params,
+                    ))
+                    .await;
+                tracked.release_postgres(conn).await;
+                result
+            }
+            DatabasePool::MySQL(pool) => {
+                let mut conn = pool
+                    .acquire()
+                    .await
+                    .map_err(|e| VelocityError::Connection(e.to_string()))?;
+                let mut tracked = self
+                    .queries
+                    .track_mysql(query_id, timeout, pool, &mut conn)
+                    .await?;
+                let result = tracked
+                    .run(Self::execute_mysql_query(&mut conn, sql, &options.params))
+                    .await;
+                tracked.release_mysql(conn).await;
+                result
+            }
+            DatabasePool::SQLite(pool) => {
+                let mut conn = pool
+                    .acquire()
+                    .await
+                    .map_err(|e| VelocityError::Connection(e.to_string()))?;
+                let mut tracked = self
+                    .queries
+                    .track_sqlite(query_id, timeout, &mut conn)
+                    .await?;
+                tracked
+                    .run(Self::execute_sqlite_query(&mut conn, sql, &options.params))
+                    .await
+            }
             _ => Err(VelocityError::Query(
                 "Query execution not supported for this database type".to_string(),
             )),
@@ -30,210 +118,98 @@ impl ConnectionPoolManager {
     }
 
     /// Execute PostgreSQL query
-    async fn execute_postgres_query(
-        pool: &sqlx::Pool<sqlx::Postgres>,
+    pub(crate) async fn execute_postgres_query(
+        conn: &mut sqlx::PgConnection,
         sql: &str,
+        params: &[QueryParam],
     ) -> Result<QueryResultData, VelocityError> {
-        let rows = sqlx::query(sql)
-            .fetch_all(pool)
-            .await
-            .map_err(|e| VelocityError::Query(e.to_string()))?;
-
-        let columns: Vec<String> = if !rows.is_empty() {
-            rows[0]
-                .columns()
-                .iter()
-                .map(|c| c.name().to_string())
-                .collect()
-        } else {
-            vec![]
-        };
+        let statement = bind_postgres(conn, sql, params).await?;
+        // Describe first so columns are known even when no rows come back
+        let described = (&mut *conn).describe(&statement.sql).await.ok();
+        let (rows, rows_affected) = drain_results::<sqlx::Postgres>(
+            (&mut *conn).fetch_many(apply_postgres(
+                sqlx::query(&statement.sql),
+                statement.values,
+            )),
+            |r| r.rows_affected(),
+        )
+        .await?;
 
-        let data: Vec<Vec<serde_json::Value>> = rows
+        let column_info = resolve_columns(described.as_ref(), rows.first());
+        let data = rows
             .iter()
-            .map(|row| {
-                (0..columns.len())
-                    .map(|i| Self::extract_pg_value(row, i))
-                    .collect()
-            })
+            .map(|row| (0..row.len()).map(|i| decode_pg_value(row, i)).collect())
             .collect();
-
-        let row_count = data.len() as i64;
-        Ok(QueryResultData {
-            columns,
-            rows: data,
-            row_count,
-        })
-    }
-
-    /// Extract value from PostgreSQL row
-    fn extract_pg_value(row: &sqlx::postgres::PgRow, i: usize) -> serde_json::Value {
-        if let Ok(v) = row.try_get::<Option<String>, _>(i) {
-            v.map(serde_json::Value::String)
-                .unwrap_or(serde_json::Value::Null)
-        } else if let Ok(v) = row.try_get::<Option<i64>, _>(i) {
-            v.map(|n| serde_json::Value::Number(n.into()))
-                .unwrap_or(serde_json::Value::Null)
-        } else if let Ok(v) = row.try_get::<Option<f64>, _>(i) {
-            v.and_then(|n| serde_json::Number::from_f64(n))
-                .map(serde_json::Value::Number)
-                .unwrap_or(serde_json::Value::Null)
-        } else if let Ok(v) = row.try_get::<Option<bool>, _>(i) {
-            v.map(serde_json::Value::Bool)
-                .unwrap_or(serde_json::Value::Null)
-        } else {
-            serde_json::Value::Null
-        }
+        Ok(QueryResultData::new(column_info, data).with_rows_affected(rows_affected))
     }
 
     /// Execute MySQL query
-    async fn execute_mysql_query(
-        pool: &sqlx::Pool<sqlx::MySql>,
+    pub(crate) async fn execute_mysql_query(
+        conn: &mut sqlx::MySqlConnection,
         sql: &str,
+        params: &[QueryParam],
     ) -> Result<QueryResultData, VelocityError> {
-        let rows = sqlx::query(sql)
-            .fetch_all(pool)
-            .await
-            .map_err(|e| VelocityError::Query(e.to_string()))?;
-
-        let columns: Vec<String> = if !rows.is_empty() {
-            rows[0]
-                .columns()
-                .iter()
-                .map(|c| c.name().to_string())
-                .collect()
-        } else {
-            vec![]
-        };
-
-        let data: Vec<Vec<serde_json::Value>> = rows
+        let statement = bind_loose(sql, params, SqlDialect::MySql)?;
+        // Describe first so columns are known even when no rows come back
+        let described = (&mut *conn).describe(&statement.sql).await.ok();
+        let (rows, rows_affected) = drain_results::<sqlx::MySql>(
+            (&mut *conn).fetch_many(apply_mysql(sqlx::query(&statement.sql), statement.values)),
+            |r| r.rows_affected(),
+        )
+        .await?;
+
+        let column_info = resolve_columns(described.as_ref(), rows.first());
+        let data = rows
             .iter()
-            .map(|row| {
-                (0..columns.len())
-                    .map(|i| Self::extract_mysql_value(row, i))
-                    .collect()
-            })
+            .map(|row| (0..row.len()).map(|i| decode_mysql_value(row, i)).collect())
             .collect();
-
-        let row_count = data.len() as i64;
-        Ok(QueryResultData {
-            columns,
-            rows: data,
-            row_count,
-        })
-    }
-
-    /// Extract value from MySQL row
-    fn extract_mysql_value(row: &sqlx::mysql::MySqlRow, i: usize) -> serde_json::Value {
-        if let Ok(v) = row.try_get::<Option<String>, _>(i) {
-            v.map(serde_json::Value::String)
-                .unwrap_or(serde_json::Value::Null)
-        } else if let Ok(v) = row.try_get::<Option<i64>, _>(i) {
-            v.map(|n| serde_json::Value::Number(n.into()))
-                .unwrap_or(serde_json::Value::Null)
-        } else {
-            serde_json::Value::Null
-        }
+        Ok(QueryResultData::new(column_info, data).with_rows_affected(rows_affected))
     }
 
     /// Execute SQLite query
-    async fn execute_sqlite_query(
-        pool: &sqlx::Pool<sqlx::Sqlite>,
+    pub(crate) async fn execute_sqlite_query(
+        conn: &mut sqlx::SqliteConnection,
         sql: &str,
+        params: &[QueryParam],
     ) -> Result<QueryResultData, VelocityError> {
-        let rows = sqlx::query(sql)
-            .fetch_all(pool)
-            .await
-            .map_err(|e| VelocityError::Query(e.to_string()))?;
-
-        let columns: Vec<String> = if !rows.is_empty() {
-            rows[0]
-                .columns()
-                .iter()
-                .map(|c| c.name().to_string())
-                .collect()
-        } else {
-            vec![]
-        };
-
-        let data: Vec<Vec<serde_json::Value>> = rows
+        let statement = bind_loose(sql, params, SqlDialect::Sqlite)?;
+        // Describe first so columns are known even when no rows come back
+        let described = (&mut *conn).describe(&statement.sql).await.ok();
+        let (rows, rows_affected) = drain_results::<sqlx::Sqlite>(
+            (&mut *conn).fetch_many(apply_sqlite(sqlx::query(&statement.sql), statement.values)),
+            |r| r.rows_affected(),
+        )
+        .await?;
+
+        let column_info = resolve_columns(described.as_ref(), rows.first());
+        let data = rows
             .iter()
             .map(|row| {
-                (0..columns.len())
-                    .map(|i| Self::extract_sqlite_value(row, i))
+                (0..row.len())
+                    .map(|i| decode_sqlite_value(row, i))
                     .collect()
             })
             .collect();
-
-        let row_count = data.len() as i64;
-        Ok(QueryResultData {
-            columns,
-            rows: data,
-            row_count,
-        })
-    }
-
-    /// Extract value from SQLite row
-    fn extract_sqlite_value(row: &sqlx::sqlite::SqliteRow, i: usize) -> serde_json::Value {
-        if let Ok(v) = row.try_get::<Option<String>, _>(i) {
-            v.map(serde_json::Value::String)
-                .unwrap_or(serde_json::Value::Null)
-        } else if let Ok(v) = row.try_get::<Option<i64>, _>(i) {
-            v.map(|n| serde_json::Value::Number(n.into()))
-                .unwrap_or(serde_json::Value::Null)
-        } else {
-            serde_json::Value::Null
-        }
+        Ok(QueryResultData::new(column_info, data).with_rows_affected(rows_affected))
     }
+}
 
-    /// Get query execution plan (EXPLAIN)
-    pub async fn explain_query(
-        &self,
-        connection_id: &str,
-        sql: &str,
-    ) -> Result<ExplainResult, VelocityError> {
-        let pool = self
-            .get_pool(connection_id)
-            .await
-            .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
-
-        let explain_sql = match pool.as_ref() {
-            DatabasePool::Postgres(_) => format!("EXPLAIN ANALYZE {}", sql),
-            DatabasePool::MySQL(_) => format!("EXPLAIN {}", sql),
-            DatabasePool::SQLite(_) => format!("EXPLAIN QUERY PLAN {}", sql),
-            _ => return Err(VelocityError::Query("EXPLAIN not supported".to_string())),
-        };
-
-        match pool.as_ref() {
-            DatabasePool::Postgres(pool) => {
-                let rows: Vec<(String,)> = sqlx::query_as(&explain_sql)
-                    .fetch_all(pool)
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
-                Ok(ExplainResult {
-                    plan: rows.into_iter().map(|r| r.0).collect(),
-                })
-            }
-            DatabasePool::MySQL(pool) => {
-                let rows = sqlx::query(&explain_sql)
-                    .fetch_all(pool)
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
-                let plan: Vec<String> = rows.iter().map(|row| format!("{:?}", row)).collect();
-                Ok(ExplainResult { plan })
-            }
-            DatabasePool::SQLite(pool) => {
-                let rows: Vec<(i32, i32, i32, String)> = sqlx::query_as(&explain_sql)
-                    .fetch_all(pool)
-                    .await
-                    .map_err(|e| VelocityError::Query(e.to_string()))?;
-                let plan: Vec<String> = rows
-                    .into_iter()
-                    .map(|(_, parent, _, detail)| format!("parent:{} {}", parent, detail))
-                    .collect();
-                Ok(ExplainResult { plan })
-            }
-            _ => Err(VelocityError::Query("EXPLAIN not supported".to_string())),
+/// Drain a `fetch_many` stream into its rows and the total affected row count
+async fn drain_results<DB: Database>(
+    mut stream: StatementStream<'_, DB>,
+    affected: fn(&DB::QueryResult) -> u64,
+) -> Result<(Vec<DB::Row>, u64), VelocityError> {
+    let mut rows = Vec::new();
+    let mut rows_affected = 0;
+    while let Some(item) = stream
+        .try_next()
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?
+    {
+        match item {
+            Either::Left(result) => rows_affected += affected(&result),
+            Either::Right(row) => rows.push(row),
         }
     }
+    Ok((rows, rows_affected))
 }
@@ -2,7 +2,11 @@
 //!
 //! Provides types and functions for CREATE TABLE, ALTER TABLE, indexes, and foreign keys.
 
+use crate::db::classify::{classify, StatementKind};
+use crate::db::dialect::SqlDialect;
+use crate::db::pool::mutation::{error_code, MutationTx};
 use crate::db::pool::DatabasePool;
+use crate::db::splitter::split_statements;
 use crate::error::VelocityError;
 use serde::{Deserialize, Serialize};
 
@@ -332,6 +336,90 @@ pub async fn execute_ddl(pool: &DatabasePool, sql: &str) -> Result<(), VelocityE
     Ok(())
 }
 
+/// Outcome of a DDL dry run
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRunResult {
+    /// True when every statement succeeded
+    pub success: bool,
+    pub statements: Vec<DryRunStatement>,
+}
+
+/// A statement run during a dry run
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRunStatement {
+    pub sql: String,
+    /// None when the statement failed
+    pub rows_affected: Option<i64>,
+    pub error: Option<String>,
+    /// SQLSTATE on PostgreSQL, extended result code on SQLite
+    pub error_code: Option<String>,
+}
+
+/// Savepoint set before each statement of a dry run
+const DRY_RUN_SAVEPOINT: &str = "velocity_dry_run";
+
+/// Run DDL inside a transaction and always roll it back. Each statement runs
+/// behind a savepoint, so one failing does not stop the others from being
+/// checked. MySQL commits DDL implicitly and is refused, as are statements
+/// that would end the transaction or cannot run inside one.
+pub async fn dry_run_ddl(pool: &DatabasePool, sql: &str) -> Result<DryRunResult, VelocityError> {
+    if matches!(pool, DatabasePool::MySQL(_)) {
+        return Err(VelocityError::Query(
+            "Dry runs of DDL are not supported on MySQL, which commits DDL implicitly".to_string(),
+        ));
+    }
+    let dialect = SqlDialect::from_pool(pool)
+        .ok_or_else(|| VelocityError::Query("Unsupported database type".to_string()))?;
+    let script = split_statements(sql, dialect);
+    for statement in &script {
+        let class = classify(&statement.sql, dialect);
+        let outside_transaction = dialect == SqlDialect::Sqlite
+            && matches!(class.command.as_str(), "VACUUM" | "ATTACH" | "DETACH");
+        if class.kind == StatementKind::TransactionControl || outside_transaction {
+            return Err(VelocityError::Query(format!(
+                "{} cannot be part of a dry run",
+                class.command
+            )));
+        }
+    }
+
+    let mut tx = MutationTx::begin(pool, "Dry run").await?;
+    let mut statements = Vec::new();
+    for statement in script {
+        tx.control(&format!("SAVEPOINT {}", DRY_RUN_SAVEPOINT))
+            .await?;
+        let result = match tx.execute(&statement.sql).await {
+            Ok(rows) => DryRunStatement {
+                sql: statement.sql,
+                rows_affected: Some(rows as i64),
+                error: None,
+                error_code: None,
+            },
+            Err(e) => {
+                tx.control(&format!("ROLLBACK TO SAVEPOINT {}", DRY_RUN_SAVEPOINT))
+                    .await?;
+                DryRunStatement {
+                    sql: statement.sql,
+                    rows_affected: None,
+                    error: Some(e.to_string()),
+                    error_code: error_code(&e),
+                }
+            }
+        };
+        tx.control(&format!("RELEASE SAVEPOINT {}", DRY_RUN_SAVEPOINT))
+            .await?;
+        statements.push(result);
+    }
+    tx.rollback().await?;
+
+    Ok(DryRunResult {
+        success: statements.iter().all(|s| s.error.is_none()),
+        statements,
+    })
+}
+
 /// Get indexes for a table
 pub async fn get_table_indexes(
     pool: &DatabasePool,
@@ -411,3 +499,35 @@ pub async fn get_table_indexes(
         _ => Ok(vec![]),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_dry_run_refuses_transaction_control() {
+        let sqlite = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let pool = DatabasePool::SQLite(sqlite.clone());
+
+        for sql in [
+            "CREATE TABLE t(x int); COMMIT",
+            "CREATE TABLE t(x int); end",
+            "VACUUM",
+        ] {
+            assert!(dry_run_ddl(&pool, sql).await.is_err(), "{}", sql);
+        }
+        let tables: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master")
+            .fetch_one(&sqlite)
+            .await
+            .unwrap();
+        assert_eq!(tables, 0);
+
+        let result = dry_run_ddl(&pool, "CREATE TABLE t(x int)").await.unwrap();
+        assert!(result.success);
+    }
+}
@@ -0,0 +1,390 @@
+//! Multi-statement script execution for the SQL editor
+//!
+//! Splits a script with the dialect-aware splitter and runs every statement in
+//! order on a single pooled connection, so session state (SET, temporary
+//! tables, transactions) carries over from one statement to the next. A
+//! transaction the script leaves open is rolled back before the connection
+//! goes back to the pool.
+
+use crate::db::classify::{classify, StatementKind};
+use crate::db::columns::{column_names, described_columns, row_columns, ResultColumn};
+use crate::db::decode::{decode_mysql_value, decode_pg_value, decode_sqlite_value};
+use crate::db::dialect::SqlDialect;
+use crate::db::splitter::{split_statements, ScriptStatement};
+use crate::db::timeout::resolve_timeout;
+use crate::db::{ConnectionPoolManager, DatabasePool};
+use crate::error::VelocityError;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use sqlx::{Database, Describe, Either, Executor, Row};
+use std::time::Instant;
+
+/// Result of a single statement within a script
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementResult {
+    /// Statement text as sent to the database
+    pub sql: String,
+    /// Character offset of the statement in the submitted script
+    pub offset: usize,
+    pub columns: Vec<String>,
+    /// Name, type and nullability of each column
+    pub column_info: Vec<ResultColumn>,
+    pub rows: Vec<Vec<serde_json::Value>>,
+    /// Affected rows, set for statements that return no result set
+    pub rows_affected: Option<u64>,
+    pub duration_ms: f64,
+    pub error: Option<String>,
+}
+
+/// Result of a script run, one entry per executed statement
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptResult {
+    pub results: Vec<StatementResult>,
+    pub duration_ms: f64,
+    /// The script began a transaction without ending it; it was rolled back
+    pub rolled_back: bool,
+}
+
+/// Rows and affected count collected from one statement
+struct StatementOutput {
+    columns: Vec<ResultColumn>,
+    rows: Vec<Vec<serde_json::Value>>,
+    rows_affected: u64,
+}
+
+impl ConnectionPoolManager {
+    /// Execute a multi-statement script, returning one result per statement.
+    /// When `stop_on_error` is set, statements after the first failure are not run.
+    /// Cancelling `query_id` interrupts the current statement and skips the rest.
+    /// The connection's statement timeout applies to each statement.
+    pub async fn execute_script(
+        &self,
+        connection_id: &str,
+        script: &str,
+        stop_on_error: bool,
+        query_id: Option<&str>,
+    ) -> Result<ScriptResult, VelocityError> {
+        let pool = self
+            .get_pool(connection_id)
+            .await
+            .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+        let settings = self.connection_settings(connection_id).await;
+        let timeout = resolve_timeout(None, settings.query_timeout_ms);
+
+        let dialect = SqlDialect::from_pool(&pool).ok_or_else(|| {
+            VelocityError::Query(
+                "Script execution not supported for this database type".to_string(),
+            )
+        })?;
+        settings.check_statement(script, dialect)?;
+        let statements = split_statements(script, dialect);
+        let started = Instant::now();
+        let mut results = Vec::with_capacity(statements.len());
+        let mut in_transaction = false;
+
+        match pool.as_ref() {
+            DatabasePool::Postgres(pool) => {
+                let mut conn = pool
+                    .acquire()
+                    .await
+                    .map_err(|e| VelocityError::Connection(e.to_string()))?;
+                let mut tracked = self
+                    .queries
+                    .track_postgres(query_id, timeout, pool, &mut conn)
+                    .await?;
+                for statement in &statements {
+                    let stmt_start = Instant::now();
+                    let output = tracked
+                        .run(async {
+                            let described = (&mut *conn).describe(&statement.sql).await.ok();
+                            collect_statement(
+                                described,
+                                (&mut *conn).fetch_many(sqlx::query(&statement.sql)),
+                                decode_pg_value,
+                                |r| r.rows_affected(),
+                            )
+                            .await
+                        })
+                        .await;
+                    let failed = output.is_err();
+                    if !failed {
+                        in_transaction = transaction_open(in_transaction, &statement.sql, dialect);
+                    }
+                    results.push(statement_result(script, statement, output, stmt_start));
+                    if tracked.is_cancelled() || tracked.is_expired() || (failed && stop_on_error) {
+                        break;
+                    }
+                }
+                if in_transaction
+                    && !tracked.is_expired()
+                    && (&mut *conn).execute("ROLLBACK").await.is_err()
+                {
+                    let _ = conn.close().await;
+                } else {
+                    tracked.release_postgres(conn).await;
+                }
+            }
+            DatabasePool::MySQL(pool) => {
+                let mut conn = pool
+                    .acquire()
+                    .await
+                    .map_err(|e| VelocityError::Connection(e.to_string()))?;
+                let mut tracked = self
+                    .queries
+                    .track_mysql(query_id, timeout, pool, &mut conn)
+                    .await?;
+                for statement in &statements {
+                    let stmt_start = Instant::now();
+                    let output = tracked
+                        .run(async {
+                            // Prepared statements reject CREATE PROCEDURE and
+                            // others, so run the text itself and do without
+                            // the column types when it cannot be described
+                            let described = (&mut *conn).describe(&statement.sql).await.ok();
+                            collect_statement(
+                                described,
+                                (&mut *conn).fetch_many(statement.sql.as_str()),
+                                decode_mysql_value,
+                                |r| r.rows_affected(),
+                            )
+                            .await
+                        })
+                        .await;
+                    let failed = output.is_err();
+                    if !failed {
+                        in_transaction = transaction_open(in_transaction, &statement.sql, dialect);
+                    }
+                    results.push(statement_result(script, statement, output, stmt_start));
+                    if tracked.is_cancelled() || tracked.is_expired() || (failed && stop_on_error) {
+                        break;
+                    }
+                }
+                if in_transaction
+                    && !tracked.is_expired()
+                    && (&mut *conn).execute("ROLLBACK").await.is_err()
+                {
+                    let _ = conn.close().await;
+                } else {
+                    tracked.release_mysql(conn).await;
+                }
+            }
+            DatabasePool::SQLite(pool) => {
+                let mut conn = pool
+                    .acquire()
+                    .await
+                    .map_err(|e| VelocityError::Connection(e.to_string()))?;
+                let mut tracked = self
+                    .queries
+                    .track_sqlite(query_id, timeout, &mut conn)
+                    .await?;
+                for statement in &statements {
+                    let stmt_start = Instant::now();
+                    let output = tracked
+                        .run(async {
+                            let described = (&mut *conn).describe(&statement.sql).await.ok();
+                            collect_statement(
+                                described,
+                                (&mut *conn).fetch_many(sqlx::query(&statement.sql)),
+                                decode_sqlite_value,
+                                |r| r.rows_affected(),
+                            )
+                            .await
+                        })
+                        .await;
+                    let failed = output.is_err();
+                    if !failed {
+                        in_transaction = transaction_open(in_transaction, &statement.sql, dialect);
+                    }
+                    results.push(statement_result(script, statement, output, stmt_start));
+                    if tracked.is_cancelled() || tracked.is_expired() || (failed && stop_on_error) {
+                        break;
+                    }
+                }
+                if in_transaction && (&mut *conn).execute("ROLLBACK").await.is_err() {
+                    let _ = conn.close().await;
+                }
+            }
+            _ => {
+                return Err(VelocityError::Query(
+                    "Script execution not supported for this database type".to_string(),
+                ))
+            }
+        }
+
+        Ok(ScriptResult {
+            results,
+            duration_ms: elapsed_ms(started),
+            rolled_back: in_transaction,
+        })
+    }
+}
+
+/// Whether a transaction is open after `sql` ran successfully
+fn transaction_open(open: bool, sql: &str, dialect: SqlDialect) -> bool {
+    let class = classify(sql, dialect);
+    if class.kind != StatementKind::TransactionControl {
+        return open;
+    }
+    match class.command.as_str() {
+        "BEGIN" | "BEGIN READ WRITE" => true,
+        "COMMIT" | "ROLLBACK" => false,
+        // Outside a transaction, a SQLite savepoint begins one
+        "SAVEPOINT" => open || dialect == SqlDialect::Sqlite,
+        _ => open,
+    }
+}
+
+/// Results of one statement as returned by `fetch_many`
+pub(crate) type StatementStream<'a, DB> = BoxStream<
+    'a,
+    Result<Either<<DB as Database>::QueryResult, <DB as Database>::Row>, sqlx::Error>,
+>;
+
+/// Drain a `fetch_many` stream, collecting rows and the affected row count
+async fn collect_statement<DB: Database>(
+    described: Option<Describe<DB>>,
+    mut stream: StatementStream<'_, DB>,
+    extract: fn(&DB::Row, usize) -> serde_json::Value,
+    affected: fn(&DB::QueryResult) -> u64,
+) -> Result<StatementOutput, VelocityError> {
+    let mut output = StatementOutput {
+        columns: described
+            .as_ref()
+            .map(described_columns)
+            .unwrap_or_default(),
+        rows: Vec::new(),
+        rows_affected: 0,
+    };
+
+    while let Some(item) = stream
+        .try_next()
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?
+    {
+        match item {
+            Either::Left(result) => output.rows_affected += affected(&result),
+            Either::Right(row) => {
+                if output.columns.is_empty() {
+                    output.columns = row_columns(&row);
+                }
+                output
+                    .rows
+                    .push((0..row.len()).map(|i| extract(&row, i)).collect());
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+fn statement_result(
+    script: &str,
+    statement: &ScriptStatement,
+    output: Result<StatementOutput, VelocityError>,
+    started: Instant,
+) -> StatementResult {
+    let duration_ms = elapsed_ms(started);
+    let offset = script[..statement.offset].chars().count();
+
+    match output {
+        Ok(output) => {
+            let rows_affected = if output.columns.is_empty() {
+                Some(output.rows_affected)
+            } else {
+                None
+            };
+            StatementResult {
+                sql: statement.sql.clone(),
+                offset,
+                columns: column_names(&output.columns),
+                column_info: output.columns,
+                rows: output.rows,
+                rows_affected,
+                duration_ms,
+                error: None,
+            }
+        }
+        Err(e) => StatementResult {
+            sql: statement.sql.clone(),
+            offset,
+            columns: vec![],
+            column_info: vec![],
+            rows: vec![],
+            rows_affected: None,
+            duration_ms,
+            error: Some(match e {
+                // Report database errors without the generic prefix
+                VelocityError::Query(message) => message,
+                e => e.to_string(),
+            }),
+        },
+    }
+}
+
+fn elapsed_ms(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::connection::{Connection, ConnectionConfig, DatabaseType};
+    use chrono::Utc;
+    use std::fs;
+    use std::path::PathBuf;
+
+    async fn sqlite_manager(path: &PathBuf) -> ConnectionPoolManager {
+        fs::File::create(path).unwrap();
+        let manager = ConnectionPoolManager::new();
+        manager
+            .connect(&Connection {
+                id: "c1".to_string(),
+                name: "file".to_string(),
+                db_type: DatabaseType::SQLite,
+                config: ConnectionConfig::SQLite { path: path.clone() },
+                favorite: false,
+                color: None,
+                last_used_at: None,
+                created_at: Utc::now(),
+                query_timeout_ms: None,
+                read_only: false,
+                protected: false,
+            })
+            .await
+            .unwrap();
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_transaction_left_open_is_rolled_back() {
+        let path =
+            std::env::temp_dir().join(format!("velocity-script-{}.db", uuid::Uuid::new_v4()));
+        let manager = sqlite_manager(&path).await;
+        let run = |script: &'static str| manager.execute_script("c1", script, true, None);
+
+        let result = run("CREATE TABLE t(x); BEGIN; INSERT INTO t VALUES (1)")
+            .await
+            .unwrap();
+        assert!(result.rolled_back);
+        let result = run("BEGIN; INSERT INTO t VALUES (2); COMMIT; SELECT count(*) FROM t")
+            .await
+            .unwrap();
+        // BEGIN would fail inside a transaction still open
+        assert!(result.results.iter().all(|r| r.error.is_none()));
+        assert!(!result.rolled_back);
+        assert_eq!(result.results[3].rows, vec![vec![serde_json::json!(1)]]);
+
+        // Outside a transaction a savepoint begins one
+        let result = run("SAVEPOINT s1; INSERT INTO t VALUES (3)").await.unwrap();
+        assert!(result.rolled_back);
+        let result = run("SELECT count(*) FROM t").await.unwrap();
+        assert_eq!(result.results[0].rows, vec![vec![serde_json::json!(1)]]);
+
+        manager.disconnect("c1").await.unwrap();
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+}
@@ -0,0 +1,597 @@
+//! Transaction sessions
+//!
+//! A session pins one pooled connection with an open transaction to a
+//! session ID (typically an editor tab), so that statements run across
+//! several calls share the transaction. Sessions left idle are rolled back.
+
+use crate::commands::database::QueryResultData;
+use crate::db::classify::{classify, StatementKind};
+use crate::db::dialect::SqlDialect;
+use crate::db::query::ExecuteOptions;
+use crate::db::splitter::split_statements;
+use crate::db::timeout::resolve_timeout;
+use crate::db::{ConnectionPoolManager, DatabasePool};
+use crate::error::VelocityError;
+use chrono::{DateTime, Utc};
+use sqlx::{MySql, Postgres, Sqlite, Transaction};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Idle time after which a session is rolled back, unless set on begin
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+enum SessionTransaction {
+    Postgres {
+        tx: Transaction<'static, Postgres>,
+        pool: sqlx::PgPool,
+        pid: i32,
+    },
+    MySql {
+        tx: Transaction<'static, MySql>,
+        pool: sqlx::MySqlPool,
+        thread_id: u64,
+    },
+    Sqlite(Transaction<'static, Sqlite>),
+}
+
+impl SessionTransaction {
+    fn dialect(&self) -> SqlDialect {
+        match self {
+            SessionTransaction::Postgres { .. } => SqlDialect::Postgres,
+            SessionTransaction::MySql { .. } => SqlDialect::MySql,
+            SessionTransaction::Sqlite(_) => SqlDialect::Sqlite,
+        }
+    }
+
+    async fn execute(&mut self, sql: &str) -> Result<(), VelocityError> {
+        let result = match self {
+            SessionTransaction::Postgres { tx, .. } => {
+                sqlx::query(sql).execute(&mut **tx).await.map(|_| ())
+            }
+            SessionTransaction::MySql { tx, .. } => {
+                sqlx::query(sql).execute(&mut **tx).await.map(|_| ())
+            }
+            SessionTransaction::Sqlite(tx) => sqlx::query(sql).execute(&mut **tx).await.map(|_| ()),
+        };
+        result.map_err(|e| VelocityError::Query(e.to_string()))
+    }
+
+    async fn commit(self) -> Result<(), sqlx::Error> {
+        match self {
+            SessionTransaction::Postgres { tx, .. } => tx.commit().await,
+            SessionTransaction::MySql { tx, .. } => tx.commit().await,
+            SessionTransaction::Sqlite(tx) => tx.commit().await,
+        }
+    }
+
+    async fn rollback(self) -> Result<(), sqlx::Error> {
+        match self {
+            SessionTransaction::Postgres { tx, .. } => tx.rollback().await,
+            SessionTransaction::MySql { tx, .. } => tx.rollback().await,
+            SessionTransaction::Sqlite(tx) => tx.rollback().await,
+        }
+    }
+}
+
+pub(crate) struct Session {
+    connection_id: String,
+    /// None until the transaction is open, and once it was committed or
+    /// rolled back
+    tx: Option<SessionTransaction>,
+    savepoints: Vec<String>,
+    started_at: DateTime<Utc>,
+    last_used: Instant,
+    idle_timeout: Duration,
+}
+
+/// State of an open transaction session
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionInfo {
+    pub session_id: String,
+    pub connection_id: String,
+    /// Savepoints created with `savepoint`, oldest first
+    pub savepoints: Vec<String>,
+    pub started_at: DateTime<Utc>,
+    pub idle_timeout_ms: u64,
+}
+
+type SessionMap = HashMap<String, Arc<tokio::sync::Mutex<Session>>>;
+
+/// Open transaction sessions, keyed by session ID
+#[derive(Default)]
+pub struct SessionRegistry {
+    sessions: Arc<Mutex<SessionMap>>,
+}
+
+impl SessionRegistry {
+    fn lock(&self) -> MutexGuard<'_, SessionMap> {
+        lock_map(&self.sessions)
+    }
+
+    fn get(&self, session_id: &str) -> Result<Arc<tokio::sync::Mutex<Session>>, VelocityError> {
+        self.lock().get(session_id).cloned().ok_or_else(|| {
+            VelocityError::NotFound(format!(
+                "Transaction session {} (it may have been rolled back after being idle)",
+                session_id
+            ))
+        })
+    }
+
+    fn remove(&self, session_id: &str) {
+        self.lock().remove(session_id);
+    }
+}
+
+fn lock_map(sessions: &Mutex<SessionMap>) -> MutexGuard<'_, SessionMap> {
+    sessions.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn validate_savepoint(name: &str) -> Result<(), VelocityError> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(VelocityError::Query(format!(
+            "Invalid savepoint name: {}",
+            name
+        )))
+    }
+}
+
+/// The transaction control a session query holds: `COMMIT` or `ROLLBACK`
+/// when it is just that. Anything else that would leave the session out of
+/// step with its transaction is refused: a second BEGIN, savepoint
+/// statements, which have their own commands, and transaction control mixed
+/// with other statements.
+fn session_control(sql: &str, dialect: SqlDialect) -> Result<Option<String>, VelocityError> {
+    let statements = split_statements(sql, dialect);
+    let Some(class) = statements
+        .iter()
+        .map(|statement| classify(&statement.sql, dialect))
+        .find(|class| class.kind == StatementKind::TransactionControl)
+    else {
+        return Ok(None);
+    };
+    if statements.len() > 1 {
+        return Err(VelocityError::Query(format!(
+            "{} must be run on its own in a transaction session",
+            class.command
+        )));
+    }
+    match class.command.as_str() {
+        "COMMIT" | "ROLLBACK" => Ok(Some(class.command)),
+        "BEGIN" | "BEGIN READ WRITE" => Err(VelocityError::Query(
+            "A transaction is already open in this session".to_string(),
+        )),
+        command => Err(VelocityError::Query(format!(
+            "Use the session's savepoint commands instead of {}",
+            command
+        ))),
+    }
+}
+
+impl Session {
+    fn info(&self, session_id: &str) -> TransactionInfo {
+        TransactionInfo {
+            session_id: session_id.to_string(),
+            connection_id: self.connection_id.clone(),
+            savepoints: self.savepoints.clone(),
+            started_at: self.started_at,
+            idle_timeout_ms: crate::db::timeout::duration_ms(self.idle_timeout),
+        }
+    }
+
+    fn tx(&mut self) -> Result<&mut SessionTransaction, VelocityError> {
+        self.tx
+            .as_mut()
+            .ok_or_else(|| VelocityError::Query("Transaction already ended".to_string()))
+    }
+}
+
+impl ConnectionPoolManager {
+    /// Start a transaction on a dedicated connection. Without a session ID
+    /// one is generated.
+    pub async fn begin_transaction(
+        &self,
+        connection_id: &str,
+        session_id: Option<&str>,
+        idle_timeout: Option<Duration>,
+    ) -> Result<TransactionInfo, VelocityError> {
+        let session_id = session_id
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let session = Arc::new(tokio::sync::Mutex::new(Session {
+            connection_id: connection_id.to_string(),
+            tx: None,
+            savepoints: Vec::new(),
+            started_at: Utc::now(),
+            last_used: Instant::now(),
+            idle_timeout: idle_timeout.unwrap_or(DEFAULT_IDLE_TIMEOUT),
+        }));
+        // Reserve the ID before connecting, so a second begin for it fails
+        // instead of replacing this session. Calls that find the session
+        // wait on its lock until the transaction is open.
+        let mut guard = session.lock().await;
+        match self.sessions.lock().entry(session_id.clone()) {
+            Entry::Occupied(_) => {
+                return Err(VelocityError::Query(format!(
+                    "Transaction session {} is already open",
+                    session_id
+                )))
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(session.clone());
+            }
+        }
+
+        match self.open_transaction(connection_id).await {
+            Ok(tx) => guard.tx = Some(tx),
+            Err(e) => {
+                self.sessions.remove(&session_id);
+                return Err(e);
+            }
+        }
+        guard.started_at = Utc::now();
+        guard.last_used = Instant::now();
+        let info = guard.info(&session_id);
+        drop(guard);
+        watch_idle(self.sessions.sessions.clone(), session_id, session);
+        Ok(info)
+    }
+
+    /// Begin a transaction on a connection of the pool
+    async fn open_transaction(
+        &self,
+        connection_id: &str,
+    ) -> Result<SessionTransaction, VelocityError> {
+        let pool = self
+            .get_pool(connection_id)
+            .await
+            .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+        let begin_error = |e: sqlx::Error| VelocityError::Query(e.to_string());
+        let tx = match pool.as_ref() {
+            DatabasePool::Postgres(pool) => {
+                let mut tx = pool.begin().await.map_err(begin_error)?;
+                let pid: i32 = sqlx::query_scalar("SELECT pg_backend_pid()")
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(begin_error)?;
+                SessionTransaction::Postgres {
+                    tx,
+                    pool: pool.clone(),
+                    pid,
+                }
+            }
+            DatabasePool::MySQL(pool) => {
+                let mut tx = pool.begin().await.map_err(begin_error)?;
+                let thread_id: u64 = sqlx::query_scalar("SELECT CONNECTION_ID()")
+                    .fetch_one(&mut *tx)
+                    .await
+                    .map_err(begin_error)?;
+                SessionTransaction::MySql {
+                    tx,
+                    pool: pool.clone(),
+                    thread_id,
+                }
+            }
+            DatabasePool::SQLite(pool) => {
+                SessionTransaction::Sqlite(pool.begin().await.map_err(begin_error)?)
+            }
+            _ => {
+                return Err(VelocityError::Query(
+                    "Transactions not supported for this database type".to_string(),
+                ))
+            }
+        };
+        Ok(tx)
+    }
+
+    /// Commit the transaction of a session and release its connection
+    pub async fn commit_transaction(&self, session_id: &str) -> Result<(), VelocityError> {
+        let session = self.sessions.get(session_id)?;
+        let mut session = session.lock().await;
+        self.sessions.remove(session_id);
+        match session.tx.take() {
+            Some(tx) => tx
+                .commit()
+                .await
+                .map_err(|e| VelocityError::Query(e.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Roll back to a savepoint, keeping the session open, or roll back the
+    /// whole transaction and release its connection
+    pub async fn rollback_transaction(
+        &self,
+        session_id: &str,
+        savepoint: Option<&str>,
+    ) -> Result<TransactionInfo, VelocityError> {
+        let session = self.sessions.get(session_id)?;
+        let mut session = session.lock().await;
+
+        if let Some(name) = savepoint {
+            let index = session
+                .savepoints
+                .iter()
+                .position(|s| s == name)
+                .ok_or_else(|| VelocityError::NotFound(format!("Savepoint {}", name)))?;
+            session
+                .tx()?
+                .execute(&format!("ROLLBACK TO SAVEPOINT {}", name))
+                .await?;
+            session.savepoints.truncate(index + 1);
+            session.last_used = Instant::now();
+            return Ok(session.info(session_id));
+        }
+
+        self.sessions.remove(session_id);
+        let info = session.info(session_id);
+        if let Some(tx) = session.tx.take() {
+            tx.rollback()
+                .await
+                .map_err(|e| VelocityError::Query(e.to_string()))?;
+        }
+        Ok(info)
+    }
+
+    /// Create a savepoint in the transaction of a session
+    pub async fn create_savepoint(
+        &self,
+        session_id: &str,
+        name: &str,
+    ) -> Result<TransactionInfo, VelocityError> {
+        validate_savepoint(name)?;
+        let session = self.sessions.get(session_id)?;
+        let mut session = session.lock().await;
+        session
+            .tx()?
+            .execute(&format!("SAVEPOINT {}", name))
+            .await?;
+        // Reusing a name moves the savepoint
+        session.savepoints.retain(|s| s != name);
+        session.savepoints.push(name.to_string());
+        session.last_used = Instant::now();
+        Ok(session.info(session_id))
+    }
+
+    /// State of a session, None when it is not open
+    pub async fn transaction_info(&self, session_id: &str) -> Option<TransactionInfo> {
+        let session = self.sessions.get(session_id).ok()?;
+        let session = session.lock().await;
+        Some(session.info(session_id))
+    }
+
+    /// Roll back every session open on a connection
+    pub(crate) async fn rollback_sessions(&self, connection_id: &str) {
+        let sessions: Vec<(String, Arc<tokio::sync::Mutex<Session>>)> = self
+            .sessions
+            .lock()
+            .iter()
+            .map(|(id, session)| (id.clone(), session.clone()))
+            .collect();
+        for (session_id, session) in sessions {
+            let mut session = session.lock().await;
+            if session.connection_id == connection_id {
+                self.sessions.remove(&session_id);
+                if let Some(tx) = session.tx.take() {
+                    let _ = tx.rollback().await;
+                }
+            }
+        }
+    }
+
+    /// Run a statement on the connection of a session. `COMMIT` and
+    /// `ROLLBACK` end the session like the corresponding commands.
+    pub(crate) async fn execute_in_session(
+        &self,
+        session_id: &str,
+        connection_id: &str,
+        sql: &str,
+        options: &ExecuteOptions,
+    ) -> Result<QueryResultData, VelocityError> {
+        let session = self.sessions.get(session_id)?;
+        let mut session = session.lock().await;
+        if session.connection_id != connection_id {
+            return Err(VelocityError::Query(format!(
+                "Transaction session {} belongs to another connection",
+                session_id
+            )));
+        }
+
+        let dialect = session.tx()?.dialect();
+        match session_control(sql, dialect)?.as_deref() {
+            Some("COMMIT") => {
+                drop(session);
+                self.commit_transaction(session_id).await?;
+                return Ok(QueryResultData::new(Vec::new(), Vec::new()));
+            }
+            Some(_) => {
+                drop(session);
+                self.rollback_transaction(session_id, None).await?;
+                return Ok(QueryResultData::new(Vec::new(), Vec::new()));
+            }
+            None => {}
+        }
+
+        let query_id = options.query_id.as_deref();
+        let settings = self.connection_settings(connection_id).await;
+        settings.check_statement(sql, dialect)?;
+        let timeout = resolve_timeout(options.timeout_ms, settings.query_timeout_ms);
+
+        let (result, usable) = match session.tx()? {
+            SessionTransaction::Postgres { tx, pool, pid } => {
+                let mut tracked = self
+                    .queries
+                    .track_postgres_session(query_id, timeout, pool, *pid, tx)
+                    .await?;
+                let result = tracked
+                    .run(Self::execute_postgres_query(tx, sql, &options.params))
+                    .await;
+                (result, tracked.restore_postgres(tx).await)
+            }
+            SessionTransaction::MySql {
+                tx,
+                pool,
+                thread_id,
+            } => {
+                let mut tracked = self
+                    .queries
+                    .track_mysql_session(query_id, timeout, pool, *thread_id, tx)
+                    .await?;
+                let result = tracked
+                    .run(Self::execute_mysql_query(tx, sql, &options.params))
+                    .await;
+                (result, tracked.restore_mysql(tx).await)
+            }
+            SessionTransaction::Sqlite(tx) => {
+                let mut tracked = self.queries.track_sqlite(query_id, timeout, tx).await?;
+                let result = tracked
+                    .run(Self::execute_sqlite_query(tx, sql, &options.params))
+                    .await;
+                (result, !tracked.is_expired())
+            }
+        };
+
+        session.last_used = Instant::now();
+        if !usable {
+            // The statement may still be running; the transaction is lost
+            self.sessions.remove(session_id);
+            session.tx = None;
+        }
+        result
+    }
+}
+
+/// Roll back a session once it has been idle for its timeout
+fn watch_idle(
+    sessions: Arc<Mutex<SessionMap>>,
+    session_id: String,
+    session: Arc<tokio::sync::Mutex<Session>>,
+) {
+    tokio::spawn(async move {
+        loop {
+            let deadline = {
+                let session = session.lock().await;
+                if session.tx.is_none() {
+                    return;
+                }
+                session.last_used + session.idle_timeout
+            };
+            tokio::time::sleep_until(deadline.into()).await;
+
+            let mut guard = session.lock().await;
+            if guard.tx.is_none() {
+                return;
+            }
+            if guard.last_used + guard.idle_timeout <= Instant::now() {
+                {
+                    let mut map = lock_map(&sessions);
+                    if map
+                        .get(&session_id)
+                        .is_some_and(|s| Arc::ptr_eq(s, &session))
+                    {
+                        map.remove(&session_id);
+                    }
+                }
+                if let Some(tx) = guard.tx.take() {
+                    let _ = tx.rollback().await;
+                }
+                println!(
+                    "[VELOCITY] Rolled back idle transaction session: {}",
+                    session_id
+                );
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::connection::{Connection, ConnectionConfig, DatabaseType};
+
+    #[test]
+    fn test_validates_savepoint_names() {
+        assert!(validate_savepoint("before_update_1").is_ok());
+        assert!(validate_savepoint("1a").is_err());
+        assert!(validate_savepoint("a; DROP TABLE t").is_err());
+    }
+
+    #[test]
+    fn test_session_control_stands_alone() {
+        let pg = SqlDialect::Postgres;
+        assert_eq!(session_control("SELECT 1", pg).unwrap(), None);
+        assert_eq!(
+            session_control("commit;", pg).unwrap().as_deref(),
+            Some("COMMIT")
+        );
+        assert!(session_control("SELECT 1; COMMIT", pg).is_err());
+        assert!(session_control("SELECT 1; ROLLBACK TO SAVEPOINT s1", pg).is_err());
+        assert!(session_control("SAVEPOINT s1", pg).is_err());
+        assert!(session_control("BEGIN", pg).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_begins_open_one_session() {
+        let manager = ConnectionPoolManager::new();
+        manager
+            .connect(&Connection {
+                id: "c1".to_string(),
+                name: "memory".to_string(),
+                db_type: DatabaseType::SQLite,
+                config: ConnectionConfig::SQLite {
+                    path: ":memory:".into(),
+                },
+                favorite: false,
+                color: None,
+                last_used_at: None,
+                created_at: Utc::now(),
+                query_timeout_ms: None,
+                read_only: false,
+                protected: false,
+            })
+            .await
+            .unwrap();
+
+        // With every connection taken, both begins wait to connect
+        let pool = manager.get_pool("c1").await.unwrap();
+        let DatabasePool::SQLite(pool) = pool.as_ref() else {
+            unreachable!()
+        };
+        let mut taken = Vec::new();
+        for _ in 0..pool.options().get_max_connections() {
+            taken.push(pool.acquire().await.unwrap());
+        }
+        let (first, second, _) = tokio::join!(
+            manager.begin_transaction("c1", Some("tab"), None),
+            manager.begin_transaction("c1", Some("tab"), None),
+            async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                drop(taken);
+            }
+        );
+        assert!(first.is_ok() != second.is_ok());
+        assert!(manager.transaction_info("tab").await.is_some());
+
+        manager.commit_transaction("tab").await.unwrap();
+        assert!(manager.transaction_info("tab").await.is_none());
+        // A failed begin gives the ID back
+        assert!(manager
+            .begin_transaction("c2", Some("tab"), None)
+            .await
+            .is_err());
+        assert!(manager
+            .begin_transaction("c1", Some("tab"), None)
+            .await
+            .is_ok());
+    }
+}
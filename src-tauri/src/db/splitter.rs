@@ -0,0 +1,363 @@
+//! Dialect-aware SQL script splitting
+//!
+//! Splits an editor buffer into individual statements while respecting string
+//! literals, quoted identifiers, comments, PostgreSQL dollar quoting, MySQL
+//! `DELIMITER` directives and SQLite trigger bodies.
+
+use crate::db::dialect::SqlDialect;
+
+/// A single statement extracted from a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptStatement {
+    /// Statement text without the trailing delimiter
+    pub sql: String,
+    /// Byte offset of the statement's first token in the original script
+    pub offset: usize,
+}
+
+/// Split a script into statements for the given dialect.
+/// Chunks that only contain whitespace or comments are dropped.
+pub fn split_statements(script: &str, dialect: SqlDialect) -> Vec<ScriptStatement> {
+    let bytes = script.as_bytes();
+    let len = bytes.len();
+    let mut statements = Vec::new();
+
+    let mut delimiter = String::from(";");
+    let mut i = 0;
+    // Position of the first significant token of the current statement
+    let mut content_start: Option<usize> = None;
+    let mut first_word: Option<String> = None;
+    // SQLite trigger bodies contain `;` between BEGIN and END
+    let mut in_trigger = false;
+    let mut block_depth: i32 = 0;
+
+    while i < len {
+        let c = bytes[i];
+
+        // MySQL client-side `DELIMITER` directive, only valid at a statement start
+        if dialect == SqlDialect::MySql
+            && content_start.is_none()
+            && starts_with_keyword(bytes, i, "DELIMITER")
+        {
+            let line_end = find_line_end(bytes, i);
+            let new_delimiter = script[i + "DELIMITER".len()..line_end].trim();
+            if !new_delimiter.is_empty() {
+                delimiter = new_delimiter.to_string();
+            }
+            i = line_end;
+            continue;
+        }
+
+        if bytes[i..].starts_with(delimiter.as_bytes()) && (!in_trigger || block_depth <= 0) {
+            if let Some(start) = content_start {
+                push_statement(&mut statements, script, start, i);
+            }
+            i += delimiter.len();
+            content_start = None;
+            first_word = None;
+            in_trigger = false;
+            block_depth = 0;
+            continue;
+        }
+
+        match c {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+            }
+            b'-' if bytes.get(i + 1) == Some(&b'-') && is_line_comment_start(bytes, i, dialect) => {
+                i = find_line_end(bytes, i);
+            }
+            b'#' if dialect == SqlDialect::MySql => {
+                i = find_line_end(bytes, i);
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_block_comment(bytes, i, dialect == SqlDialect::Postgres);
+            }
+            b'\'' => {
+                content_start.get_or_insert(i);
+                i = skip_quoted(bytes, i, b'\'', string_backslash_escapes(bytes, i, dialect));
+            }
+            b'"' => {
+                content_start.get_or_insert(i);
+                i = skip_quoted(bytes, i, b'"', dialect == SqlDialect::MySql);
+            }
+            b'`' if dialect != SqlDialect::Postgres => {
+                content_start.get_or_insert(i);
+                i = skip_quoted(bytes, i, b'`', false);
+            }
+            b'[' if dialect == SqlDialect::Sqlite => {
+                content_start.get_or_insert(i);
+                i = bytes[i..]
+                    .iter()
+                    .position(|&b| b == b']')
+                    .map(|p| i + p + 1)
+                    .unwrap_or(len);
+            }
+            b'$' if dialect == SqlDialect::Postgres => {
+                content_start.get_or_insert(i);
+                i = skip_dollar_quote(bytes, i).unwrap_or(i + 1);
+            }
+            b if b.is_ascii_alphabetic() || b == b'_' => {
+                content_start.get_or_insert(i);
+                let end = i + bytes[i..]
+                    .iter()
+                    .position(|&b| !is_ident_byte(b))
+                    .unwrap_or(len - i);
+                let word = script[i..end].to_ascii_uppercase();
+
+                if dialect == SqlDialect::Sqlite {
+                    if in_trigger {
+                        match word.as_str() {
+                            "BEGIN" | "CASE" => block_depth += 1,
+                            "END" => block_depth -= 1,
+                            _ => {}
+                        }
+                    } else if word == "TRIGGER" && first_word.as_deref() == Some("CREATE") {
+                        in_trigger = true;
+                    }
+                }
+                if first_word.is_none() {
+                    first_word = Some(word);
+                }
+                i = end;
+            }
+            _ => {
+                content_start.get_or_insert(i);
+                i += 1;
+            }
+        }
+    }
+
+    if let Some(start) = content_start {
+        push_statement(&mut statements, script, start, len);
+    }
+
+    statements
+}
+
+fn push_statement(statements: &mut Vec<ScriptStatement>, script: &str, start: usize, end: usize) {
+    let sql = script[start..end].trim_end();
+    if !sql.is_empty() {
+        statements.push(ScriptStatement {
+            sql: sql.to_string(),
+            offset: start,
+        });
+    }
+}
+
+/// Skip a comment, string literal, quoted identifier or dollar-quoted string
+/// starting at `i`, returning the index right after it. Returns None when `i`
+/// is ordinary SQL.
+pub(crate) fn skip_non_code(bytes: &[u8], i: usize, dialect: SqlDialect) -> Option<usize> {
+    let next = bytes.get(i + 1).copied();
+    match bytes[i] {
+        b'-' if next == Some(b'-') && is_line_comment_start(bytes, i, dialect) => {
+            Some(find_line_end(bytes, i))
+        }
+        b'#' if dialect == SqlDialect::MySql => Some(find_line_end(bytes, i)),
+        b'/' if next == Some(b'*') => Some(skip_block_comment(
+            bytes,
+            i,
+            dialect == SqlDialect::Postgres,
+        )),
+        b'\'' => Some(skip_quoted(
+            bytes,
+            i,
+            b'\'',
+            string_backslash_escapes(bytes, i, dialect),
+        )),
+        b'"' => Some(skip_quoted(bytes, i, b'"', dialect == SqlDialect::MySql)),
+        b'`' if dialect != SqlDialect::Postgres => Some(skip_quoted(bytes, i, b'`', false)),
+        b'[' if dialect == SqlDialect::Sqlite => Some(
+            bytes[i..]
+                .iter()
+                .position(|&b| b == b']')
+                .map(|p| i + p + 1)
+                .unwrap_or(bytes.len()),
+        ),
+        b'$' if dialect == SqlDialect::Postgres => skip_dollar_quote(bytes, i),
+        _ => None,
+    }
+}
+
+/// Whether backslashes escape quotes in the string literal opening at `i`
+fn string_backslash_escapes(bytes: &[u8], i: usize, dialect: SqlDialect) -> bool {
+    match dialect {
+        SqlDialect::MySql => true,
+        // E'...' escape strings
+        SqlDialect::Postgres => {
+            i > 0 && matches!(bytes[i - 1], b'E' | b'e') && (i < 2 || !is_ident_byte(bytes[i - 2]))
+        }
+        SqlDialect::Sqlite => false,
+    }
+}
+
+pub(crate) fn is_ident_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || b >= 0x80
+}
+
+fn starts_with_keyword(bytes: &[u8], i: usize, keyword: &str) -> bool {
+    let end = i + keyword.len();
+    end <= bytes.len()
+        && bytes[i..end].eq_ignore_ascii_case(keyword.as_bytes())
+        && (i == 0 || !is_ident_byte(bytes[i - 1]))
+        && bytes.get(end).is_none_or(|b| b.is_ascii_whitespace())
+}
+
+/// MySQL only treats `--` as a comment when followed by whitespace
+fn is_line_comment_start(bytes: &[u8], i: usize, dialect: SqlDialect) -> bool {
+    dialect != SqlDialect::MySql || bytes.get(i + 2).is_none_or(|b| b.is_ascii_whitespace())
+}
+
+fn find_line_end(bytes: &[u8], i: usize) -> usize {
+    bytes[i..]
+        .iter()
+        .position(|&b| b == b'\n')
+        .map(|p| i + p)
+        .unwrap_or(bytes.len())
+}
+
+/// Skip a `/* ... */` comment, returning the index right after it
+fn skip_block_comment(bytes: &[u8], i: usize, nested: bool) -> usize {
+    let mut depth = 0;
+    let mut j = i;
+    while j + 1 < bytes.len() {
+        if bytes[j] == b'/' && bytes[j + 1] == b'*' {
+            if depth == 0 || nested {
+                depth += 1;
+            }
+            j += 2;
+        } else if bytes[j] == b'*' && bytes[j + 1] == b'/' {
+            depth -= 1;
+            j += 2;
+            if depth == 0 {
+                return j;
+            }
+        } else {
+            j += 1;
+        }
+    }
+    bytes.len()
+}
+
+/// Skip a quoted literal or identifier, returning the index right after the closing quote
+fn skip_quoted(bytes: &[u8], i: usize, quote: u8, backslash_escapes: bool) -> usize {
+    let mut j = i + 1;
+    while j < bytes.len() {
+        let b = bytes[j];
+        if backslash_escapes && b == b'\\' {
+            j += 2;
+            continue;
+        }
+        if b == quote {
+            if bytes.get(j + 1) == Some(&quote) {
+                j += 2;
+                continue;
+            }
+            return j + 1;
+        }
+        j += 1;
+    }
+    bytes.len()
+}
+
+/// Skip a PostgreSQL dollar-quoted string (`$$...$$` or `$tag$...$tag$`).
+/// Returns None when the `$` does not open a dollar quote (e.g. `$1`).
+fn skip_dollar_quote(bytes: &[u8], i: usize) -> Option<usize> {
+    if i > 0 && is_ident_byte(bytes[i - 1]) {
+        return None;
+    }
+    let mut j = i + 1;
+    if j < bytes.len() && (bytes[j].is_ascii_alphabetic() || bytes[j] == b'_') {
+        while j < bytes.len() && (bytes[j].is_ascii_alphanumeric() || bytes[j] == b'_') {
+            j += 1;
+        }
+    }
+    if bytes.get(j) != Some(&b'$') {
+        return None;
+    }
+    let tag = &bytes[i..=j];
+    let body_start = j + 1;
+    let end = bytes[body_start..]
+        .windows(tag.len())
+        .position(|w| w == tag)
+        .map(|p| body_start + p + tag.len())
+        .unwrap_or(bytes.len());
+    Some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sqls(script: &str, dialect: SqlDialect) -> Vec<String> {
+        split_statements(script, dialect)
+            .into_iter()
+            .map(|s| s.sql)
+            .collect()
+    }
+
+    #[test]
+    fn test_splits_on_semicolons_and_skips_empty() {
+        assert_eq!(
+            sqls(
+                "SELECT 1; ;\n SELECT 2;\n-- trailing comment\n",
+                SqlDialect::Postgres
+            ),
+            vec!["SELECT 1", "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn test_ignores_semicolons_in_strings_and_comments() {
+        let script = "INSERT INTO t VALUES ('a;b', \"c;d\"); /* x; */ SELECT 1 -- y;\n;";
+        assert_eq!(
+            sqls(script, SqlDialect::Sqlite),
+            vec!["INSERT INTO t VALUES ('a;b', \"c;d\")", "SELECT 1 -- y;"]
+        );
+    }
+
+    #[test]
+    fn test_postgres_dollar_quotes() {
+        let script = "CREATE FUNCTION f() RETURNS int AS $body$ BEGIN RETURN 1; END; $body$ LANGUAGE plpgsql; SELECT $1";
+        let result = sqls(script, SqlDialect::Postgres);
+        assert_eq!(result.len(), 2);
+        assert!(result[0].ends_with("LANGUAGE plpgsql"));
+        assert_eq!(result[1], "SELECT $1");
+    }
+
+    #[test]
+    fn test_mysql_delimiter_directive() {
+        let script =
+            "DELIMITER //\nCREATE PROCEDURE p() BEGIN SELECT 1; END//\nDELIMITER ;\nCALL p();";
+        assert_eq!(
+            sqls(script, SqlDialect::MySql),
+            vec!["CREATE PROCEDURE p() BEGIN SELECT 1; END", "CALL p()"]
+        );
+    }
+
+    #[test]
+    fn test_mysql_backslash_escapes() {
+        assert_eq!(
+            sqls("SELECT 'it\\'s;'; SELECT 2", SqlDialect::MySql),
+            vec!["SELECT 'it\\'s;'", "SELECT 2"]
+        );
+    }
+
+    #[test]
+    fn test_sqlite_trigger_body() {
+        let script = "CREATE TRIGGER tr AFTER INSERT ON t BEGIN UPDATE t SET x = CASE WHEN 1 THEN 2 END; DELETE FROM u; END; SELECT 1;";
+        let result = sqls(script, SqlDialect::Sqlite);
+        assert_eq!(result.len(), 2);
+        assert!(result[0].ends_with("DELETE FROM u; END"));
+        assert_eq!(result[1], "SELECT 1");
+    }
+
+    #[test]
+    fn test_offsets_point_at_first_token() {
+        let script = "  -- lead\n  SELECT 1;\nSELECT 2";
+        let result = split_statements(script, SqlDialect::Postgres);
+        assert_eq!(&script[result[0].offset..result[0].offset + 8], "SELECT 1");
+        assert_eq!(result[1].offset, script.rfind("SELECT 2").unwrap());
+    }
+}
@@ -0,0 +1,351 @@
+//! Streaming query execution
+//!
+//! Runs a query through sqlx's `fetch` stream and hands rows to a sink in
+//! fixed-size batches instead of materialising the whole result set.
+//! Flow control is credit based: every batch consumes one credit and the
+//! consumer hands credits back with `ack`, so a slow frontend pauses the
+//! database cursor rather than piling rows up in memory.
+
+use crate::db::columns::{column_names, described_columns, ResultColumn};
+use crate::db::decode::{decode_mysql_value, decode_pg_value, decode_sqlite_value};
+use crate::db::dialect::SqlDialect;
+use crate::db::{ConnectionPoolManager, DatabasePool};
+use crate::error::VelocityError;
+use futures::stream::BoxStream;
+use futures::TryStreamExt;
+use sqlx::{Executor, Row};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, Semaphore};
+
+/// Default number of rows per batch
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+/// Default number of batches that may be sent before an acknowledgement
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 4;
+/// How long a stream waits for the consumer before giving up
+const ACK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Events emitted while streaming a query result
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum QueryStreamEvent {
+    /// Stream opened; `stream_id` is used to acknowledge or close it
+    #[serde(rename_all = "camelCase")]
+    Started {
+        stream_id: String,
+        columns: Vec<String>,
+        /// Name, type and nullability of each column
+        column_info: Vec<ResultColumn>,
+    },
+    /// A batch of rows
+    Batch { rows: Vec<Vec<serde_json::Value>> },
+    /// All rows were sent
+    #[serde(rename_all = "camelCase")]
+    Done { row_count: u64, duration_ms: f64 },
+    /// The consumer closed the stream after `row_count` rows were sent
+    #[serde(rename_all = "camelCase")]
+    Closed { row_count: u64 },
+    /// The query failed or the stream was aborted
+    Error { message: String },
+}
+
+/// Batching and flow-control settings for a stream
+#[derive(Debug, Clone, Copy)]
+pub struct StreamOptions {
+    pub batch_size: usize,
+    pub max_in_flight: usize,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+        }
+    }
+}
+
+/// Credits of the streams currently running, keyed by stream ID
+#[derive(Default)]
+pub struct StreamRegistry {
+    credits: RwLock<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl StreamRegistry {
+    async fn register(&self, stream_id: &str, max_in_flight: usize) -> Arc<Semaphore> {
+        let credits = Arc::new(Semaphore::new(max_in_flight));
+        self.credits
+            .write()
+            .await
+            .insert(stream_id.to_string(), credits.clone());
+        credits
+    }
+
+    async fn unregister(&self, stream_id: &str) {
+        self.credits.write().await.remove(stream_id);
+    }
+
+    /// Return `batches` credits to a stream, letting it send more rows.
+    /// Acknowledging a stream that already finished is a no-op.
+    pub async fn ack(&self, stream_id: &str, batches: usize) {
+        if let Some(semaphore) = self.credits.read().await.get(stream_id) {
+            semaphore.add_permits(batches);
+        }
+    }
+
+    /// Stop a stream; it ends with a `Closed` event instead of its next batch
+    pub async fn close(&self, stream_id: &str) {
+        if let Some(semaphore) = self.credits.write().await.remove(stream_id) {
+            semaphore.close();
+        }
+    }
+}
+
+impl ConnectionPoolManager {
+    /// Stream a query's rows to `sink` in batches.
+    /// Query failures are reported through the sink as `Error` events.
+    pub async fn execute_query_stream<F>(
+        &self,
+        connection_id: &str,
+        sql: &str,
+        options: StreamOptions,
+        mut sink: F,
+    ) -> Result<(), VelocityError>
+    where
+        F: FnMut(QueryStreamEvent) -> Result<(), VelocityError>,
+    {
+        let pool = self
+            .get_pool(connection_id)
+            .await
+            .ok_or_else(|| VelocityError::Connection("Not connected".to_string()))?;
+        if let Some(dialect) = SqlDialect::from_pool(&pool) {
+            self.connection_settings(connection_id)
+                .await
+                .check_statement(sql, dialect)?;
+        }
+
+        let stream_id = uuid::Uuid::new_v4().to_string();
+        let credits = self
+            .streams
+            .register(&stream_id, options.max_in_flight.max(1))
+            .await;
+        let mut pump = BatchPump {
+            stream_id: &stream_id,
+            credits,
+            batch_size: options.batch_size.max(1),
+            sent: 0,
+            sink: &mut sink,
+        };
+
+        let result = match pool.as_ref() {
+            DatabasePool::Postgres(pool) => match pool.acquire().await {
+                Ok(mut conn) => match (&mut *conn).describe(sql).await {
+                    Ok(described) => {
+                        let columns = described_columns(&described);
+                        pump.run(
+                            columns,
+                            (&mut *conn).fetch(sqlx::query(sql)),
+                            decode_pg_value,
+                        )
+                        .await
+                    }
+                    Err(e) => pump.fail(e.to_string()),
+                },
+                Err(e) => pump.fail(e.to_string()),
+            },
+            DatabasePool::MySQL(pool) => match pool.acquire().await {
+                Ok(mut conn) => match (&mut *conn).describe(sql).await {
+                    Ok(described) => {
+                        let columns = described_columns(&described);
+                        pump.run(
+                            columns,
+                            (&mut *conn).fetch(sqlx::query(sql)),
+                            decode_mysql_value,
+                        )
+                        .await
+                    }
+                    Err(e) => pump.fail(e.to_string()),
+                },
+                Err(e) => pump.fail(e.to_string()),
+            },
+            DatabasePool::SQLite(pool) => match pool.acquire().await {
+                Ok(mut conn) => match (&mut *conn).describe(sql).await {
+                    Ok(described) => {
+                        let columns = described_columns(&described);
+                        pump.run(
+                            columns,
+                            (&mut *conn).fetch(sqlx::query(sql)),
+                            decode_sqlite_value,
+                        )
+                        .await
+                    }
+                    Err(e) => pump.fail(e.to_string()),
+                },
+                Err(e) => pump.fail(e.to_string()),
+            },
+            _ => Err(VelocityError::Query(
+                "Streaming not supported for this database type".to_string(),
+            )),
+        };
+
+        self.streams.unregister(&stream_id).await;
+        result
+    }
+
+    /// Acknowledge consumed batches of a running stream
+    pub async fn ack_query_stream(&self, stream_id: &str, batches: usize) {
+        self.streams.ack(stream_id, batches).await
+    }
+
+    /// Close a running stream early
+    pub async fn close_query_stream(&self, stream_id: &str) {
+        self.streams.close(stream_id).await
+    }
+}
+
+/// Moves rows from a database cursor into sink batches, honouring credits
+struct BatchPump<'a, F> {
+    stream_id: &'a str,
+    credits: Arc<Semaphore>,
+    batch_size: usize,
+    /// Rows sent so far
+    sent: u64,
+    sink: &'a mut F,
+}
+
+impl<F> BatchPump<'_, F>
+where
+    F: FnMut(QueryStreamEvent) -> Result<(), VelocityError>,
+{
+    async fn run<R: Row>(
+        &mut self,
+        columns: Vec<ResultColumn>,
+        mut rows: BoxStream<'_, Result<R, sqlx::Error>>,
+        extract: fn(&R, usize) -> serde_json::Value,
+    ) -> Result<(), VelocityError> {
+        let started = Instant::now();
+        (self.sink)(QueryStreamEvent::Started {
+            stream_id: self.stream_id.to_string(),
+            columns: column_names(&columns),
+            column_info: columns,
+        })?;
+
+        let mut row_count: u64 = 0;
+        let mut batch = Vec::with_capacity(self.batch_size);
+        loop {
+            match rows.try_next().await {
+                Ok(Some(row)) => {
+                    batch.push((0..row.len()).map(|i| extract(&row, i)).collect());
+                    row_count += 1;
+                    if batch.len() >= self.batch_size && !self.send_batch(&mut batch).await? {
+                        return Ok(());
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => return self.fail(e.to_string()),
+            }
+        }
+
+        if !batch.is_empty() && !self.send_batch(&mut batch).await? {
+            return Ok(());
+        }
+
+        (self.sink)(QueryStreamEvent::Done {
+            row_count,
+            duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+        })
+    }
+
+    /// Wait for a credit and send the batch.
+    /// Returns false when the stream ended, after sending its final event.
+    async fn send_batch(
+        &mut self,
+        batch: &mut Vec<Vec<serde_json::Value>>,
+    ) -> Result<bool, VelocityError> {
+        let credits = self.credits.clone();
+        match tokio::time::timeout(ACK_TIMEOUT, credits.acquire()).await {
+            Ok(Ok(permit)) => permit.forget(),
+            Ok(Err(_)) => {
+                (self.sink)(QueryStreamEvent::Closed {
+                    row_count: self.sent,
+                })?;
+                return Ok(false);
+            }
+            Err(_) => {
+                self.fail("Stream aborted: consumer stopped acknowledging batches".to_string())?;
+                return Ok(false);
+            }
+        }
+
+        let rows = std::mem::replace(batch, Vec::with_capacity(self.batch_size));
+        self.sent += rows.len() as u64;
+        (self.sink)(QueryStreamEvent::Batch { rows })?;
+        Ok(true)
+    }
+
+    fn fail(&mut self, message: String) -> Result<(), VelocityError> {
+        (self.sink)(QueryStreamEvent::Error { message })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn test_batches_wait_for_credits() {
+        let sqlite = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let mut conn = sqlite.acquire().await.unwrap();
+        let registry = StreamRegistry::default();
+        let (events, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let mut sink = |event| {
+            events.send(event).unwrap();
+            Ok(())
+        };
+        let mut pump = BatchPump {
+            stream_id: "s1",
+            credits: registry.register("s1", 1).await,
+            batch_size: 2,
+            sent: 0,
+            sink: &mut sink,
+        };
+
+        let rows = (&mut *conn).fetch(sqlx::query(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 10)
+             SELECT i FROM n",
+        ));
+        let consumer = async {
+            assert!(matches!(
+                received.recv().await,
+                Some(QueryStreamEvent::Started { .. })
+            ));
+            assert!(matches!(
+                received.recv().await,
+                Some(QueryStreamEvent::Batch { .. })
+            ));
+            // The only credit is spent until the batch is acknowledged
+            let waiting = tokio::time::timeout(Duration::from_millis(50), received.recv()).await;
+            assert!(waiting.is_err());
+            registry.ack("s1", 1).await;
+            assert!(matches!(
+                received.recv().await,
+                Some(QueryStreamEvent::Batch { .. })
+            ));
+            registry.close("s1").await;
+            received.recv().await
+        };
+        let (result, last) =
+            tokio::join!(pump.run(Vec::new(), rows, decode_sqlite_value), consumer);
+        result.unwrap();
+        assert!(matches!(
+            last,
+            Some(QueryStreamEvent::Closed { row_count: 4 })
+        ));
+    }
+}
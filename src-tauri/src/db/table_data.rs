@@ -3,7 +3,13 @@
 //! This module handles the actual data retrieval from databases,
 //! keeping this logic separate from the connection pool management.
 
+use crate::commands::database::RowKey;
+use crate::db::cancel::QueryRegistry;
+use crate::db::columns::{table_columns, ResultColumn};
+use crate::db::decode::{decode_mysql_value, decode_pg_value, decode_sqlite_value};
 use crate::db::filters::QueryOptions;
+use crate::db::identity::{resolve_table_key, RowIdentity};
+use crate::db::timeout::resolve_timeout;
 use crate::db::pool::{ColumnInfo, DatabasePool};
 use crate::error::VelocityError;
 use sqlx::Row;
@@ -13,16 +19,28 @@ use sqlx::Row;
 #[serde(rename_all = "camelCase")]
 pub struct TableDataResponse {
     pub columns: Vec<String>,
+    /// Type, nullability and source of each column
+    pub column_info: Vec<ResultColumn>,
     pub rows: Vec<Vec<serde_json::Value>>,
     /// Total count of rows matching filters (None if skip_count was true)
     pub total_count: Option<i64>,
     /// Next cursor value for pagination (last row's cursor column value)
     pub next_cursor: Option<serde_json::Value>,
+    /// How rows are identified when editing
+    pub row_identity: RowIdentity,
+    /// Columns of the row keys
+    pub key_columns: Vec<String>,
+    /// Key of each row, to send back with its changes. None when the key
+    /// columns were not all selected.
+    pub row_keys: Option<Vec<RowKey>>,
 }
 
-/// Fetch table data with filtering, sorting, and pagination
+/// Fetch table data with filtering, sorting, and pagination.
+/// Runs on a single connection so it can be cancelled via `options.query_id`
+/// and limited by `options.timeout_ms`.
 pub async fn fetch_table_data(
     pool: &DatabasePool,
+    queries: &QueryRegistry,
     table_name: &str,
     columns: &[ColumnInfo],
     options: &QueryOptions,
@@ -49,10 +67,19 @@ pub async fn fetch_table_data(
         }
     }
 
+    // Tables without a primary key also return ctid or rowid as the last
+    // column
+    let table_key = resolve_table_key(pool, table_name, columns).await?;
+    let row_id_select = table_key
+        .identity
+        .select_expr()
+        .map(|expr| format!(", {}", expr))
+        .unwrap_or_default();
+
     // Build the main data query
     let query = format!(
-        "SELECT {} FROM \"{}\"{}{}{}",
-        select_columns, table_name, where_clause, order_clause, pagination
+        "SELECT {}{} FROM \"{}\"{}{}{}",
+        select_columns, row_id_select, table_name, where_clause, order_clause, pagination
     );
 
     // Build count query (skip if skip_count is true)
@@ -78,123 +105,174 @@ pub async fn fetch_table_data(
         None
     };
 
+    let timeout = resolve_timeout(options.timeout_ms, None);
+
     match pool {
         DatabasePool::Postgres(pg_pool) => {
-            // Get total count (skip if skip_count is true)
-            let total_count: Option<i64> = if options.skip_count {
-                None
-            } else {
-                let count_row = sqlx::query(&count_query)
-                    .fetch_one(pg_pool)
+            let mut conn = pg_pool
+                .acquire()
+                .await
+                .map_err(|e| VelocityError::Connection(e.to_string()))?;
+            let mut tracked = queries
+                .track_postgres(options.query_id.as_deref(), timeout, pg_pool, &mut conn)
+                .await?;
+            let result = tracked.run(async {
+                // Get total count (skip if skip_count is true)
+                let total_count: Option<i64> = if options.skip_count {
+                    None
+                } else {
+                    let count_row = sqlx::query(&count_query)
+                        .fetch_one(&mut *conn)
+                        .await
+                        .map_err(|e| VelocityError::Query(e.to_string()))?;
+                    Some(count_row.try_get("count").unwrap_or(0))
+                };
+
+                // Get data
+                let rows = sqlx::query(&query)
+                    .fetch_all(&mut *conn)
                     .await
                     .map_err(|e| VelocityError::Query(e.to_string()))?;
-                Some(count_row.try_get("count").unwrap_or(0))
-            };
 
-            // Get data
-            let rows = sqlx::query(&query)
-                .fetch_all(pg_pool)
-                .await
-                .map_err(|e| VelocityError::Query(e.to_string()))?;
-
-            let data: Vec<Vec<serde_json::Value>> = rows
-                .iter()
-                .map(|row| {
-                    column_names
-                        .iter()
-                        .enumerate()
-                        .map(|(i, _)| row_value_to_json(row, i))
-                        .collect()
-                })
-                .collect();
+                let data: Vec<Vec<serde_json::Value>> = rows
+                    .iter()
+                    .map(|row| {
+                        column_names
+                            .iter()
+                            .enumerate()
+                            .map(|(i, _)| decode_pg_value(row, i))
+                            .collect()
+                    })
+                    .collect();
+                let row_ids = (!row_id_select.is_empty())
+                    .then(|| rows.iter().map(|row| decode_pg_value(row, row.len() - 1)).collect());
 
-            let next_cursor = options.cursor.as_ref()
-                .and_then(|c| get_next_cursor(&data, &c.column));
+                let next_cursor = options.cursor.as_ref()
+                    .and_then(|c| get_next_cursor(&data, &c.column));
 
-            Ok(TableDataResponse {
-                columns: column_names,
-                rows: data,
-                total_count,
-                next_cursor,
-            })
+                Ok(TableDataResponse {
+                    columns: column_names.clone(),
+                    column_info: table_columns(table_name, columns, &column_names),
+                    row_keys: table_key.row_keys(&column_names, &data, row_ids),
+                    rows: data,
+                    total_count,
+                    next_cursor,
+                    row_identity: table_key.identity,
+                    key_columns: table_key.columns.clone(),
+                })
+            }).await;
+            tracked.release_postgres(conn).await;
+            result
         }
         DatabasePool::MySQL(mysql_pool) => {
-            // Get total count (skip if skip_count is true)
-            let total_count: Option<i64> = if options.skip_count {
-                None
-            } else {
-                let count_row = sqlx::query(&count_query)
-                    .fetch_one(mysql_pool)
+            let mut conn = mysql_pool
+                .acquire()
+                .await
+                .map_err(|e| VelocityError::Connection(e.to_string()))?;
+            let mut tracked = queries
+                .track_mysql(options.query_id.as_deref(), timeout, mysql_pool, &mut conn)
+                .await?;
+            let result = tracked.run(async {
+                // Get total count (skip if skip_count is true)
+                let total_count: Option<i64> = if options.skip_count {
+                    None
+                } else {
+                    let count_row = sqlx::query(&count_query)
+                        .fetch_one(&mut *conn)
+                        .await
+                        .map_err(|e| VelocityError::Query(e.to_string()))?;
+                    Some(count_row.try_get("count").unwrap_or(0))
+                };
+
+                // Get data
+                let rows = sqlx::query(&query)
+                    .fetch_all(&mut *conn)
                     .await
                     .map_err(|e| VelocityError::Query(e.to_string()))?;
-                Some(count_row.try_get("count").unwrap_or(0))
-            };
 
-            // Get data
-            let rows = sqlx::query(&query)
-                .fetch_all(mysql_pool)
-                .await
-                .map_err(|e| VelocityError::Query(e.to_string()))?;
-
-            let data: Vec<Vec<serde_json::Value>> = rows
-                .iter()
-                .map(|row| {
-                    column_names
-                        .iter()
-                        .enumerate()
-                        .map(|(i, _)| row_value_to_json_mysql(row, i))
-                        .collect()
-                })
-                .collect();
+                let data: Vec<Vec<serde_json::Value>> = rows
+                    .iter()
+                    .map(|row| {
+                        column_names
+                            .iter()
+                            .enumerate()
+                            .map(|(i, _)| decode_mysql_value(row, i))
+                            .collect()
+                    })
+                    .collect();
+                let row_ids = (!row_id_select.is_empty())
+                    .then(|| rows.iter().map(|row| decode_mysql_value(row, row.len() - 1)).collect());
 
-            let next_cursor = options.cursor.as_ref()
-                .and_then(|c| get_next_cursor(&data, &c.column));
+                let next_cursor = options.cursor.as_ref()
+                    .and_then(|c| get_next_cursor(&data, &c.column));
 
-            Ok(TableDataResponse {
-                columns: column_names,
-                rows: data,
-                total_count,
-                next_cursor,
-            })
+                Ok(TableDataResponse {
+                    columns: column_names.clone(),
+                    column_info: table_columns(table_name, columns, &column_names),
+                    row_keys: table_key.row_keys(&column_names, &data, row_ids),
+                    rows: data,
+                    total_count,
+                    next_cursor,
+                    row_identity: table_key.identity,
+                    key_columns: table_key.columns.clone(),
+                })
+            }).await;
+            tracked.release_mysql(conn).await;
+            result
         }
         DatabasePool::SQLite(sqlite_pool) => {
-            // Get total count (skip if skip_count is true)
-            let total_count: Option<i64> = if options.skip_count {
-                None
-            } else {
-                let count_row = sqlx::query(&count_query)
-                    .fetch_one(sqlite_pool)
+            let mut conn = sqlite_pool
+                .acquire()
+                .await
+                .map_err(|e| VelocityError::Connection(e.to_string()))?;
+            let mut tracked = queries
+                .track_sqlite(options.query_id.as_deref(), timeout, &mut conn)
+                .await?;
+            tracked.run(async {
+                // Get total count (skip if skip_count is true)
+                let total_count: Option<i64> = if options.skip_count {
+                    None
+                } else {
+                    let count_row = sqlx::query(&count_query)
+                        .fetch_one(&mut *conn)
+                        .await
+                        .map_err(|e| VelocityError::Query(e.to_string()))?;
+                    Some(count_row.try_get("count").unwrap_or(0))
+                };
+
+                // Get data
+                let rows = sqlx::query(&query)
+                    .fetch_all(&mut *conn)
                     .await
                     .map_err(|e| VelocityError::Query(e.to_string()))?;
-                Some(count_row.try_get("count").unwrap_or(0))
-            };
 
-            // Get data
-            let rows = sqlx::query(&query)
-                .fetch_all(sqlite_pool)
-                .await
-                .map_err(|e| VelocityError::Query(e.to_string()))?;
+                let data: Vec<Vec<serde_json::Value>> = rows
+                    .iter()
+                    .map(|row| {
+                        column_names
+                            .iter()
+                            .enumerate()
+                            .map(|(i, _)| decode_sqlite_value(row, i))
+                            .collect()
+                    })
+                    .collect();
+                let row_ids = (!row_id_select.is_empty())
+                    .then(|| rows.iter().map(|row| decode_sqlite_value(row, row.len() - 1)).collect());
 
-            let data: Vec<Vec<serde_json::Value>> = rows
-                .iter()
-                .map(|row| {
-                    column_names
-                        .iter()
-                        .enumerate()
-                        .map(|(i, _)| row_value_to_json_sqlite(row, i))
-                        .collect()
-                })
-                .collect();
-
-            let next_cursor = options.cursor.as_ref()
-                .and_then(|c| get_next_cursor(&data, &c.column));
+                let next_cursor = options.cursor.as_ref()
+                    .and_then(|c| get_next_cursor(&data, &c.column));
 
-            Ok(TableDataResponse {
-                columns: column_names,
-                rows: data,
-                total_count,
-                next_cursor,
-            })
+                Ok(TableDataResponse {
+                    columns: column_names.clone(),
+                    column_info: table_columns(table_name, columns, &column_names),
+                    row_keys: table_key.row_keys(&column_names, &data, row_ids),
+                    rows: data,
+                    total_count,
+                    next_cursor,
+                    row_identity: table_key.identity,
+                    key_columns: table_key.columns.clone(),
+                })
+            }).await
         }
         DatabasePool::Redis(_) => Err(VelocityError::Query(
             "Redis does not support table data fetching".to_string(),
@@ -207,58 +285,3 @@ pub async fn fetch_table_data(
         )),
     }
 }
-
-/// Convert PostgreSQL row value to JSON
-fn row_value_to_json(row: &sqlx::postgres::PgRow, index: usize) -> serde_json::Value {
-    row.try_get::<String, _>(index)
-        .map(serde_json::Value::String)
-        .or_else(|_| {
-            row.try_get::<i64, _>(index)
-                .map(|v| serde_json::Value::Number(v.into()))
-        })
-        .or_else(|_| {
-            row.try_get::<i32, _>(index)
-                .map(|v| serde_json::Value::Number(v.into()))
-        })
-        .or_else(|_| {
-            row.try_get::<f64, _>(index).map(|v| {
-                serde_json::Number::from_f64(v)
-                    .map(serde_json::Value::Number)
-                    .unwrap_or(serde_json::Value::Null)
-            })
-        })
-        .or_else(|_| row.try_get::<bool, _>(index).map(serde_json::Value::Bool))
-        .unwrap_or(serde_json::Value::Null)
-}
-
-/// Convert MySQL row value to JSON
-fn row_value_to_json_mysql(row: &sqlx::mysql::MySqlRow, index: usize) -> serde_json::Value {
-    row.try_get::<String, _>(index)
-        .map(serde_json::Value::String)
-        .or_else(|_| {
-            row.try_get::<i64, _>(index)
-                .map(|v| serde_json::Value::Number(v.into()))
-        })
-        .or_else(|_| {
-            row.try_get::<i32, _>(index)
-                .map(|v| serde_json::Value::Number(v.into()))
-        })
-        .or_else(|_| row.try_get::<bool, _>(index).map(serde_json::Value::Bool))
-        .unwrap_or(serde_json::Value::Null)
-}
-
-/// Convert SQLite row value to JSON
-fn row_value_to_json_sqlite(row: &sqlx::sqlite::SqliteRow, index: usize) -> serde_json::Value {
-    row.try_get::<String, _>(index)
-        .map(serde_json::Value::String)
-        .or_else(|_| {
-            row.try_get::<i64, _>(index)
-                .map(|v| serde_json::Value::Number(v.into()))
-        })
-        .or_else(|_| {
-            row.try_get::<i32, _>(index)
-                .map(|v| serde_json::Value::Number(v.into()))
-        })
-        .or_else(|_| row.try_get::<bool, _>(index).map(serde_json::Value::Bool))
-        .unwrap_or(serde_json::Value::Null)
-}
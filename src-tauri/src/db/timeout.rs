@@ -0,0 +1,91 @@
+//! Statement timeouts
+//!
+//! Limits are enforced by the database where possible (`statement_timeout` on
+//! PostgreSQL, `max_execution_time` on MySQL, `max_statement_time` on MariaDB,
+//! a progress-handler deadline on SQLite). A client-side `tokio::time::timeout`
+//! slightly longer than the limit acts as a backstop for statements the server
+//! does not interrupt.
+
+use std::time::Duration;
+
+/// Extra time given to the server to report a timeout before the client gives up
+pub const BACKSTOP_GRACE: Duration = Duration::from_secs(2);
+
+/// Effective timeout of a call: the per-call value wins over the connection
+/// default, and 0 disables the limit.
+pub fn resolve_timeout(per_call_ms: Option<u64>, default_ms: Option<u64>) -> Option<Duration> {
+    per_call_ms
+        .or(default_ms)
+        .filter(|ms| *ms > 0)
+        .map(Duration::from_millis)
+}
+
+/// Set a session statement timeout on a PostgreSQL connection
+pub(crate) async fn set_postgres_timeout(
+    conn: &mut sqlx::PgConnection,
+    limit: Duration,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!("SET statement_timeout = {}", limit.as_millis()))
+        .execute(conn)
+        .await
+        .map(|_| ())
+}
+
+/// Restore the session default statement timeout on a PostgreSQL connection
+pub(crate) async fn reset_postgres_timeout(conn: &mut sqlx::PgConnection) {
+    let _ = sqlx::query("RESET statement_timeout").execute(conn).await;
+}
+
+/// Set a session statement timeout on a MySQL or MariaDB connection.
+/// Returns the variable that was set, or None when the server supports neither.
+pub(crate) async fn set_mysql_timeout(
+    conn: &mut sqlx::MySqlConnection,
+    limit: Duration,
+) -> Option<&'static str> {
+    // MySQL: milliseconds, SELECT statements only
+    let mysql = format!("SET SESSION max_execution_time = {}", limit.as_millis());
+    if sqlx::query(&mysql).execute(&mut *conn).await.is_ok() {
+        return Some("max_execution_time");
+    }
+    // MariaDB: seconds, all statements
+    let mariadb = format!("SET SESSION max_statement_time = {}", limit.as_secs_f64());
+    if sqlx::query(&mariadb).execute(&mut *conn).await.is_ok() {
+        return Some("max_statement_time");
+    }
+    None
+}
+
+/// Restore a session timeout variable set by `set_mysql_timeout`
+pub(crate) async fn reset_mysql_timeout(conn: &mut sqlx::MySqlConnection, variable: &str) {
+    let _ = sqlx::query(&format!("SET SESSION {} = DEFAULT", variable))
+        .execute(conn)
+        .await;
+}
+
+pub(crate) fn duration_ms(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_per_call_overrides_default() {
+        assert_eq!(
+            resolve_timeout(Some(500), Some(30_000)),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(
+            resolve_timeout(None, Some(30_000)),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(resolve_timeout(None, None), None);
+    }
+
+    #[test]
+    fn test_zero_disables_timeout() {
+        assert_eq!(resolve_timeout(Some(0), Some(30_000)), None);
+        assert_eq!(resolve_timeout(None, Some(0)), None);
+    }
+}
@@ -0,0 +1,286 @@
+//! Tracked query execution
+//!
+//! Wraps an execution on a pinned pooled connection: registers it for
+//! `cancel_query`, applies the statement timeout on the server and as a
+//! client-side backstop, and restores the session before the connection goes
+//! back to the pool. When the backstop fires, the statement is also cancelled
+//! on the server.
+
+use crate::db::cancel::{CancelTarget, QueryRegistry, QueryState};
+use crate::db::timeout::{self, BACKSTOP_GRACE};
+use crate::error::VelocityError;
+use sqlx::pool::PoolConnection;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// SQLite VM instructions between two interrupt checks
+const SQLITE_PROGRESS_OPS: i32 = 1000;
+
+/// One execution registered for cancellation and timeouts.
+/// Unregisters itself when dropped.
+pub struct TrackedQuery<'a> {
+    registry: &'a QueryRegistry,
+    query_id: Option<String>,
+    state: Arc<QueryState>,
+    timeout: Option<Duration>,
+    /// How to stop the statement on the server, for `cancel_query` and the
+    /// backstop
+    target: Option<CancelTarget>,
+    /// Session variable holding the server-side timeout, reset on release
+    session_timeout: Option<&'static str>,
+    /// Set when the client-side backstop fired mid-statement
+    expired: bool,
+}
+
+impl QueryRegistry {
+    fn track(
+        &self,
+        query_id: Option<&str>,
+        timeout: Option<Duration>,
+        target: Option<CancelTarget>,
+    ) -> Result<TrackedQuery<'_>, VelocityError> {
+        let state = QueryState::new();
+        let query_id = match (query_id, &target) {
+            (Some(query_id), Some(target)) => {
+                self.register(query_id, state.clone(), target.clone())?;
+                Some(query_id.to_string())
+            }
+            _ => None,
+        };
+        Ok(TrackedQuery {
+            registry: self,
+            query_id,
+            state,
+            timeout,
+            target,
+            session_timeout: None,
+            expired: false,
+        })
+    }
+
+    /// Track a PostgreSQL execution pinned to `conn`
+    pub async fn track_postgres(
+        &self,
+        query_id: Option<&str>,
+        timeout: Option<Duration>,
+        pool: &sqlx::PgPool,
+        conn: &mut sqlx::PgConnection,
+    ) -> Result<TrackedQuery<'_>, VelocityError> {
+        let target = if query_id.is_some() || timeout.is_some() {
+            let pid: i32 = sqlx::query_scalar("SELECT pg_backend_pid()")
+                .fetch_one(&mut *conn)
+                .await
+                .map_err(|e| VelocityError::Query(e.to_string()))?;
+            Some(CancelTarget::Postgres {
+                pool: pool.clone(),
+                pid,
+            })
+        } else {
+            None
+        };
+
+        let mut tracked = self.track(query_id, timeout, target)?;
+        if let Some(limit) = timeout {
+            timeout::set_postgres_timeout(conn, limit)
+                .await
+                .map_err(|e| VelocityError::Query(e.to_string()))?;
+            tracked.session_timeout = Some("statement_timeout");
+        }
+        Ok(tracked)
+    }
+
+    /// Track a MySQL execution pinned to `conn`
+    pub async fn track_mysql(
+        &self,
+        query_id: Option<&str>,
+        timeout: Option<Duration>,
+        pool: &sqlx::MySqlPool,
+        conn: &mut sqlx::MySqlConnection,
+    ) -> Result<TrackedQuery<'_>, VelocityError> {
+        let target = if query_id.is_some() || timeout.is_some() {
+            let thread_id: u64 = sqlx::query_scalar("SELECT CONNECTION_ID()")
+                .fetch_one(&mut *conn)
+                .await
+                .map_err(|e| VelocityError::Query(e.to_string()))?;
+            Some(CancelTarget::MySql {
+                pool: pool.clone(),
+                thread_id,
+            })
+        } else {
+            None
+        };
+
+        let mut tracked = self.track(query_id, timeout, target)?;
+        if let Some(limit) = timeout {
+            // Servers without a session timeout rely on the backstop alone
+            tracked.session_timeout = timeout::set_mysql_timeout(conn, limit).await;
+        }
+        Ok(tracked)
+    }
+
+    /// Track a PostgreSQL execution in a transaction session, whose backend
+    /// PID is known. Inside an aborted transaction the timeout cannot be set
+    /// and only the client-side backstop applies.
+    pub async fn track_postgres_session(
+        &self,
+        query_id: Option<&str>,
+        timeout: Option<Duration>,
+        pool: &sqlx::PgPool,
+        pid: i32,
+        conn: &mut sqlx::PgConnection,
+    ) -> Result<TrackedQuery<'_>, VelocityError> {
+        let target = (query_id.is_some() || timeout.is_some()).then(|| CancelTarget::Postgres {
+            pool: pool.clone(),
+            pid,
+        });
+        let mut tracked = self.track(query_id, timeout, target)?;
+        if let Some(limit) = timeout {
+            if timeout::set_postgres_timeout(conn, limit).await.is_ok() {
+                tracked.session_timeout = Some("statement_timeout");
+            }
+        }
+        Ok(tracked)
+    }
+
+    /// Track a MySQL execution in a transaction session, whose connection
+    /// thread ID is known
+    pub async fn track_mysql_session(
+        &self,
+        query_id: Option<&str>,
+        timeout: Option<Duration>,
+        pool: &sqlx::MySqlPool,
+        thread_id: u64,
+        conn: &mut sqlx::MySqlConnection,
+    ) -> Result<TrackedQuery<'_>, VelocityError> {
+        let target = (query_id.is_some() || timeout.is_some()).then(|| CancelTarget::MySql {
+            pool: pool.clone(),
+            thread_id,
+        });
+        let mut tracked = self.track(query_id, timeout, target)?;
+        if let Some(limit) = timeout {
+            tracked.session_timeout = timeout::set_mysql_timeout(conn, limit).await;
+        }
+        Ok(tracked)
+    }
+
+    /// Track a SQLite execution pinned to `conn`
+    pub async fn track_sqlite(
+        &self,
+        query_id: Option<&str>,
+        timeout: Option<Duration>,
+        conn: &mut sqlx::SqliteConnection,
+    ) -> Result<TrackedQuery<'_>, VelocityError> {
+        let target = query_id.map(|_| CancelTarget::Sqlite);
+        let tracked = self.track(query_id, timeout, target)?;
+
+        if query_id.is_some() || timeout.is_some() {
+            let state = tracked.state.clone();
+            conn.lock_handle()
+                .await
+                .map_err(|e| VelocityError::Query(e.to_string()))?
+                .set_progress_handler(SQLITE_PROGRESS_OPS, move || !state.should_interrupt());
+        }
+        Ok(tracked)
+    }
+}
+
+impl TrackedQuery<'_> {
+    /// Whether `cancel_query` was called for this execution
+    pub fn is_cancelled(&self) -> bool {
+        self.state.is_cancelled()
+    }
+
+    /// Whether the client-side backstop gave up on a statement; the
+    /// connection must not be used for further statements
+    pub fn is_expired(&self) -> bool {
+        self.expired
+    }
+
+    /// Await one statement, reporting failures caused by a cancellation as
+    /// `VelocityError::Cancelled` and by the time limit as `VelocityError::Timeout`
+    pub async fn run<T, F>(&mut self, query: F) -> Result<T, VelocityError>
+    where
+        F: Future<Output = Result<T, VelocityError>>,
+    {
+        if self.is_cancelled() {
+            return Err(VelocityError::Cancelled);
+        }
+
+        let started = self.state.start_statement(self.timeout);
+        let result = match self.timeout {
+            Some(limit) => match tokio::time::timeout(limit + BACKSTOP_GRACE, query).await {
+                Ok(result) => result,
+                Err(_) => {
+                    self.expired = true;
+                    // Otherwise the statement keeps running on the server
+                    // after its connection is discarded
+                    if let Some(target) = &self.target {
+                        let _ = tokio::time::timeout(BACKSTOP_GRACE, target.interrupt()).await;
+                    }
+                    return Err(VelocityError::Timeout(timeout::duration_ms(limit)));
+                }
+            },
+            None => query.await,
+        };
+
+        match (result, self.timeout) {
+            (Err(_), _) if self.is_cancelled() => Err(VelocityError::Cancelled),
+            (Err(_), Some(limit)) if self.state.statement_elapsed(started) >= limit => {
+                Err(VelocityError::Timeout(timeout::duration_ms(limit)))
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Hand a PostgreSQL connection back to the pool with its session restored
+    pub async fn release_postgres(self, mut conn: PoolConnection<sqlx::Postgres>) {
+        if !self.restore_postgres(&mut conn).await {
+            let _ = conn.close().await;
+        }
+    }
+
+    /// Hand a MySQL connection back to the pool with its session restored
+    pub async fn release_mysql(self, mut conn: PoolConnection<sqlx::MySql>) {
+        if !self.restore_mysql(&mut conn).await {
+            let _ = conn.close().await;
+        }
+    }
+
+    /// Restore the session of a PostgreSQL connection that stays in use,
+    /// once a cancellation being sent went through. Returns false when the
+    /// connection has to be discarded.
+    pub async fn restore_postgres(self, conn: &mut sqlx::PgConnection) -> bool {
+        self.state.finish_interrupts().await;
+        if self.expired {
+            return false;
+        }
+        if self.session_timeout.is_some() {
+            timeout::reset_postgres_timeout(conn).await;
+        }
+        true
+    }
+
+    /// Restore the session of a MySQL connection that stays in use, once a
+    /// cancellation being sent went through. Returns false when the
+    /// connection has to be discarded.
+    pub async fn restore_mysql(self, conn: &mut sqlx::MySqlConnection) -> bool {
+        self.state.finish_interrupts().await;
+        if self.expired {
+            return false;
+        }
+        if let Some(variable) = self.session_timeout {
+            timeout::reset_mysql_timeout(conn, variable).await;
+        }
+        true
+    }
+}
+
+impl Drop for TrackedQuery<'_> {
+    fn drop(&mut self) {
+        if let Some(query_id) = &self.query_id {
+            self.registry.unregister(query_id);
+        }
+        self.state.finish();
+    }
+}
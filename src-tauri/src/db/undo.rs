@@ -0,0 +1,411 @@
+//! Undo for committed grid edits
+//!
+//! Every batch committed by `execute_changes` is pushed onto a per-table stack
+//! together with the values it overwrote. Undoing the last batch runs the
+//! inverse statements in one transaction, each restricted to rows that still
+//! hold what the batch wrote. If any row changed since, nothing is reverted
+//! and the conflicts are reported. Batches on rows identified by `ctid` are
+//! not kept, since every revert would move the rows again.
+
+use crate::commands::database::{AppliedStatement, RowKey};
+use crate::db::dialect::SqlDialect;
+use crate::db::identity::RowIdentity;
+use crate::db::params::ColumnTypes;
+use crate::db::pool::metadata::get_generated_columns;
+use crate::db::pool::mutation::{describe_key, key_filter, mark_rolled_back, MutationTx};
+use crate::db::ConnectionPoolManager;
+use crate::error::VelocityError;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Committed batches kept per table; older ones are dropped
+const MAX_UNDO_DEPTH: usize = 50;
+
+/// What a committed change did
+#[derive(Debug, Clone)]
+pub enum CommittedChange {
+    Update {
+        key: RowKey,
+        column: String,
+        old_value: Value,
+        new_value: Value,
+    },
+    /// A row was inserted; `key` holds its generated key
+    Insert {
+        key: RowKey,
+        values: Map<String, Value>,
+    },
+    /// A row was deleted; `row` holds all of its columns
+    Delete {
+        key: RowKey,
+        row: Map<String, Value>,
+    },
+}
+
+struct ChangeBatch {
+    identity: RowIdentity,
+    changes: Vec<CommittedChange>,
+}
+
+type StackKey = (String, String);
+
+/// Undo stacks keyed by connection and table
+#[derive(Default)]
+pub struct UndoRegistry {
+    stacks: Mutex<HashMap<StackKey, Vec<ChangeBatch>>>,
+}
+
+impl UndoRegistry {
+    fn lock(&self) -> MutexGuard<'_, HashMap<StackKey, Vec<ChangeBatch>>> {
+        self.stacks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn key(connection_id: &str, table_name: &str) -> StackKey {
+        (connection_id.to_string(), table_name.to_string())
+    }
+
+    fn push(&self, connection_id: &str, table_name: &str, batch: ChangeBatch) {
+        let mut stacks = self.lock();
+        let stack = stacks
+            .entry(Self::key(connection_id, table_name))
+            .or_default();
+        stack.push(batch);
+        if stack.len() > MAX_UNDO_DEPTH {
+            stack.remove(0);
+        }
+    }
+
+    fn pop(&self, connection_id: &str, table_name: &str) -> Option<ChangeBatch> {
+        self.lock()
+            .get_mut(&Self::key(connection_id, table_name))
+            .and_then(Vec::pop)
+    }
+
+    fn depth(&self, connection_id: &str, table_name: &str) -> usize {
+        self.lock()
+            .get(&Self::key(connection_id, table_name))
+            .map_or(0, Vec::len)
+    }
+}
+
+/// Outcome of undoing a batch
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoResult {
+    pub success: bool,
+    pub rows_affected: i64,
+    /// Rows that changed since the batch was committed. Nothing is reverted
+    /// when there are any.
+    pub conflicts: Vec<String>,
+    /// Batches left to undo on the table
+    pub remaining: usize,
+    /// Statements sent to the database, for the audit log
+    #[serde(skip)]
+    pub statements: Vec<AppliedStatement>,
+}
+
+impl ConnectionPoolManager {
+    /// Remember a committed batch so it can be undone
+    pub(crate) fn push_undo(
+        &self,
+        connection_id: &str,
+        table_name: &str,
+        identity: RowIdentity,
+        changes: Vec<CommittedChange>,
+    ) {
+        if changes.is_empty() || identity == RowIdentity::Ctid {
+            return;
+        }
+        self.undo
+            .push(connection_id, table_name, ChangeBatch { identity, changes });
+    }
+
+    /// Revert the last batch committed on a table. On conflicts or errors the
+    /// batch stays on the stack.
+    pub async fn undo_last_changes(
+        &self,
+        connection_id: &str,
+        table_name: &str,
+    ) -> Result<UndoResult, VelocityError> {
+        let pool = self.get_pool(connection_id).await.ok_or_else(|| {
+            VelocityError::NotFound(format!("Connection {} not found", connection_id))
+        })?;
+        self.ensure_writable(connection_id, "Undoing changes")
+            .await?;
+        let batch = self.undo.pop(connection_id, table_name).ok_or_else(|| {
+            VelocityError::NotFound(format!("No changes to undo on {}", table_name))
+        })?;
+
+        let result = revert_batch(&pool, table_name, &batch).await;
+        let success = result.as_ref().is_ok_and(|r| r.conflicts.is_empty());
+        if !success {
+            self.undo.push(connection_id, table_name, batch);
+        }
+        let mut result = result?;
+        result.success = success;
+        result.remaining = self.undo.depth(connection_id, table_name);
+        Ok(result)
+    }
+}
+
+async fn revert_batch(
+    pool: &crate::db::DatabasePool,
+    table_name: &str,
+    batch: &ChangeBatch,
+) -> Result<UndoResult, VelocityError> {
+    let Some(dialect) = SqlDialect::from_pool(pool) else {
+        return Err(VelocityError::Query(
+            "Undo not supported for this database type".to_string(),
+        ));
+    };
+    let types = ColumnTypes::load(pool, dialect, table_name).await?;
+    let generated = get_generated_columns(pool, table_name).await?;
+    let mut tx = MutationTx::begin(pool, "Undo").await?;
+    let table = dialect.quote_identifier(table_name);
+
+    let mut rows_affected = 0;
+    let mut conflicts = Vec::new();
+    let mut statements = Vec::new();
+
+    // Newest first, so a row updated and then deleted is inserted back before
+    // its update is reverted
+    for change in batch.changes.iter().rev() {
+        let (key, statement, conflict) = match change {
+            CommittedChange::Update {
+                key,
+                column,
+                old_value,
+                new_value,
+            } => (
+                key,
+                types.statement(|params| {
+                    format!(
+                        "UPDATE {} SET {} = {} WHERE {} AND {}",
+                        table,
+                        dialect.quote_identifier(column),
+                        params.bind(column, old_value),
+                        key_filter(params, key),
+                        params.holds(column, new_value)
+                    )
+                }),
+                format!(
+                    "Row {} no longer holds the value written to {}",
+                    describe_key(key),
+                    column
+                ),
+            ),
+            CommittedChange::Insert { key, values } => {
+                let statement = types.statement(|params| {
+                    let mut filter = vec![key_filter(params, key)];
+                    for (column, value) in values {
+                        filter.push(params.holds(column, value));
+                    }
+                    format!("DELETE FROM {} WHERE {}", table, filter.join(" AND "))
+                });
+                (
+                    key,
+                    statement,
+                    format!(
+                        "Inserted row {} was changed or deleted since",
+                        describe_key(key)
+                    ),
+                )
+            }
+            CommittedChange::Delete { key, row } => {
+                // Without a primary key or rowid other rows may hold the same
+                // values
+                if matches!(batch.identity, RowIdentity::PrimaryKey | RowIdentity::Rowid) {
+                    let existing = types.statement(|params| {
+                        format!("SELECT 1 FROM {} WHERE {}", table, key_filter(params, key))
+                    });
+                    let existing = match existing.check() {
+                        Ok(()) => tx.fetch_row(&existing).await.map_err(|e| e.to_string()),
+                        Err(e) => Err(e),
+                    };
+                    match existing {
+                        Ok(None) => {}
+                        Ok(Some(_)) => {
+                            conflicts
+                                .push(format!("A row with key {} exists again", describe_key(key)));
+                            continue;
+                        }
+                        Err(e) => {
+                            tx.rollback().await?;
+                            return Err(VelocityError::Query(e));
+                        }
+                    }
+                }
+                // Computed columns are left to the database. Identity values
+                // and the rowid are written back so the row keeps its key.
+                let mut values: Vec<(&String, &Value)> = row
+                    .iter()
+                    .filter(|(column, _)| {
+                        !generated.iter().any(|g| !g.identity && &g.name == *column)
+                    })
+                    .collect();
+                if batch.identity == RowIdentity::Rowid {
+                    values.extend(key.iter().filter(|(column, _)| !row.contains_key(*column)));
+                }
+                let overriding = if values
+                    .iter()
+                    .any(|(column, _)| generated.iter().any(|g| g.identity && &g.name == *column))
+                {
+                    " OVERRIDING SYSTEM VALUE"
+                } else {
+                    ""
+                };
+                let statement = types.statement(|params| {
+                    let columns: Vec<String> = values
+                        .iter()
+                        .map(|(column, _)| dialect.quote_identifier(column))
+                        .collect();
+                    let values: Vec<String> = values
+                        .iter()
+                        .map(|(column, value)| params.bind(column, value))
+                        .collect();
+                    format!(
+                        "INSERT INTO {} ({}){} VALUES ({})",
+                        table,
+                        columns.join(", "),
+                        overriding,
+                        values.join(", ")
+                    )
+                });
+                (
+                    key,
+                    statement,
+                    format!(
+                        "Deleted row {} could not be inserted again",
+                        describe_key(key)
+                    ),
+                )
+            }
+        };
+
+        let result = match statement.check() {
+            Ok(()) => tx
+                .execute_statement(&statement)
+                .await
+                .map(|(rows, _)| rows)
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(0) => conflicts.push(conflict),
+            // Rows matched by all their values may have duplicates
+            Ok(rows) if rows > 1 => conflicts.push(format!(
+                "{} rows match {}; only one was changed",
+                rows,
+                describe_key(key)
+            )),
+            Ok(rows) => {
+                rows_affected += rows as i64;
+                statements.push(AppliedStatement {
+                    sql: statement.to_string(),
+                    rows_affected: Some(rows as i64),
+                    error: None,
+                });
+            }
+            Err(e) => {
+                tx.rollback().await?;
+                return Err(VelocityError::Query(e));
+            }
+        }
+    }
+
+    if conflicts.is_empty() {
+        tx.commit().await?;
+    } else {
+        tx.rollback().await?;
+        mark_rolled_back(&mut statements);
+        rows_affected = 0;
+    }
+
+    Ok(UndoResult {
+        success: conflicts.is_empty(),
+        rows_affected,
+        conflicts,
+        remaining: 0,
+        statements,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::DatabasePool;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    fn batch(id: &str) -> ChangeBatch {
+        ChangeBatch {
+            identity: RowIdentity::PrimaryKey,
+            changes: vec![CommittedChange::Update {
+                key: RowKey::from_iter([("id".to_string(), Value::from(id))]),
+                column: "name".to_string(),
+                old_value: Value::Null,
+                new_value: Value::from("x"),
+            }],
+        }
+    }
+
+    #[test]
+    fn test_stacks_are_per_table_and_bounded() {
+        let registry = UndoRegistry::default();
+        for i in 0..MAX_UNDO_DEPTH + 5 {
+            registry.push("c1", "users", batch(&i.to_string()));
+        }
+        registry.push("c1", "orders", batch("1"));
+        assert_eq!(registry.depth("c1", "users"), MAX_UNDO_DEPTH);
+        assert_eq!(registry.depth("c2", "users"), 0);
+
+        let last = registry.pop("c1", "users").unwrap();
+        assert!(matches!(
+            &last.changes[0],
+            CommittedChange::Update { key, .. }
+                if key["id"].as_str() == Some(&*(MAX_UNDO_DEPTH + 4).to_string())
+        ));
+        assert_eq!(registry.depth("c1", "orders"), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_row_returns_without_generated_columns() {
+        let sqlite = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        sqlx::query(
+            "CREATE TABLE t(a INTEGER, b INTEGER GENERATED ALWAYS AS (a * 2) STORED);
+             INSERT INTO t(a) VALUES (1), (2);
+             DELETE FROM t WHERE rowid = 1",
+        )
+        .execute(&sqlite)
+        .await
+        .unwrap();
+        let pool = DatabasePool::SQLite(sqlite.clone());
+
+        let deleted = ChangeBatch {
+            identity: RowIdentity::Rowid,
+            changes: vec![CommittedChange::Delete {
+                key: RowKey::from_iter([("rowid".to_string(), Value::from(1))]),
+                row: Map::from_iter([
+                    ("a".to_string(), Value::from(1)),
+                    ("b".to_string(), Value::from(2)),
+                ]),
+            }],
+        };
+        let result = revert_batch(&pool, "t", &deleted).await.unwrap();
+        assert!(result.conflicts.is_empty(), "{:?}", result.conflicts);
+
+        let rows: Vec<(i64, i64, i64)> = sqlx::query_as("SELECT rowid, a, b FROM t ORDER BY rowid")
+            .fetch_all(&sqlite)
+            .await
+            .unwrap();
+        assert_eq!(rows, vec![(1, 1, 2), (2, 2, 4)]);
+
+        // The rowid is taken again, so a second revert conflicts
+        let result = revert_batch(&pool, "t", &deleted).await.unwrap();
+        assert_eq!(result.conflicts.len(), 1);
+    }
+}
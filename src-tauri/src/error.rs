@@ -17,6 +17,9 @@ pub enum VelocityError {
     #[error("Timeout after {0}ms")]
     Timeout(u64),
     
+    #[error("Query cancelled")]
+    Cancelled,
+    
     #[error("Connection not found: {0}")]
     ConnectionNotFound(String),
     
@@ -37,6 +40,12 @@ pub enum VelocityError {
     
     #[error("Import error: {0}")]
     Import(String),
+    
+    #[error("Connection is read-only: {0} is not allowed")]
+    ReadOnly(String),
+    
+    #[error("Confirmation required: {message} (confirmation token: {token})")]
+    ConfirmationRequired { message: String, token: String },
 }
 
 impl Serialize for VelocityError {
@@ -1,5 +1,7 @@
 use std::fs;
 use std::path::Path;
+use crate::db::dialect::SqlDialect;
+use crate::db::splitter::split_statements;
 use crate::error::VelocityError;
 
 /// Read SQL file contents for execution
@@ -9,10 +11,9 @@ pub fn read_sql_file<P: AsRef<Path>>(path: P) -> Result<String, VelocityError> {
 }
 
 /// Split SQL file into individual statements
-pub fn split_sql_statements(sql: &str) -> Vec<String> {
-    sql.split(';')
-        .map(|s| s.trim())
-        .filter(|s| !s.is_empty() && !s.starts_with("--"))
-        .map(|s| format!("{};", s))
+pub fn split_sql_statements(sql: &str, dialect: SqlDialect) -> Vec<String> {
+    split_statements(sql, dialect)
+        .into_iter()
+        .map(|statement| statement.sql)
         .collect()
 }
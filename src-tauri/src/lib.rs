@@ -8,16 +8,22 @@ pub mod ssh;
 pub mod store;
 
 use commands::ai::*;
+use commands::audit::*;
 use commands::connections::*;
 use commands::database::*;
 use commands::export::*;
+use commands::history::*;
 use commands::import::*;
 use commands::keychain::*;
+use commands::saved_queries::*;
 use commands::ssh::*;
+use commands::transaction::*;
 use db::ConnectionPoolManager;
 use ssh::tunnel::SshTunnelManager;
 use std::sync::Arc;
+use store::audit::AuditLogStore;
 use store::connections::ConnectionsStore;
+use store::history::QueryHistoryStore;
 use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -38,6 +44,12 @@ pub fn run() {
             let store = ConnectionsStore::new(&app.handle())
                 .expect("Failed to initialize connections store");
             app.manage(store);
+            let history = QueryHistoryStore::new(app.handle())
+                .expect("Failed to initialize query history store");
+            app.manage(history);
+            let audit = AuditLogStore::new(app.handle())
+                .expect("Failed to initialize audit log store");
+            app.manage(audit);
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
@@ -60,8 +72,37 @@ pub fn run() {
             get_table_foreign_keys,
             get_column_values, // <-- Added here
             execute_changes,
+            undo_last_changes,
             execute_query,
+            list_query_parameters,
+            classify_sql,
+            cancel_query,
+            execute_script,
+            execute_query_stream,
+            ack_query_stream,
+            close_query_stream,
             explain_query,
+            // Transaction sessions
+            begin_transaction,
+            commit_transaction,
+            rollback_transaction,
+            savepoint,
+            get_transaction,
+            // Query history
+            search_query_history,
+            rerun_query_history,
+            prune_query_history,
+            // Audit log
+            search_audit_log,
+            export_audit_log,
+            // Saved query library
+            list_saved_queries,
+            save_saved_query,
+            delete_saved_query,
+            save_query_folder,
+            delete_query_folder,
+            export_saved_queries,
+            import_saved_queries,
             // Schema / DDL commands
             preview_create_table,
             execute_ddl,
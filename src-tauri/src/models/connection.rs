@@ -13,6 +13,15 @@ pub struct Connection {
     pub color: Option<String>,
     pub last_used_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
+    /// Default statement timeout in milliseconds (None = no limit)
+    #[serde(default)]
+    pub query_timeout_ms: Option<u64>,
+    /// Open sessions read-only and reject data-editing, DDL and import commands
+    #[serde(default)]
+    pub read_only: bool,
+    /// Ask for confirmation before DROP, TRUNCATE and UPDATE/DELETE without WHERE
+    #[serde(default)]
+    pub protected: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -112,7 +121,7 @@ pub enum SslMode {
     VerifyFull,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DatabaseType {
     SQLite,
     PostgreSQL,
@@ -0,0 +1,88 @@
+use crate::db::params::ParamType;
+use crate::models::connection::DatabaseType;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Version written to exported library files
+pub const LIBRARY_VERSION: u32 = 1;
+
+/// Saved queries and the folders they are organised in
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedQueryLibrary {
+    #[serde(default = "library_version")]
+    pub version: u32,
+    #[serde(default)]
+    pub folders: Vec<QueryFolder>,
+    #[serde(default)]
+    pub queries: Vec<SavedQuery>,
+}
+
+fn library_version() -> u32 {
+    LIBRARY_VERSION
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueryFolder {
+    /// Empty for a new folder; an ID is assigned on save
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    /// None for top-level folders
+    #[serde(default)]
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedQuery {
+    /// Empty for a new query; an ID is assigned on save
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub sql: String,
+    /// None for queries outside any folder
+    #[serde(default)]
+    pub folder_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Only offer the query on this connection
+    #[serde(default)]
+    pub connection_id: Option<String>,
+    /// Only offer the query on connections of this type
+    #[serde(default)]
+    pub db_type: Option<DatabaseType>,
+    /// Placeholders the SQL expects, to prompt for before running
+    #[serde(default)]
+    pub parameters: Vec<SavedQueryParameter>,
+    #[serde(default = "Utc::now")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default = "Utc::now")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedQueryParameter {
+    /// `user_id` for `:user_id`, `1` for `$1`
+    pub name: String,
+    #[serde(default, rename = "type")]
+    pub param_type: Option<ParamType>,
+    #[serde(default)]
+    pub default_value: Option<serde_json::Value>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl SavedQuery {
+    /// Whether the query applies to a connection with the given ID and type
+    pub fn applies_to(&self, connection_id: &str, db_type: &DatabaseType) -> bool {
+        self.connection_id
+            .as_deref()
+            .is_none_or(|id| id == connection_id)
+            && self.db_type.as_ref().is_none_or(|t| t == db_type)
+    }
+}
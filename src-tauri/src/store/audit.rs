@@ -0,0 +1,532 @@
+//! Audit log of data and schema changes
+//!
+//! Every statement that changes data or schema is appended to a SQLite
+//! database next to `connections.json` in the app config dir. Entries cannot
+//! be updated or deleted: triggers reject both.
+
+use crate::error::VelocityError;
+use crate::export::ExportFormat;
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{Executor, QueryBuilder, Row, Sqlite, SqlitePool};
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS audit_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        connection_id TEXT NOT NULL,
+        command TEXT NOT NULL,
+        sql TEXT NOT NULL,
+        rows_affected INTEGER,
+        success INTEGER NOT NULL,
+        error TEXT,
+        executed_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS audit_log_connection
+        ON audit_log (connection_id, executed_at);
+    CREATE TRIGGER IF NOT EXISTS audit_log_no_update BEFORE UPDATE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+    CREATE TRIGGER IF NOT EXISTS audit_log_no_delete BEFORE DELETE ON audit_log
+        BEGIN SELECT RAISE(ABORT, 'the audit log is append-only'); END;
+";
+
+const ENTRY_COLUMNS: &str =
+    "id, connection_id, command, sql, rows_affected, success, error, executed_at";
+
+/// Column headers of exported entries
+const EXPORT_HEADERS: [&str; 8] = [
+    "id",
+    "connectionId",
+    "command",
+    "sql",
+    "rowsAffected",
+    "success",
+    "error",
+    "executedAt",
+];
+
+/// The command that ran a statement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditCommand {
+    ExecuteQuery,
+    ExecuteQueryStream,
+    ExecuteScript,
+    ExecuteSqlSafe,
+    RerunQueryHistory,
+    ExecuteChanges,
+    UndoLastChanges,
+    ExecuteDdl,
+    ImportCsv,
+    ImportSql,
+}
+
+impl AuditCommand {
+    fn as_str(self) -> &'static str {
+        match self {
+            AuditCommand::ExecuteQuery => "execute_query",
+            AuditCommand::ExecuteQueryStream => "execute_query_stream",
+            AuditCommand::ExecuteScript => "execute_script",
+            AuditCommand::ExecuteSqlSafe => "execute_sql_safe",
+            AuditCommand::RerunQueryHistory => "rerun_query_history",
+            AuditCommand::ExecuteChanges => "execute_changes",
+            AuditCommand::UndoLastChanges => "undo_last_changes",
+            AuditCommand::ExecuteDdl => "execute_ddl",
+            AuditCommand::ImportCsv => "import_csv",
+            AuditCommand::ImportSql => "import_sql",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "execute_query_stream" => AuditCommand::ExecuteQueryStream,
+            "execute_script" => AuditCommand::ExecuteScript,
+            "execute_sql_safe" => AuditCommand::ExecuteSqlSafe,
+            "rerun_query_history" => AuditCommand::RerunQueryHistory,
+            "execute_changes" => AuditCommand::ExecuteChanges,
+            "undo_last_changes" => AuditCommand::UndoLastChanges,
+            "execute_ddl" => AuditCommand::ExecuteDdl,
+            "import_csv" => AuditCommand::ImportCsv,
+            "import_sql" => AuditCommand::ImportSql,
+            _ => AuditCommand::ExecuteQuery,
+        }
+    }
+}
+
+/// A recorded change
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub id: i64,
+    pub connection_id: String,
+    pub command: AuditCommand,
+    /// The statement exactly as it was sent to the database
+    pub sql: String,
+    /// None when the statement failed or the count is unknown
+    pub rows_affected: Option<i64>,
+    pub success: bool,
+    pub error: Option<String>,
+    pub executed_at: DateTime<Utc>,
+}
+
+/// A change to record
+#[derive(Debug, Clone)]
+pub struct NewAuditEntry<'a> {
+    pub connection_id: &'a str,
+    pub command: AuditCommand,
+    pub sql: &'a str,
+    pub rows_affected: Option<i64>,
+    pub error: Option<String>,
+    pub executed_at: DateTime<Utc>,
+}
+
+/// Criteria for searching the audit log; all are optional and combined with AND
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditFilter {
+    pub connection_id: Option<String>,
+    /// Case-insensitive substring of the SQL, e.g. a table name or key value
+    pub text: Option<String>,
+    pub command: Option<AuditCommand>,
+    /// Inclusive lower bound of the execution time
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound of the execution time
+    pub to: Option<DateTime<Utc>>,
+    /// Only failed (true) or only successful (false) statements
+    pub failed: Option<bool>,
+    /// Maximum number of entries, newest first (default 100, all when exporting)
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+pub struct AuditLogStore {
+    pool: SqlitePool,
+}
+
+impl AuditLogStore {
+    pub fn new(app: &AppHandle) -> Result<Self, VelocityError> {
+        let app_config_dir = app
+            .path()
+            .app_config_dir()
+            .map_err(|e| VelocityError::TauriError(e.to_string()))?;
+
+        if !app_config_dir.exists() {
+            fs::create_dir_all(&app_config_dir)?;
+        }
+
+        Ok(Self::open(&app_config_dir.join("audit_log.db")))
+    }
+
+    /// Open the audit database at `path`. The file and table are created on
+    /// first use.
+    pub fn open(path: &Path) -> Self {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .after_connect(|conn, _| {
+                Box::pin(async move {
+                    conn.execute(SCHEMA).await?;
+                    Ok(())
+                })
+            })
+            .connect_lazy_with(options);
+        Self { pool }
+    }
+
+    /// Append an entry, returning its ID
+    pub async fn record(&self, entry: NewAuditEntry<'_>) -> Result<i64, VelocityError> {
+        let result = sqlx::query(
+            "INSERT INTO audit_log
+                (connection_id, command, sql, rows_affected, success, error, executed_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(entry.connection_id)
+        .bind(entry.command.as_str())
+        .bind(entry.sql)
+        .bind(entry.rows_affected)
+        .bind(entry.error.is_none())
+        .bind(entry.error)
+        .bind(entry.executed_at.timestamp_millis())
+        .execute(&self.pool)
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Entries matching the filter, newest first
+    pub async fn search(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, VelocityError> {
+        self.fetch(filter, 100).await
+    }
+
+    /// Write the entries matching the filter to a CSV or JSON file, returning
+    /// the number of exported entries
+    pub async fn export(
+        &self,
+        filter: &AuditFilter,
+        format: &ExportFormat,
+        path: &Path,
+    ) -> Result<usize, VelocityError> {
+        // SQLite treats a negative limit as no limit
+        let entries = self.fetch(filter, -1).await?;
+        let headers: Vec<String> = EXPORT_HEADERS.iter().map(|h| h.to_string()).collect();
+        let rows: Vec<Vec<serde_json::Value>> = entries.iter().map(export_row).collect();
+        match format {
+            ExportFormat::Csv => crate::export::csv::export_to_csv(path, &headers, &rows, None),
+            ExportFormat::Json => crate::export::json::export_to_json(path, &headers, &rows, true),
+            _ => Err(VelocityError::Export(
+                "The audit log can only be exported to CSV or JSON".to_string(),
+            )),
+        }
+    }
+
+    async fn fetch(
+        &self,
+        filter: &AuditFilter,
+        default_limit: i64,
+    ) -> Result<Vec<AuditEntry>, VelocityError> {
+        let mut query = QueryBuilder::<Sqlite>::new(format!(
+            "SELECT {} FROM audit_log WHERE 1 = 1",
+            ENTRY_COLUMNS
+        ));
+        if let Some(connection_id) = &filter.connection_id {
+            query
+                .push(" AND connection_id = ")
+                .push_bind(connection_id.clone());
+        }
+        if let Some(text) = filter.text.as_deref().filter(|t| !t.is_empty()) {
+            let pattern = format!(
+                "%{}%",
+                text.replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            query
+                .push(" AND sql LIKE ")
+                .push_bind(pattern)
+                .push(" ESCAPE '\\'");
+        }
+        if let Some(command) = filter.command {
+            query.push(" AND command = ").push_bind(command.as_str());
+        }
+        if let Some(from) = filter.from {
+            query
+                .push(" AND executed_at >= ")
+                .push_bind(from.timestamp_millis());
+        }
+        if let Some(to) = filter.to {
+            query
+                .push(" AND executed_at < ")
+                .push_bind(to.timestamp_millis());
+        }
+        if let Some(failed) = filter.failed {
+            query.push(if failed {
+                " AND success = 0"
+            } else {
+                " AND success = 1"
+            });
+        }
+        query
+            .push(" ORDER BY executed_at DESC, id DESC LIMIT ")
+            .push_bind(filter.limit.unwrap_or(default_limit))
+            .push(" OFFSET ")
+            .push_bind(filter.offset.unwrap_or(0));
+
+        let rows = query
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+        Ok(rows.iter().map(entry_from_row).collect())
+    }
+}
+
+fn entry_from_row(row: &sqlx::sqlite::SqliteRow) -> AuditEntry {
+    let executed_at: i64 = row.get("executed_at");
+    AuditEntry {
+        id: row.get("id"),
+        connection_id: row.get("connection_id"),
+        command: AuditCommand::parse(row.get("command")),
+        sql: row.get("sql"),
+        rows_affected: row.get("rows_affected"),
+        success: row.get("success"),
+        error: row.get("error"),
+        executed_at: Utc
+            .timestamp_millis_opt(executed_at)
+            .single()
+            .unwrap_or_default(),
+    }
+}
+
+fn export_row(entry: &AuditEntry) -> Vec<serde_json::Value> {
+    vec![
+        entry.id.into(),
+        entry.connection_id.clone().into(),
+        entry.command.as_str().into(),
+        entry.sql.clone().into(),
+        entry.rows_affected.into(),
+        entry.success.into(),
+        entry.error.clone().into(),
+        entry.executed_at.to_rfc3339().into(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::path::PathBuf;
+
+    /// A store on a new file in the temp dir
+    fn temp_store() -> (AuditLogStore, PathBuf) {
+        let path = temp_path("db");
+        (AuditLogStore::open(&path), path)
+    }
+
+    fn temp_path(extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "velocity-audit-{}.{}",
+            uuid::Uuid::new_v4(),
+            extension
+        ))
+    }
+
+    fn remove(path: PathBuf) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap()
+    }
+
+    /// An entry executed `minutes` after `start()`
+    fn entry<'a>(command: AuditCommand, sql: &'a str, minutes: i64) -> NewAuditEntry<'a> {
+        NewAuditEntry {
+            connection_id: "c1",
+            command,
+            sql,
+            rows_affected: Some(1),
+            error: None,
+            executed_at: start() + Duration::minutes(minutes),
+        }
+    }
+
+    fn sqls(entries: &[AuditEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.sql.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_entries_cannot_be_changed() {
+        let (store, path) = temp_store();
+        store
+            .record(entry(AuditCommand::ExecuteQuery, "DELETE FROM t", 0))
+            .await
+            .unwrap();
+
+        for sql in [
+            "UPDATE audit_log SET sql = 'SELECT 1'",
+            "DELETE FROM audit_log",
+        ] {
+            let error = sqlx::query(sql).execute(&store.pool).await.unwrap_err();
+            assert!(error.to_string().contains("append-only"), "{}", error);
+        }
+        let entries = store.search(&AuditFilter::default()).await.unwrap();
+        assert_eq!(sqls(&entries), vec!["DELETE FROM t"]);
+        remove(path);
+    }
+
+    #[tokio::test]
+    async fn test_search_filters() {
+        let (store, path) = temp_store();
+        store
+            .record(entry(
+                AuditCommand::ExecuteQuery,
+                "UPDATE t SET a = '50%'",
+                0,
+            ))
+            .await
+            .unwrap();
+        store
+            .record(entry(
+                AuditCommand::ExecuteQuery,
+                "UPDATE t SET a = '500'",
+                10,
+            ))
+            .await
+            .unwrap();
+        store
+            .record(entry(AuditCommand::ExecuteChanges, "DELETE FROM t_x", 20))
+            .await
+            .unwrap();
+        store
+            .record(NewAuditEntry {
+                connection_id: "c2",
+                rows_affected: None,
+                error: Some("no such table: tax".to_string()),
+                ..entry(AuditCommand::ExecuteDdl, "DROP TABLE tax", 30)
+            })
+            .await
+            .unwrap();
+
+        let search = |filter: AuditFilter| {
+            let store = &store;
+            async move { store.search(&filter).await.unwrap() }
+        };
+        let text = |text: &str| AuditFilter {
+            text: Some(text.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            sqls(&search(text("0%")).await),
+            vec!["UPDATE t SET a = '50%'"]
+        );
+        assert_eq!(sqls(&search(text("t_x")).await), vec!["DELETE FROM t_x"]);
+        assert_eq!(
+            sqls(&search(text("update T")).await),
+            vec!["UPDATE t SET a = '500'", "UPDATE t SET a = '50%'"]
+        );
+
+        let found = search(AuditFilter {
+            command: Some(AuditCommand::ExecuteChanges),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(sqls(&found), vec!["DELETE FROM t_x"]);
+        assert_eq!(found[0].command, AuditCommand::ExecuteChanges);
+
+        // From is inclusive, to exclusive
+        let found = search(AuditFilter {
+            from: Some(start() + Duration::minutes(10)),
+            to: Some(start() + Duration::minutes(30)),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(
+            sqls(&found),
+            vec!["DELETE FROM t_x", "UPDATE t SET a = '500'"]
+        );
+
+        let found = search(AuditFilter {
+            connection_id: Some("c2".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(sqls(&found), vec!["DROP TABLE tax"]);
+        assert!(!found[0].success);
+        assert_eq!(found[0].rows_affected, None);
+        let found = search(AuditFilter {
+            failed: Some(false),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(found.len(), 3);
+        remove(path);
+    }
+
+    #[tokio::test]
+    async fn test_export_csv_and_json() {
+        let (store, path) = temp_store();
+        store
+            .record(entry(AuditCommand::ExecuteQuery, "UPDATE t SET a = 1", 0))
+            .await
+            .unwrap();
+        store
+            .record(entry(
+                AuditCommand::ImportCsv,
+                "INSERT INTO t VALUES (1, 'a,b')",
+                10,
+            ))
+            .await
+            .unwrap();
+        let filter = AuditFilter {
+            command: Some(AuditCommand::ImportCsv),
+            ..Default::default()
+        };
+
+        let csv_path = temp_path("csv");
+        let exported = store
+            .export(&filter, &ExportFormat::Csv, &csv_path)
+            .await
+            .unwrap();
+        assert_eq!(exported, 1);
+        let mut reader = csv::Reader::from_path(&csv_path).unwrap();
+        assert_eq!(
+            reader.headers().unwrap().iter().collect::<Vec<_>>(),
+            EXPORT_HEADERS
+        );
+        let records: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(records.len(), 1);
+        assert_eq!(&records[0][2], "import_csv");
+        assert_eq!(&records[0][3], "INSERT INTO t VALUES (1, 'a,b')");
+
+        let json_path = temp_path("json");
+        let exported = store
+            .export(&AuditFilter::default(), &ExportFormat::Json, &json_path)
+            .await
+            .unwrap();
+        assert_eq!(exported, 2);
+        let entries: Vec<serde_json::Value> =
+            serde_json::from_str(&fs::read_to_string(&json_path).unwrap()).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["command"], "import_csv");
+        assert_eq!(entries[1]["sql"], "UPDATE t SET a = 1");
+        assert_eq!(entries[1]["rowsAffected"], 1);
+        assert_eq!(entries[1]["success"], true);
+        assert_eq!(entries[1]["executedAt"], start().to_rfc3339());
+
+        let excel = store
+            .export(&filter, &ExportFormat::Excel, &temp_path("xlsx"))
+            .await;
+        assert!(excel.is_err());
+        for file in [csv_path, json_path] {
+            let _ = fs::remove_file(file);
+        }
+        remove(path);
+    }
+}
@@ -0,0 +1,440 @@
+//! Query history
+//!
+//! Every statement run from the editor or the AI agent is recorded in a
+//! SQLite database next to `connections.json` in the app config dir.
+
+use crate::db::params::QueryParam;
+use crate::error::VelocityError;
+use chrono::{DateTime, TimeZone, Utc};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+use sqlx::{Executor, QueryBuilder, Row, Sqlite, SqlitePool};
+use std::fs;
+use std::path::Path;
+use tauri::{AppHandle, Manager};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS query_history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        connection_id TEXT NOT NULL,
+        sql TEXT NOT NULL,
+        params TEXT,
+        source TEXT NOT NULL,
+        started_at INTEGER NOT NULL,
+        duration_ms INTEGER NOT NULL,
+        row_count INTEGER,
+        error TEXT
+    );
+    CREATE INDEX IF NOT EXISTS query_history_started_at ON query_history (started_at);
+    CREATE INDEX IF NOT EXISTS query_history_connection
+        ON query_history (connection_id, started_at);
+";
+
+const ENTRY_COLUMNS: &str =
+    "id, connection_id, sql, params, source, started_at, duration_ms, row_count, error";
+
+/// Where a statement was run from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HistorySource {
+    Editor,
+    Agent,
+}
+
+impl HistorySource {
+    fn as_str(self) -> &'static str {
+        match self {
+            HistorySource::Editor => "editor",
+            HistorySource::Agent => "agent",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "agent" => HistorySource::Agent,
+            _ => HistorySource::Editor,
+        }
+    }
+}
+
+/// A recorded execution
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryEntry {
+    pub id: i64,
+    pub connection_id: String,
+    pub sql: String,
+    /// Values bound to the placeholders of the statement
+    pub params: Vec<QueryParam>,
+    pub source: HistorySource,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    /// Rows returned, or changed by statements without a result set. None
+    /// when the statement failed.
+    pub row_count: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// An execution to record
+#[derive(Debug, Clone)]
+pub struct NewHistoryEntry<'a> {
+    pub connection_id: &'a str,
+    pub sql: &'a str,
+    pub params: &'a [QueryParam],
+    pub source: HistorySource,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    pub row_count: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Criteria for searching the history; all are optional and combined with AND
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryFilter {
+    /// Case-insensitive substring of the SQL
+    pub text: Option<String>,
+    pub connection_id: Option<String>,
+    /// Inclusive lower bound of the start time
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound of the start time
+    pub to: Option<DateTime<Utc>>,
+    /// Only failed (true) or only successful (false) executions
+    pub failed: Option<bool>,
+    /// Maximum number of entries, newest first (default 100)
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+pub struct QueryHistoryStore {
+    pool: SqlitePool,
+}
+
+impl QueryHistoryStore {
+    pub fn new(app: &AppHandle) -> Result<Self, VelocityError> {
+        let app_config_dir = app
+            .path()
+            .app_config_dir()
+            .map_err(|e| VelocityError::TauriError(e.to_string()))?;
+
+        if !app_config_dir.exists() {
+            fs::create_dir_all(&app_config_dir)?;
+        }
+
+        Ok(Self::open(&app_config_dir.join("query_history.db")))
+    }
+
+    /// Open the history database at `path`. The file and table are created
+    /// on first use.
+    pub fn open(path: &Path) -> Self {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(4)
+            .after_connect(|conn, _| {
+                Box::pin(async move {
+                    conn.execute(SCHEMA).await?;
+                    Ok(())
+                })
+            })
+            .connect_lazy_with(options);
+        Self { pool }
+    }
+
+    /// Record an execution, returning the ID of the new entry
+    pub async fn record(&self, entry: NewHistoryEntry<'_>) -> Result<i64, VelocityError> {
+        let params = if entry.params.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(entry.params)?)
+        };
+        let result = sqlx::query(
+            "INSERT INTO query_history
+                (connection_id, sql, params, source, started_at, duration_ms, row_count, error)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(entry.connection_id)
+        .bind(entry.sql)
+        .bind(params)
+        .bind(entry.source.as_str())
+        .bind(entry.started_at.timestamp_millis())
+        .bind(entry.duration_ms)
+        .bind(entry.row_count)
+        .bind(entry.error)
+        .execute(&self.pool)
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+        Ok(result.last_insert_rowid())
+    }
+
+    /// Entries matching the filter, newest first
+    pub async fn search(&self, filter: &HistoryFilter) -> Result<Vec<HistoryEntry>, VelocityError> {
+        let mut query = QueryBuilder::<Sqlite>::new(format!(
+            "SELECT {} FROM query_history WHERE 1 = 1",
+            ENTRY_COLUMNS
+        ));
+        if let Some(text) = filter.text.as_deref().filter(|t| !t.is_empty()) {
+            let pattern = format!(
+                "%{}%",
+                text.replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            query
+                .push(" AND sql LIKE ")
+                .push_bind(pattern)
+                .push(" ESCAPE '\\'");
+        }
+        if let Some(connection_id) = &filter.connection_id {
+            query
+                .push(" AND connection_id = ")
+                .push_bind(connection_id.clone());
+        }
+        if let Some(from) = filter.from {
+            query
+                .push(" AND started_at >= ")
+                .push_bind(from.timestamp_millis());
+        }
+        if let Some(to) = filter.to {
+            query
+                .push(" AND started_at < ")
+                .push_bind(to.timestamp_millis());
+        }
+        if let Some(failed) = filter.failed {
+            query.push(if failed {
+                " AND error IS NOT NULL"
+            } else {
+                " AND error IS NULL"
+            });
+        }
+        query
+            .push(" ORDER BY started_at DESC, id DESC LIMIT ")
+            .push_bind(filter.limit.unwrap_or(100))
+            .push(" OFFSET ")
+            .push_bind(filter.offset.unwrap_or(0));
+
+        let rows = query
+            .build()
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?;
+        Ok(rows.iter().map(entry_from_row).collect())
+    }
+
+    /// A single entry by ID
+    pub async fn get(&self, id: i64) -> Result<HistoryEntry, VelocityError> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM query_history WHERE id = ?",
+            ENTRY_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|e| VelocityError::Query(e.to_string()))?;
+        row.as_ref()
+            .map(entry_from_row)
+            .ok_or_else(|| VelocityError::NotFound(format!("History entry {}", id)))
+    }
+
+    /// Delete entries started before `before` and all but the newest
+    /// `max_entries`. Returns the number of deleted entries.
+    pub async fn prune(
+        &self,
+        before: Option<DateTime<Utc>>,
+        max_entries: Option<i64>,
+    ) -> Result<u64, VelocityError> {
+        let mut deleted = 0;
+        if let Some(before) = before {
+            deleted += sqlx::query("DELETE FROM query_history WHERE started_at < ?")
+                .bind(before.timestamp_millis())
+                .execute(&self.pool)
+                .await
+                .map_err(|e| VelocityError::Query(e.to_string()))?
+                .rows_affected();
+        }
+        if let Some(max_entries) = max_entries {
+            deleted += sqlx::query(
+                "DELETE FROM query_history WHERE id NOT IN (
+                    SELECT id FROM query_history ORDER BY started_at DESC, id DESC LIMIT ?
+                )",
+            )
+            .bind(max_entries.max(0))
+            .execute(&self.pool)
+            .await
+            .map_err(|e| VelocityError::Query(e.to_string()))?
+            .rows_affected();
+        }
+        Ok(deleted)
+    }
+}
+
+fn entry_from_row(row: &sqlx::sqlite::SqliteRow) -> HistoryEntry {
+    let started_at: i64 = row.get("started_at");
+    HistoryEntry {
+        id: row.get("id"),
+        connection_id: row.get("connection_id"),
+        sql: row.get("sql"),
+        params: row
+            .get::<Option<String>, _>("params")
+            .and_then(|params| serde_json::from_str(&params).ok())
+            .unwrap_or_default(),
+        source: HistorySource::parse(row.get("source")),
+        started_at: Utc
+            .timestamp_millis_opt(started_at)
+            .single()
+            .unwrap_or_default(),
+        duration_ms: row.get("duration_ms"),
+        row_count: row.get("row_count"),
+        error: row.get("error"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+    use std::path::PathBuf;
+
+    /// A store on a new file in the temp dir
+    fn temp_store() -> (QueryHistoryStore, PathBuf) {
+        let path =
+            std::env::temp_dir().join(format!("velocity-history-{}.db", uuid::Uuid::new_v4()));
+        (QueryHistoryStore::open(&path), path)
+    }
+
+    fn remove(path: PathBuf) {
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 10, 0, 0).unwrap()
+    }
+
+    /// An entry started `minutes` after `start()`
+    fn entry<'a>(connection_id: &'a str, sql: &'a str, minutes: i64) -> NewHistoryEntry<'a> {
+        NewHistoryEntry {
+            connection_id,
+            sql,
+            params: &[],
+            source: HistorySource::Editor,
+            started_at: start() + Duration::minutes(minutes),
+            duration_ms: 1,
+            row_count: Some(1),
+            error: None,
+        }
+    }
+
+    fn sqls(entries: &[HistoryEntry]) -> Vec<&str> {
+        entries.iter().map(|e| e.sql.as_str()).collect()
+    }
+
+    fn text(text: &str) -> HistoryFilter {
+        HistoryFilter {
+            text: Some(text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_matches_text_literally() {
+        let (store, path) = temp_store();
+        for (i, sql) in [
+            "SELECT '100%' AS pct",
+            "SELECT 1000 AS pct",
+            "SELECT a_b FROM t",
+            "SELECT axb FROM t",
+            "SELECT 'C:\\temp' AS dir",
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            store.record(entry("c1", sql, i as i64)).await.unwrap();
+        }
+
+        let found = store.search(&text("0%")).await.unwrap();
+        assert_eq!(sqls(&found), vec!["SELECT '100%' AS pct"]);
+        let found = store.search(&text("a_b")).await.unwrap();
+        assert_eq!(sqls(&found), vec!["SELECT a_b FROM t"]);
+        let found = store.search(&text("\\")).await.unwrap();
+        assert_eq!(sqls(&found), vec!["SELECT 'C:\\temp' AS dir"]);
+        // Case-insensitive, newest first
+        let found = store.search(&text("as PCT")).await.unwrap();
+        assert_eq!(
+            sqls(&found),
+            vec!["SELECT 1000 AS pct", "SELECT '100%' AS pct"]
+        );
+        remove(path);
+    }
+
+    #[tokio::test]
+    async fn test_search_filters_by_connection_and_time() {
+        let (store, path) = temp_store();
+        store.record(entry("c1", "SELECT 1", 0)).await.unwrap();
+        store.record(entry("c2", "SELECT 2", 10)).await.unwrap();
+        store.record(entry("c1", "SELECT 3", 20)).await.unwrap();
+        store
+            .record(NewHistoryEntry {
+                row_count: None,
+                error: Some("no such table".to_string()),
+                ..entry("c1", "SELECT 4", 30)
+            })
+            .await
+            .unwrap();
+
+        let filter = HistoryFilter {
+            connection_id: Some("c1".to_string()),
+            ..Default::default()
+        };
+        let found = store.search(&filter).await.unwrap();
+        assert_eq!(sqls(&found), vec!["SELECT 4", "SELECT 3", "SELECT 1"]);
+
+        // From is inclusive, to exclusive
+        let filter = HistoryFilter {
+            from: Some(start() + Duration::minutes(10)),
+            to: Some(start() + Duration::minutes(30)),
+            ..Default::default()
+        };
+        let found = store.search(&filter).await.unwrap();
+        assert_eq!(sqls(&found), vec!["SELECT 3", "SELECT 2"]);
+
+        let filter = HistoryFilter {
+            failed: Some(true),
+            ..Default::default()
+        };
+        let found = store.search(&filter).await.unwrap();
+        assert_eq!(sqls(&found), vec!["SELECT 4"]);
+        assert_eq!(found[0].row_count, None);
+        assert_eq!(found[0].started_at, start() + Duration::minutes(30));
+        remove(path);
+    }
+
+    #[tokio::test]
+    async fn test_prune_by_age_and_count() {
+        let (store, path) = temp_store();
+        for i in 0..5 {
+            store.record(entry("c1", "SELECT 1", i)).await.unwrap();
+        }
+
+        let deleted = store
+            .prune(Some(start() + Duration::minutes(1)), None)
+            .await
+            .unwrap();
+        assert_eq!(deleted, 1);
+        let deleted = store.prune(None, Some(2)).await.unwrap();
+        assert_eq!(deleted, 2);
+
+        let left = store.search(&HistoryFilter::default()).await.unwrap();
+        let started: Vec<DateTime<Utc>> = left.iter().map(|e| e.started_at).collect();
+        assert_eq!(
+            started,
+            vec![
+                start() + Duration::minutes(4),
+                start() + Duration::minutes(3)
+            ]
+        );
+        remove(path);
+    }
+}
@@ -1 +1,4 @@
+pub mod audit;
 pub mod connections;
+pub mod history;
+pub mod saved_queries;
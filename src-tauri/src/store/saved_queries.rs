@@ -0,0 +1,304 @@
+//! Saved query library
+//!
+//! Stored as `saved_queries.json` in the app config dir. The same format is
+//! used to export and import a library, so a team can share one file.
+
+use crate::error::VelocityError;
+use crate::models::saved_query::{QueryFolder, SavedQuery, SavedQueryLibrary, LIBRARY_VERSION};
+use chrono::Utc;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Manager};
+
+pub struct SavedQueriesStore {
+    path: PathBuf,
+}
+
+impl SavedQueriesStore {
+    pub fn new(app: &AppHandle) -> Result<Self, VelocityError> {
+        let app_config_dir = app
+            .path()
+            .app_config_dir()
+            .map_err(|e| VelocityError::TauriError(e.to_string()))?;
+
+        if !app_config_dir.exists() {
+            fs::create_dir_all(&app_config_dir)?;
+        }
+
+        Ok(Self {
+            path: app_config_dir.join("saved_queries.json"),
+        })
+    }
+
+    pub fn load(&self) -> Result<SavedQueryLibrary, VelocityError> {
+        if !self.path.exists() {
+            return Ok(SavedQueryLibrary::default());
+        }
+        read_library(&self.path)
+    }
+
+    pub fn save(&self, library: &SavedQueryLibrary) -> Result<(), VelocityError> {
+        write_library(&self.path, library)
+    }
+
+    /// Add or update a query, returning it with its ID and timestamps set
+    pub fn save_query(&self, mut query: SavedQuery) -> Result<SavedQuery, VelocityError> {
+        let mut library = self.load()?;
+        if let Some(folder_id) = &query.folder_id {
+            if !library.folders.iter().any(|f| &f.id == folder_id) {
+                return Err(VelocityError::NotFound(format!("Folder {}", folder_id)));
+            }
+        }
+
+        query.updated_at = Utc::now();
+        match library.queries.iter_mut().find(|q| q.id == query.id) {
+            Some(existing) if !query.id.is_empty() => {
+                query.created_at = existing.created_at;
+                *existing = query.clone();
+            }
+            _ => {
+                if query.id.is_empty() {
+                    query.id = uuid::Uuid::new_v4().to_string();
+                }
+                query.created_at = query.updated_at;
+                library.queries.push(query.clone());
+            }
+        }
+        self.save(&library)?;
+        Ok(query)
+    }
+
+    pub fn delete_query(&self, id: &str) -> Result<(), VelocityError> {
+        let mut library = self.load()?;
+        let initial_len = library.queries.len();
+        library.queries.retain(|q| q.id != id);
+        if library.queries.len() == initial_len {
+            return Err(VelocityError::NotFound(format!("Saved query {}", id)));
+        }
+        self.save(&library)
+    }
+
+    /// Add, rename or move a folder
+    pub fn save_folder(&self, mut folder: QueryFolder) -> Result<QueryFolder, VelocityError> {
+        let mut library = self.load()?;
+        if folder.id.is_empty() {
+            folder.id = uuid::Uuid::new_v4().to_string();
+        }
+        if let Some(parent_id) = &folder.parent_id {
+            if !library.folders.iter().any(|f| &f.id == parent_id) {
+                return Err(VelocityError::NotFound(format!("Folder {}", parent_id)));
+            }
+            if is_descendant(&library.folders, parent_id, &folder.id) {
+                return Err(VelocityError::Query(
+                    "A folder cannot be moved into itself".to_string(),
+                ));
+            }
+        }
+
+        match library.folders.iter_mut().find(|f| f.id == folder.id) {
+            Some(existing) => *existing = folder.clone(),
+            None => library.folders.push(folder.clone()),
+        }
+        self.save(&library)?;
+        Ok(folder)
+    }
+
+    /// Delete a folder. Its queries and subfolders move to its parent.
+    pub fn delete_folder(&self, id: &str) -> Result<(), VelocityError> {
+        let mut library = self.load()?;
+        let folder = library
+            .folders
+            .iter()
+            .position(|f| f.id == id)
+            .map(|i| library.folders.remove(i))
+            .ok_or_else(|| VelocityError::NotFound(format!("Folder {}", id)))?;
+
+        for child in library
+            .folders
+            .iter_mut()
+            .filter(|f| f.parent_id.as_deref() == Some(id))
+        {
+            child.parent_id = folder.parent_id.clone();
+        }
+        for query in library
+            .queries
+            .iter_mut()
+            .filter(|q| q.folder_id.as_deref() == Some(id))
+        {
+            query.folder_id = folder.parent_id.clone();
+        }
+        self.save(&library)
+    }
+
+    /// Write the whole library to `path`, returning the number of queries
+    pub fn export(&self, path: &Path) -> Result<usize, VelocityError> {
+        let library = self.load()?;
+        write_library(path, &library)?;
+        Ok(library.queries.len())
+    }
+
+    /// Read a library file. With `replace` it takes the place of the current
+    /// library; otherwise its folders and queries are added, overwriting
+    /// entries with the same ID.
+    pub fn import(&self, path: &Path, replace: bool) -> Result<SavedQueryLibrary, VelocityError> {
+        let imported = read_library(path)?;
+        if imported.version > LIBRARY_VERSION {
+            return Err(VelocityError::Import(format!(
+                "Library version {} is newer than this app supports",
+                imported.version
+            )));
+        }
+
+        let library = if replace {
+            imported
+        } else {
+            merge(self.load()?, imported)
+        };
+        let library = repair(library);
+        self.save(&library)?;
+        Ok(library)
+    }
+}
+
+fn read_library(path: &Path) -> Result<SavedQueryLibrary, VelocityError> {
+    let content = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn write_library(path: &Path, library: &SavedQueryLibrary) -> Result<(), VelocityError> {
+    let library = SavedQueryLibrary {
+        version: LIBRARY_VERSION,
+        ..library.clone()
+    };
+    fs::write(path, serde_json::to_string_pretty(&library)?)?;
+    Ok(())
+}
+
+/// Whether `folder_id` is `ancestor_id` or one of its subfolders
+fn is_descendant(folders: &[QueryFolder], folder_id: &str, ancestor_id: &str) -> bool {
+    let mut current = Some(folder_id);
+    // Bounded in case the file already contains a cycle
+    for _ in 0..=folders.len() {
+        match current {
+            Some(id) if id == ancestor_id => return true,
+            Some(id) => {
+                current = folders
+                    .iter()
+                    .find(|f| f.id == id)
+                    .and_then(|f| f.parent_id.as_deref());
+            }
+            None => return false,
+        }
+    }
+    false
+}
+
+fn merge(mut library: SavedQueryLibrary, imported: SavedQueryLibrary) -> SavedQueryLibrary {
+    for folder in imported.folders {
+        match library.folders.iter_mut().find(|f| f.id == folder.id) {
+            Some(existing) => *existing = folder,
+            None => library.folders.push(folder),
+        }
+    }
+    for query in imported.queries {
+        match library.queries.iter_mut().find(|q| q.id == query.id) {
+            Some(existing) => *existing = query,
+            None => library.queries.push(query),
+        }
+    }
+    library
+}
+
+/// Give entries without an ID one and detach references to missing or
+/// cyclic folders, so an edited or partial file still loads cleanly
+fn repair(mut library: SavedQueryLibrary) -> SavedQueryLibrary {
+    for folder in &mut library.folders {
+        if folder.id.is_empty() {
+            folder.id = uuid::Uuid::new_v4().to_string();
+        }
+    }
+    for query in &mut library.queries {
+        if query.id.is_empty() {
+            query.id = uuid::Uuid::new_v4().to_string();
+        }
+    }
+
+    let ids: Vec<String> = library.folders.iter().map(|f| f.id.clone()).collect();
+    for i in 0..library.folders.len() {
+        let detach = match library.folders[i].parent_id.as_deref() {
+            Some(parent) => {
+                !ids.iter().any(|id| id == parent)
+                    || is_descendant(&library.folders, parent, &library.folders[i].id)
+            }
+            None => false,
+        };
+        if detach {
+            library.folders[i].parent_id = None;
+        }
+    }
+    for query in &mut library.queries {
+        if query
+            .folder_id
+            .as_ref()
+            .is_some_and(|folder| !ids.contains(folder))
+        {
+            query.folder_id = None;
+        }
+    }
+    library
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folder(id: &str, parent_id: Option<&str>) -> QueryFolder {
+        QueryFolder {
+            id: id.to_string(),
+            name: id.to_string(),
+            parent_id: parent_id.map(str::to_string),
+        }
+    }
+
+    fn query(id: &str, folder_id: Option<&str>) -> SavedQuery {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "sql": "SELECT 1",
+            "folderId": folder_id,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_detects_folder_cycles() {
+        let folders = vec![folder("a", None), folder("b", Some("a"))];
+        assert!(is_descendant(&folders, "b", "a"));
+        assert!(!is_descendant(&folders, "a", "b"));
+    }
+
+    #[test]
+    fn test_merge_overwrites_by_id_and_repairs_references() {
+        let current = SavedQueryLibrary {
+            version: LIBRARY_VERSION,
+            folders: vec![folder("a", None)],
+            queries: vec![query("q1", Some("a")), query("q2", None)],
+        };
+        let mut renamed = query("q1", Some("gone"));
+        renamed.name = "renamed".to_string();
+        let imported = SavedQueryLibrary {
+            version: LIBRARY_VERSION,
+            folders: vec![folder("b", Some("b"))],
+            queries: vec![renamed, query("", Some("b"))],
+        };
+
+        let library = repair(merge(current, imported));
+        assert_eq!(library.folders.len(), 2);
+        assert_eq!(library.folders[1].parent_id, None);
+        assert_eq!(library.queries.len(), 3);
+        assert_eq!(library.queries[0].name, "renamed");
+        assert_eq!(library.queries[0].folder_id, None);
+        assert!(!library.queries[2].id.is_empty());
+        assert_eq!(library.queries[2].folder_id.as_deref(), Some("b"));
+    }
+}
@@ -48,6 +48,11 @@ const formSchema = z.object({
   useTls: z.boolean().default(false),
   // MongoDB specific
   authSource: z.string().optional(),
+  // Statement timeout in seconds (empty = no limit)
+  queryTimeout: z.string().optional(),
+  // Reject writes on the backend
+  readOnly: z.boolean().default(false),
+  protected: z.boolean().default(false),
 });
 
 type FormValues = z.infer<typeof formSchema>;
@@ -160,6 +165,9 @@ export function ConnectionForm({ connection, onSuccess, onCancel }: ConnectionFo
         url: "",
         ssl: connection.config.ssl?.enabled || false,
         favorite: connection.favorite,
+        queryTimeout: connection.queryTimeoutMs ? String(connection.queryTimeoutMs / 1000) : "",
+        readOnly: connection.readOnly || false,
+        protected: connection.protected || false,
       });
     } else {
       form.reset({
@@ -174,6 +182,9 @@ export function ConnectionForm({ connection, onSuccess, onCancel }: ConnectionFo
         url: "",
         ssl: false,
         favorite: false,
+        queryTimeout: "",
+        readOnly: false,
+        protected: false,
       });
     }
   }, [connection, form]);
@@ -250,6 +261,11 @@ export function ConnectionForm({ connection, onSuccess, onCancel }: ConnectionFo
         favorite: values.favorite,
         createdAt: connection?.createdAt || new Date().toISOString(),
         lastUsedAt: connection?.lastUsedAt,
+        queryTimeoutMs: values.queryTimeout
+          ? Math.round(parseFloat(values.queryTimeout) * 1000) || undefined
+          : undefined,
+        readOnly: values.readOnly,
+        protected: values.protected,
       };
 
       await save.mutateAsync(newConnection);
@@ -534,6 +550,72 @@ export function ConnectionForm({ connection, onSuccess, onCancel }: ConnectionFo
                 )}
               </>
             )}
+
+            {/* Statement timeout for SQL databases */}
+            {dbType !== "Redis" && dbType !== "MongoDB" && dbType !== "SQLServer" && (
+              <FormField
+                control={form.control as any}
+                name="queryTimeout"
+                render={({ field }) => (
+                  <FormItem>
+                    <FormLabel>Query Timeout (seconds)</FormLabel>
+                    <FormControl>
+                      <Input
+                        type="number"
+                        min="0"
+                        placeholder="No limit"
+                        {...field}
+                        value={field.value || ''}
+                        className="bg-secondary border-border"
+                      />
+                    </FormControl>
+                    <FormMessage />
+                  </FormItem>
+                )}
+              />
+            )}
+
+            {/* Read-only mode, enforced by the backend */}
+            {dbType !== "Redis" && dbType !== "MongoDB" && dbType !== "SQLServer" && (
+              <FormField
+                control={form.control as any}
+                name="readOnly"
+                render={({ field }) => (
+                  <FormItem className="flex flex-row items-start space-x-3 space-y-0 rounded-md border border-border p-4 bg-secondary/50">
+                    <FormControl>
+                      <Checkbox
+                        checked={!!field.value}
+                        onCheckedChange={field.onChange}
+                      />
+                    </FormControl>
+                    <div className="space-y-1 leading-none">
+                      <FormLabel>Read-only</FormLabel>
+                    </div>
+                  </FormItem>
+                )}
+              />
+            )}
+
+            {/* Protected mode: destructive statements need a confirmation */}
+            {dbType !== "Redis" && dbType !== "MongoDB" && dbType !== "SQLServer" && (
+              <FormField
+                control={form.control as any}
+                name="protected"
+                render={({ field }) => (
+                  <FormItem className="flex flex-row items-start space-x-3 space-y-0 rounded-md border border-border p-4 bg-secondary/50">
+                    <FormControl>
+                      <Checkbox
+                        checked={!!field.value}
+                        onCheckedChange={field.onChange}
+                      />
+                    </FormControl>
+                    <div className="space-y-1 leading-none">
+                      <FormLabel>Protected (confirm destructive statements)</FormLabel>
+                    </div>
+                  </FormItem>
+                )}
+              />
+            )}
           </TabsContent>
         </Tabs>
         
@@ -1,7 +1,7 @@
 import { useEffect, useState, useRef, useCallback, useMemo } from 'react';
 import { useVirtualizer } from '@tanstack/react-virtual';
-import { getTableSchema, getTableData, executeChanges, ColumnInfo, TableData } from '@/lib/tauri';
-import { useTableEditor } from '@/hooks/useTableEditor';
+import { getTableSchema, getTableData, executeChanges, ColumnInfo, TableData, type PendingChange } from '@/lib/tauri';
+import { useTableEditor, newRowValues, reconcileRows, type RowKey } from '@/hooks/useTableEditor';
 import { EditableCell } from './EditableCell';
 import { SqlPreviewModal } from './SqlPreviewModal';
 import { ForeignKeysPanel } from './ForeignKeysPanel';
@@ -30,14 +30,8 @@ export function TableViewer({ connectionId, tableName }: TableViewerProps) {
 
   const containerRef = useRef<HTMLDivElement>(null);
 
-  // Find primary key column
-  const primaryKeyColumn = useMemo(() => {
-    const pk = schema.find(col => col.isPrimaryKey);
-    return pk?.name || schema[0]?.name || null;
-  }, [schema]);
-
   // Table editor state
-  const editor = useTableEditor(primaryKeyColumn);
+  const editor = useTableEditor();
 
   // Column widths (based on data type and name length)
   const columnWidths = useMemo(() => {
@@ -93,13 +87,10 @@ export function TableViewer({ connectionId, tableName }: TableViewerProps) {
     }
   };
 
-  // Get primary key value for a row
-  const getRowId = useCallback((rowIndex: number): string => {
-    if (!data || !primaryKeyColumn) return String(rowIndex);
-    const colIndex = data.columns.indexOf(primaryKeyColumn);
-    if (colIndex === -1) return String(rowIndex);
-    return String(data.rows[rowIndex]?.[colIndex] ?? rowIndex);
-  }, [data, primaryKeyColumn]);
+  // Get the key of a row (primary key, ctid/rowid or full row)
+  const getRowKey = useCallback((rowIndex: number): RowKey => {
+    return data?.rowKeys?.[rowIndex] ?? {};
+  }, [data]);
 
   // Get column data type
   const getColumnType = useCallback((columnName: string): string => {
@@ -170,50 +161,66 @@ export function TableViewer({ connectionId, tableName }: TableViewerProps) {
   }, [editor]);
 
   // Handle commit
-  const handleCommit = async () => {
-    if (!primaryKeyColumn) {
-      toast.error('Cannot commit: No primary key column found');
+  // Updates refuse to overwrite rows changed since they were loaded unless forced
+  const handleCommit = async (force = false) => {
+    if (!data?.rowKeys) {
+      toast.error('Cannot commit: rows of this table cannot be identified');
       return;
     }
 
     setIsCommitting(true);
     try {
       // Convert pending changes to the format expected by backend
-      const backendChanges = editor.pendingChanges.map(change => ({
-        rowId: change.rowId,
+      const backendChanges: PendingChange[] = editor.pendingChanges.map(change => ({
+        key: change.rowKey,
         column: change.column,
         oldValue: change.oldValue,
         newValue: change.newValue,
+        force,
         type: change.type,
       }));
 
-      // Add new rows as inserts
+      // Add new rows as inserts, one per row
       editor.newRows.forEach(newRow => {
-        Object.entries(newRow.values).forEach(([column, value]) => {
-          if (value !== null) {
-            backendChanges.push({
-              rowId: newRow.tempId,
-              column,
-              oldValue: null,
-              newValue: value,
-              type: 'insert' as const,
-            });
-          }
+        backendChanges.push({
+          key: {},
+          column: '',
+          oldValue: null,
+          newValue: null,
+          values: newRowValues(newRow),
+          type: 'insert',
         });
       });
 
       const result = await executeChanges(
         connectionId,
         tableName,
-        primaryKeyColumn,
-        backendChanges
+        data.keyColumns,
+        backendChanges,
+        'allOrNothing',
+        false,
+        data.rowIdentity,
+        true
       );
+      const conflicts = result.changes.filter(change => change.status === 'conflict');
 
       if (result.success) {
         toast.success(`${result.rowsAffected} row${result.rowsAffected !== 1 ? 's' : ''} affected`);
+        // Show the rows as stored without fetching the page again
+        const rowIndexes = [...editor.pendingChanges.map(change => change.rowIndex), ...editor.newRows.map(() => null)];
+        const reconciled = reconcileRows(data.columns, data.rows, data.rowKeys, rowIndexes, result.changes);
         editor.rollbackAll();
         setShowSqlPreview(false);
-        loadData(); // Reload data
+        if (reconciled) {
+          setData({ ...data, ...reconciled });
+        } else {
+          loadData(); // Reload data
+        }
+      } else if (conflicts.length > 0) {
+        toast.error(`Commit failed: ${conflicts.length} row${conflicts.length !== 1 ? 's were' : ' was'} changed since loaded`, {
+          description: conflicts[0].error ?? undefined,
+          action: { label: 'Overwrite', onClick: () => handleCommit(true) },
+        });
       } else {
         toast.error(`Commit failed: ${result.errors.join(', ')}`);
       }
@@ -354,9 +361,10 @@ export function TableViewer({ connectionId, tableName }: TableViewerProps) {
               ? Object.values(editor.newRows[rowIndex - (data?.rows.length || 0)]?.values || {})
               : data?.rows[rowIndex] || [];
 
-            const rowId = isNewRow
+            const tempId = isNewRow
               ? editor.newRows[rowIndex - (data?.rows.length || 0)]?.tempId || ''
-              : getRowId(rowIndex);
+              : '';
+            const rowKey = isNewRow ? {} : getRowKey(rowIndex);
 
             return (
               <div
@@ -398,9 +406,9 @@ export function TableViewer({ connectionId, tableName }: TableViewerProps) {
                         onStartEdit={() => editor.startEditing(rowIndex, col)}
                         onSave={(newValue) => {
                           if (isNewRow) {
-                            editor.updateNewRow(rowId, col, newValue);
+                            editor.updateNewRow(tempId, col, newValue);
                           } else {
-                            editor.updateCell(rowIndex, rowId, col, cellValue, newValue);
+                            editor.updateCell(rowIndex, rowKey, col, cellValue, newValue);
                           }
                         }}
                         onCancel={() => editor.stopEditing()}
@@ -427,7 +435,7 @@ export function TableViewer({ connectionId, tableName }: TableViewerProps) {
                         variant="ghost"
                         size="icon"
                         className="h-6 w-6 text-red-500 hover:text-red-600"
-                        onClick={() => editor.deleteRow(rowIndex, rowId)}
+                        onClick={() => editor.deleteRow(rowIndex, rowKey)}
                       >
                         <Trash2 className="h-3 w-3" />
                       </Button>
@@ -482,7 +490,7 @@ export function TableViewer({ connectionId, tableName }: TableViewerProps) {
       <SqlPreviewModal
         open={showSqlPreview}
         onClose={() => setShowSqlPreview(false)}
-        onConfirm={handleCommit}
+        onConfirm={() => handleCommit()}
         sql={editor.generateSqlPreview(tableName)}
         changeCount={editor.changeCount}
         isExecuting={isCommitting}
@@ -58,6 +58,4 @@ export interface DatabaseSchemaInfo {
   functions: string[];
 }
 
-export interface ExplainResult {
-  plan: string[];
-}
+export type { ExplainResult } from '@/lib/tauri';
@@ -7,6 +7,7 @@ import { useAISettingsStore, hasAnyProviderAvailable, getBestProvider } from './
 import { getModeConfig } from './ai-modes';
 import type { AgentMode, PendingSqlConfirmation } from './types';
 import type { Mention } from './useMentions';
+import { classifySql, withConfirmation } from '@/lib/tauri';
 
 // Message types
 export interface ChatMessage {
@@ -68,17 +69,21 @@ interface UseVelocityAgentReturn {
   currentProvider: string;
 }
 
-// Helper to check for destructive SQL
-const isDestructive = (toolName: string, args: Record<string, unknown>): boolean => {
+// Helper to check for destructive SQL, using the backend statement classifier
+const isDestructive = async (
+  connectionId: string,
+  toolName: string,
+  args: Record<string, unknown>
+): Promise<boolean> => {
   if (toolName === 'execute_ddl') return true;
   if (toolName === 'run_sql_query' && typeof args.sql === 'string') {
-    const sql = args.sql.trim().toUpperCase();
-    return sql.startsWith('INSERT') || 
-           sql.startsWith('UPDATE') || 
-           sql.startsWith('DELETE') || 
-           sql.startsWith('DROP') || 
-           sql.startsWith('ALTER') || 
-           sql.startsWith('TRUNCATE');
+    try {
+      const statements = await classifySql(connectionId, args.sql);
+      return statements.some((s) => s.kind !== 'read');
+    } catch {
+      // Ask for confirmation when the statement cannot be classified
+      return true;
+    }
   }
   return false;
 };
@@ -134,7 +139,9 @@ export function useVelocityAgent({ connectionId, mode }: UseVelocityAgentOptions
           return await invoke('get_database_schema_full', { id: connectionId });
         
         case 'run_sql_query':
-          return await invoke('execute_query', { id: connectionId, sql: args.sql as string });
+          return await withConfirmation((confirmationToken) =>
+            invoke('execute_query', { id: connectionId, sql: args.sql as string, confirmationToken })
+          );
         
         case 'list_tables':
           return await invoke('list_tables', { id: connectionId });
@@ -143,7 +150,9 @@ export function useVelocityAgent({ connectionId, mode }: UseVelocityAgentOptions
           return await invoke('get_table_schema', { id: connectionId, tableName: args.table_name as string });
         
         case 'execute_ddl':
-          return await invoke('execute_ddl', { id: connectionId, sql: args.sql as string });
+          return await withConfirmation((confirmationToken) =>
+            invoke('execute_ddl', { id: connectionId, sql: args.sql as string, confirmationToken })
+          );
         
         case 'explain_query':
           return await invoke('explain_query', { id: connectionId, sql: args.sql as string });
@@ -198,8 +207,8 @@ export function useVelocityAgent({ connectionId, mode }: UseVelocityAgentOptions
 
           for (const tc of currentPendingToolCalls) {
             const isBlocked = !settings.autoAcceptSql && 
-                              isDestructive(tc.name, tc.args) && 
-                              !approvedToolIds.includes(tc.id);
+                              !approvedToolIds.includes(tc.id) &&
+                              await isDestructive(connectionId, tc.name, tc.args);
             
             if (isBlocked) {
               blockedTools.push(tc);
@@ -462,7 +471,7 @@ export function useVelocityAgent({ connectionId, mode }: UseVelocityAgentOptions
       setIsLoading(false);
       loopStateRef.current = null;
     }
-  }, [mode, settings, executeTool]);
+  }, [connectionId, mode, settings, executeTool]);
 
 
   // Initialize conversation
@@ -21,7 +21,8 @@ import {
   Table,
   FileQuestion,
   Sparkles,
-  ArrowUpRight
+  ArrowUpRight,
+  Square
 } from 'lucide-react';
 import { cn } from '@/lib/utils';
 
@@ -193,6 +194,13 @@ export function SqlEditor({ connectionId, initialSql = '' }: SqlEditorProps) {
           Run (⌘↵)
         </Button>
 
+        {editor.isExecuting && (
+          <Button variant="outline" size="sm" onClick={editor.cancel}>
+            <Square className="h-4 w-4 mr-1" />
+            Stop
+          </Button>
+        )}
+
         <Button variant="outline" size="sm" onClick={editor.formatSql}>
           <Wand2 className="h-4 w-4 mr-1" />
           Format
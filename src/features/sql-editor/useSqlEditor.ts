@@ -1,7 +1,7 @@
-import { useState, useCallback, useEffect } from 'react';
+import { useState, useCallback, useEffect, useRef } from 'react';
 import { format } from 'sql-formatter';
 import { QueryResult, QueryHistory } from './types';
-import { executeQuery } from '@/lib/tauri';
+import { executeScript, cancelQuery, withConfirmation } from '@/lib/tauri';
 
 interface UseSqlEditorProps {
   connectionId: string;
@@ -15,6 +15,7 @@ export function useSqlEditor({ connectionId, initialSql = '' }: UseSqlEditorProp
   const [activeResultIndex, setActiveResultIndex] = useState(0);
   const [error, setError] = useState<string | null>(null);
   const [history, setHistory] = useState<QueryHistory[]>([]);
+  const runningQueryId = useRef<string | null>(null);
 
   // Load history from localStorage on mount
   useEffect(() => {
@@ -41,31 +42,32 @@ export function useSqlEditor({ connectionId, initialSql = '' }: UseSqlEditorProp
     setIsExecuting(true);
     setError(null);
     
-    // Split statements by semicolon (simple split, handles most cases)
-    const statements = sql
-      .split(';')
-      .map(s => s.trim())
-      .filter(s => s.length > 0);
-
-    const newResults: QueryResult[] = [];
     const startTime = performance.now();
+    const queryId = crypto.randomUUID();
+    runningQueryId.current = queryId;
 
     try {
-      for (const statement of statements) {
-        const stmtStart = performance.now();
-        const result = await executeQuery(connectionId, statement);
-        const executionTime = performance.now() - stmtStart;
-        
+      // The backend splits the script with dialect-aware rules and runs every
+      // statement on the same connection. Protected connections ask before
+      // running destructive statements.
+      const script = await withConfirmation((token) =>
+        executeScript(connectionId, sql, true, queryId, token)
+      );
+
+      const newResults: QueryResult[] = script.results.map((result) => {
         // Extract table name from SQL (FROM tablename or UPDATE tablename or INTO tablename)
-        const tableMatch = statement.match(/(?:FROM|UPDATE|INTO|JOIN)\s+["`]?(\w+)["`]?/i);
+        const tableMatch = result.sql.match(/(?:FROM|UPDATE|INTO|JOIN)\s+["`]?(\w+)["`]?/i);
         const tableName = tableMatch ? tableMatch[1] : undefined;
-        
-        newResults.push({
-          ...result,
-          executionTime,
+
+        return {
+          columns: result.columns,
+          rows: result.rows,
+          rowCount: result.rowsAffected ?? result.rows.length,
+          executionTime: result.durationMs,
+          error: result.error ?? undefined,
           tableName,
-        });
-      }
+        };
+      });
 
       setResults(newResults);
       setActiveResultIndex(0);
@@ -90,10 +92,21 @@ export function useSqlEditor({ connectionId, initialSql = '' }: UseSqlEditorProp
       setError(errorMessage);
       setResults([]);
     } finally {
+      runningQueryId.current = null;
       setIsExecuting(false);
     }
   }, [sql, connectionId, isExecuting, history, saveHistory]);
 
+  // Cancel the running script
+  const cancel = useCallback(async () => {
+    if (!runningQueryId.current) return;
+    try {
+      await cancelQuery(runningQueryId.current);
+    } catch (err) {
+      setError(err instanceof Error ? err.message : String(err));
+    }
+  }, []);
+
   // Format SQL
   const formatSql = useCallback(() => {
     try {
@@ -139,6 +152,7 @@ export function useSqlEditor({ connectionId, initialSql = '' }: UseSqlEditorProp
     
     // Actions
     execute,
+    cancel,
     formatSql,
     toggleFavorite,
     loadFromHistory,
@@ -33,7 +33,7 @@ export function ForeignKeyPanel({ connectionId, tableName, foreignKeys, onRefres
       const sql = await api.previewDropConstraint(connectionId, tableName, constraintName);
       
       // 2. Execute
-      await api.executeDdl(connectionId, sql);
+      await api.withConfirmation((token) => api.executeDdl(connectionId, sql, token));
       
       toast.success(`Constraint ${constraintName} deleted`);
       onRefresh();
@@ -33,7 +33,7 @@ export function IndexPanel({ connectionId, tableName, indexes, onRefresh }: Inde
       const sql = await api.previewDropIndex(connectionId, tableName, indexName);
       
       // 2. Execute
-      await api.executeDdl(connectionId, sql);
+      await api.withConfirmation((token) => api.executeDdl(connectionId, sql, token));
       
       toast.success(`Index ${indexName} deleted`);
       onRefresh();
@@ -67,7 +67,7 @@ export function useSchemaEditor(connectionId: string) {
     
     setIsExecuting(true);
     try {
-      await api.executeDdl(connectionId, sqlPreview);
+      await api.withConfirmation((token) => api.executeDdl(connectionId, sqlPreview, token));
       toast.success('Structure updated successfully');
       setSqlPreview('');
       // Ideally reset state or close dialog here
@@ -6,8 +6,8 @@
  */
 import { useEffect, useState, useRef, useCallback, useMemo } from 'react';
 import { useVirtualizer } from '@tanstack/react-virtual';
-import { getTableSchema, getTableDataFiltered, ColumnInfo } from '@/lib/tauri';
-import { useTableEditor } from '@/hooks/useTableEditor';
+import { getTableSchema, getTableDataFiltered, ColumnInfo, type RowIdentity } from '@/lib/tauri';
+import { useTableEditor, newRowValues, reconcileRows, type RowKey } from '@/hooks/useTableEditor';
 import { EditableCell } from '@/components/tables/EditableCell';
 import { SqlPreviewModal } from '@/components/tables/SqlPreviewModal';
 import { ForeignKeysPanel } from '@/components/tables/ForeignKeysPanel';
@@ -50,14 +50,13 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
   // Filter/sort/pagination state
   const filters = useTableFilters({ defaultLimit: 100 });
 
-  // Primary key column
-  const primaryKeyColumn = useMemo(() => {
-    const pk = schema.find(col => col.isPrimaryKey);
-    return pk?.name || schema[0]?.name || null;
-  }, [schema]);
+  // Row keys returned with the data (primary key, ctid/rowid or full row)
+  const [rowKeys, setRowKeys] = useState<RowKey[] | null>(null);
+  const [keyColumns, setKeyColumns] = useState<string[]>([]);
+  const [rowIdentity, setRowIdentity] = useState<RowIdentity>('primaryKey');
 
   // Table editor for changes
-  const editor = useTableEditor(primaryKeyColumn);
+  const editor = useTableEditor();
 
   // Column widths (based on data type and name length)
   const columnWidths = useMemo(() => {
@@ -109,6 +108,9 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
       const result = await getTableDataFiltered(connectionId, tableName, filters.queryOptions);
       setColumns(result.columns);
       setRows(result.rows);
+      setRowKeys(result.rowKeys);
+      setKeyColumns(result.keyColumns);
+      setRowIdentity(result.rowIdentity);
       setTotalCount(result.totalCount ?? result.rows.length);
     } catch (err) {
       setError(String(err));
@@ -122,12 +124,10 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
     return schema.find(c => c.name === colName)?.dataType || 'text';
   }, [schema]);
 
-  // Get row ID (primary key value)
-  const getRowId = useCallback((rowIndex: number) => {
-    if (!primaryKeyColumn || !rows[rowIndex]) return String(rowIndex);
-    const pkIndex = columns.indexOf(primaryKeyColumn);
-    return String(rows[rowIndex][pkIndex] ?? rowIndex);
-  }, [rows, columns, primaryKeyColumn]);
+  // Get row key (as returned by the backend)
+  const getRowKey = useCallback((rowIndex: number): RowKey => {
+    return rowKeys?.[rowIndex] ?? {};
+  }, [rowKeys]);
 
   // Add row
   const handleAddRow = useCallback(() => {
@@ -139,10 +139,10 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
     toast.success('New row added');
   }, [schema, editor]);
 
-  // Commit changes
-  const handleCommit = async () => {
-    if (!primaryKeyColumn) {
-      toast.error('Cannot commit: no primary key found');
+  // Commit changes; updates refuse to overwrite rows changed since they were loaded unless forced
+  const handleCommit = async (force = false) => {
+    if (!rowKeys && editor.pendingChanges.length > 0) {
+      toast.error('Cannot commit: select all key columns (' + keyColumns.join(', ') + ') to edit rows');
       return;
     }
 
@@ -150,27 +150,46 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
     try {
       const changes = [
         ...editor.pendingChanges.map(c => ({
-          rowId: c.rowId,
+          key: c.rowKey,
           column: c.column,
           oldValue: c.oldValue,
           newValue: c.newValue,
+          force,
           type: c.type as 'update' | 'insert' | 'delete',
         })),
         ...editor.newRows.map(row => ({
-          rowId: row.tempId,
+          key: {},
           column: '',
           oldValue: null,
-          newValue: row.values,
+          newValue: null,
+          values: newRowValues(row),
           type: 'insert' as const,
         })),
       ];
 
-      const result = await executeChanges(connectionId, tableName, primaryKeyColumn, changes);
+      const result = await executeChanges(
+        connectionId, tableName, keyColumns, changes, 'allOrNothing', false, rowIdentity, true
+      );
+      const conflicts = result.changes.filter(c => c.status === 'conflict');
 
       if (result.success) {
         toast.success(`${result.rowsAffected} row(s) affected`);
+        // Show the rows as stored without fetching the page again
+        const rowIndexes = [...editor.pendingChanges.map(c => c.rowIndex), ...editor.newRows.map(() => null)];
+        const reconciled = reconcileRows(columns, rows, rowKeys, rowIndexes, result.changes);
         editor.rollbackAll();
-        loadData();
+        if (reconciled) {
+          setRows(reconciled.rows);
+          setRowKeys(reconciled.rowKeys);
+          setTotalCount(count => count + reconciled.rows.length - rows.length);
+        } else {
+          loadData();
+        }
+      } else if (conflicts.length > 0) {
+        toast.error(`${conflicts.length} row(s) were changed by someone else since they were loaded`, {
+          description: conflicts[0].error ?? undefined,
+          action: { label: 'Overwrite', onClick: () => handleCommit(true) },
+        });
       } else {
         toast.error(result.errors?.[0] || 'Failed to commit changes');
       }
@@ -334,9 +353,10 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
               ? Object.values(editor.newRows[rowIndex - rows.length]?.values || {})
               : rows[rowIndex] || [];
 
-            const rowId = isNewRow
+            const tempId = isNewRow
               ? editor.newRows[rowIndex - rows.length]?.tempId || ''
-              : getRowId(rowIndex);
+              : '';
+            const rowKey = isNewRow ? {} : getRowKey(rowIndex);
 
             return (
               <div
@@ -378,9 +398,9 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
                         onStartEdit={() => editor.startEditing(rowIndex, col)}
                         onSave={(newValue) => {
                           if (isNewRow) {
-                            editor.updateNewRow(rowId, col, newValue);
+                            editor.updateNewRow(tempId, col, newValue);
                           } else {
-                            editor.updateCell(rowIndex, rowId, col, cellValue, newValue);
+                            editor.updateCell(rowIndex, rowKey, col, cellValue, newValue);
                           }
                         }}
                         onCancel={() => editor.stopEditing()}
@@ -407,7 +427,7 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
                         variant="ghost"
                         size="icon"
                         className="h-6 w-6 text-red-500 hover:text-red-600"
-                        onClick={() => editor.deleteRow(rowIndex, rowId)}
+                        onClick={() => editor.deleteRow(rowIndex, rowKey)}
                       >
                         <Trash2 className="h-3 w-3" />
                       </Button>
@@ -439,7 +459,7 @@ export function EnhancedTableViewer({ connectionId, tableName }: EnhancedTableVi
       <SqlPreviewModal
         open={showSqlPreview}
         onClose={() => setShowSqlPreview(false)}
-        onConfirm={handleCommit}
+        onConfirm={() => handleCommit()}
         sql={editor.generateSqlPreview(tableName)}
         changeCount={editor.changeCount}
         isExecuting={isCommitting}
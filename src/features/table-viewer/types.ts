@@ -1,3 +1,5 @@
+import type { ResultColumn, RowIdentity } from '@/lib/tauri';
+
 // Filter types matching the Rust backend
 export type FilterOperator = 
   | 'equals'
@@ -48,9 +50,13 @@ export interface QueryOptions {
 
 export interface TableDataResponse {
   columns: string[];
+  columnInfo: ResultColumn[]; // Type, nullability and source table of each column
   rows: unknown[][];
   totalCount: number | null; // null when skip_count is true
   nextCursor?: unknown; // Cursor value for next page (keyset pagination)
+  rowIdentity: RowIdentity;
+  keyColumns: string[];
+  rowKeys: Record<string, unknown>[] | null; // Key of each row; null when the key columns weren't all selected
 }
 
 // Helper to create default query options
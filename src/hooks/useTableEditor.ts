@@ -1,10 +1,13 @@
 import { useState, useCallback, useMemo } from 'react';
+import type { ChangeResult } from '@/lib/tauri';
 
 export type ChangeType = 'update' | 'insert' | 'delete';
 
+export type RowKey = Record<string, unknown>; // Key columns of a row and their values
+
 export interface PendingChange {
   rowIndex: number;
-  rowId: string; // Primary key value
+  rowKey: RowKey;
   column: string;
   oldValue: unknown;
   newValue: unknown;
@@ -16,6 +19,46 @@ export interface NewRow {
   values: Record<string, unknown>;
 }
 
+// Values of a new row to insert; unset (null) columns get their defaults
+export function newRowValues(row: NewRow): Record<string, unknown> {
+  return Object.fromEntries(Object.entries(row.values).filter(([, value]) => value !== null));
+}
+
+// Grid rows after a commit, from the rows the database stored: updated rows take
+// their stored values, deleted rows are dropped and inserted rows appended.
+// rowIndexes holds the grid row of each submitted change, null for inserts.
+// Returns null when a row was not returned and the table has to be fetched again.
+export function reconcileRows(
+  columns: string[],
+  rows: unknown[][],
+  rowKeys: RowKey[] | null,
+  rowIndexes: (number | null)[],
+  results: ChangeResult[]
+): { rows: unknown[][]; rowKeys: RowKey[] | null } | null {
+  const nextRows = [...rows];
+  const nextKeys = rowKeys ? [...rowKeys] : null;
+  const deleted = new Set<number>();
+  for (const result of results) {
+    if (result.status !== 'applied') continue;
+    const rowIndex = rowIndexes[result.index];
+    if (result.changeType === 'delete') {
+      if (rowIndex !== null) deleted.add(rowIndex);
+      continue;
+    }
+    if (!result.row) return null;
+    const stored = columns.map(column => result.row?.[column] ?? null);
+    if (rowIndex === null) {
+      nextRows.push(stored);
+      nextKeys?.push(result.key ?? {});
+    } else {
+      nextRows[rowIndex] = stored;
+      if (nextKeys && result.key) nextKeys[rowIndex] = result.key;
+    }
+  }
+  const kept = (_: unknown, i: number) => !deleted.has(i);
+  return { rows: nextRows.filter(kept), rowKeys: nextKeys?.filter(kept) ?? null };
+}
+
 export interface TableEditorState {
   pendingChanges: PendingChange[];
   deletedRows: Set<number>;
@@ -23,7 +66,7 @@ export interface TableEditorState {
   editingCell: { rowIndex: number; column: string } | null;
 }
 
-export function useTableEditor(primaryKeyColumn: string | null) {
+export function useTableEditor() {
   const [state, setState] = useState<TableEditorState>({
     pendingChanges: [],
     deletedRows: new Set(),
@@ -50,7 +93,7 @@ export function useTableEditor(primaryKeyColumn: string | null) {
   // Update a cell value
   const updateCell = useCallback((
     rowIndex: number,
-    rowId: string,
+    rowKey: RowKey,
     column: string,
     oldValue: unknown,
     newValue: unknown
@@ -68,7 +111,7 @@ export function useTableEditor(primaryKeyColumn: string | null) {
 
       const newChange: PendingChange = {
         rowIndex,
-        rowId,
+        rowKey,
         column,
         oldValue: existingIndex >= 0 ? prev.pendingChanges[existingIndex].oldValue : oldValue,
         newValue,
@@ -97,7 +140,7 @@ export function useTableEditor(primaryKeyColumn: string | null) {
   }, [stopEditing]);
 
   // Mark a row for deletion
-  const deleteRow = useCallback((rowIndex: number, rowId: string) => {
+  const deleteRow = useCallback((rowIndex: number, rowKey: RowKey) => {
     setState(prev => {
       const newDeleted = new Set(prev.deletedRows);
       newDeleted.add(rowIndex);
@@ -105,7 +148,7 @@ export function useTableEditor(primaryKeyColumn: string | null) {
       // Add delete change
       const deleteChange: PendingChange = {
         rowIndex,
-        rowId,
+        rowKey,
         column: '',
         oldValue: null,
         newValue: null,
@@ -216,42 +259,50 @@ export function useTableEditor(primaryKeyColumn: string | null) {
 
     // Updates
     const updates = state.pendingChanges.filter(c => c.type === 'update');
-    const updatesByRow = new Map<string, PendingChange[]>();
+    const updatesByRow = new Map<number, PendingChange[]>();
     updates.forEach(c => {
-      const existing = updatesByRow.get(c.rowId) || [];
+      const existing = updatesByRow.get(c.rowIndex) || [];
       existing.push(c);
-      updatesByRow.set(c.rowId, existing);
+      updatesByRow.set(c.rowIndex, existing);
     });
 
-    updatesByRow.forEach((changes, rowId) => {
+    // Match a row by all of its key columns
+    const whereClause = (rowKey: RowKey) => Object.entries(rowKey).map(([column, value]) => {
+      if (value === null) return `"${column}" IS NULL`;
+      return `"${column}" = ${typeof value === 'string' ? `'${value}'` : value}`;
+    }).join(' AND ');
+
+    updatesByRow.forEach((changes) => {
       const setClauses = changes.map(c => {
         const value = typeof c.newValue === 'string' ? `'${c.newValue}'` : c.newValue;
         return `"${c.column}" = ${value === null ? 'NULL' : value}`;
       }).join(', ');
-      statements.push(`UPDATE "${tableName}" SET ${setClauses} WHERE "${primaryKeyColumn}" = ${typeof rowId === 'string' ? `'${rowId}'` : rowId};`);
+      statements.push(`UPDATE "${tableName}" SET ${setClauses} WHERE ${whereClause(changes[0].rowKey)};`);
     });
 
     // Inserts
     state.newRows.forEach(row => {
-      const columns = Object.keys(row.values).map(c => `"${c}"`).join(', ');
-      const values = Object.values(row.values).map(v => {
-        if (v === null) return 'NULL';
+      const rowValues = newRowValues(row);
+      const columns = Object.keys(rowValues).map(c => `"${c}"`).join(', ');
+      const values = Object.values(rowValues).map(v => {
         if (typeof v === 'string') return `'${v}'`;
         return String(v);
       }).join(', ');
-      statements.push(`INSERT INTO "${tableName}" (${columns}) VALUES (${values});`);
+      statements.push(columns
+        ? `INSERT INTO "${tableName}" (${columns}) VALUES (${values});`
+        : `INSERT INTO "${tableName}" DEFAULT VALUES;`);
     });
 
     // Deletes
     const deletes = state.pendingChanges.filter(c => c.type === 'delete');
     deletes.forEach(c => {
-      statements.push(`DELETE FROM "${tableName}" WHERE "${primaryKeyColumn}" = ${typeof c.rowId === 'string' ? `'${c.rowId}'` : c.rowId};`);
+      statements.push(`DELETE FROM "${tableName}" WHERE ${whereClause(c.rowKey)};`);
     });
 
     if (statements.length === 0) return '-- No changes';
 
     return `BEGIN TRANSACTION;\n\n${statements.join('\n')}\n\nCOMMIT;`;
-  }, [state, primaryKeyColumn]);
+  }, [state]);
 
   // Has unsaved changes
   const hasChanges = useMemo(() => {
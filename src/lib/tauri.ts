@@ -1,5 +1,5 @@
-import { invoke } from "@tauri-apps/api/core";
-import { Connection } from "@/types";
+import { invoke, Channel } from "@tauri-apps/api/core";
+import { Connection, DatabaseType } from "@/types";
 
 // Connection Commands
 export async function loadConnections(): Promise<Connection[]> {
@@ -70,9 +70,16 @@ export async function getTableForeignKeys(
   return await invoke("get_table_foreign_keys", { id: connectionId, tableName });
 }
 
+// How rows are identified when editing: ctid (PostgreSQL) and rowid (SQLite)
+// stand in for a missing primary key, fullRow matches all column values
+export type RowIdentity = 'primaryKey' | 'ctid' | 'rowid' | 'fullRow';
+
 export interface TableData {
   columns: string[];
   rows: unknown[][];
+  rowIdentity: RowIdentity;
+  keyColumns: string[];
+  rowKeys: Record<string, unknown>[] | null; // Key of each row; null when rows can't be edited
 }
 
 export async function getTableSchema(connectionId: string, tableName: string): Promise<ColumnInfo[]> {
@@ -112,13 +119,27 @@ export interface QueryOptions {
   sort: { column: string; direction: 'asc' | 'desc' } | null;
   limit: number;
   offset: number;
+  queryId?: string; // Allows cancelling the fetch with cancelQuery
+  timeoutMs?: number; // Overrides the connection's timeout, 0 = no limit
+}
+
+export interface ResultColumn {
+  name: string;
+  typeName: string; // Database type name, e.g. INT4, VARCHAR
+  nullable: boolean | null; // null when the database cannot tell
+  sourceTable: string | null;
+  sourceColumn: string | null;
 }
 
 export interface TableDataResponse {
   columns: string[];
+  columnInfo: ResultColumn[];
   rows: unknown[][];
   totalCount: number | null; // null when skip_count is true
   nextCursor?: unknown; // Cursor value for next page (keyset pagination)
+  rowIdentity: RowIdentity;
+  keyColumns: string[];
+  rowKeys: Record<string, unknown>[] | null; // Key of each row; null when the key columns weren't all selected
 }
 
 export async function getTableDataFiltered(
@@ -150,40 +171,105 @@ export async function getColumnValues(
 
 // Data editing
 export interface PendingChange {
-  rowId: string;
+  key: Record<string, unknown>; // All key columns of the row and their values; empty for inserts
   column: string;
   oldValue: unknown;
   newValue: unknown;
+  values?: Record<string, unknown>; // Column values of an inserted row; left out columns get defaults
+  force?: boolean; // Apply the update even if the row changed since it was loaded
   type: 'update' | 'insert' | 'delete';
 }
 
+// allOrNothing rolls back the batch when any change fails; applySucceeded commits the rest
+export type ApplyMode = 'allOrNothing' | 'applySucceeded';
+
+export interface ChangeResult {
+  index: number; // Position in the submitted changes
+  changeType: string;
+  status: 'applied' | 'failed' | 'rolledBack' | 'skipped' | 'conflict';
+  rowsAffected: number | null;
+  error: string | null;
+  errorCode: string | null; // SQLSTATE on PostgreSQL/MySQL, result code on SQLite
+  key: Record<string, unknown> | null; // Key of an inserted or updated row
+  row: Record<string, unknown> | null; // Inserted or updated row as stored (generated keys, defaults, triggers); null for deletes
+  current: Record<string, unknown> | null; // Current values of a conflicting row; null when it is gone
+}
+
 export interface ExecuteResult {
-  success: boolean;
-  rowsAffected: number;
+  success: boolean; // False when any change failed, even if others were applied
+  rowsAffected: number; // Rows changed by the committed changes, or that would be on a dry run
   errors: string[];
+  changes: ChangeResult[];
+  dryRun: boolean; // Everything was rolled back
 }
 
-// Execute pending changes (INSERT, UPDATE, DELETE)
+// Execute pending changes (INSERT, UPDATE, DELETE); a dry run always rolls back.
+// With checkConflicts an update only applies while the column still holds oldValue.
 export async function executeChanges(
   connectionId: string,
   tableName: string,
-  primaryKeyColumn: string,
-  changes: PendingChange[]
+  keyColumns: string[],
+  changes: PendingChange[],
+  mode: ApplyMode = 'allOrNothing',
+  dryRun = false,
+  rowIdentity: RowIdentity = 'primaryKey',
+  checkConflicts = false
 ): Promise<ExecuteResult> {
-  return await invoke("execute_changes", { id: connectionId, tableName, primaryKeyColumn, changes });
+  return await invoke("execute_changes", {
+    id: connectionId, tableName, primaryKeyColumns: keyColumns, changes, mode, dryRun, rowIdentity,
+    checkConflicts,
+  });
+}
+
+export interface UndoResult {
+  success: boolean;
+  rowsAffected: number;
+  conflicts: string[]; // Rows changed since the commit; nothing is reverted when non-empty
+  remaining: number; // Batches left to undo on the table
+}
+
+// Revert the last batch of changes committed on a table
+export async function undoLastChanges(connectionId: string, tableName: string): Promise<UndoResult> {
+  return await invoke("undo_last_changes", { id: connectionId, tableName });
 }
 
 export interface QueryResult {
   columns: string[];
+  columnInfo: ResultColumn[]; // Also set when no rows are returned
   rows: unknown[][];
   row_count: number;
+  rowsAffected: number | null; // Set for statements without a result set
+}
+
+export type ParamType =
+  | 'text' | 'integer' | 'float' | 'boolean' | 'decimal'
+  | 'date' | 'time' | 'dateTime' | 'json' | 'uuid' | 'binary';
+
+export interface QueryParam {
+  name?: string; // "user_id" for :user_id, "1" for $1; unnamed values bind by position
+  type?: ParamType; // Inferred from the value when omitted
+  value: unknown; // Binary values are base64 strings
 }
 
-export async function executeQuery(connectionId: string, sql: string): Promise<QueryResult> {
+export interface StatementParameter {
+  name: string;
+  position: number;
+  typeName: string | null; // Reported by PostgreSQL only
+}
+
+export async function executeQuery(
+  connectionId: string,
+  sql: string,
+  queryId?: string,
+  timeoutMs?: number, // Overrides the connection's timeout, 0 = no limit
+  params?: QueryParam[],
+  sessionId?: string, // Run inside an open transaction session
+  confirmationToken?: string // From a ConfirmationRequired error on a protected connection
+): Promise<QueryResult> {
   const start = performance.now();
   try {
     // The backend returns QueryResultData which matches our QueryResult interface
-    const result = await invoke<QueryResult>("execute_query", { id: connectionId, sql });
+    const result = await invoke<QueryResult>("execute_query", { id: connectionId, sql, queryId, timeoutMs, params, sessionId, confirmationToken });
     const duration = performance.now() - start;
     usePerformanceStore.getState().recordQuery(duration, result.row_count);
     return result;
@@ -194,12 +280,358 @@ export async function executeQuery(connectionId: string, sql: string): Promise<Q
   }
 }
 
+// List the placeholders (:name, $1, ?) of a statement to prompt for values
+export async function listQueryParameters(
+  connectionId: string,
+  sql: string
+): Promise<StatementParameter[]> {
+  return await invoke("list_query_parameters", { id: connectionId, sql });
+}
+
+/**
+ * Cancel a query started with the given queryId.
+ * Resolves to false when the query already finished.
+ */
+export type StatementKind = "read" | "write" | "ddl" | "transactionControl" | "admin";
+
+export interface StatementClass {
+  kind: StatementKind;
+  command: string; // Leading keyword, e.g. "SELECT", "DROP", "ROLLBACK TO"
+  tables: string[];
+  hasWhere: boolean | null; // UPDATE and DELETE only
+  parsed: boolean; // False when classified by keywords only
+}
+
+// Classify each statement: kind, target tables and whether UPDATE/DELETE has a WHERE clause
+export async function classifySql(connectionId: string, sql: string): Promise<StatementClass[]> {
+  return await invoke("classify_sql", { id: connectionId, sql });
+}
+
+export interface ConfirmationRequest {
+  message: string; // What the refused statements would affect
+  token: string; // Pass back as confirmationToken to run them
+}
+
+// Parse a ConfirmationRequired error returned for protected connections
+export function confirmationRequest(error: unknown): ConfirmationRequest | null {
+  const match = String(error).match(/Confirmation required: ([\s\S]*) \(confirmation token: ([\w-]+)\)$/);
+  return match ? { message: match[1], token: match[2] } : null;
+}
+
+// Run a command, asking the user to confirm and retrying with the token when the connection is protected
+export async function withConfirmation<T>(run: (confirmationToken?: string) => Promise<T>): Promise<T> {
+  try {
+    return await run();
+  } catch (error) {
+    const request = confirmationRequest(error);
+    if (!request || !window.confirm(`${request.message}.\n\nRun anyway?`)) throw error;
+    return await run(request.token);
+  }
+}
+
+export async function cancelQuery(queryId: string): Promise<boolean> {
+  return await invoke("cancel_query", { queryId });
+}
+
+export interface StatementResult {
+  sql: string;
+  offset: number; // Character offset of the statement in the script
+  columns: string[];
+  columnInfo: ResultColumn[];
+  rows: unknown[][];
+  rowsAffected: number | null; // Set for statements without a result set
+  durationMs: number;
+  error: string | null;
+}
+
+export interface ScriptResult {
+  results: StatementResult[];
+  durationMs: number;
+  rolledBack: boolean; // A transaction the script left open was rolled back
+}
+
+// Execute a multi-statement script, one result per statement
+export async function executeScript(
+  connectionId: string,
+  sql: string,
+  stopOnError: boolean = true,
+  queryId?: string,
+  confirmationToken?: string
+): Promise<ScriptResult> {
+  const start = performance.now();
+  try {
+    const result = await invoke<ScriptResult>("execute_script", { id: connectionId, sql, stopOnError, queryId, confirmationToken });
+    const duration = performance.now() - start;
+    const rowCount = result.results.reduce((sum, r) => sum + (r.rowsAffected ?? r.rows.length), 0);
+    usePerformanceStore.getState().recordQuery(duration, rowCount);
+    return result;
+  } catch (error) {
+    const duration = performance.now() - start;
+    usePerformanceStore.getState().recordQuery(duration, 0, String(error));
+    throw error;
+  }
+}
+
+export type QueryStreamEvent =
+  | { type: "started"; streamId: string; columns: string[]; columnInfo: ResultColumn[] }
+  | { type: "batch"; rows: unknown[][] }
+  | { type: "done"; rowCount: number; durationMs: number }
+  | { type: "closed"; rowCount: number } // After closeQueryStream
+  | { type: "error"; message: string };
+
+export interface QueryStreamOptions {
+  batchSize?: number;
+  maxInFlight?: number;
+  confirmationToken?: string; // From a ConfirmationRequired error on a protected connection
+}
+
+/**
+ * Stream a query's rows in batches. Each "batch" event consumes one credit;
+ * call ackQueryStream once a batch has been rendered to receive more.
+ */
+export async function executeQueryStream(
+  connectionId: string,
+  sql: string,
+  onEvent: (event: QueryStreamEvent) => void,
+  options: QueryStreamOptions = {}
+): Promise<void> {
+  const channel = new Channel<QueryStreamEvent>();
+  channel.onmessage = onEvent;
+  return await invoke("execute_query_stream", {
+    id: connectionId,
+    sql,
+    batchSize: options.batchSize,
+    maxInFlight: options.maxInFlight,
+    confirmationToken: options.confirmationToken,
+    onEvent: channel,
+  });
+}
+
+export async function ackQueryStream(streamId: string, batches: number = 1): Promise<void> {
+  return await invoke("ack_query_stream", { streamId, batches });
+}
+
+export async function closeQueryStream(streamId: string): Promise<void> {
+  return await invoke("close_query_stream", { streamId });
+}
+
+export interface PlanNode {
+  nodeType: string; // e.g. "Seq Scan", "Nested loop inner join", "SEARCH"
+  detail: string | null; // e.g. "using users_pkey on users"
+  startupCost: number | null;
+  totalCost: number | null;
+  estimatedRows: number | null;
+  actualStartupTime: number | null; // Milliseconds, ANALYZE only
+  actualTotalTime: number | null; // Milliseconds, ANALYZE only
+  actualRows: number | null; // Per loop, ANALYZE only
+  loops: number | null; // ANALYZE only
+  properties: Record<string, unknown>; // Remaining database-specific fields
+  children: PlanNode[];
+}
+
 export interface ExplainResult {
-  plan: string[];
+  plan: string[]; // Text rendering, one line per node
+  nodes: PlanNode[];
+  analyzed: boolean;
+  planningTime: number | null; // PostgreSQL only
+  executionTime: number | null; // PostgreSQL ANALYZE only
+}
+
+/**
+ * Get the execution plan of a statement.
+ * `analyze` executes it; statements that modify data are refused unless
+ * `rollback` is set, which runs them in a transaction that is rolled back.
+ */
+export async function explainQuery(
+  connectionId: string,
+  sql: string,
+  analyze: boolean = false,
+  rollback: boolean = false
+): Promise<ExplainResult> {
+  return await invoke("explain_query", { id: connectionId, sql, analyze, rollback });
+}
+
+// Transaction sessions
+export interface TransactionInfo {
+  sessionId: string;
+  connectionId: string;
+  savepoints: string[];
+  startedAt: string;
+  idleTimeoutMs: number;
+}
+
+// Open a transaction on a dedicated connection; pass the session ID to executeQuery to run inside it
+export async function beginTransaction(
+  connectionId: string,
+  sessionId?: string,
+  idleTimeoutMs?: number
+): Promise<TransactionInfo> {
+  return await invoke("begin_transaction", { id: connectionId, sessionId, idleTimeoutMs });
+}
+
+export async function commitTransaction(sessionId: string): Promise<void> {
+  return await invoke("commit_transaction", { sessionId });
+}
+
+// Roll back to a savepoint, or roll back and close the session when none is given
+export async function rollbackTransaction(sessionId: string, savepoint?: string): Promise<TransactionInfo> {
+  return await invoke("rollback_transaction", { sessionId, savepoint });
+}
+
+export async function savepoint(sessionId: string, name: string): Promise<TransactionInfo> {
+  return await invoke("savepoint", { sessionId, name });
+}
+
+// Resolves to null when the session is not open (e.g. rolled back after being idle)
+export async function getTransaction(sessionId: string): Promise<TransactionInfo | null> {
+  return await invoke("get_transaction", { sessionId });
+}
+
+// Query history
+export interface HistoryEntry {
+  id: number;
+  connectionId: string;
+  sql: string;
+  params: QueryParam[];
+  source: 'editor' | 'agent';
+  startedAt: string; // ISO 8601
+  durationMs: number;
+  rowCount: number | null; // null when the statement failed
+  error: string | null;
+}
+
+export interface HistoryFilter {
+  text?: string; // Case-insensitive substring of the SQL
+  connectionId?: string;
+  from?: string; // ISO 8601, inclusive
+  to?: string; // ISO 8601, exclusive
+  failed?: boolean;
+  limit?: number; // Default 100
+  offset?: number;
+}
+
+export async function searchQueryHistory(filter: HistoryFilter = {}): Promise<HistoryEntry[]> {
+  return await invoke("search_query_history", { filter });
+}
+
+// Run a history entry again, on its original connection unless another is given
+export async function rerunQueryHistory(
+  entryId: number,
+  connectionId?: string,
+  confirmationToken?: string // From a ConfirmationRequired error on a protected connection
+): Promise<QueryResult> {
+  return await invoke("rerun_query_history", { entryId, connectionId, confirmationToken });
+}
+
+// Delete entries older than `before` and beyond the newest `maxEntries`; resolves to the number deleted
+export async function pruneQueryHistory(before?: string, maxEntries?: number): Promise<number> {
+  return await invoke("prune_query_history", { before, maxEntries });
+}
+
+// Audit log of data and schema changes (append-only)
+export type AuditCommand =
+  | 'execute_query' | 'execute_query_stream' | 'execute_script' | 'execute_sql_safe' | 'rerun_query_history'
+  | 'execute_changes' | 'undo_last_changes' | 'execute_ddl' | 'import_csv' | 'import_sql';
+
+export interface AuditEntry {
+  id: number;
+  connectionId: string;
+  command: AuditCommand;
+  sql: string; // Exactly as sent to the database
+  rowsAffected: number | null; // null when the statement failed or the count is unknown
+  success: boolean;
+  error: string | null;
+  executedAt: string; // ISO 8601
+}
+
+export interface AuditFilter {
+  connectionId?: string;
+  text?: string; // Case-insensitive substring of the SQL
+  command?: AuditCommand;
+  from?: string; // ISO 8601, inclusive
+  to?: string; // ISO 8601, exclusive
+  failed?: boolean;
+  limit?: number; // Default 100, all entries when exporting
+  offset?: number;
+}
+
+export async function searchAuditLog(filter: AuditFilter = {}): Promise<AuditEntry[]> {
+  return await invoke("search_audit_log", { filter });
+}
+
+// Write the matching entries to a CSV or JSON file
+export async function exportAuditLog(
+  format: Extract<ExportFormat, "csv" | "json">,
+  filePath: string,
+  filter: AuditFilter = {}
+): Promise<ExportResult> {
+  return await invoke("export_audit_log", { filter, format, filePath });
+}
+
+// Saved query library
+export interface QueryFolder {
+  id: string; // Empty for a new folder
+  name: string;
+  parentId?: string | null;
+}
+
+export interface SavedQueryParameter {
+  name: string; // "user_id" for :user_id, "1" for $1
+  type?: ParamType;
+  defaultValue?: unknown;
+  description?: string | null;
 }
 
-export async function explainQuery(connectionId: string, sql: string): Promise<ExplainResult> {
-  return await invoke("explain_query", { id: connectionId, sql });
+export interface SavedQuery {
+  id: string; // Empty for a new query
+  name: string;
+  description?: string | null;
+  sql: string;
+  folderId?: string | null;
+  tags: string[];
+  connectionId?: string | null; // Only offered on this connection
+  dbType?: DatabaseType | null; // Only offered on connections of this type
+  parameters: SavedQueryParameter[];
+  createdAt?: string;
+  updatedAt?: string;
+}
+
+export interface SavedQueryLibrary {
+  version: number;
+  folders: QueryFolder[];
+  queries: SavedQuery[];
+}
+
+// With connectionId, only queries that apply to that connection are returned
+export async function listSavedQueries(connectionId?: string): Promise<SavedQueryLibrary> {
+  return await invoke("list_saved_queries", { connectionId });
+}
+
+export async function saveSavedQuery(query: SavedQuery): Promise<SavedQuery> {
+  return await invoke("save_saved_query", { query });
+}
+
+export async function deleteSavedQuery(id: string): Promise<void> {
+  return await invoke("delete_saved_query", { id });
+}
+
+export async function saveQueryFolder(folder: QueryFolder): Promise<QueryFolder> {
+  return await invoke("save_query_folder", { folder });
+}
+
+// Queries and subfolders of a deleted folder move to its parent
+export async function deleteQueryFolder(id: string): Promise<void> {
+  return await invoke("delete_query_folder", { id });
+}
+
+// Resolves to the number of exported queries
+export async function exportSavedQueries(filePath: string): Promise<number> {
+  return await invoke("export_saved_queries", { filePath });
+}
+
+// Merges into the current library (same IDs are overwritten) unless replace is set
+export async function importSavedQueries(filePath: string, replace: boolean = false): Promise<SavedQueryLibrary> {
+  return await invoke("import_saved_queries", { filePath, replace });
 }
 
 // AI-powered SQL completion
@@ -253,11 +685,29 @@ export async function previewCreateTable(
   return await invoke("preview_create_table", { connectionId, request });
 }
 
+export interface DryRunStatement {
+  sql: string;
+  rowsAffected: number | null; // Null when the statement failed
+  error: string | null;
+  errorCode: string | null;
+}
+
+export interface DryRunResult {
+  success: boolean;
+  statements: DryRunStatement[];
+}
+
 export async function executeDdl(
   connectionId: string, 
-  sql: string
+  sql: string,
+  confirmationToken?: string
 ): Promise<void> {
-  return await invoke("execute_ddl", { id: connectionId, sql });
+  await invoke("execute_ddl", { id: connectionId, sql, confirmationToken });
+}
+
+// Run DDL in a transaction that is always rolled back (PostgreSQL and SQLite)
+export async function dryRunDdl(connectionId: string, sql: string): Promise<DryRunResult> {
+  return await invoke("execute_ddl", { id: connectionId, sql, dryRun: true });
 }
 
 export async function previewAddColumn(
@@ -44,6 +44,9 @@ export interface Connection {
   color?: string;
   lastUsedAt?: string;
   createdAt: string;
+  queryTimeoutMs?: number; // Default statement timeout (undefined = no limit)
+  readOnly?: boolean; // Reject writes at session level and in data-editing commands
+  protected?: boolean; // Ask for confirmation before DROP, TRUNCATE and unbounded UPDATE/DELETE
 }
 
 export type TabType = 'query' | 'table' | 'structure' | 'erd';